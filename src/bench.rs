@@ -0,0 +1,182 @@
+use std::{
+    fmt::{self, Display, Formatter, Write as _},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+/// Time `runs` runs of `run`, after `warmup` runs that aren't timed.
+///
+/// `prepare` is called before every run, outside of the timing, to set up the state it runs on.
+pub fn measure<T>(
+    warmup: u32,
+    runs: u32,
+    mut prepare: impl FnMut() -> T,
+    mut run: impl FnMut(T) -> Result<()>,
+) -> Result<Vec<Duration>> {
+    for _ in 0..warmup {
+        run(prepare())?;
+    }
+
+    (0..runs)
+        .map(|_| {
+            let state = prepare();
+            let start = Instant::now();
+            run(state)?;
+            Ok(start.elapsed())
+        })
+        .collect()
+}
+
+/// A summary of the timed runs of a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub runs: usize,
+    pub min: Duration,
+    pub median: Duration,
+    pub mean: Duration,
+
+    /// The number of ops of the optimized program executed by each run.
+    pub ops: u64,
+}
+
+impl Report {
+    /// Summarize the times of runs that each executed `ops` ops.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no times, or more than [`u32::MAX`] of them.
+    pub fn new(mut times: Vec<Duration>, ops: u64) -> Report {
+        assert!(!times.is_empty(), "there should be at least one run");
+        times.sort_unstable();
+
+        let runs = times.len();
+        let median = if runs.is_multiple_of(2) {
+            (times[runs / 2 - 1] + times[runs / 2]) / 2
+        } else {
+            times[runs / 2]
+        };
+        let total = times.iter().sum::<Duration>();
+        let mean = total / u32::try_from(runs).expect("there should be at most u32::MAX runs");
+
+        Report {
+            runs,
+            min: times[0],
+            median,
+            mean,
+            ops,
+        }
+    }
+
+    /// Return how many ops are executed per second, going by the median time.
+    pub fn ops_per_second(&self) -> u128 {
+        u128::from(self.ops) * 1_000_000_000 / self.median.as_nanos().max(1)
+    }
+
+    /// Return the report as a single-line JSON object, with times in nanoseconds.
+    pub fn to_json(&self, program: &str) -> String {
+        format!(
+            concat!(
+                r#"{{"program":{},"runs":{},"min_ns":{},"median_ns":{},"mean_ns":{},"#,
+                r#""ops":{},"ops_per_second":{}}}"#,
+            ),
+            json_string(program),
+            self.runs,
+            self.min.as_nanos(),
+            self.median.as_nanos(),
+            self.mean.as_nanos(),
+            self.ops,
+            self.ops_per_second(),
+        )
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Timed {} runs:", self.runs)?;
+        writeln!(f, "  Min: {:.3?}", self.min)?;
+        writeln!(f, "  Median: {:.3?}", self.median)?;
+        writeln!(f, "  Mean: {:.3?}", self.mean)?;
+        write!(
+            f,
+            "  Ops: {} per run, {} per second",
+            self.ops,
+            self.ops_per_second()
+        )
+    }
+}
+
+/// Quote and escape `text` as a JSON string.
+pub fn json_string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => write!(json, "\\u{:04x}", u32::from(c)).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(times: &[u64]) -> Vec<Duration> {
+        times.iter().copied().map(Duration::from_millis).collect()
+    }
+
+    #[test]
+    fn summarize_times() {
+        let report = Report::new(millis(&[30, 10, 20]), 1000);
+        assert_eq!(report.runs, 3);
+        assert_eq!(report.min, Duration::from_millis(10));
+        assert_eq!(report.median, Duration::from_millis(20));
+        assert_eq!(report.mean, Duration::from_millis(20));
+        assert_eq!(report.ops_per_second(), 50_000);
+
+        let report = Report::new(millis(&[40, 10, 20, 10]), 0);
+        assert_eq!(report.median, Duration::from_millis(15));
+        assert_eq!(report.mean, Duration::from_millis(20));
+        assert_eq!(report.ops_per_second(), 0);
+    }
+
+    #[test]
+    fn measure_runs() {
+        let mut prepared = 0;
+        let mut ran = Vec::new();
+        let times = measure(
+            2,
+            3,
+            || {
+                prepared += 1;
+                prepared
+            },
+            |run| {
+                ran.push(run);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(times.len(), 3);
+        assert_eq!(ran, [1, 2, 3, 4, 5]);
+
+        assert!(measure(0, 1, || (), |()| Err(anyhow::anyhow!("failed"))).is_err());
+    }
+
+    #[test]
+    fn emit_json() {
+        let report = Report::new(millis(&[2]), 4);
+        assert_eq!(
+            report.to_json("a \"b\"\\c\u{1}.b"),
+            r#"{"program":"a \"b\"\\c\u0001.b","runs":1,"min_ns":2000000,"median_ns":2000000,"mean_ns":2000000,"ops":4,"ops_per_second":2000}"#
+        );
+    }
+}
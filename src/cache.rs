@@ -0,0 +1,311 @@
+//! An on-disk cache of optimized programs, so big programs don't have to be compiled and optimized
+//! again every time they run.
+//!
+//! Programs are cached in `$XDG_CACHE_HOME/beef`, or `~/.cache/beef` if it is unset, in a file
+//! named after the SHA-256 hash of the program and the version of beef that optimized it.
+
+use std::{
+    env,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Result};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    ir::{self, Extensions, Op, Program, Span},
+    optimizer::{self, StartingTape},
+};
+
+/// The bytes every cache file starts with.
+const MAGIC: &[u8; 4] = b"BFC\0";
+
+/// The version of the format of cache files, which must be bumped whenever it changes, including
+/// when ops are added or changed, or when programs cached before no longer run the same.
+const FORMAT_VERSION: u32 = 6;
+
+/// Compile a program with `extensions` and optimize it at `level` for a program starting on
+/// `tape`, or load it from the cache if it has been compiled that way before, and return it along
+/// with whether it came from the cache.
+///
+/// Failing to read or write the cache is not an error, since it only makes beef slower. An error
+/// is only returned if the program cannot be compiled.
+pub fn load_or_compile(
+    program: &str,
+    extensions: Extensions,
+    level: u8,
+    tape: StartingTape,
+) -> Result<(Program, bool)> {
+    let path = path(program, extensions, level, tape);
+
+    if let Some(compiled) = path
+        .as_ref()
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|bytes| decode(&bytes).ok())
+    {
+        return Ok((compiled, true));
+    }
+
+    let compiled = optimizer::optimize_on(ir::compile_with(program, extensions)?, level, tape).0;
+    if let Some(path) = path {
+        let _ = store(&path, &compiled);
+    }
+    Ok((compiled, false))
+}
+
+/// Return the path of the cache file for a program compiled like [`load_or_compile`], or `None`
+/// if there is no cache directory.
+pub fn path(
+    program: &str,
+    extensions: Extensions,
+    level: u8,
+    tape: StartingTape,
+) -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME").filter(|dir| !dir.is_empty())?).join(".cache"),
+    };
+    Some(
+        dir.join("beef")
+            .join(format!("{}.bfc", key(program, extensions, level, tape))),
+    )
+}
+
+/// Return the hash that identifies a program compiled like [`load_or_compile`] in the cache, as
+/// lowercase hex.
+///
+/// The version of beef is part of the hash, so programs are optimized again after an upgrade.
+fn key(program: &str, extensions: Extensions, level: u8, tape: StartingTape) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update([0, level, u8::from(tape == StartingTape::Preloaded)]);
+    if let Some(switches) = extensions.tape_switch {
+        hasher.update(String::from_iter(switches));
+    }
+    hasher.update([0]);
+    hasher.update(program);
+    hasher
+        .finalize()
+        .iter()
+        .fold(String::new(), |mut key, byte| {
+            write!(key, "{byte:02x}").unwrap();
+            key
+        })
+}
+
+/// Write a cache file, through a temporary file so that other instances of beef never see it half
+/// written.
+fn store(path: &Path, program: &Program) -> Result<()> {
+    let dir = path.parent().expect("cache files are in a directory");
+    fs::create_dir_all(dir)?;
+    let temp_path = dir.join(format!(".{}.tmp", std::process::id()));
+    fs::write(&temp_path, encode(program))?;
+    fs::rename(&temp_path, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp_path);
+    })?;
+    Ok(())
+}
+
+/// Serialize a program into the format of cache files.
+///
+/// The file starts with [`MAGIC`] and [`FORMAT_VERSION`], followed by the number of ops, then the
+/// ops themselves, each followed by the start and end of its span. Each op is a tag byte followed
+/// by its operands, in declaration order. All numbers are little-endian, and operands and spans are
+/// 64 bits wide, so the format is the same on every machine.
+fn encode(program: &Program) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(16 + program.len() * 33);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(program.len() as u64).to_le_bytes());
+
+    for (op, span) in program.iter() {
+        let (tag, first, second) = match op {
+            Op::Add { offset, amount } => (0, offset as i64, Some(i64::from(amount))),
+            Op::Move(amount) => (1, amount as i64, None),
+            Op::SetZero { offset } => (2, offset as i64, None),
+            Op::ClearRange { offset, len } => (10, offset as i64, Some(len.cast_signed() as i64)),
+            Op::Set { offset, value } => (3, offset as i64, Some(i64::from(value))),
+            Op::MulAdd { offset, factor } => (4, offset as i64, Some(i64::from(factor))),
+            Op::Scan(stride) => (5, stride as i64, None),
+            Op::JumpIfZero(target) => (6, target.cast_signed() as i64, None),
+            Op::JumpIfNonZero(target) => (7, target.cast_signed() as i64, None),
+            Op::Output { offset } => (8, offset as i64, None),
+            Op::Input { offset } => (9, offset as i64, None),
+            Op::Memoize => (11, 0, None),
+            Op::SwitchTape(amount) => (12, amount as i64, None),
+        };
+        bytes.push(tag);
+        bytes.extend_from_slice(&first.to_le_bytes());
+        if let Some(second) = second {
+            bytes.extend_from_slice(&second.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(span.start as u64).to_le_bytes());
+        bytes.extend_from_slice(&(span.end as u64).to_le_bytes());
+    }
+
+    bytes
+}
+
+/// Deserialize a program written by [`encode`].
+///
+/// An error is returned if the bytes aren't a valid cache file, including if they were written by
+/// another version of the format, or if the jumps don't match up.
+fn decode(bytes: &[u8]) -> Result<Program> {
+    let mut reader = Reader { bytes };
+
+    if reader.take::<4>()? != *MAGIC {
+        bail!("Not a cache file");
+    }
+    let version = u32::from_le_bytes(reader.take()?);
+    if version != FORMAT_VERSION {
+        bail!("Unsupported cache format version {version}");
+    }
+
+    let len = usize::try_from(u64::from_le_bytes(reader.take()?))?;
+    // Every op takes at least 25 bytes, which bounds how much has to be allocated for a bogus
+    // length.
+    let mut program = Program::with_capacity(len.min(reader.bytes.len() / 25));
+    for _ in 0..len {
+        let tag = reader.take::<1>()?[0];
+        let first = i64::from_le_bytes(reader.take()?);
+        let offset = isize::try_from(first)?;
+        let target = || usize::try_from(first);
+        let mut second = || reader.take().map(i64::from_le_bytes);
+
+        let op = match tag {
+            0 => Op::Add {
+                offset,
+                amount: i32::try_from(second()?)?,
+            },
+            1 => Op::Move(offset),
+            2 => Op::SetZero { offset },
+            3 => Op::Set {
+                offset,
+                value: i32::try_from(second()?)?,
+            },
+            4 => Op::MulAdd {
+                offset,
+                factor: i32::try_from(second()?)?,
+            },
+            5 => Op::Scan(offset),
+            6 => Op::JumpIfZero(target()?),
+            7 => Op::JumpIfNonZero(target()?),
+            8 => Op::Output { offset },
+            9 => Op::Input { offset },
+            10 => Op::ClearRange {
+                offset,
+                len: usize::try_from(second()?)?,
+            },
+            11 => Op::Memoize,
+            12 => Op::SwitchTape(offset),
+            tag => bail!("Unknown op tag {tag}"),
+        };
+        let start = usize::try_from(u64::from_le_bytes(reader.take()?))?;
+        let end = usize::try_from(u64::from_le_bytes(reader.take()?))?;
+        program.push(op, Span { start, end });
+    }
+    if !reader.bytes.is_empty() {
+        bail!("Trailing bytes after the last op");
+    }
+
+    // The interpreter trusts jump targets, so make sure they are the ones `ir::link` would pick.
+    let mut depth = 0usize;
+    for op in &program.ops {
+        match op {
+            Op::JumpIfZero(_) => depth += 1,
+            Op::JumpIfNonZero(_) => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| anyhow!("Unmatched jump"))?;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        bail!("Unmatched jump");
+    }
+    let mut linked = program.ops.clone();
+    ir::link(&mut linked);
+    if linked != program.ops {
+        bail!("Mislinked jump");
+    }
+
+    Ok(program)
+}
+
+/// Reads fixed-size chunks from the start of a byte slice.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let (chunk, rest) = self
+            .bytes
+            .split_first_chunk()
+            .ok_or_else(|| anyhow!("Unexpected end of cache file"))?;
+        self.bytes = rest;
+        Ok(*chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn optimize(program: &str) -> Program {
+        optimizer::optimize(ir::compile(program).unwrap())
+    }
+
+    fn encode_ops(ops: &[Op]) -> Vec<u8> {
+        let mut program = Program::default();
+        for &op in ops {
+            program.push(op, Span { start: 0, end: 1 });
+        }
+        encode(&program)
+    }
+
+    #[test]
+    fn round_trip() {
+        let program = optimize(",[-]+++>[->++<<-->]<[<<]>.");
+        assert_eq!(decode(&encode(&program)).unwrap(), program);
+        assert_eq!(decode(&encode_ops(&[])).unwrap(), Program::default());
+
+        let ops = [Op::SwitchTape(-2), Op::Memoize];
+        assert_eq!(decode(&encode_ops(&ops)).unwrap().ops, ops);
+    }
+
+    #[test]
+    fn reject_invalid_files() {
+        let bytes = encode(&optimize(",[->+<]."));
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(decode(&[bytes.as_slice(), &[0]].concat()).is_err());
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] += 1;
+        assert!(decode(&wrong_version).is_err());
+
+        let mut mislinked = encode_ops(&[Op::JumpIfZero(1), Op::JumpIfNonZero(0)]);
+        mislinked[17] = 2;
+        assert!(decode(&mislinked).is_err());
+        assert!(decode(&encode_ops(&[Op::JumpIfNonZero(0)])).is_err());
+    }
+
+    #[test]
+    fn keys_depend_on_how_programs_are_compiled() {
+        let key = |program, level, tape| key(program, Extensions::default(), level, tape);
+        let zeroed = StartingTape::Zeroed;
+        assert_eq!(key("+", 3, zeroed), key("+", 3, zeroed));
+        assert_ne!(key("+", 3, zeroed), key("-", 3, zeroed));
+        assert_ne!(key("+", 3, zeroed), key("+", 2, zeroed));
+        assert_ne!(key("+", 3, zeroed), key("+", 3, StartingTape::Preloaded));
+        assert_eq!(key("", 3, zeroed).len(), 64);
+
+        let extensions = Extensions {
+            tape_switch: Some(['{', '}']),
+        };
+        assert_ne!(super::key("{", extensions, 3, zeroed), key("{", 3, zeroed));
+    }
+}
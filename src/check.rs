@@ -0,0 +1,158 @@
+use std::fmt::{self, Display, Formatter};
+
+/// The rules a program is checked against, besides its loop brackets matching.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rules {
+    /// The most instructions the program may have, if there's a limit.
+    pub max_instructions: Option<usize>,
+
+    /// The deepest its loops may nest, if there's a limit.
+    pub max_depth: Option<usize>,
+
+    /// Whether every character has to be an instruction or whitespace, so comments are problems.
+    pub strict: bool,
+}
+
+/// Something wrong with a program, found without running it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// The line of the source the problem is at, from 1.
+    pub line: usize,
+
+    /// The character of the line the problem is at, from 1.
+    pub column: usize,
+
+    pub message: String,
+}
+
+impl Problem {
+    /// Return the problem described by `message` at the byte `offset` of `source`.
+    pub fn new(source: &str, offset: usize, message: String) -> Problem {
+        let (line, column) = position(source, offset);
+        Problem {
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Check `source` against `rules`, returning every problem with it in the order they come in.
+///
+/// This looks at the source the way [`crate::ir::compile`] does, but doesn't stop at the first
+/// problem.
+pub fn check(source: &str, rules: &Rules) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    // Holds the source index of every `[` that is still waiting for its `]`.
+    let mut stack = Vec::new();
+    let mut instructions = 0;
+    // The source index at which a run of characters that aren't instructions started.
+    let mut comment = None;
+
+    for (i, ch) in source.char_indices() {
+        let instruction = "+-<>.,[]".contains(ch);
+        if instruction {
+            instructions += 1;
+            if let Some(max) = rules
+                .max_instructions
+                .filter(|&max| instructions == max + 1)
+            {
+                problems.push((i, format!("More than {max} instructions")));
+            }
+        }
+        match ch {
+            '[' => {
+                stack.push(i);
+                // Only the outermost loop that is too deep is a problem, not every loop inside it.
+                if let Some(max) = rules.max_depth.filter(|&max| stack.len() == max + 1) {
+                    problems.push((i, format!("Loop nested more than {max} deep")));
+                }
+            }
+            ']' if stack.pop().is_none() => problems.push((i, "Unmatched ]".to_owned())),
+            _ => {}
+        }
+
+        if !rules.strict {
+            continue;
+        }
+        if instruction || ch.is_whitespace() {
+            if let Some(start) = comment.take() {
+                problems.push(not_instructions(source, start, i));
+            }
+        } else {
+            comment.get_or_insert(i);
+        }
+    }
+
+    if let Some(start) = comment {
+        problems.push(not_instructions(source, start, source.len()));
+    }
+    problems.extend(stack.into_iter().map(|i| (i, "Unmatched [".to_owned())));
+    problems.sort_by_key(|&(i, _)| i);
+
+    problems
+        .into_iter()
+        .map(|(i, message)| Problem::new(source, i, message))
+        .collect()
+}
+
+/// Return the problem of the characters of `source` from `start` to `end` not being instructions.
+fn not_instructions(source: &str, start: usize, end: usize) -> (usize, String) {
+    (
+        start,
+        format!("Not an instruction: `{}`", &source[start..end]),
+    )
+}
+
+/// Return the line and the character of that line that the byte `offset` of `source` is at, both
+/// from 1.
+pub fn position(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_problems() {
+        assert_eq!(check("+[->+<] a comment\n", &Rules::default()), []);
+
+        let problems: Vec<_> = check("]+\n[[-]\n", &Rules::default())
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(problems, ["1:1: Unmatched ]", "2:1: Unmatched ["]);
+
+        let rules = Rules {
+            max_instructions: Some(8),
+            max_depth: Some(1),
+            strict: true,
+        };
+        let problems: Vec<_> = check("+ [[[-]]]\né+. done", &rules)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                "1:4: Loop nested more than 1 deep",
+                "2:1: Not an instruction: `é`",
+                "2:2: More than 8 instructions",
+                "2:5: Not an instruction: `done`",
+            ]
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use std::fmt::Display;
+
+pub mod asm;
+pub mod brainfuck;
+pub mod c;
+pub mod js;
+pub mod llvm;
+pub mod rust;
+pub mod wasm;
+
+/// Accumulates generated source code one line at a time, keeping track of indentation.
+struct Writer {
+    code: String,
+    indent: &'static str,
+    depth: usize,
+}
+
+impl Writer {
+    /// Return an empty writer that indents nested lines with `indent`.
+    fn new(indent: &'static str) -> Writer {
+        Writer {
+            code: String::new(),
+            indent,
+            depth: 0,
+        }
+    }
+
+    /// Write a line at the current indentation.
+    fn line(&mut self, line: impl Display) {
+        let line = line.to_string();
+        if !line.is_empty() {
+            self.code.push_str(&self.indent.repeat(self.depth));
+            self.code.push_str(&line);
+        }
+        self.code.push('\n');
+    }
+
+    /// Write a line one level less indented than the current one, like a label.
+    fn label(&mut self, line: impl Display) {
+        self.depth -= 1;
+        self.line(line);
+        self.depth += 1;
+    }
+
+    /// Write a line, then indent the lines after it.
+    fn open(&mut self, line: impl Display) {
+        self.line(line);
+        self.depth += 1;
+    }
+
+    /// Stop indenting, then write a line.
+    fn close(&mut self, line: impl Display) {
+        self.depth -= 1;
+        self.line(line);
+    }
+
+    /// Return the code written so far.
+    fn finish(self) -> String {
+        self.code
+    }
+}
@@ -0,0 +1,269 @@
+use super::Writer;
+use crate::{interpreter, ir::Op};
+
+/// Everything before the body of `main`.
+const PRELUDE: &str = r#"# Generated by beef.
+    .intel_syntax noprefix
+
+    .set TAPE_SIZE, {tape_size}
+
+    .bss
+tape:
+    .zero TAPE_SIZE
+
+    .section .rodata
+input_error:
+    .ascii "Failed to read character from stdin\n"
+    .set INPUT_ERROR_LEN, . - input_error
+
+    .text
+
+# Read a byte from stdin into eax, or exit if there is none.
+input:
+    sub rsp, 8
+    xor edi, edi
+    call fflush@PLT
+    call getchar@PLT
+    test eax, eax
+    js .Linput_error
+    add rsp, 8
+    ret
+.Linput_error:
+    mov edi, 2
+    lea rsi, [rip + input_error]
+    mov edx, INPUT_ERROR_LEN
+    call write@PLT
+    mov edi, 1
+    call exit@PLT
+
+    .globl main
+main:
+    push rbx
+    push r12
+    push r13
+    push r15
+    sub rsp, 8
+    lea r12, [rip + tape]
+    xor r13d, r13d
+    mov r15, TAPE_SIZE"#;
+
+/// Everything after the body of `main`.
+const EPILOGUE: &str = r#"    xor eax, eax
+    add rsp, 8
+    pop r15
+    pop r13
+    pop r12
+    pop rbx
+    ret
+
+    .section .note.GNU-stack, "", @progbits"#;
+
+/// Translate a compiled program into x86-64 assembly that behaves the same way.
+///
+/// The generated code is written for the GNU assembler in Intel syntax, defines `main`, and calls
+/// into the C standard library for I/O, following the System V calling convention used by Linux
+/// and most other Unix-like systems. It follows the semantics of the interpreter: the tape holds
+/// `tape_size` wrapping 8-bit cells, and the tape pointer wraps around its ends.
+///
+/// The registers are used just like in the x86-64 JIT backend:
+///
+/// - `r12` holds the address of the start of the tape
+/// - `r13` holds the tape pointer, as an index into the tape
+/// - `r15` holds the length of the tape
+/// - `rax`, `rcx`, and `rdx` are scratch registers
+pub fn emit(ops: &[Op], tape_size: usize) -> String {
+    let mut writer = Writer::new("    ");
+    let len = tape_size.cast_signed();
+
+    writer.line(PRELUDE.replace("{tape_size}", &tape_size.to_string()));
+    writer.depth = 1;
+
+    // Loops and scans are numbered in order, to give their labels unique names.
+    let mut labels = 0;
+    let mut loops = Vec::new();
+
+    for &op in ops {
+        match op {
+            Op::Add { offset, amount } => {
+                emit_index(&mut writer, offset % len);
+                let amount = interpreter::wrap_to_cell(amount).cast_signed();
+                writer.line(format!("add byte ptr [r12 + rax], {amount}"));
+            }
+            Op::Move(amount) => {
+                emit_index(&mut writer, amount % len);
+                writer.line("mov r13, rax");
+            }
+            Op::SetZero { offset } => {
+                emit_index(&mut writer, offset % len);
+                writer.line("mov byte ptr [r12 + rax], 0");
+            }
+            Op::ClearRange { offset, len: count } => {
+                for offset in (offset..).take(count) {
+                    emit_index(&mut writer, offset % len);
+                    writer.line("mov byte ptr [r12 + rax], 0");
+                }
+            }
+            Op::Set { offset, value } => {
+                emit_index(&mut writer, offset % len);
+                let value = interpreter::wrap_to_cell(value);
+                writer.line(format!("mov byte ptr [r12 + rax], {value}"));
+            }
+            Op::MulAdd { offset, factor } => {
+                let factor = interpreter::wrap_to_cell(factor);
+                writer.line("movzx edx, byte ptr [r12 + r13]");
+                writer.line(format!("imul edx, edx, {factor}"));
+                emit_index(&mut writer, offset % len);
+                writer.line("add byte ptr [r12 + rax], dl");
+            }
+            Op::Scan(stride) => {
+                // Scans always sit inside a loop that checks the current cell, so they only need
+                // to check it again after moving.
+                labels += 1;
+                writer.label(format!(".Lscan{labels}:"));
+                emit_index(&mut writer, stride % len);
+                writer.line("mov r13, rax");
+                writer.line("cmp byte ptr [r12 + r13], 0");
+                writer.line(format!("jne .Lscan{labels}"));
+            }
+
+            Op::JumpIfZero(_) => {
+                labels += 1;
+                loops.push(labels);
+                writer.line("cmp byte ptr [r12 + r13], 0");
+                writer.line(format!("je .Lend{labels}"));
+                writer.label(format!(".Lstart{labels}:"));
+            }
+            Op::JumpIfNonZero(_) => {
+                let label = loops.pop().expect("jumps should be balanced");
+                writer.line("cmp byte ptr [r12 + r13], 0");
+                writer.line(format!("jne .Lstart{label}"));
+                writer.label(format!(".Lend{label}:"));
+            }
+
+            Op::Output { offset } => {
+                emit_index(&mut writer, offset % len);
+                writer.line("movzx edi, byte ptr [r12 + rax]");
+                writer.line("call putchar@PLT");
+            }
+            Op::Input { offset } => {
+                writer.line("call input");
+                writer.line("mov ebx, eax");
+                emit_index(&mut writer, offset % len);
+                writer.line("mov byte ptr [r12 + rax], bl");
+            }
+            Op::Memoize | Op::SwitchTape(_) => {}
+        }
+    }
+
+    writer.depth = 0;
+    writer.line(EPILOGUE);
+    writer.finish()
+}
+
+/// Emit code that puts the index `offset` cells away from the tape pointer in `rax`, wrapping
+/// around the ends of the tape.
+///
+/// `offset` must be less than the length of the tape.
+fn emit_index(writer: &mut Writer, offset: isize) {
+    match offset {
+        0 => {
+            writer.line("mov rax, r13");
+            return;
+        }
+        1.. => writer.line(format!("lea rax, [r13 + {offset}]")),
+        _ => writer.line(format!("lea rax, [r13 - {}]", offset.unsigned_abs())),
+    }
+
+    if offset > 0 {
+        writer.line("mov rcx, rax");
+        writer.line("sub rcx, r15");
+        writer.line("cmp rax, r15");
+        writer.line("cmovae rax, rcx");
+    } else {
+        writer.line("lea rcx, [rax + r15]");
+        writer.line("test rax, rax");
+        writer.line("cmovs rax, rcx");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ir, optimizer};
+
+    fn emit_str(program: &str) -> String {
+        emit(&optimizer::optimize(ir::compile(program).unwrap()).ops, 100)
+    }
+
+    fn body(code: &str) -> Vec<&str> {
+        let start = code.find("    mov r15, TAPE_SIZE\n").unwrap();
+        let end = code.find("    xor eax, eax\n    add rsp, 8\n").unwrap();
+        code[start..end].lines().skip(1).collect()
+    }
+
+    #[test]
+    fn emit_ops() {
+        let code = emit_str("+>-[->++<]<.");
+        assert!(code.contains("    .set TAPE_SIZE, 100\n"));
+        assert_eq!(
+            body(&code),
+            [
+                "    mov rax, r13",
+                "    add byte ptr [r12 + rax], 1",
+                "    lea rax, [r13 + 1]",
+                "    mov rcx, rax",
+                "    sub rcx, r15",
+                "    cmp rax, r15",
+                "    cmovae rax, rcx",
+                "    add byte ptr [r12 + rax], -1",
+                "    lea rax, [r13 + 1]",
+                "    mov rcx, rax",
+                "    sub rcx, r15",
+                "    cmp rax, r15",
+                "    cmovae rax, rcx",
+                "    mov r13, rax",
+                "    movzx edx, byte ptr [r12 + r13]",
+                "    imul edx, edx, 2",
+                "    lea rax, [r13 + 1]",
+                "    mov rcx, rax",
+                "    sub rcx, r15",
+                "    cmp rax, r15",
+                "    cmovae rax, rcx",
+                "    add byte ptr [r12 + rax], dl",
+                "    mov rax, r13",
+                "    mov byte ptr [r12 + rax], 0",
+                "    lea rax, [r13 - 1]",
+                "    lea rcx, [rax + r15]",
+                "    test rax, rax",
+                "    cmovs rax, rcx",
+                "    movzx edi, byte ptr [r12 + rax]",
+                "    call putchar@PLT",
+                "    lea rax, [r13 - 1]",
+                "    lea rcx, [rax + r15]",
+                "    test rax, rax",
+                "    cmovs rax, rcx",
+                "    mov r13, rax",
+            ]
+        );
+    }
+
+    #[test]
+    fn emit_loops() {
+        let code = emit_str(",[>[<]]");
+        let lines = body(&code);
+        for line in [
+            "    je .Lend1",
+            ".Lstart1:",
+            "    je .Lend2",
+            ".Lstart2:",
+            ".Lscan3:",
+            "    jne .Lscan3",
+            "    jne .Lstart2",
+            ".Lend2:",
+            "    jne .Lstart1",
+            ".Lend1:",
+        ] {
+            assert!(lines.contains(&line), "{line}");
+        }
+    }
+}
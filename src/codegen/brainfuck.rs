@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+use crate::{interpreter, ir::Op};
+
+/// The number of instructions on each line of the generated program.
+const LINE_WIDTH: usize = 80;
+
+/// Translate a compiled program back into Brainfuck.
+///
+/// The generated program only contains instructions, wrapped into lines of [`LINE_WIDTH`]. It
+/// relies on the semantics of beef: cells are 8 bits wide and wrap on overflow, and the tape
+/// pointer wraps around the ends of the tape.
+///
+/// Cells are tracked while their values are known, so sets and clears only emit the difference
+/// from the previous value instead of clearing the cell first.
+///
+/// # Panics
+///
+/// Panics if a run of [`Op::MulAdd`]s isn't followed by a clear or set of the current cell, which
+/// the optimizer never produces.
+pub fn emit(ops: &[Op]) -> String {
+    let mut emitter = Emitter {
+        code: String::new(),
+        position: 0,
+        known: Known {
+            values: HashMap::new(),
+            zero_by_default: true,
+        },
+        in_multiply_loop: false,
+    };
+
+    for (i, &op) in ops.iter().enumerate() {
+        emitter.emit_op(op, ops.get(i + 1).copied());
+    }
+
+    let mut lines = String::with_capacity(emitter.code.len() + emitter.code.len() / LINE_WIDTH + 1);
+    for line in emitter.code.as_bytes().chunks(LINE_WIDTH) {
+        lines.push_str(std::str::from_utf8(line).expect("Brainfuck instructions are ASCII"));
+        lines.push('\n');
+    }
+    lines
+}
+
+/// The values of the cells that are known at a point in the program, by their offset from the tape
+/// pointer.
+struct Known {
+    values: HashMap<isize, u8>,
+
+    /// Whether cells missing from `values` are known to be zero, as they are at the start.
+    zero_by_default: bool,
+}
+
+impl Known {
+    fn get(&self, offset: isize) -> Option<u8> {
+        self.values
+            .get(&offset)
+            .copied()
+            .or(self.zero_by_default.then_some(0))
+    }
+
+    /// Forget everything, except that the current cell is `current`.
+    fn reset(&mut self, current: Option<u8>) {
+        self.values.clear();
+        self.zero_by_default = false;
+        if let Some(current) = current {
+            self.values.insert(0, current);
+        }
+    }
+}
+
+struct Emitter {
+    code: String,
+
+    /// The offset of the tape pointer of the generated program from the one of the IR.
+    ///
+    /// Moves are only emitted once a cell needs to be accessed, or before a loop.
+    position: isize,
+
+    known: Known,
+
+    /// Whether a multiply loop has been opened by an [`Op::MulAdd`].
+    in_multiply_loop: bool,
+}
+
+impl Emitter {
+    fn emit_op(&mut self, op: Op, next: Option<Op>) {
+        match op {
+            Op::Add { offset, amount } => {
+                self.move_to(offset);
+                let amount = interpreter::wrap_to_cell(amount);
+                self.add(amount);
+                let value = self
+                    .known
+                    .get(offset)
+                    .map(|value| value.wrapping_add(amount));
+                self.record(offset, value);
+            }
+            Op::Move(amount) => {
+                self.position -= amount;
+                let values = self.known.values.drain().collect::<Vec<_>>();
+                self.known.values = values
+                    .into_iter()
+                    .map(|(offset, value)| (offset - amount, value))
+                    .collect();
+            }
+            Op::SetZero { offset } => self.set(offset, 0),
+            Op::ClearRange { offset, len } => {
+                for offset in (offset..).take(len) {
+                    self.set(offset, 0);
+                }
+            }
+            Op::Set { offset, value } => self.set(offset, interpreter::wrap_to_cell(value)),
+            Op::MulAdd { offset, factor } => {
+                if !self.in_multiply_loop {
+                    self.move_to(0);
+                    self.code.push_str("[-");
+                    self.in_multiply_loop = true;
+                }
+
+                self.move_to(offset);
+                self.add(interpreter::wrap_to_cell(factor));
+                self.record(offset, None);
+
+                if !matches!(next, Some(Op::MulAdd { .. })) {
+                    assert!(
+                        matches!(
+                            next,
+                            Some(Op::SetZero { offset: 0 } | Op::Set { offset: 0, .. })
+                        ),
+                        "multiply ops should be followed by a clear of the current cell"
+                    );
+                    self.move_to(0);
+                    self.code.push(']');
+                    self.in_multiply_loop = false;
+                    self.known.values.insert(0, 0);
+                }
+            }
+            Op::Scan(stride) => {
+                self.move_to(0);
+                self.move_by(stride);
+            }
+
+            Op::JumpIfZero(_) => {
+                self.move_to(0);
+                self.code.push('[');
+                self.known.reset(None);
+            }
+            Op::JumpIfNonZero(_) => {
+                self.move_to(0);
+                self.code.push(']');
+                self.known.reset(Some(0));
+            }
+
+            Op::Output { offset } => {
+                self.move_to(offset);
+                self.code.push('.');
+            }
+            Op::Input { offset } => {
+                self.move_to(offset);
+                self.code.push(',');
+                self.record(offset, None);
+            }
+            Op::Memoize | Op::SwitchTape(_) => {}
+        }
+    }
+
+    /// Emit the instructions to set the cell at `offset` to `value`.
+    fn set(&mut self, offset: isize, value: u8) {
+        self.move_to(offset);
+        let current = self.known.get(offset).unwrap_or_else(|| {
+            self.code.push_str("[-]");
+            0
+        });
+        self.add(value.wrapping_sub(current));
+        self.record(offset, Some(value));
+    }
+
+    /// Emit the shortest run of `+` or `-` that adds `amount` to the current cell.
+    fn add(&mut self, amount: u8) {
+        if amount <= 128 {
+            self.code.push_str(&"+".repeat(amount.into()));
+        } else {
+            self.code
+                .push_str(&"-".repeat((256 - u16::from(amount)).into()));
+        }
+    }
+
+    /// Record the new value of the cell at `offset`.
+    fn record(&mut self, offset: isize, value: Option<u8>) {
+        match value {
+            Some(value) => self.known.values.insert(offset, value),
+            None if self.known.zero_by_default => {
+                // Cells that aren't listed are known to be zero, so this one has to be listed as
+                // unknown some other way. Give up on tracking the rest instead.
+                let values = self.known.values.drain().filter(|&(o, _)| o != offset);
+                let values = values.collect();
+                self.known = Known {
+                    values,
+                    zero_by_default: false,
+                };
+                None
+            }
+            None => self.known.values.remove(&offset),
+        };
+    }
+
+    /// Emit the moves to get to the cell at `offset`.
+    fn move_to(&mut self, offset: isize) {
+        self.move_by(offset - self.position);
+        self.position = offset;
+    }
+
+    /// Emit a move by `amount` cells.
+    fn move_by(&mut self, amount: isize) {
+        let direction = if amount < 0 { "<" } else { ">" };
+        self.code.push_str(&direction.repeat(amount.unsigned_abs()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ir, optimizer};
+
+    fn emit_str(program: &str) -> String {
+        emit(&optimizer::optimize(ir::compile(program).unwrap()).ops)
+    }
+
+    #[test]
+    fn emit_ops() {
+        assert_eq!(emit_str("+++>-<<.,"), "+++>-<<.,\n");
+        assert_eq!(emit_str(",[-]+++>,[+]--<"), ",[-]+++>,[-]--\n");
+    }
+
+    #[test]
+    fn emit_loops() {
+        assert_eq!(emit_str(",[->+++>-<<]"), ",[->+++>-<<]\n");
+        assert_eq!(emit_str(",[.[>>]<]"), ",[.[>>]<]\n");
+    }
+
+    #[test]
+    fn emit_known_values() {
+        let program = optimizer::optimize(ir::compile("++.+.").unwrap());
+        assert_eq!(emit(&optimizer::fold_prefix(&program, 8).ops), "++.+.\n");
+    }
+
+    #[test]
+    fn wrap_lines() {
+        let code = emit_str(&format!(",{}", ".".repeat(LINE_WIDTH)));
+        assert_eq!(
+            code.lines().map(str::len).collect::<Vec<_>>(),
+            [LINE_WIDTH, 1]
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use super::Writer;
+use crate::{interpreter, ir::Op};
+
+/// Everything before the body of `main`.
+const PRELUDE: &str = r#"/* Generated by beef. */
+#include <stdio.h>
+#include <stdlib.h>
+
+#define TAPE_SIZE {tape_size}
+#define CELL(offset) tape[(p + TAPE_SIZE + (offset)) % TAPE_SIZE]
+#define MOVE(amount) (p = (p + TAPE_SIZE + (amount)) % TAPE_SIZE)
+
+static unsigned char tape[TAPE_SIZE];
+static size_t p;
+
+static unsigned char input(void) {
+    fflush(stdout);
+    int c = getchar();
+    if (c == EOF) {
+        fputs("Failed to read character from stdin\n", stderr);
+        exit(1);
+    }
+    return (unsigned char)c;
+}
+"#;
+
+/// Translate a compiled program into a C program that behaves the same way.
+///
+/// The generated code follows the semantics of the interpreter: the tape holds `tape_size`
+/// wrapping 8-bit cells, and the tape pointer wraps around its ends.
+pub fn emit(ops: &[Op], tape_size: usize) -> String {
+    let mut writer = Writer::new("    ");
+    let len = tape_size.cast_signed();
+
+    writer.line(PRELUDE.replace("{tape_size}", &tape_size.to_string()));
+    writer.open("int main(void) {");
+
+    for &op in ops {
+        match op {
+            Op::Add { offset, amount } => {
+                let sign = if amount < 0 { '-' } else { '+' };
+                let amount = amount.unsigned_abs();
+                writer.line(format!("CELL({}) {sign}= {amount};", offset % len));
+            }
+            Op::Move(amount) => writer.line(format!("MOVE({});", amount % len)),
+            Op::SetZero { offset } => writer.line(format!("CELL({}) = 0;", offset % len)),
+            Op::ClearRange { offset, len: count } => {
+                for offset in (offset..).take(count) {
+                    writer.line(format!("CELL({}) = 0;", offset % len));
+                }
+            }
+            Op::Set { offset, value } => {
+                let value = interpreter::wrap_to_cell(value);
+                writer.line(format!("CELL({}) = {value};", offset % len));
+            }
+            Op::MulAdd { offset, factor } => {
+                let sign = if factor < 0 { '-' } else { '+' };
+                let product = match factor.unsigned_abs() {
+                    1 => "CELL(0)".to_string(),
+                    factor => format!("CELL(0) * {factor}"),
+                };
+                writer.line(format!("CELL({}) {sign}= {product};", offset % len));
+            }
+            Op::Scan(stride) => writer.line(format!("while (CELL(0)) MOVE({});", stride % len)),
+
+            Op::JumpIfZero(_) => writer.open("while (CELL(0)) {"),
+            Op::JumpIfNonZero(_) => writer.close("}"),
+
+            Op::Output { offset } => writer.line(format!("putchar(CELL({}));", offset % len)),
+            Op::Input { offset } => writer.line(format!("CELL({}) = input();", offset % len)),
+            Op::Memoize | Op::SwitchTape(_) => {}
+        }
+    }
+
+    writer.line("return 0;");
+    writer.close("}");
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ir, optimizer};
+
+    fn emit_str(program: &str) -> String {
+        emit(&optimizer::optimize(ir::compile(program).unwrap()).ops, 100)
+    }
+
+    fn body(code: &str) -> Vec<&str> {
+        let start = code.find("int main(void) {\n").unwrap();
+        code[start..].lines().skip(1).map(str::trim_end).collect()
+    }
+
+    #[test]
+    fn emit_ops() {
+        let code = emit_str("++>--<<.,[-]>[->+++>-<<]");
+        assert!(code.contains("#define TAPE_SIZE 100\n"));
+        assert_eq!(
+            body(&code),
+            [
+                "    CELL(0) += 2;",
+                "    CELL(1) -= 2;",
+                "    putchar(CELL(-1));",
+                "    CELL(-1) = input();",
+                "    CELL(-1) = 0;",
+                "    CELL(1) += CELL(0) * 3;",
+                "    CELL(2) -= CELL(0);",
+                "    CELL(0) = 0;",
+                "    return 0;",
+                "}",
+            ]
+        );
+    }
+
+    #[test]
+    fn emit_loops() {
+        let code = emit_str("+[>[<]-.]");
+        assert_eq!(
+            body(&code),
+            [
+                "    CELL(0) += 1;",
+                "    while (CELL(0)) {",
+                "        MOVE(1);",
+                "        while (CELL(0)) {",
+                "            while (CELL(0)) MOVE(-1);",
+                "        }",
+                "        CELL(0) -= 1;",
+                "        putchar(CELL(0));",
+                "    }",
+                "    return 0;",
+                "}",
+            ]
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use super::Writer;
+use crate::{interpreter, ir::Op};
+
+/// Everything before the program itself.
+const PRELUDE: &str = r#"// Generated by beef.
+"use strict";
+
+const fs = require("fs");
+
+const TAPE_SIZE = {tape_size};
+const tape = new Uint8Array(TAPE_SIZE);
+let p = 0;
+
+/** Return the index of the cell `offset` cells away from the tape pointer. */
+function at(offset) {
+  return (p + TAPE_SIZE + offset) % TAPE_SIZE;
+}
+
+const outputBuffer = Buffer.alloc(4096);
+let outputLength = 0;
+
+function flush() {
+  fs.writeSync(1, outputBuffer, 0, outputLength);
+  outputLength = 0;
+}
+
+function output(value) {
+  outputBuffer[outputLength++] = value;
+  if (outputLength === outputBuffer.length) {
+    flush();
+  }
+}
+
+const inputBuffer = Buffer.alloc(1);
+
+function input() {
+  flush();
+  for (;;) {
+    try {
+      if (fs.readSync(0, inputBuffer, 0, 1, null) === 1) {
+        return inputBuffer[0];
+      }
+      break;
+    } catch (error) {
+      // stdin may be non-blocking, in which case reading has to be retried until a byte arrives.
+      if (error.code !== "EAGAIN") {
+        break;
+      }
+    }
+  }
+  flush();
+  process.stderr.write("Failed to read character from stdin\n");
+  process.exit(1);
+}
+"#;
+
+/// Translate a compiled program into a JavaScript program for Node.js that behaves the same way.
+///
+/// The generated code only depends on Node's `fs` module for I/O, and follows the semantics of
+/// the interpreter: the tape holds `tape_size` wrapping 8-bit cells, and the tape pointer wraps
+/// around its ends.
+pub fn emit(ops: &[Op], tape_size: usize) -> String {
+    let mut writer = Writer::new("  ");
+    let len = tape_size.cast_signed();
+
+    // Return the expression for the cell `offset` cells away from the tape pointer.
+    let cell = |offset: isize| match offset % len {
+        0 => "tape[p]".to_string(),
+        offset => format!("tape[at({offset})]"),
+    };
+
+    writer.line(PRELUDE.replace("{tape_size}", &tape_size.to_string()));
+
+    for &op in ops {
+        match op {
+            Op::Add { offset, amount } => {
+                let sign = if amount < 0 { '-' } else { '+' };
+                let amount = amount.unsigned_abs();
+                writer.line(format!("{} {sign}= {amount};", cell(offset)));
+            }
+            Op::Move(amount) => writer.line(format!("p = at({});", amount % len)),
+            Op::SetZero { offset } => writer.line(format!("{} = 0;", cell(offset))),
+            Op::ClearRange { offset, len: count } => {
+                for offset in (offset..).take(count) {
+                    writer.line(format!("{} = 0;", cell(offset)));
+                }
+            }
+            Op::Set { offset, value } => {
+                let value = interpreter::wrap_to_cell(value);
+                writer.line(format!("{} = {value};", cell(offset)));
+            }
+            Op::MulAdd { offset, factor } => {
+                let sign = if factor < 0 { '-' } else { '+' };
+                let product = match factor.unsigned_abs() {
+                    1 => "tape[p]".to_string(),
+                    factor => format!("tape[p] * {factor}"),
+                };
+                writer.line(format!("{} {sign}= {product};", cell(offset)));
+            }
+            Op::Scan(stride) => writer.line(format!("while (tape[p]) p = at({});", stride % len)),
+
+            Op::JumpIfZero(_) => writer.open("while (tape[p]) {"),
+            Op::JumpIfNonZero(_) => writer.close("}"),
+
+            Op::Output { offset } => writer.line(format!("output({});", cell(offset))),
+            Op::Input { offset } => writer.line(format!("{} = input();", cell(offset))),
+            Op::Memoize | Op::SwitchTape(_) => {}
+        }
+    }
+
+    writer.line("flush();");
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ir, optimizer};
+
+    fn emit_str(program: &str) -> String {
+        emit(&optimizer::optimize(ir::compile(program).unwrap()).ops, 100)
+    }
+
+    fn body(code: &str) -> Vec<&str> {
+        let start = code.find("  process.exit(1);\n}\n\n").unwrap();
+        code[start..].lines().skip(3).map(str::trim_end).collect()
+    }
+
+    #[test]
+    fn emit_ops() {
+        let code = emit_str("++>--<<.,[-]>[->+++>-<<]");
+        assert!(code.contains("const TAPE_SIZE = 100;\n"));
+        assert_eq!(
+            body(&code),
+            [
+                "tape[p] += 2;",
+                "tape[at(1)] -= 2;",
+                "output(tape[at(-1)]);",
+                "tape[at(-1)] = input();",
+                "tape[at(-1)] = 0;",
+                "tape[at(1)] += tape[p] * 3;",
+                "tape[at(2)] -= tape[p];",
+                "tape[p] = 0;",
+                "flush();",
+            ]
+        );
+    }
+
+    #[test]
+    fn emit_loops() {
+        let code = emit_str("+[>[<]-.]");
+        assert_eq!(
+            body(&code),
+            [
+                "tape[p] += 1;",
+                "while (tape[p]) {",
+                "  p = at(1);",
+                "  while (tape[p]) {",
+                "    while (tape[p]) p = at(-1);",
+                "  }",
+                "  tape[p] -= 1;",
+                "  output(tape[p]);",
+                "}",
+                "flush();",
+            ]
+        );
+    }
+}
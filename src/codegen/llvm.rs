@@ -0,0 +1,319 @@
+use super::Writer;
+use crate::{interpreter, ir::Op};
+
+/// Everything before the definition of `main`.
+const PRELUDE: &str = r#"; Generated by beef.
+
+@tape = internal global [{tape_size} x i8] zeroinitializer
+@input.error = private constant [36 x i8] c"Failed to read character from stdin\0A"
+
+declare i32 @putchar(i32)
+declare i32 @getchar()
+declare i32 @fflush(ptr)
+declare i64 @write(i32, ptr, i64)
+declare void @exit(i32) noreturn
+
+define internal i8 @input() {
+  %flushed = call i32 @fflush(ptr null)
+  %c = call i32 @getchar()
+  %eof = icmp slt i32 %c, 0
+  br i1 %eof, label %error, label %ok
+
+error:
+  %written = call i64 @write(i32 2, ptr @input.error, i64 36)
+  call void @exit(i32 1)
+  unreachable
+
+ok:
+  %value = trunc i32 %c to i8
+  ret i8 %value
+}
+"#;
+
+/// Translate a compiled program into textual LLVM IR that behaves the same way.
+///
+/// The module defines `main`, and calls into the C standard library for I/O. It follows the
+/// semantics of the interpreter: the tape holds `tape_size` wrapping 8-bit cells, and the tape
+/// pointer wraps around its ends. The tape pointer lives in an `alloca`, which `opt` promotes to
+/// a register.
+pub fn emit(ops: &[Op], tape_size: usize) -> String {
+    let mut codegen = Codegen {
+        writer: Writer::new("  "),
+        tape_size,
+        temps: 0,
+        labels: 0,
+        loops: Vec::new(),
+    };
+
+    codegen
+        .writer
+        .line(PRELUDE.replace("{tape_size}", &tape_size.to_string()));
+    codegen.writer.open("define i32 @main() {");
+    codegen.writer.line("%p = alloca i64");
+    codegen.writer.line("store i64 0, ptr %p");
+
+    for &op in ops {
+        codegen.emit_op(op);
+    }
+
+    codegen.writer.line("ret i32 0");
+    codegen.writer.close("}");
+    codegen.writer.finish()
+}
+
+struct Codegen {
+    writer: Writer,
+    tape_size: usize,
+
+    /// The number of temporaries defined so far.
+    temps: usize,
+    /// The number of loops started so far, used to name their labels.
+    labels: usize,
+    /// The label numbers of the loops that are still open.
+    loops: Vec<usize>,
+}
+
+impl Codegen {
+    fn emit_op(&mut self, op: Op) {
+        match op {
+            Op::Add { offset, amount } => {
+                let cell = self.cell(offset);
+                let value = self.load(&cell);
+                let sum = self.temp();
+                let amount = interpreter::wrap_to_cell(amount).cast_signed();
+                self.writer
+                    .line(format!("{sum} = add i8 {value}, {amount}"));
+                self.store(&sum, &cell);
+            }
+            Op::Move(amount) => {
+                let index = self.index(amount);
+                self.writer.line(format!("store i64 {index}, ptr %p"));
+            }
+            Op::SetZero { offset } => {
+                let cell = self.cell(offset);
+                self.store("0", &cell);
+            }
+            Op::ClearRange { offset, len } => {
+                for offset in (offset..).take(len) {
+                    let cell = self.cell(offset);
+                    self.store("0", &cell);
+                }
+            }
+            Op::Set { offset, value } => {
+                let cell = self.cell(offset);
+                let value = interpreter::wrap_to_cell(value).cast_signed();
+                self.store(&value.to_string(), &cell);
+            }
+            Op::MulAdd { offset, factor } => {
+                let current = self.cell(0);
+                let multiplier = self.load(&current);
+                let product = self.temp();
+                let factor = interpreter::wrap_to_cell(factor).cast_signed();
+                self.writer
+                    .line(format!("{product} = mul i8 {multiplier}, {factor}"));
+
+                let cell = self.cell(offset);
+                let value = self.load(&cell);
+                let sum = self.temp();
+                self.writer
+                    .line(format!("{sum} = add i8 {value}, {product}"));
+                self.store(&sum, &cell);
+            }
+            Op::Scan(stride) => {
+                // Scans always sit inside a loop that checks the current cell, so they only need
+                // to check it again after moving.
+                let label = self.label();
+                self.writer.line(format!("br label %scan{label}"));
+                self.writer.label(format!("scan{label}:"));
+                self.emit_op(Op::Move(stride));
+                let condition = self.current_is_zero();
+                self.writer.line(format!(
+                    "br i1 {condition}, label %scan{label}.end, label %scan{label}"
+                ));
+                self.writer.label(format!("scan{label}.end:"));
+            }
+
+            Op::JumpIfZero(_) => {
+                let label = self.label();
+                self.loops.push(label);
+                let condition = self.current_is_zero();
+                self.writer.line(format!(
+                    "br i1 {condition}, label %loop{label}.end, label %loop{label}"
+                ));
+                self.writer.label(format!("loop{label}:"));
+            }
+            Op::JumpIfNonZero(_) => {
+                let label = self.loops.pop().expect("jumps should be balanced");
+                let condition = self.current_is_zero();
+                self.writer.line(format!(
+                    "br i1 {condition}, label %loop{label}.end, label %loop{label}"
+                ));
+                self.writer.label(format!("loop{label}.end:"));
+            }
+
+            Op::Output { offset } => {
+                let cell = self.cell(offset);
+                let value = self.load(&cell);
+                let char = self.temp();
+                self.writer.line(format!("{char} = zext i8 {value} to i32"));
+                let result = self.temp();
+                self.writer
+                    .line(format!("{result} = call i32 @putchar(i32 {char})"));
+            }
+            Op::Input { offset } => {
+                let value = self.temp();
+                self.writer.line(format!("{value} = call i8 @input()"));
+                let cell = self.cell(offset);
+                self.store(&value, &cell);
+            }
+            Op::Memoize | Op::SwitchTape(_) => {}
+        }
+    }
+
+    /// Return a new temporary.
+    fn temp(&mut self) -> String {
+        self.temps += 1;
+        format!("%t{}", self.temps)
+    }
+
+    /// Return a new label number.
+    fn label(&mut self) -> usize {
+        self.labels += 1;
+        self.labels
+    }
+
+    /// Emit a load from `cell`, and return the loaded value.
+    fn load(&mut self, cell: &str) -> String {
+        let value = self.temp();
+        self.writer.line(format!("{value} = load i8, ptr {cell}"));
+        value
+    }
+
+    /// Emit a store of `value` to `cell`.
+    fn store(&mut self, value: &str, cell: &str) {
+        self.writer.line(format!("store i8 {value}, ptr {cell}"));
+    }
+
+    /// Emit a check of whether the current cell is zero, and return the result.
+    fn current_is_zero(&mut self) -> String {
+        let cell = self.cell(0);
+        let value = self.load(&cell);
+        let condition = self.temp();
+        self.writer
+            .line(format!("{condition} = icmp eq i8 {value}, 0"));
+        condition
+    }
+
+    /// Emit the address of the cell `offset` cells away from the tape pointer, and return it.
+    fn cell(&mut self, offset: isize) -> String {
+        let index = self.index(offset);
+        let cell = self.temp();
+        self.writer.line(format!(
+            "{cell} = getelementptr inbounds [{} x i8], ptr @tape, i64 0, i64 {index}",
+            self.tape_size
+        ));
+        cell
+    }
+
+    /// Emit the index `offset` cells away from the tape pointer, wrapping around the ends of the
+    /// tape, and return it.
+    fn index(&mut self, offset: isize) -> String {
+        let len = self.tape_size;
+        let pointer = self.temp();
+        self.writer.line(format!("{pointer} = load i64, ptr %p"));
+
+        let offset = offset.rem_euclid(len.cast_signed());
+        if offset == 0 {
+            return pointer;
+        }
+
+        // Both the tape pointer and the offset are less than the length of the tape, so their sum
+        // needs to be wrapped at most once.
+        let sum = self.temp();
+        let wraps = self.temp();
+        let wrapped = self.temp();
+        let index = self.temp();
+        self.writer
+            .line(format!("{sum} = add i64 {pointer}, {offset}"));
+        self.writer
+            .line(format!("{wraps} = icmp uge i64 {sum}, {len}"));
+        self.writer
+            .line(format!("{wrapped} = sub i64 {sum}, {len}"));
+        self.writer.line(format!(
+            "{index} = select i1 {wraps}, i64 {wrapped}, i64 {sum}"
+        ));
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ir, optimizer};
+
+    fn emit_str(program: &str) -> String {
+        emit(&optimizer::optimize(ir::compile(program).unwrap()).ops, 100)
+    }
+
+    fn body(code: &str) -> Vec<&str> {
+        let start = code.find("define i32 @main() {\n").unwrap();
+        code[start..].lines().skip(1).map(str::trim_end).collect()
+    }
+
+    #[test]
+    fn emit_ops() {
+        let code = emit_str("<-.");
+        assert!(code.contains("@tape = internal global [100 x i8] zeroinitializer\n"));
+        assert_eq!(
+            body(&code),
+            [
+                "  %p = alloca i64",
+                "  store i64 0, ptr %p",
+                "  %t1 = load i64, ptr %p",
+                "  %t2 = add i64 %t1, 99",
+                "  %t3 = icmp uge i64 %t2, 100",
+                "  %t4 = sub i64 %t2, 100",
+                "  %t5 = select i1 %t3, i64 %t4, i64 %t2",
+                "  %t6 = getelementptr inbounds [100 x i8], ptr @tape, i64 0, i64 %t5",
+                "  %t7 = load i8, ptr %t6",
+                "  %t8 = add i8 %t7, -1",
+                "  store i8 %t8, ptr %t6",
+                "  %t9 = load i64, ptr %p",
+                "  %t10 = add i64 %t9, 99",
+                "  %t11 = icmp uge i64 %t10, 100",
+                "  %t12 = sub i64 %t10, 100",
+                "  %t13 = select i1 %t11, i64 %t12, i64 %t10",
+                "  %t14 = getelementptr inbounds [100 x i8], ptr @tape, i64 0, i64 %t13",
+                "  %t15 = load i8, ptr %t14",
+                "  %t16 = zext i8 %t15 to i32",
+                "  %t17 = call i32 @putchar(i32 %t16)",
+                "  %t18 = load i64, ptr %p",
+                "  %t19 = add i64 %t18, 99",
+                "  %t20 = icmp uge i64 %t19, 100",
+                "  %t21 = sub i64 %t19, 100",
+                "  %t22 = select i1 %t20, i64 %t21, i64 %t19",
+                "  store i64 %t22, ptr %p",
+                "  ret i32 0",
+                "}",
+            ]
+        );
+    }
+
+    #[test]
+    fn emit_loops() {
+        let code = emit_str(",[>]");
+        let lines = body(&code);
+        for line in [
+            "  br i1 %t7, label %loop1.end, label %loop1",
+            "loop1:",
+            "  br label %scan2",
+            "scan2:",
+            "  br i1 %t16, label %scan2.end, label %scan2",
+            "scan2.end:",
+            "  br i1 %t20, label %loop1.end, label %loop1",
+            "loop1.end:",
+        ] {
+            assert!(lines.contains(&line), "{line}");
+        }
+    }
+}
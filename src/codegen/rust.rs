@@ -0,0 +1,175 @@
+use super::Writer;
+use crate::{interpreter, ir::Op};
+
+/// Everything before the body of `main`.
+const PRELUDE: &str = r#"// Generated by beef.
+#![allow(dead_code)]
+
+use std::io::{self, Read, Write};
+use std::process;
+
+const TAPE_SIZE: usize = {tape_size};
+
+/// A tape of wrapping 8-bit cells, along with the tape pointer.
+struct Tape {
+    cells: [u8; TAPE_SIZE],
+    pointer: usize,
+}
+
+impl Tape {
+    /// Return the index of the cell `offset` cells away from the tape pointer.
+    fn index(&self, offset: isize) -> usize {
+        (self.pointer as isize + offset).rem_euclid(TAPE_SIZE as isize) as usize
+    }
+
+    fn get(&self, offset: isize) -> u8 {
+        self.cells[self.index(offset)]
+    }
+
+    fn set(&mut self, offset: isize, value: u8) {
+        self.cells[self.index(offset)] = value;
+    }
+
+    fn add(&mut self, offset: isize, amount: i32) {
+        self.set(offset, self.get(offset).wrapping_add(amount as u8));
+    }
+
+    fn mul_add(&mut self, offset: isize, factor: i32) {
+        let product = self.get(0).wrapping_mul(factor as u8);
+        self.set(offset, self.get(offset).wrapping_add(product));
+    }
+
+    fn shift(&mut self, amount: isize) {
+        self.pointer = self.index(amount);
+    }
+
+    fn scan(&mut self, stride: isize) {
+        while self.get(0) != 0 {
+            self.shift(stride);
+        }
+    }
+}
+
+fn output(value: u8) {
+    if io::stdout().write_all(&[value]).is_err() {
+        eprintln!("Failed to write to stdout");
+        process::exit(1);
+    }
+}
+
+fn input() -> u8 {
+    let _ = io::stdout().flush();
+    let mut value = [0];
+    if io::stdin().read_exact(&mut value).is_err() {
+        eprintln!("Failed to read character from stdin");
+        process::exit(1);
+    }
+    value[0]
+}
+"#;
+
+/// Translate a compiled program into a Rust program that behaves the same way.
+///
+/// The generated code only depends on the standard library, and follows the semantics of the
+/// interpreter: the tape holds `tape_size` wrapping 8-bit cells, and the tape pointer wraps
+/// around its ends.
+pub fn emit(ops: &[Op], tape_size: usize) -> String {
+    let mut writer = Writer::new("    ");
+    let len = tape_size.cast_signed();
+
+    writer.line(PRELUDE.replace("{tape_size}", &tape_size.to_string()));
+    writer.open("fn main() {");
+    writer.line("let mut tape = Tape {");
+    writer.line("    cells: [0; TAPE_SIZE],");
+    writer.line("    pointer: 0,");
+    writer.line("};");
+    writer.line("");
+
+    for &op in ops {
+        match op {
+            Op::Add { offset, amount } => {
+                writer.line(format!("tape.add({}, {amount});", offset % len));
+            }
+            Op::Move(amount) => writer.line(format!("tape.shift({});", amount % len)),
+            Op::SetZero { offset } => writer.line(format!("tape.set({}, 0);", offset % len)),
+            Op::ClearRange { offset, len: count } => {
+                for offset in (offset..).take(count) {
+                    writer.line(format!("tape.set({}, 0);", offset % len));
+                }
+            }
+            Op::Set { offset, value } => {
+                let value = interpreter::wrap_to_cell(value);
+                writer.line(format!("tape.set({}, {value});", offset % len));
+            }
+            Op::MulAdd { offset, factor } => {
+                writer.line(format!("tape.mul_add({}, {factor});", offset % len));
+            }
+            Op::Scan(stride) => writer.line(format!("tape.scan({});", stride % len)),
+
+            Op::JumpIfZero(_) => writer.open("while tape.get(0) != 0 {"),
+            Op::JumpIfNonZero(_) => writer.close("}"),
+
+            Op::Output { offset } => writer.line(format!("output(tape.get({}));", offset % len)),
+            Op::Input { offset } => writer.line(format!("tape.set({}, input());", offset % len)),
+            Op::Memoize | Op::SwitchTape(_) => {}
+        }
+    }
+
+    writer.close("}");
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ir, optimizer};
+
+    fn emit_str(program: &str) -> String {
+        emit(&optimizer::optimize(ir::compile(program).unwrap()).ops, 100)
+    }
+
+    fn body(code: &str) -> Vec<&str> {
+        let start = code.find("    };\n\n").unwrap();
+        code[start..].lines().skip(2).map(str::trim_end).collect()
+    }
+
+    #[test]
+    fn emit_ops() {
+        let code = emit_str("++>--<<.,[-]>[->+++>-<<]");
+        assert!(code.contains("const TAPE_SIZE: usize = 100;\n"));
+        assert_eq!(
+            body(&code),
+            [
+                "    tape.add(0, 2);",
+                "    tape.add(1, -2);",
+                "    output(tape.get(-1));",
+                "    tape.set(-1, input());",
+                "    tape.set(-1, 0);",
+                "    tape.mul_add(1, 3);",
+                "    tape.mul_add(2, -1);",
+                "    tape.set(0, 0);",
+                "}",
+            ]
+        );
+    }
+
+    #[test]
+    fn emit_loops() {
+        let code = emit_str("+[>[<]-.]");
+        assert_eq!(
+            body(&code),
+            [
+                "    tape.add(0, 1);",
+                "    while tape.get(0) != 0 {",
+                "        tape.shift(1);",
+                "        while tape.get(0) != 0 {",
+                "            tape.scan(-1);",
+                "        }",
+                "        tape.add(0, -1);",
+                "        output(tape.get(0));",
+                "    }",
+                "}",
+            ]
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use anyhow::{Context, Result};
+use wasm_encoder::{
+    BlockType, CodeSection, EntityType, ExportKind, ExportSection, Function, FunctionSection,
+    ImportSection, InstructionSink, MemArg, MemorySection, MemoryType, Module, TypeSection,
+    ValType,
+};
+
+use crate::ir::Op;
+
+/// The name of the module the host functions are imported from.
+pub const IMPORT_MODULE: &str = "beef";
+
+/// The size of a WebAssembly page, in bytes.
+const PAGE_SIZE: usize = 65_536;
+
+// The indices of the functions, imports first.
+const INPUT: u32 = 0;
+const OUTPUT: u32 = 1;
+const RUN: u32 = 2;
+
+// The indices of the locals of `run`.
+const POINTER: u32 = 0;
+const INDEX: u32 = 1;
+const VALUE: u32 = 2;
+
+/// Accesses single bytes, which have no alignment requirements.
+const BYTE: MemArg = MemArg {
+    offset: 0,
+    align: 0,
+    memory_index: 0,
+};
+
+/// Translate a compiled program into a WebAssembly module that behaves the same way.
+///
+/// The module imports two functions from [`IMPORT_MODULE`]:
+///
+/// - `input: [] -> [i32]` reads a byte, or returns a negative number to stop the program with a
+///   trap
+/// - `output: [i32] -> []` writes the byte in the lowest 8 bits of its argument
+///
+/// It exports the memory holding the tape as `memory`, and the program itself as `run`. The tape
+/// holds `tape_size` wrapping 8-bit cells at the start of the memory, and the tape pointer wraps
+/// around its ends.
+///
+/// An error is returned if the tape does not fit in a 32-bit memory.
+pub fn emit(ops: &[Op], tape_size: usize) -> Result<Vec<u8>> {
+    let len = i32::try_from(tape_size).context("The tape is too large for WebAssembly")?;
+
+    let mut types = TypeSection::new();
+    types.ty().function([], [ValType::I32]);
+    types.ty().function([ValType::I32], []);
+    types.ty().function([], []);
+
+    let mut imports = ImportSection::new();
+    imports.import(IMPORT_MODULE, "input", EntityType::Function(INPUT));
+    imports.import(IMPORT_MODULE, "output", EntityType::Function(OUTPUT));
+
+    let mut functions = FunctionSection::new();
+    functions.function(RUN);
+
+    let mut memories = MemorySection::new();
+    memories.memory(MemoryType {
+        minimum: tape_size.div_ceil(PAGE_SIZE).max(1) as u64,
+        maximum: None,
+        memory64: false,
+        shared: false,
+        page_size_log2: None,
+    });
+
+    let mut exports = ExportSection::new();
+    exports.export("memory", ExportKind::Memory, 0);
+    exports.export("run", ExportKind::Func, RUN);
+
+    let mut run = Function::new([(3, ValType::I32)]);
+    let mut sink = run.instructions();
+    for &op in ops {
+        emit_op(&mut sink, op, len);
+    }
+    sink.end();
+
+    let mut code = CodeSection::new();
+    code.function(&run);
+
+    let mut module = Module::new();
+    module
+        .section(&types)
+        .section(&imports)
+        .section(&functions)
+        .section(&memories)
+        .section(&exports)
+        .section(&code);
+    Ok(module.finish())
+}
+
+/// Emit the instructions for a single op.
+fn emit_op(sink: &mut InstructionSink, op: Op, len: i32) {
+    match op {
+        Op::Add { offset, amount } => {
+            emit_index(sink, offset, len);
+            sink.local_tee(INDEX)
+                .local_get(INDEX)
+                .i32_load8_u(BYTE)
+                .i32_const(amount)
+                .i32_add()
+                .i32_store8(BYTE);
+        }
+        Op::Move(amount) => {
+            emit_index(sink, amount, len);
+            sink.local_set(POINTER);
+        }
+        Op::SetZero { offset } => {
+            emit_index(sink, offset, len);
+            sink.i32_const(0).i32_store8(BYTE);
+        }
+        Op::ClearRange { offset, len: count } => {
+            for offset in (offset..).take(count) {
+                emit_index(sink, offset, len);
+                sink.i32_const(0).i32_store8(BYTE);
+            }
+        }
+        Op::Set { offset, value } => {
+            emit_index(sink, offset, len);
+            sink.i32_const(value).i32_store8(BYTE);
+        }
+        Op::MulAdd { offset, factor } => {
+            emit_index(sink, offset, len);
+            sink.local_tee(INDEX)
+                .local_get(INDEX)
+                .i32_load8_u(BYTE)
+                .local_get(POINTER)
+                .i32_load8_u(BYTE)
+                .i32_const(factor)
+                .i32_mul()
+                .i32_add()
+                .i32_store8(BYTE);
+        }
+        Op::Scan(stride) => {
+            // Scans always sit inside a loop that checks the current cell, so they only need to
+            // check it again after moving.
+            sink.loop_(BlockType::Empty);
+            emit_index(sink, stride, len);
+            sink.local_tee(POINTER).i32_load8_u(BYTE).br_if(0).end();
+        }
+
+        Op::JumpIfZero(_) => {
+            sink.block(BlockType::Empty)
+                .local_get(POINTER)
+                .i32_load8_u(BYTE)
+                .i32_eqz()
+                .br_if(0)
+                .loop_(BlockType::Empty);
+        }
+        Op::JumpIfNonZero(_) => {
+            sink.local_get(POINTER)
+                .i32_load8_u(BYTE)
+                .br_if(0)
+                .end()
+                .end();
+        }
+
+        Op::Output { offset } => {
+            emit_index(sink, offset, len);
+            sink.i32_load8_u(BYTE).call(OUTPUT);
+        }
+        Op::Input { offset } => {
+            sink.call(INPUT)
+                .local_tee(VALUE)
+                .i32_const(0)
+                .i32_lt_s()
+                .if_(BlockType::Empty)
+                .unreachable()
+                .end();
+            emit_index(sink, offset, len);
+            sink.local_get(VALUE).i32_store8(BYTE);
+        }
+        Op::Memoize | Op::SwitchTape(_) => {}
+    }
+}
+
+/// Emit instructions that push the index `offset` cells away from the tape pointer, wrapping
+/// around the ends of the tape.
+fn emit_index(sink: &mut InstructionSink, offset: isize, len: i32) {
+    #[allow(clippy::cast_possible_truncation)]
+    let offset = offset.rem_euclid(len as isize) as i32;
+    if offset == 0 {
+        sink.local_get(POINTER);
+        return;
+    }
+
+    // Both the tape pointer and the offset are less than the length of the tape, so their sum
+    // needs to be wrapped at most once.
+    sink.local_get(POINTER)
+        .i32_const(offset)
+        .i32_add()
+        .local_tee(INDEX)
+        .i32_const(len)
+        .i32_sub()
+        .local_get(INDEX)
+        .local_get(INDEX)
+        .i32_const(len)
+        .i32_ge_u()
+        .select();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interpreter, ir, optimizer};
+
+    fn emit_str(program: &str) -> Vec<u8> {
+        emit(
+            &optimizer::optimize(ir::compile(program).unwrap()).ops,
+            interpreter::TAPE_SIZE,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn emit_valid_modules() {
+        for program in ["", "++>--<<.,[-]>[->+++>-<<]", "+[>[<]-.]", "<<+[>>]"] {
+            wasmparser::validate(&emit_str(program)).unwrap();
+        }
+    }
+
+    #[test]
+    fn tape_too_large() {
+        assert!(emit(&[], usize::MAX).is_err());
+    }
+}
@@ -0,0 +1,419 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{builder::ValueParser, Arg, ArgAction, Command};
+
+/// The defaults of the options of beef, as set in its configuration files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// The setting of every option, in the order they were read, with those of later files in
+    /// place of the same ones of earlier files.
+    settings: Vec<Setting>,
+}
+
+/// The default of an option, set on a line of a configuration file like `tape-size = 65536`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Setting {
+    /// The long name of the option, without its `--`.
+    option: String,
+
+    /// The value, as it would be given on the command line.
+    value: String,
+
+    /// Where the setting is, like `beef.toml:3`, to point errors at.
+    location: String,
+}
+
+impl Config {
+    /// Read the configuration of the user, in `~/.config/beef/config.toml`, then the one of the
+    /// project, in `beef.toml` in the current directory or the closest directory above it that has
+    /// one. Settings of the project take precedence over those of the user, and files that don't
+    /// exist set nothing.
+    pub fn load() -> Result<Config> {
+        let user = env::var_os("XDG_CONFIG_HOME")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                let home = env::var_os("HOME").filter(|home| !home.is_empty())?;
+                Some(Path::new(&home).join(".config"))
+            })
+            .map(|config| config.join("beef").join("config.toml"));
+        let project = env::current_dir().ok().and_then(|directory| {
+            directory
+                .ancestors()
+                .map(|directory| directory.join("beef.toml"))
+                .find(|path| path.is_file())
+        });
+
+        let mut config = Config::default();
+        for path in user.iter().chain(&project) {
+            let text = match fs::read_to_string(path) {
+                Ok(text) => text,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => {
+                    return Err(error).context(format!("Failed to read {}", path.display()))
+                }
+            };
+            config.extend(Config::parse(&text, &path.display().to_string())?);
+        }
+        Ok(config)
+    }
+
+    /// Read the configuration file `text`, which is at `path`.
+    ///
+    /// Only the little TOML settings need is understood: one `key = value` per line, where the
+    /// value is a string in quotes, a number or a boolean, and comments after `#`.
+    pub fn parse(text: &str, path: &str) -> Result<Config> {
+        let mut config = Config::default();
+        for (i, line) in text.lines().enumerate() {
+            let location = format!("{path}:{}", i + 1);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let setting = parse_setting(line, location.clone())
+                .context(format!("Invalid setting at {location}"))?;
+            if config.get(&setting.option).is_some() {
+                bail!("`{}` is set twice, again at {location}", setting.option);
+            }
+            config.settings.push(setting);
+        }
+        Ok(config)
+    }
+
+    /// Put the settings of `other` in place of those of the same options.
+    pub fn extend(&mut self, other: Config) {
+        for setting in other.settings {
+            self.settings.retain(|old| old.option != setting.option);
+            self.settings.push(setting);
+        }
+    }
+
+    fn get(&self, option: &str) -> Option<&Setting> {
+        self.settings
+            .iter()
+            .find(|setting| setting.option == option)
+    }
+
+    /// Set the defaults of the options of `command` and its subcommands to the settings, so that
+    /// the options given on the command line still take precedence, and the help shows them.
+    ///
+    /// Only options that have a default and flags can be set, where a flag set to `true` can't be
+    /// turned off on the command line. An error is returned for a setting that is neither of
+    /// those, or whose value isn't valid for its option.
+    pub fn apply(&self, command: Command) -> Result<Command> {
+        let mut used = vec![false; self.settings.len()];
+        let command = self.apply_to(command, &mut used)?;
+        if let Some((setting, _)) = self.settings.iter().zip(used).find(|(_, used)| !used) {
+            bail!(
+                "`{}` at {} isn't a flag, nor an option with a default",
+                setting.option,
+                setting.location
+            );
+        }
+        Ok(command)
+    }
+
+    /// Apply the settings to `command` and its subcommands, marking those of options it has as
+    /// `used`.
+    fn apply_to(&self, mut command: Command, used: &mut [bool]) -> Result<Command> {
+        let names: Vec<_> = command
+            .get_subcommands()
+            .map(|subcommand| subcommand.get_name().to_owned())
+            .collect();
+        for name in names {
+            let subcommand = command
+                .find_subcommand(&name)
+                .expect("the name is of a subcommand")
+                .clone();
+            let subcommand = self.apply_to(subcommand, used)?;
+            command = command.mut_subcommand(name, |_| subcommand);
+        }
+
+        for (setting, used) in self.settings.iter().zip(used) {
+            let Some(arg) = command
+                .get_arguments()
+                .find(|arg| settable(arg) && arg.get_long() == Some(&setting.option))
+            else {
+                continue;
+            };
+            let parser = if matches!(arg.get_action(), ArgAction::SetTrue) {
+                ValueParser::bool()
+            } else {
+                arg.get_value_parser().clone()
+            };
+            // clap only takes names and defaults that live as long as the program, which these do
+            // anyway.
+            let option: &'static str = setting.option.clone().leak();
+            let value: &'static str = setting.value.clone().leak();
+            // The value is checked on its own, like the option would be on the command line.
+            let check = Arg::new(arg.get_id().clone())
+                .long(option)
+                .value_names(arg.get_value_names().unwrap_or_default().to_vec())
+                .value_parser(parser);
+            Command::new("beef")
+                .arg(check)
+                .try_get_matches_from(["beef".to_owned(), format!("--{option}={value}")])
+                .map_err(|error| {
+                    // Only the error itself is shown, without the hint to look at the help.
+                    let error = error.render().to_string();
+                    let error = error.split("\n\n").next().unwrap_or_default();
+                    let error: Vec<_> = error.lines().map(str::trim).collect();
+                    let error = error.join(" ");
+                    let error = error.trim_start_matches("error: ");
+                    anyhow!("Invalid setting at {}: {error}", setting.location)
+                })?;
+            let id = arg.get_id().clone();
+            command = command.mut_arg(id, |arg| arg.default_value(value));
+            *used = true;
+        }
+        Ok(command)
+    }
+}
+
+/// Let every option of `command` and its subcommands that can be set in a configuration file be
+/// set by an environment variable too, named after the option like `BEEF_TAPE_SIZE` for
+/// `--tape-size`.
+///
+/// The variables take precedence over the defaults, including those of the configuration files,
+/// while the options given on the command line take precedence over the variables. They're left
+/// out of the help, which would get twice as long with them.
+pub fn with_env(mut command: Command) -> Command {
+    let names: Vec<_> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_owned())
+        .collect();
+    for name in names {
+        command = command.mut_subcommand(name, with_env);
+    }
+
+    let options: Vec<_> = command
+        .get_arguments()
+        .filter(|arg| settable(arg))
+        .filter_map(|arg| Some((arg.get_id().clone(), arg.get_long()?.to_owned())))
+        .collect();
+    for (id, option) in options {
+        // Like defaults, clap only takes names of variables that live as long as the program.
+        let variable: &'static str = env_variable(&option).leak();
+        command = command.mut_arg(id, |arg| arg.env(variable).hide_env(true));
+    }
+    command
+}
+
+/// Return the name of the environment variable that sets `option`.
+fn env_variable(option: &str) -> String {
+    format!("BEEF_{}", option.to_ascii_uppercase().replace('-', "_"))
+}
+
+/// Return whether `arg` can be set by a configuration file or an environment variable, as an
+/// option with a default or a flag.
+fn settable(arg: &Arg) -> bool {
+    arg.get_long().is_some()
+        && (!arg.get_default_values().is_empty() || matches!(arg.get_action(), ArgAction::SetTrue))
+}
+
+/// Read a `key = value` line at `location`, whose key is the long name of an option, with `_` or
+/// `-` between words.
+fn parse_setting(line: &str, location: String) -> Result<Setting> {
+    if line.starts_with('[') {
+        bail!("Tables aren't supported, as every setting applies to every command");
+    }
+    let (key, rest) = line
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Expected `key = value`"))?;
+    let key = key.trim();
+    if key.is_empty()
+        || !key
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+    {
+        bail!("`{key}` isn't the name of an option");
+    }
+
+    let rest = rest.trim_start();
+    let (value, rest) = match rest.chars().next() {
+        Some('"') => basic_string(&rest[1..])?,
+        Some('\'') => {
+            let end = rest[1..]
+                .find('\'')
+                .ok_or_else(|| anyhow!("The string isn't closed"))?;
+            (rest[1..=end].to_owned(), &rest[end + 2..])
+        }
+        _ => {
+            let end = rest.find('#').unwrap_or(rest.len());
+            let value = rest[..end].trim();
+            let number = value.starts_with(|ch: char| ch.is_ascii_digit() || "+-".contains(ch))
+                && value
+                    .chars()
+                    .all(|ch| ch.is_ascii_digit() || "_.eE+-".contains(ch));
+            if !number && value != "true" && value != "false" {
+                bail!("`{value}` isn't a string in quotes, a number or a boolean");
+            }
+            (value.replace('_', ""), &rest[end..])
+        }
+    };
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        bail!("Unexpected `{rest}` after the value");
+    }
+
+    Ok(Setting {
+        option: key.replace('_', "-"),
+        value,
+        location,
+    })
+}
+
+/// Read the rest of a string in double quotes, after its opening quote, replacing its escapes,
+/// and return it with what's left of the line after its closing quote.
+fn basic_string(text: &str) -> Result<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '"' => return Ok((value, &text[i + 1..])),
+            '\\' => value.push(match chars.next().map(|(_, ch)| ch) {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('\\') => '\\',
+                Some('"') => '"',
+                Some(other) => bail!("Unknown escape `\\{other}`"),
+                None => break,
+            }),
+            _ => value.push(ch),
+        }
+    }
+    bail!("The string isn't closed")
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::value_parser;
+
+    use super::*;
+
+    fn setting(option: &str, value: &str, location: &str) -> Setting {
+        Setting {
+            option: option.to_owned(),
+            value: value.to_owned(),
+            location: location.to_owned(),
+        }
+    }
+
+    #[test]
+    fn parse_settings() {
+        let text = "# Defaults for this project\n\
+                    \n\
+                    tape_size = 65_536\n\
+                    eof = \"zero\"  # like most programs expect\n\
+                    tape-switch = '{}'\n\
+                    input-str = \"a\\tb\\\"\"\n";
+        assert_eq!(
+            Config::parse(text, "beef.toml").unwrap().settings,
+            [
+                setting("tape-size", "65536", "beef.toml:3"),
+                setting("eof", "zero", "beef.toml:4"),
+                setting("tape-switch", "{}", "beef.toml:5"),
+                setting("input-str", "a\tb\"", "beef.toml:6"),
+            ]
+        );
+
+        let error = |text| format!("{:#}", Config::parse(text, "beef.toml").unwrap_err());
+        assert_eq!(
+            error("eof = zero"),
+            "Invalid setting at beef.toml:1: `zero` isn't a string in quotes, a number or a boolean"
+        );
+        assert_eq!(
+            error("[run]"),
+            "Invalid setting at beef.toml:1: Tables aren't supported, as every setting applies to \
+             every command"
+        );
+        assert_eq!(
+            error("eof = 'zero'\neof = 'unchanged'"),
+            "`eof` is set twice, again at beef.toml:2"
+        );
+    }
+
+    #[test]
+    fn replace_settings() {
+        let mut config = Config::parse("eof = 'zero'\nopt-level = 1", "config.toml").unwrap();
+        config.extend(Config::parse("opt-level = 3", "beef.toml").unwrap());
+        assert_eq!(
+            config.settings,
+            [
+                setting("eof", "zero", "config.toml:1"),
+                setting("opt-level", "3", "beef.toml:1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn set_defaults() {
+        let tape_size = Arg::new("tape_size")
+            .long("tape-size")
+            .default_value("30000")
+            .value_parser(value_parser!(usize));
+        let command = Command::new("beef")
+            .arg(tape_size.clone())
+            .subcommand(Command::new("bench").arg(tape_size))
+            .arg(Arg::new("input").long("input"))
+            .arg(Arg::new("signed").long("signed").action(ArgAction::SetTrue));
+
+        let config = Config::parse("tape-size = 100\nsigned = true", "beef.toml").unwrap();
+        let command = config.apply(command.clone()).unwrap();
+        let size = |args: &[&str]| {
+            let matches = command.clone().get_matches_from(args);
+            let matches = matches.subcommand_matches("bench").unwrap_or(&matches);
+            *matches.get_one::<usize>("tape_size").unwrap()
+        };
+        assert_eq!(size(&["beef"]), 100);
+        assert_eq!(size(&["beef", "bench"]), 100);
+        assert_eq!(size(&["beef", "--tape-size", "5"]), 5);
+        assert!(command
+            .clone()
+            .get_matches_from(["beef"])
+            .get_flag("signed"));
+
+        let error = |text| {
+            let config = Config::parse(text, "beef.toml").unwrap();
+            config.apply(command.clone()).unwrap_err().to_string()
+        };
+        assert_eq!(
+            error("tape-size = 'a lot'"),
+            "Invalid setting at beef.toml:1: invalid value 'a lot' for '--tape-size <tape_size>': \
+             invalid digit found in string"
+        );
+        assert_eq!(
+            error("input = 'in.txt'"),
+            "`input` at beef.toml:1 isn't a flag, nor an option with a default"
+        );
+        assert_eq!(
+            error("signed = 1"),
+            "Invalid setting at beef.toml:1: invalid value '1' for '--signed <signed>' \
+             [possible values: true, false]"
+        );
+    }
+
+    #[test]
+    fn read_env() {
+        assert_eq!(env_variable("tape-size"), "BEEF_TAPE_SIZE");
+
+        let command =
+            Command::new("beef").arg(Arg::new("cells").long("env-test-cells").default_value("1"));
+        let config = Config::parse("env-test-cells = 2", "beef.toml").unwrap();
+        let cells = |args: &[&str]| {
+            // The variables are read as they're attached to the options.
+            let command = config.apply(with_env(command.clone())).unwrap();
+            let matches = command.get_matches_from(args);
+            matches.get_one::<String>("cells").unwrap().clone()
+        };
+        assert_eq!(cells(&["beef"]), "2");
+        env::set_var("BEEF_ENV_TEST_CELLS", "3");
+        assert_eq!(cells(&["beef"]), "3");
+        assert_eq!(cells(&["beef", "--env-test-cells", "4"]), "4");
+    }
+}
@@ -0,0 +1,471 @@
+use std::{
+    io::{self, BufRead, Read, Write},
+    ops::Range,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{check, diagnostic, input::LineInput, interpreter::Interpreter};
+
+/// The names of the commands, and what they're shortened to.
+const COMMANDS: [&str; 13] = [
+    "step", "s", "continue", "c", "print", "p", "where", "w", "set", "quit", "q", "help", "h",
+];
+
+/// What `help` prints.
+const HELP: &str = "\
+step [N]          Run the next instruction, or the next N of them
+continue          Run the program until it finishes, or reaches a `#` in its source
+print [CELL]      Show the cell under the tape pointer, or CELL
+print START..END  Show the cells from START up to END
+where             Show the instruction the program is at, and the cell under the tape pointer
+set CELL VALUE    Set CELL to VALUE
+quit              Stop debugging
+
+Commands other than `set` can be shortened to their first letter, like `s` for `step`, and an empty
+line runs the last command again.";
+
+/// A program run one command at a time, so that a person can look at and change its tape on the
+/// way.
+pub struct Debugger<'a> {
+    /// The interpreter of the program, which must be compiled without optimizing it, for every op
+    /// to be a single instruction.
+    interpreter: Interpreter,
+
+    /// The source of the program, and what it's called, to show where it's at.
+    source: &'a str,
+    name: &'a str,
+
+    /// Whether to color where the program is at.
+    color: bool,
+
+    /// The source offset of every instruction right after a `#`, which `continue` stops at, with
+    /// the offset of the `#`.
+    breakpoints: Vec<(usize, usize)>,
+}
+
+/// What the debugger does once a command is done.
+#[derive(Debug, PartialEq, Eq)]
+enum Reply {
+    /// Print this, and wait for the next command.
+    Text(String),
+
+    /// Stop debugging.
+    Quit,
+}
+
+impl<'a> Debugger<'a> {
+    /// Return a debugger for the program of `interpreter`, whose source is `source` in the file
+    /// called `name`.
+    pub fn new(interpreter: Interpreter, source: &'a str, name: &'a str, color: bool) -> Self {
+        Debugger {
+            interpreter,
+            source,
+            name,
+            color,
+            breakpoints: breakpoints(source),
+        }
+    }
+
+    /// Read commands from `commands` and run them until `quit` or the end of `commands`, writing
+    /// a prompt before each, and what it shows to `output`.
+    ///
+    /// The program writes to `program_output`, or else to `output` in between what the commands
+    /// show.
+    ///
+    /// The program reads `input`, or else the lines of `commands` in between the commands, so that
+    /// a person types its input once it asks for it. What's left of such a line once the command
+    /// is done stays the input of the program, rather than being taken for a command.
+    ///
+    /// With `run_first`, the program runs like with `continue` before the first prompt, which
+    /// only shows if it stopped at a `#`.
+    pub fn run(
+        &mut self,
+        commands: &mut impl BufRead,
+        mut input: Option<&[u8]>,
+        output: &mut impl Write,
+        mut program_output: Option<&mut (dyn Write + '_)>,
+        run_first: bool,
+    ) -> Result<()> {
+        let mut output = LineOutput {
+            inner: output,
+            line_start: true,
+        };
+        let mut commands = LineInput::new(commands);
+        if run_first {
+            let input: &mut dyn Read = match &mut input {
+                Some(input) => input,
+                None => &mut commands,
+            };
+            let program_output: &mut dyn Write = match &mut program_output {
+                Some(program_output) => program_output,
+                None => &mut output,
+            };
+            let stopped = self.resume(input, program_output)?;
+            if self.interpreter.span().is_none() {
+                return Ok(());
+            }
+            if !output.line_start {
+                writeln!(output)?;
+            }
+            writeln!(output, "{stopped}")?;
+        } else {
+            writeln!(output, "{}", self.location())?;
+        }
+        let mut last = String::new();
+        loop {
+            // The person typing the command ends the line of the prompt.
+            write!(output.inner, "(beef) ")?;
+            output.flush()?;
+            let mut line = String::new();
+            if commands
+                .get_mut()
+                .read_line(&mut line)
+                .context("Failed to read the command")?
+                == 0
+            {
+                writeln!(output)?;
+                return Ok(());
+            }
+            if !line.trim().is_empty() {
+                last = line.trim().to_owned();
+            } else if last.is_empty() {
+                continue;
+            }
+
+            let input: &mut dyn Read = match &mut input {
+                Some(input) => input,
+                None => &mut commands,
+            };
+            let program_output: &mut dyn Write = match &mut program_output {
+                Some(program_output) => program_output,
+                None => &mut output,
+            };
+            let reply = self.execute(&last, input, program_output);
+            if !output.line_start {
+                writeln!(output)?;
+            }
+            match reply {
+                Ok(Reply::Text(text)) => writeln!(output, "{text}")?,
+                Ok(Reply::Quit) => return Ok(()),
+                Err(error) => writeln!(output, "Error: {error:#}")?,
+            }
+        }
+    }
+
+    /// Run `command`, reading the input of the program from `input` and writing its output to
+    /// `output`.
+    fn execute(
+        &mut self,
+        command: &str,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+    ) -> Result<Reply> {
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let arguments: Vec<&str> = words.collect();
+        let text = match (name, &arguments[..]) {
+            ("step" | "s", []) => self.step(1, input, output)?,
+            ("step" | "s", [steps]) => {
+                let steps = steps
+                    .parse()
+                    .map_err(|_| anyhow!("`{steps}` isn't a number of instructions"))?;
+                self.step(steps, input, output)?
+            }
+            ("continue" | "c", []) => self.resume(input, output)?,
+            ("print" | "p", []) => {
+                let pointer = self.interpreter.tape_pointer();
+                self.print(pointer..pointer + 1)?
+            }
+            ("print" | "p", [cells]) => self.print(parse_cells(cells)?)?,
+            ("where" | "w", []) => self.location(),
+            ("set", [cell, value]) => {
+                let cell = parse_cell(cell)?;
+                let value = value
+                    .parse()
+                    .map_err(|_| anyhow!("`{value}` isn't a value from 0 to 255"))?;
+                if !self.interpreter.set_cell(cell, value) {
+                    bail!(self.past_end(cell));
+                }
+                self.print(cell..cell + 1)?
+            }
+            ("quit" | "q", []) => return Ok(Reply::Quit),
+            ("help" | "h", []) => HELP.to_owned(),
+            _ if COMMANDS.contains(&name) => {
+                bail!("`{command}` doesn't take these arguments, see `help`")
+            }
+            _ => bail!("`{name}` isn't a command, see `help`"),
+        };
+        Ok(Reply::Text(text))
+    }
+
+    /// Run at most `steps` instructions, and return where the program is at then.
+    fn step(&mut self, steps: u64, input: &mut dyn Read, output: &mut dyn Write) -> Result<String> {
+        let result = self.interpreter.run_budget_with(steps, input, output);
+        output.flush().context("Failed to write the output")?;
+        result.map_err(|error| diagnostic::with_source(error, self.source, self.name))?;
+        Ok(self.location())
+    }
+
+    /// Run the program until it finishes or reaches an instruction right after a `#`, other than
+    /// the one it's at, and return where it's at then.
+    fn resume(&mut self, input: &mut dyn Read, output: &mut dyn Write) -> Result<String> {
+        if self.breakpoints.is_empty() {
+            return self.step(u64::MAX, input, output);
+        }
+        let mut result = Ok(false);
+        let mut hash = None;
+        while hash.is_none() && matches!(result, Ok(false)) {
+            result = self.interpreter.run_budget_with(1, input, output);
+            hash = self.interpreter.span().and_then(|span| {
+                let index = self
+                    .breakpoints
+                    .binary_search_by_key(&span.start, |&(offset, _)| offset)
+                    .ok()?;
+                Some(self.breakpoints[index].1)
+            });
+        }
+        output.flush().context("Failed to write the output")?;
+        result.map_err(|error| diagnostic::with_source(error, self.source, self.name))?;
+
+        let location = self.location();
+        Ok(match hash {
+            Some(hash) => {
+                let (line, column) = check::position(self.source, hash);
+                format!("Stopped at the `#` at {line}:{column}\n{location}")
+            }
+            None => location,
+        })
+    }
+
+    /// Return the values of `cells`.
+    fn print(&self, cells: Range<usize>) -> Result<String> {
+        let tape = self.interpreter.tape();
+        if cells.end > tape.len() {
+            bail!(self.past_end(cells.end - 1));
+        }
+        let values: Vec<_> = tape[cells.clone()]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        Ok(match cells.len() {
+            1 => format!("Cell {}: {}", cells.start, values[0]),
+            _ => format!("Cells {cells:?}: {}", values.join(" ")),
+        })
+    }
+
+    /// Describe where the program is at, with the line of the instruction it runs next.
+    fn location(&self) -> String {
+        let pointer = self.interpreter.tape_pointer();
+        let cell = &self.interpreter.tape()[pointer];
+        let Some(span) = self.interpreter.span() else {
+            return format!(
+                "The program finished, with the tape pointer on cell {pointer}: {cell}"
+            );
+        };
+        let (line, column) = check::position(self.source, span.start);
+        let snippet = diagnostic::render(self.source, self.name, span.start, self.color);
+        format!(
+            "At {line}:{column}, with the tape pointer on cell {pointer}: {cell}\n{}",
+            snippet.trim_end()
+        )
+    }
+
+    /// Return the error for a `cell` that isn't on the tape.
+    fn past_end(&self, cell: usize) -> String {
+        let len = self.interpreter.tape().len();
+        format!("Cell {cell} is past the end of the tape, which has {len} cells")
+    }
+}
+
+/// An output that remembers whether it's at the start of a line, so that what the debugger shows
+/// doesn't continue a line the program wrote.
+struct LineOutput<'a, W> {
+    inner: &'a mut W,
+    line_start: bool,
+}
+
+impl<W: Write> Write for LineOutput<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(&last) = buf[..written].last() {
+            self.line_start = last == b'\n';
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Return the source offset of every instruction of `source` right after a `#`, with the offset
+/// of the first `#` before it.
+fn breakpoints(source: &str) -> Vec<(usize, usize)> {
+    let mut breakpoints = Vec::new();
+    let mut hash = None;
+    for (offset, ch) in source.char_indices() {
+        if ch == '#' {
+            hash.get_or_insert(offset);
+        } else if "+-<>.,[]".contains(ch) {
+            if let Some(hash) = hash.take() {
+                breakpoints.push((offset, hash));
+            }
+        }
+    }
+    breakpoints
+}
+
+/// Parse the index of a cell.
+fn parse_cell(value: &str) -> Result<usize> {
+    value
+        .parse()
+        .map_err(|_| anyhow!("`{value}` isn't the index of a cell"))
+}
+
+/// Parse a range of cells, either `START..END` or a single cell.
+fn parse_cells(value: &str) -> Result<Range<usize>> {
+    let Some((start, end)) = value.split_once("..") else {
+        let cell = parse_cell(value)?;
+        return Ok(cell..cell + 1);
+    };
+    let cells = parse_cell(start)?..parse_cell(end)?;
+    if cells.is_empty() {
+        bail!("`{value}` has no cells in it");
+    }
+    Ok(cells)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir;
+
+    /// Run the debugger on `source` with `commands`, and return what it wrote.
+    fn debug(source: &str, commands: &str, input: Option<&[u8]>) -> String {
+        let interpreter = Interpreter::from_program(ir::compile(source).unwrap());
+        let mut output = Vec::new();
+        Debugger::new(interpreter, source, "a.b", false)
+            .run(&mut commands.as_bytes(), input, &mut output, None, false)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn step_through_programs() {
+        assert_eq!(
+            debug(
+                "++\n>+.",
+                "s\n\nstep 2\np 0..2\nset 1 72\nc\nw\nq\nstep\n",
+                None
+            ),
+            "At 1:1, with the tape pointer on cell 0: 0\n \
+             --> a.b:1:1\n  |\n1 | ++\n  | ^\n\
+             (beef) At 1:2, with the tape pointer on cell 0: 1\n \
+             --> a.b:1:2\n  |\n1 | ++\n  |  ^\n\
+             (beef) At 2:1, with the tape pointer on cell 0: 2\n \
+             --> a.b:2:1\n  |\n2 | >+.\n  | ^\n\
+             (beef) At 2:3, with the tape pointer on cell 1: 1\n \
+             --> a.b:2:3\n  |\n2 | >+.\n  |   ^\n\
+             (beef) Cells 0..2: 2 1\n\
+             (beef) Cell 1: 72\n\
+             (beef) H\n\
+             The program finished, with the tape pointer on cell 1: 72\n\
+             (beef) The program finished, with the tape pointer on cell 1: 72\n\
+             (beef) "
+        );
+    }
+
+    #[test]
+    fn read_input_between_commands() {
+        let output = debug(",.", "c\nhi\np\n", None);
+        assert!(output.ends_with("(beef) h\nThe program finished, with the tape pointer on cell 0: 104\n(beef) Cell 0: 104\n(beef) \n"));
+        let output = debug(",.", "c\n", Some(b"x"));
+        assert!(output.ends_with(
+            "(beef) x\nThe program finished, with the tape pointer on cell 0: 120\n(beef) \n"
+        ));
+    }
+
+    #[test]
+    fn stop_at_breakpoints() {
+        let output = debug("+# +#\n#+>", "c\nc\nc\n", None);
+        let replies: Vec<_> = output
+            .lines()
+            .filter(|line| !line.starts_with(|ch: char| ch == ' ' || ch.is_ascii_digit()))
+            .collect();
+        assert_eq!(
+            replies,
+            [
+                "At 1:1, with the tape pointer on cell 0: 0",
+                "(beef) Stopped at the `#` at 1:2",
+                "At 1:4, with the tape pointer on cell 0: 1",
+                "(beef) Stopped at the `#` at 1:5",
+                "At 2:2, with the tape pointer on cell 0: 2",
+                "(beef) The program finished, with the tape pointer on cell 1: 0",
+                "(beef) ",
+            ]
+        );
+
+        let run_first = |source| {
+            let interpreter = Interpreter::from_program(ir::compile(source).unwrap());
+            let mut output = Vec::new();
+            Debugger::new(interpreter, source, "a.b", false)
+                .run(&mut &b""[..], None, &mut output, None, true)
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        assert_eq!(run_first("+++."), "\x03");
+        assert_eq!(
+            run_first("+.#+"),
+            "\x01\nStopped at the `#` at 1:3\nAt 1:4, with the tape pointer on cell 0: 1\n \
+             --> a.b:1:4\n  |\n1 | +.#+\n  |    ^\n(beef) \n"
+        );
+    }
+
+    #[test]
+    fn write_program_output_elsewhere() {
+        let source = "+++.#+.";
+        let interpreter = Interpreter::from_program(ir::compile(source).unwrap());
+        let mut output = Vec::new();
+        let mut program_output = Vec::new();
+        Debugger::new(interpreter, source, "a.b", false)
+            .run(
+                &mut &b"c\n"[..],
+                None,
+                &mut output,
+                Some(&mut program_output),
+                true,
+            )
+            .unwrap();
+        assert_eq!(program_output, b"\x03\x04");
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Stopped at the `#` at 1:5\nAt 1:6, with the tape pointer on cell 0: 3\n \
+             --> a.b:1:6\n  |\n1 | +++.#+.\n  |      ^\n\
+             (beef) The program finished, with the tape pointer on cell 0: 4\n(beef) \n"
+        );
+    }
+
+    #[test]
+    fn reject_commands() {
+        let output = debug(
+            "+",
+            "jump\nstep x\nprint 5..5\nset 30000 1\nwhere now\n",
+            None,
+        );
+        let errors: Vec<_> = output
+            .lines()
+            .filter_map(|line| line.strip_prefix("(beef) Error: "))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "`jump` isn't a command, see `help`",
+                "`x` isn't a number of instructions",
+                "`5..5` has no cells in it",
+                "Cell 30000 is past the end of the tape, which has 30000 cells",
+                "`where now` doesn't take these arguments, see `help`",
+            ]
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    env,
+    error::Error,
+    fmt::{self, Display, Formatter, Write as _},
+    io::{self, IsTerminal},
+};
+
+use crate::check;
+
+/// The most characters of a line shown on either side of an error, so that the snippet of a
+/// program on a single long line still fits on the screen.
+const CONTEXT: usize = 40;
+
+/// An error at a character of the source of a program, which can show the line it's on.
+#[derive(Debug)]
+pub struct SourceError {
+    message: String,
+
+    /// The byte offset of the character in the source.
+    offset: usize,
+
+    /// The line and the character of that line the error is at, both from 1, once the source is
+    /// known.
+    position: Option<(usize, usize)>,
+
+    /// The line of the source the error is at, with a caret under the character, once the source
+    /// is known.
+    snippet: Option<String>,
+}
+
+impl SourceError {
+    /// Return the error `message` at the byte `offset` of the source.
+    pub fn new(message: impl Into<String>, offset: usize) -> SourceError {
+        SourceError {
+            message: message.into(),
+            offset,
+            position: None,
+            snippet: None,
+        }
+    }
+
+    /// Return the error `message` at the byte `offset` of `source`, at its line and column.
+    pub fn in_source(message: impl Into<String>, source: &str, offset: usize) -> SourceError {
+        SourceError {
+            position: Some(check::position(source, offset)),
+            ..SourceError::new(message, offset)
+        }
+    }
+
+    /// Return the line and the character of that line the error is at, both from 1, if the source
+    /// is known.
+    pub fn position(&self) -> Option<(usize, usize)> {
+        self.position
+    }
+}
+
+impl Display for SourceError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.position {
+            Some((line, column)) => write!(f, "{} at {line}:{column}", self.message),
+            None => write!(f, "{} at {}", self.message, self.offset),
+        }
+    }
+}
+
+impl Error for SourceError {}
+
+/// Show the line and column of `source`, the program called `name`, that `error` is at, and the
+/// line itself, if it's a [`SourceError`] that doesn't show them yet.
+///
+/// The offsets of the source must be those of the file, as they are with its `#!` line blanked
+/// out and its input after a `!` split off.
+pub fn with_source(mut error: anyhow::Error, source: &str, name: &str) -> anyhow::Error {
+    if let Some(error) = error.downcast_mut::<SourceError>() {
+        if error.snippet.is_none() && error.offset <= source.len() {
+            error
+                .position
+                .get_or_insert_with(|| check::position(source, error.offset));
+            error.snippet = Some(render(source, name, error.offset, colored(&io::stderr())));
+        }
+    }
+    error
+}
+
+/// Return the line and the character of that line that `error` is at, both from 1, if it's known.
+pub fn position(error: &anyhow::Error) -> Option<(usize, usize)> {
+    error.downcast_ref::<SourceError>()?.position()
+}
+
+/// Return the line of the source that `error` is at, with a caret under the character, if it's
+/// known.
+pub fn snippet(error: &anyhow::Error) -> Option<&str> {
+    error.downcast_ref::<SourceError>()?.snippet.as_deref()
+}
+
+/// Return whether what's written to `stream` is colored, which it is on a terminal unless
+/// `NO_COLOR` is set.
+pub fn colored(stream: &impl IsTerminal) -> bool {
+    stream.is_terminal() && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+/// Return the line of `source`, the program called `name`, that the byte `offset` is on, with a
+/// caret under the character there, like rustc does.
+pub fn render(source: &str, name: &str, offset: usize, color: bool) -> String {
+    let (line, column) = check::position(source, offset);
+    let start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let end = source[offset..]
+        .find('\n')
+        .map_or(source.len(), |i| offset + i);
+    let text: Vec<char> = source[start..end].trim_end_matches('\r').chars().collect();
+
+    let first = (column - 1).saturating_sub(CONTEXT);
+    let last = (column + CONTEXT).min(text.len());
+    let mut shown: String = text[first..last].iter().collect();
+    // Tabs stay tabs under the line, so that the caret lines up with the character either way.
+    let mut under: String = text[first..column - 1]
+        .iter()
+        .map(|&ch| if ch == '\t' { '\t' } else { ' ' })
+        .collect();
+    if first > 0 {
+        shown.insert_str(0, "...");
+        under.insert_str(0, "   ");
+    }
+    if last < text.len() {
+        shown.push_str("...");
+    }
+
+    let (blue, red, reset) = if color {
+        ("\x1b[1;34m", "\x1b[1;31m", "\x1b[0m")
+    } else {
+        ("", "", "")
+    };
+    let gutter = " ".repeat(line.to_string().len());
+    let mut snippet = String::new();
+    writeln!(snippet, "{gutter}{blue}-->{reset} {name}:{line}:{column}").unwrap();
+    writeln!(snippet, "{gutter} {blue}|{reset}").unwrap();
+    writeln!(snippet, "{blue}{line} |{reset} {shown}").unwrap();
+    writeln!(snippet, "{gutter} {blue}|{reset} {under}{red}^{reset}").unwrap();
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn show_snippets() {
+        assert_eq!(
+            render("+++\n\t[->+<]]\n", "hello.b", 11, false),
+            " --> hello.b:2:8\n  \
+             |\n\
+             2 | \t[->+<]]\n  \
+             | \t      ^\n"
+        );
+
+        let source = format!("{}]{}", "+".repeat(100), "-".repeat(100));
+        assert_eq!(
+            render(&source, "long.b", 100, false),
+            format!(
+                " --> long.b:1:101\n  |\n1 | ...{}]{}...\n  | {}^\n",
+                "+".repeat(40),
+                "-".repeat(40),
+                " ".repeat(43)
+            )
+        );
+    }
+
+    #[test]
+    fn attach_snippets() {
+        let error = anyhow::Error::new(SourceError::new("Unmatched ]", 1)).context("Failed");
+        assert_eq!(format!("{error:#}"), "Failed: Unmatched ] at 1");
+        assert_eq!((position(&error), snippet(&error)), (None, None));
+        let error = with_source(error, "+]", "a.b");
+        assert_eq!(format!("{error:#}"), "Failed: Unmatched ] at 1:2");
+        assert_eq!(position(&error), Some((1, 2)));
+        assert_eq!(snippet(&error), Some(" --> a.b:1:2\n  |\n1 | +]\n  |  ^\n"));
+    }
+}
@@ -0,0 +1,244 @@
+use std::{fmt::Write as _, io::Write as _};
+
+use crate::output::Escaped;
+
+/// The most lines of each output shown where they differ.
+const MAX_LINES: usize = 10;
+
+/// What a run of a program leaves behind: its output and its tape.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunState {
+    pub output: Vec<u8>,
+    pub tape: Vec<u8>,
+    pub tape_pointer: usize,
+}
+
+/// How the states two runs ended in differ.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateDiff {
+    /// The index of the first byte the outputs differ at, with the byte of each run there, or
+    /// `None` where its output already ended. This is `None` if the outputs are the same.
+    pub output: Option<(usize, [Option<u8>; 2])>,
+
+    /// The tape pointers of both runs, if they differ.
+    pub tape_pointers: Option<[usize; 2]>,
+
+    /// Every cell the tapes differ at, by its index, with the value of each run there. Cells past
+    /// the end of the shorter tape count as zero.
+    pub cells: Vec<(usize, [u8; 2])>,
+}
+
+impl StateDiff {
+    /// Compare the states of two runs.
+    pub fn new(runs: [&RunState; 2]) -> StateDiff {
+        let [first, second] = runs;
+
+        let output = (0..first.output.len().max(second.output.len()))
+            .map(|index| (index, runs.map(|run| run.output.get(index).copied())))
+            .find(|(_, bytes)| bytes[0] != bytes[1]);
+        let tape_pointers = [first.tape_pointer, second.tape_pointer];
+        let cells = (0..first.tape.len().max(second.tape.len()))
+            .map(|index| {
+                (
+                    index,
+                    runs.map(|run| run.tape.get(index).copied().unwrap_or(0)),
+                )
+            })
+            .filter(|(_, values)| values[0] != values[1])
+            .collect();
+
+        StateDiff {
+            output,
+            tape_pointers: (tape_pointers[0] != tape_pointers[1]).then_some(tape_pointers),
+            cells,
+        }
+    }
+
+    /// Return whether the runs ended in the same state.
+    pub fn is_empty(&self) -> bool {
+        self.output.is_none() && self.tape_pointers.is_none() && self.cells.is_empty()
+    }
+
+    /// Describe the differences for a person, calling the runs by `names`.
+    pub fn report(&self, names: [&str; 2]) -> String {
+        let mut report = String::new();
+        if self.is_empty() {
+            report.push_str("The runs ended in the same state");
+            return report;
+        }
+
+        let [first, second] = names;
+        if let Some((index, bytes)) = self.output {
+            let [a, b] =
+                bytes.map(|byte| byte.map_or("its end".to_owned(), |byte| byte.to_string()));
+            writeln!(
+                report,
+                "Output differs from byte {index}: {a} in {first}, {b} in {second}"
+            )
+            .unwrap();
+        }
+        if let Some([a, b]) = self.tape_pointers {
+            writeln!(
+                report,
+                "Tape pointer differs: {a} in {first}, {b} in {second}"
+            )
+            .unwrap();
+        }
+        if let Some((start, _)) = self.cells.first() {
+            let count = self.cells.len();
+            let cells = if count == 1 {
+                "cell differs"
+            } else {
+                "cells differ"
+            };
+            writeln!(report, "{count} {cells}, from cell {start}:").unwrap();
+            for (index, [a, b]) in &self.cells {
+                writeln!(report, "  Cell {index}: {a} in {first}, {b} in {second}").unwrap();
+            }
+        }
+
+        report.truncate(report.trim_end().len());
+        report
+    }
+}
+
+/// Describe how two outputs, called by `names`, differ, if they do.
+///
+/// This shows the lines from the first one that differs to the last one, leaving out those both
+/// outputs end with, with `-` before those of the first output and `+` before those of the
+/// second, colored if `color` is set. Bytes that aren't printable ASCII are escaped, like `\x0D`.
+pub fn lines(outputs: [&[u8]; 2], names: [&str; 2], color: bool) -> Option<String> {
+    if outputs[0] == outputs[1] {
+        return None;
+    }
+    let [left, right] = outputs.map(|output| {
+        output
+            .split_inclusive(|&byte| byte == b'\n')
+            .collect::<Vec<_>>()
+    });
+    let start = left.iter().zip(&right).take_while(|(a, b)| a == b).count();
+    let end = left[start..]
+        .iter()
+        .rev()
+        .zip(right[start..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let [first, second] = names;
+    let mut diff = format!(
+        "Output differs from line {} (- {first}, + {second}):",
+        start + 1
+    );
+    let sides = [
+        ('-', "\x1b[31m", &left[start..left.len() - end]),
+        ('+', "\x1b[32m", &right[start..right.len() - end]),
+    ];
+    for (sign, start_color, lines) in sides {
+        let (start_color, reset) = if color {
+            (start_color, "\x1b[0m")
+        } else {
+            ("", "")
+        };
+        for line in lines.iter().take(MAX_LINES) {
+            write!(diff, "\n{start_color}{sign} {}{reset}", printable(line)).unwrap();
+        }
+        if lines.len() > MAX_LINES {
+            let more = lines.len() - MAX_LINES;
+            write!(
+                diff,
+                "\n{start_color}{sign} ...and {more} more lines{reset}"
+            )
+            .unwrap();
+        }
+    }
+    Some(diff)
+}
+
+/// Return `line` without its line break, with its bytes escaped like [`Escaped`] does, noting if
+/// it has no line break.
+fn printable(line: &[u8]) -> String {
+    let (line, ended) = match line.strip_suffix(b"\n") {
+        Some(line) => (line, true),
+        None => (line, false),
+    };
+    let mut escaped = Vec::new();
+    Escaped::new(&mut escaped).write_all(line).unwrap();
+    let mut printable = String::from_utf8(escaped).unwrap();
+    if !ended {
+        printable.push_str(" (no line break at the end)");
+    }
+    printable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(output: &[u8], tape: &[u8], tape_pointer: usize) -> RunState {
+        RunState {
+            output: output.to_vec(),
+            tape: tape.to_vec(),
+            tape_pointer,
+        }
+    }
+
+    #[test]
+    fn find_differences() {
+        let first = state(b"abc", &[1, 2, 3, 0], 1);
+        assert!(StateDiff::new([&first, &first]).is_empty());
+
+        let second = state(b"abd", &[1, 5, 3, 7], 1);
+        let diff = StateDiff::new([&first, &second]);
+        assert_eq!(diff.output, Some((2, [Some(b'c'), Some(b'd')])));
+        assert_eq!(diff.tape_pointers, None);
+        assert_eq!(diff.cells, [(1, [2, 5]), (3, [0, 7])]);
+
+        // An output that stops early differs where it ends, and missing cells are zero.
+        let second = state(b"ab", &[1, 2], 0);
+        let diff = StateDiff::new([&first, &second]);
+        assert_eq!(diff.output, Some((2, [Some(b'c'), None])));
+        assert_eq!(diff.tape_pointers, Some([1, 0]));
+        assert_eq!(diff.cells, [(2, [3, 0])]);
+    }
+
+    #[test]
+    fn report_differences() {
+        let first = state(b"ab", &[1, 2, 3], 1);
+        assert_eq!(
+            StateDiff::new([&first, &first]).report(["a.b", "b.b"]),
+            "The runs ended in the same state"
+        );
+
+        let second = state(b"a", &[1, 0, 3], 2);
+        assert_eq!(
+            StateDiff::new([&first, &second]).report(["a.b", "b.b"]),
+            "Output differs from byte 1: 98 in a.b, its end in b.b\n\
+             Tape pointer differs: 1 in a.b, 2 in b.b\n\
+             1 cell differs, from cell 1:\n  \
+             Cell 1: 2 in a.b, 0 in b.b"
+        );
+    }
+
+    #[test]
+    fn diff_lines() {
+        let names = ["expected", "actual"];
+        assert_eq!(lines([b"Hi\n", b"Hi\n"], names, false), None);
+        assert_eq!(
+            lines([b"a\nb\nc\nd\n", b"a\nB\r\nd\n"], names, false).unwrap(),
+            "Output differs from line 2 (- expected, + actual):\n- b\n- c\n+ B\\x0D"
+        );
+        assert_eq!(
+            lines([b"Hi\n", b"Hi"], ["a.b", "b.b"], true).unwrap(),
+            "Output differs from line 1 (- a.b, + b.b):\n\x1b[31m- Hi\x1b[0m\n\
+             \x1b[32m+ Hi (no line break at the end)\x1b[0m"
+        );
+        let many = "x\n".repeat(12);
+        assert_eq!(
+            lines([b"", many.as_bytes()], names, false).unwrap(),
+            format!(
+                "Output differs from line 1 (- expected, + actual):{}\n+ ...and 2 more lines",
+                "\n+ x".repeat(10)
+            )
+        );
+    }
+}
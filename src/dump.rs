@@ -0,0 +1,72 @@
+use std::{collections::HashMap, fmt::Display, fmt::Write as _};
+
+/// The number of cells on every row of a dump.
+const ROW: isize = 16;
+
+/// Describe the tape a program left behind for a person, out of the cells that aren't zero, by
+/// their index, and the index of the cell the tape pointer is on.
+///
+/// The cells are shown 16 to a row, after the index of the first one, with the cell under the
+/// tape pointer in brackets. Only the rows with a cell that isn't zero or the tape pointer on them
+/// are shown, and a `*` stands in for the rows left out between them, like `hexdump` does.
+pub fn format_tape<C: Display>(cells: &[(isize, C)], pointer: isize) -> String {
+    let values: HashMap<_, _> = cells
+        .iter()
+        .map(|(index, cell)| (*index, cell.to_string()))
+        .collect();
+    let width = values.values().map(String::len).max().unwrap_or(1);
+    let mut rows: Vec<_> = cells
+        .iter()
+        .map(|(index, _)| index.div_euclid(ROW))
+        .chain([pointer.div_euclid(ROW)])
+        .collect();
+    rows.sort_unstable();
+    rows.dedup();
+
+    let mut dump = format!("The tape pointer is on cell {pointer}\n");
+    for (i, &row) in rows.iter().enumerate() {
+        if i > 0 && rows[i - 1] + 1 < row {
+            dump.push_str("*\n");
+        }
+        let mut line = format!("{:>8}:", row * ROW);
+        for index in row * ROW..(row + 1) * ROW {
+            let value = values.get(&index).map_or("0", String::as_str);
+            let [open, close] = if index == pointer {
+                ['[', ']']
+            } else {
+                [' '; 2]
+            };
+            write!(line, "{open}{value:>width$}{close}").unwrap();
+        }
+        dump.push_str(line.trim_end());
+        dump.push('\n');
+    }
+    dump
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_tape() {
+        let cells = [(0, 72), (1, 69), (2, 10), (40, 99)];
+        let dump = format_tape(&cells, 1);
+        assert_eq!(
+            dump.lines().collect::<Vec<_>>(),
+            [
+                "The tape pointer is on cell 1",
+                "       0: 72 [69] 10   0   0   0   0   0   0   0   0   0   0   0   0   0",
+                "*",
+                "      32:  0   0   0   0   0   0   0   0  99   0   0   0   0   0   0   0",
+            ]
+        );
+
+        // Rows are aligned to multiples of 16 left of the starting cell too.
+        let dump = format_tape::<u8>(&[], -3);
+        assert_eq!(
+            dump,
+            "The tape pointer is on cell -3\n     -16: 0  0  0  0  0  0  0  0  0  0  0  0  0 [0] 0  0\n"
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use std::io::{self, Write};
+
+/// A character set other than UTF-8 that the bytes a program writes can be in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// ISO 8859-1, where every byte stands for the Unicode character of the same number.
+    Latin1,
+
+    /// Code page 437 of the IBM PC, with its box drawing and shading characters.
+    ///
+    /// Only the bytes from 128 up stand for its own characters, and the ones below stay ASCII, so
+    /// that line breaks still work.
+    Cp437,
+}
+
+/// The characters of code page 437 for the bytes from 128 up.
+const CP437: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', 'É', 'æ', 'Æ',
+    'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', 'á', 'í', 'ó', 'ú', 'ñ', 'Ñ',
+    'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕',
+    '╣', '║', '╗', '╝', '╜', '╛', '┐', '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦',
+    '╠', '═', '╬', '╧', '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐',
+    '▀', 'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', '≡', '±',
+    '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+impl Encoding {
+    /// Return the character `byte` stands for.
+    pub fn decode(self, byte: u8) -> char {
+        match (self, byte) {
+            (Encoding::Latin1, _) | (Encoding::Cp437, ..0x80) => char::from(byte),
+            (Encoding::Cp437, 0x80..) => CP437[usize::from(byte - 0x80)],
+        }
+    }
+}
+
+/// An output that translates the bytes written to it from an [`Encoding`] into UTF-8.
+#[derive(Debug)]
+pub struct Encoded<W> {
+    inner: W,
+    encoding: Encoding,
+}
+
+impl<W: Write> Encoded<W> {
+    /// Return an output writing the bytes in `encoding` to `inner` as UTF-8.
+    pub fn new(inner: W, encoding: Encoding) -> Encoded<W> {
+        Encoded { inner, encoding }
+    }
+}
+
+impl<W: Write> Write for Encoded<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // ASCII is the same in all of them, so it's copied over a run at a time.
+        for run in buf.split_inclusive(|byte| !byte.is_ascii()) {
+            let (&last, ascii) = run.split_last().unwrap();
+            if last.is_ascii() {
+                self.inner.write_all(run)?;
+            } else {
+                self.inner.write_all(ascii)?;
+                let mut utf8 = [0; 4];
+                let character = self.encoding.decode(last).encode_utf8(&mut utf8);
+                self.inner.write_all(character.as_bytes())?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translate_to_utf8() {
+        let mut output = Encoded::new(Vec::new(), Encoding::Latin1);
+        output.write_all(b"caf\xe9 \xb0C\n").unwrap();
+        assert_eq!(String::from_utf8(output.inner).unwrap(), "café °C\n");
+
+        let mut output = Encoded::new(Vec::new(), Encoding::Cp437);
+        output
+            .write_all(b"\xc9\xcd\xbb\n\xba\xb0\xb1\xb2\xdb\xba")
+            .unwrap();
+        assert_eq!(String::from_utf8(output.inner).unwrap(), "╔═╗\n║░▒▓█║");
+        assert_eq!(Encoding::Cp437.decode(0xe0), 'α');
+        assert_eq!(Encoding::Cp437.decode(0xff), '\u{a0}');
+    }
+}
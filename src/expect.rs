@@ -0,0 +1,145 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+/// The extensions of the programs that are looked for in directories.
+const EXTENSIONS: [&str; 2] = ["b", "bf"];
+
+/// A program to run with the input in the file next to it, whose output must be that of another
+/// file next to it, like `foo.b` with `foo.in` and `foo.out`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case {
+    pub program: PathBuf,
+
+    /// The file with the input of the program, if there is one, else it gets no input.
+    pub input: Option<PathBuf>,
+
+    /// The file with the output the program must write.
+    pub output: PathBuf,
+}
+
+impl Case {
+    /// Return the case of the program at `path`, if there's a file with its expected output.
+    fn new(path: &Path) -> Option<Case> {
+        let output = path.with_extension("out");
+        if !output.is_file() {
+            return None;
+        }
+        let input = Some(path.with_extension("in")).filter(|input| input.is_file());
+        Some(Case {
+            program: path.to_owned(),
+            input,
+            output,
+        })
+    }
+}
+
+/// Return the cases of the programs at `paths`, and of those in the directories at `paths` and
+/// their subdirectories, in the order of their paths.
+///
+/// Programs in directories that have no file with their expected output are left out, as they're
+/// taken to be for something else, but those given by their path are an error.
+pub fn discover(paths: &[PathBuf]) -> Result<Vec<Case>> {
+    let mut cases = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            visit(path, &mut cases)?;
+        } else if let Some(case) = Case::new(path) {
+            cases.push(case);
+        } else if path.is_file() {
+            bail!(
+                "{} has no expected output, which goes in {}",
+                path.display(),
+                path.with_extension("out").display()
+            );
+        } else {
+            bail!("{} doesn't exist", path.display());
+        }
+    }
+    Ok(cases)
+}
+
+/// Add the cases of the programs in the directory at `path` and its subdirectories to `cases`.
+fn visit(path: &Path, cases: &mut Vec<Case>) -> Result<()> {
+    // The tests of the current directory are shown as `hello.b` rather than `./hello.b`.
+    let join = |name| {
+        if path == Path::new(".") {
+            PathBuf::from(name)
+        } else {
+            path.join(name)
+        }
+    };
+    let mut entries = fs::read_dir(path)
+        .and_then(|entries| {
+            entries
+                .map(|entry| Ok(join(entry?.file_name())))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .context(format!("Failed to read {}", path.display()))?;
+    entries.sort();
+    for entry in entries {
+        let program = entry
+            .extension()
+            .is_some_and(|extension| EXTENSIONS.iter().any(|&other| extension == other));
+        if entry.is_dir() {
+            visit(&entry, cases)?;
+        } else if let Some(case) = Case::new(&entry).filter(|_| program) {
+            cases.push(case);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    #[test]
+    fn discover_cases() {
+        let dir = env::temp_dir().join(format!("beef-expect-{}", process::id()));
+        fs::create_dir_all(dir.join("more")).unwrap();
+        for file in [
+            "b.b",
+            "b.out",
+            "a.bf",
+            "a.in",
+            "a.out",
+            "lib.b",
+            "more/c.b",
+            "more/c.out",
+        ] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+
+        let cases = discover(std::slice::from_ref(&dir)).unwrap();
+        let case = |name: &str, input: bool| Case {
+            program: dir.join(name),
+            input: input.then(|| dir.join(name).with_extension("in")),
+            output: dir.join(name).with_extension("out"),
+        };
+        assert_eq!(
+            cases,
+            [
+                case("a.bf", true),
+                case("b.b", false),
+                case("more/c.b", false)
+            ]
+        );
+        assert_eq!(discover(&[dir.join("b.b")]).unwrap(), [case("b.b", false)]);
+        assert_eq!(
+            discover(&[dir.join("lib.b")]).unwrap_err().to_string(),
+            format!(
+                "{} has no expected output, which goes in {}",
+                dir.join("lib.b").display(),
+                dir.join("lib.out").display()
+            )
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+/// A named pipe that `,` reads from or `.` writes to, which is only opened once the program first
+/// uses it.
+///
+/// Opening a FIFO waits for the other end to be opened too, so opening it any earlier could leave
+/// two programs that talk to each other waiting on each other's FIFO forever.
+#[derive(Debug)]
+pub struct Fifo {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl Fifo {
+    /// Return the FIFO at `path`, making it first if there's nothing there yet.
+    pub fn new(path: &Path) -> Result<Fifo> {
+        make(path)?;
+        Ok(Fifo {
+            path: path.to_owned(),
+            file: None,
+        })
+    }
+
+    /// Return the FIFO, opening it for writing if `write` is set, or for reading otherwise.
+    fn file(&mut self, write: bool) -> io::Result<&mut File> {
+        if self.file.is_none() {
+            let file = OpenOptions::new()
+                .read(!write)
+                .write(write)
+                .open(&self.path)
+                .map_err(|error| {
+                    io::Error::new(
+                        error.kind(),
+                        format!("Failed to open {}: {error}", self.path.display()),
+                    )
+                })?;
+            self.file = Some(file);
+        }
+        Ok(self.file.as_mut().unwrap())
+    }
+}
+
+impl Read for Fifo {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file(false)?.read(buf)
+    }
+}
+
+impl Write for Fifo {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file(true)?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Nothing is buffered on this side of the FIFO.
+        Ok(())
+    }
+}
+
+/// Make a FIFO at `path`, unless there already is one.
+#[cfg(unix)]
+fn make(path: &Path) -> Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt, os::unix::fs::FileTypeExt};
+
+    use anyhow::{bail, Context};
+
+    match path.metadata() {
+        Ok(metadata) if metadata.file_type().is_fifo() => return Ok(()),
+        Ok(_) => bail!("{} isn't a FIFO", path.display()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => {
+            return Err(error).context(format!("Failed to open {}", path.display()));
+        }
+    }
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .context(format!("{} has a NUL byte in it", path.display()))?;
+    // SAFETY: `c_path` is a NUL-terminated path that lives until the call returns.
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o666) } != 0 {
+        return Err(io::Error::last_os_error())
+            .context(format!("Failed to make a FIFO at {}", path.display()));
+    }
+    Ok(())
+}
+
+/// Fail, as only Unix has FIFOs beef can make.
+#[cfg(not(unix))]
+fn make(_path: &Path) -> Result<()> {
+    anyhow::bail!("FIFOs only work on Unix")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{env, fs, process, thread};
+
+    use super::*;
+
+    #[test]
+    fn talk_through_fifo() {
+        let path = env::temp_dir().join(format!("beef-fifo-{}", process::id()));
+        let mut reader = Fifo::new(&path).unwrap();
+        // The FIFO isn't opened yet, so neither end waits for the other here.
+        let mut writer = Fifo::new(&path).unwrap();
+
+        let written = thread::spawn(move || writer.write_all(b"hi"));
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        written.join().unwrap().unwrap();
+        assert_eq!(read, b"hi");
+
+        let file = env::temp_dir().join(format!("beef-not-fifo-{}", process::id()));
+        fs::write(&file, b"").unwrap();
+        let error = Fifo::new(&file).unwrap_err().to_string();
+        assert_eq!(error, format!("{} isn't a FIFO", file.display()));
+
+        fs::remove_file(path).unwrap();
+        fs::remove_file(file).unwrap();
+    }
+}
@@ -0,0 +1,289 @@
+/// How [`format`] lays out a program.
+#[derive(Debug, Clone, Copy)]
+pub struct Style {
+    /// The most characters on a line, unless a comment is longer than that on its own.
+    pub width: usize,
+
+    /// The number of spaces to indent the body of a loop by, for each loop it's in.
+    pub indent: usize,
+}
+
+/// A piece of the source of a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Item {
+    /// A run of instructions other than loop brackets, with nothing between them.
+    Code(String),
+
+    Loop(Vec<Item>),
+
+    /// Text that isn't instructions on one line, trimmed, and whether nothing but whitespace came
+    /// before it on its line.
+    Comment {
+        text: String,
+        own_line: bool,
+    },
+
+    /// One or more empty lines.
+    Blank,
+}
+
+/// Lay out the source of a program in `style`.
+///
+/// Loops that only hold instructions stay on a single line if they fit, like `[->+<]`. Every
+/// other loop puts its brackets on lines of their own, with its body indented between them. The
+/// instructions in between are wrapped to the width of the style, with a space wherever there
+/// was something else between them.
+///
+/// Comments keep their text. A comment that had a line of its own still does, and one that came
+/// after instructions stays after them if it fits. Either way, it ends its line. Several empty
+/// lines in a row become one, and a `#!` line at the start is kept as it is.
+///
+/// Formatting the formatted program again leaves it unchanged. The source must have matching
+/// loop brackets, as checked by [`crate::ir::compile`].
+pub fn format(source: &str, style: Style) -> String {
+    let (shebang, source) = match source.strip_prefix("#!") {
+        Some(_) => source.split_at(source.find('\n').map_or(source.len(), |end| end + 1)),
+        None => ("", source),
+    };
+
+    let mut formatter = Formatter {
+        style,
+        code: shebang.to_owned(),
+        line: String::new(),
+        depth: 0,
+        line_done: false,
+    };
+    if !formatter.code.is_empty() && !formatter.code.ends_with('\n') {
+        formatter.code.push('\n');
+    }
+    for item in parse(source) {
+        formatter.item(&item);
+    }
+    formatter.end_line();
+
+    let mut code = formatter.code;
+    code.truncate(code.trim_end().len());
+    if !code.is_empty() {
+        code.push('\n');
+    }
+    code
+}
+
+/// Split `source` into items, nesting the items in loops into them.
+fn parse(source: &str) -> Vec<Item> {
+    // Holds the items of every loop that is still open, after those of the program itself.
+    let mut stack = vec![Vec::new()];
+    let mut blank = false;
+
+    for line in source.lines() {
+        if line.trim().is_empty() {
+            blank = true;
+            continue;
+        }
+        let items = stack
+            .last_mut()
+            .expect("the program itself is never closed");
+        if blank && !items.is_empty() {
+            items.push(Item::Blank);
+        }
+        blank = false;
+
+        let mut own_line = true;
+        let mut rest = line;
+        while let Some(ch) = rest.chars().next() {
+            let end = rest
+                .find(|other| is_instruction(other) != is_instruction(ch) || "[]".contains(other))
+                .map_or(rest.len(), |end| end.max(1));
+            let (piece, after) = rest.split_at(end);
+            rest = after;
+            let items = stack
+                .last_mut()
+                .expect("the program itself is never closed");
+            match ch {
+                '[' => stack.push(Vec::new()),
+                ']' => {
+                    let body = stack.pop().expect("the loop brackets match");
+                    let items = stack.last_mut().expect("the loop brackets match");
+                    items.push(Item::Loop(body));
+                }
+                _ if is_instruction(ch) => items.push(Item::Code(piece.to_owned())),
+                _ if piece.trim().is_empty() => continue,
+                _ => items.push(Item::Comment {
+                    text: piece.trim().to_owned(),
+                    own_line,
+                }),
+            }
+            own_line = false;
+        }
+    }
+
+    stack.pop().expect("the program itself is never closed")
+}
+
+fn is_instruction(ch: char) -> bool {
+    "+-<>.,[]".contains(ch)
+}
+
+/// Lays out items one line at a time.
+struct Formatter {
+    style: Style,
+
+    /// The lines laid out so far.
+    code: String,
+
+    /// The line being laid out, without its indentation.
+    line: String,
+
+    /// The number of loops the line is in.
+    depth: usize,
+
+    /// Whether the line is done, so that only a comment may still be added to it.
+    line_done: bool,
+}
+
+impl Formatter {
+    fn item(&mut self, item: &Item) {
+        match item {
+            Item::Code(code) => self.code_word(code),
+            Item::Loop(body) => match inline(body) {
+                Some(code) if code.len() <= self.available() => self.code_word(&code),
+                _ => {
+                    self.end_line();
+                    self.line.push('[');
+                    // A comment right after the `[` stays on its line, outside of the body.
+                    let body = match body.split_first() {
+                        Some((
+                            comment @ Item::Comment {
+                                own_line: false, ..
+                            },
+                            rest,
+                        )) => {
+                            self.item(comment);
+                            rest
+                        }
+                        _ => body,
+                    };
+                    self.end_line();
+                    self.depth += 1;
+                    for item in body {
+                        self.item(item);
+                    }
+                    self.end_line();
+                    self.depth -= 1;
+                    self.line.push(']');
+                    self.line_done = true;
+                }
+            },
+            Item::Comment { text, own_line } => {
+                let width = self.line.chars().count() + 1 + text.chars().count();
+                if *own_line || width > self.available() {
+                    self.end_line();
+                } else if !self.line.is_empty() {
+                    self.line.push(' ');
+                }
+                self.line.push_str(text);
+                self.end_line();
+            }
+            Item::Blank => {
+                self.end_line();
+                if !self.code.is_empty() && !self.code.ends_with("\n\n") {
+                    self.code.push('\n');
+                }
+            }
+        }
+    }
+
+    /// Add instructions to the line, wrapping them onto lines of their own if they don't fit.
+    fn code_word(&mut self, mut word: &str) {
+        if self.line_done {
+            self.end_line();
+        }
+        if !self.line.is_empty() && self.line.len() + 1 + word.len() > self.available() {
+            self.end_line();
+        }
+        if !self.line.is_empty() {
+            self.line.push(' ');
+        }
+        // Instructions are ASCII, and a line always has room for at least one of them.
+        let available = self.available().max(1);
+        while word.len() > available {
+            let (start, rest) = word.split_at(available);
+            self.line.push_str(start);
+            self.end_line();
+            word = rest;
+        }
+        self.line.push_str(word);
+    }
+
+    /// Return how many characters fit on a line after its indentation.
+    fn available(&self) -> usize {
+        self.style
+            .width
+            .saturating_sub(self.depth * self.style.indent)
+    }
+
+    fn end_line(&mut self) {
+        if !self.line.is_empty() {
+            self.code
+                .push_str(&" ".repeat(self.depth * self.style.indent));
+            self.code.push_str(&self.line);
+            self.code.push('\n');
+            self.line.clear();
+        }
+        self.line_done = false;
+    }
+}
+
+/// Return the loop with `body` on a single line, if it only holds instructions.
+fn inline(body: &[Item]) -> Option<String> {
+    let mut words = Vec::with_capacity(body.len());
+    for item in body {
+        match item {
+            Item::Code(code) => words.push(code.clone()),
+            Item::Loop(_) | Item::Comment { .. } => return None,
+            Item::Blank => {}
+        }
+    }
+    Some(format!("[{}]", words.join(" ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STYLE: Style = Style {
+        width: 20,
+        indent: 2,
+    };
+
+    #[test]
+    fn format_loops() {
+        assert_eq!(format("++[->+<]>.", STYLE), "++ [->+<] >.\n");
+        assert_eq!(format("+[>++[-]<-]", STYLE), "+\n[\n  >++ [-] <-\n]\n");
+        assert_eq!(
+            format(&"+".repeat(25), STYLE),
+            format!("{}\n{}\n", "+".repeat(20), "+".repeat(5))
+        );
+    }
+
+    #[test]
+    fn keep_comments() {
+        let source = "#!/usr/bin/env -S beef\n\
+                      Print a cell\n\n\n\
+                      +++ three [ loop\n\
+                      -] .   done\n";
+        let formatted = format(source, STYLE);
+        assert_eq!(
+            formatted,
+            "#!/usr/bin/env -S beef\n\
+             Print a cell\n\
+             \n\
+             +++ three\n\
+             [ loop\n  \
+               -\n\
+             ]\n\
+             . done\n"
+        );
+        assert_eq!(format(&formatted, STYLE), formatted);
+    }
+}
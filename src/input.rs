@@ -0,0 +1,200 @@
+use std::{
+    io::{self, BufRead, ErrorKind, Read},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+/// An input that reads a whole line at a time from `inner`, and only once `,` has used up the line
+/// before, so that interactive programs wait for a line the terminal lets the user edit, and don't
+/// read ahead of what they asked for.
+#[derive(Debug)]
+pub struct LineInput<R> {
+    inner: R,
+    line: Vec<u8>,
+
+    /// The index in `line` of the next byte `,` reads.
+    position: usize,
+}
+
+impl<R: BufRead> LineInput<R> {
+    /// Return an input reading the lines of `inner`.
+    pub fn new(inner: R) -> LineInput<R> {
+        LineInput {
+            inner,
+            line: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Return the reader of the lines, to read other lines from it in between, while what's left
+    /// of the line `,` is on stays for it.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+}
+
+impl<R: BufRead> Read for LineInput<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.line.len() {
+            self.line.clear();
+            self.position = 0;
+            // The last line may not end in a newline, and an empty line means the input ended.
+            self.inner.read_until(b'\n', &mut self.line)?;
+        }
+
+        let rest = &self.line[self.position..];
+        let len = rest.len().min(buf.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// An input that never makes `,` wait, and gives it a byte of its own choosing while none of the
+/// input has come in yet, so that programs can poll for keypresses.
+///
+/// The input is read on a thread of its own, which sends whatever arrives over to `,`.
+#[derive(Debug)]
+pub struct NonBlockingInput {
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+
+    /// The index in `chunk` of the next byte `,` reads.
+    position: usize,
+
+    /// The byte `,` reads while there's no input.
+    idle: u8,
+}
+
+impl NonBlockingInput {
+    /// Return an input reading `inner` in the background, which reads as `idle` while none of
+    /// its input is available.
+    pub fn new(mut inner: impl Read + Send + 'static, idle: u8) -> NonBlockingInput {
+        let (sender, chunks) = mpsc::channel();
+        thread::spawn(move || loop {
+            let mut chunk = vec![0; 1 << 12];
+            let result = match inner.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(len) => {
+                    chunk.truncate(len);
+                    Ok(chunk)
+                }
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => Err(error),
+            };
+            let failed = result.is_err();
+            // Nobody is listening anymore once the program is done.
+            if sender.send(result).is_err() || failed {
+                break;
+            }
+        });
+
+        NonBlockingInput {
+            chunks,
+            chunk: Vec::new(),
+            position: 0,
+            idle,
+        }
+    }
+}
+
+impl Read for NonBlockingInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.position == self.chunk.len() {
+            match self.chunks.try_recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk?;
+                    self.position = 0;
+                }
+                Err(TryRecvError::Empty) => {
+                    buf[0] = self.idle;
+                    return Ok(1);
+                }
+                // The thread stops once the input ends.
+                Err(TryRecvError::Disconnected) => return Ok(0),
+            }
+        }
+
+        let rest = &self.chunk[self.position..];
+        let len = rest.len().min(buf.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An input that counts how many times it was read from.
+    struct Counted<'a> {
+        bytes: &'a [u8],
+        reads: usize,
+    }
+
+    impl Read for Counted<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            self.bytes.read(buf)
+        }
+    }
+
+    /// An input whose bytes only come in when the test sends them.
+    struct Typed(Receiver<u8>);
+
+    impl Read for Typed {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            Ok(self.0.recv().map_or(0, |byte| {
+                buf[0] = byte;
+                1
+            }))
+        }
+    }
+
+    #[test]
+    fn read_lines() {
+        let mut input = LineInput::new(io::BufReader::with_capacity(
+            1,
+            Counted {
+                bytes: b"ab\ncd",
+                reads: 0,
+            },
+        ));
+        let mut buf = [0; 8];
+        assert_eq!(input.read(&mut buf[..1]).unwrap(), 1);
+        assert_eq!(buf[0], b'a');
+        // The rest of the line was read along with its first byte, and nothing past it.
+        assert_eq!(input.inner.get_ref().reads, 3);
+        assert_eq!(input.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"b\n");
+        assert_eq!(input.inner.get_ref().reads, 3);
+
+        assert_eq!(input.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"cd");
+        assert_eq!(input.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn read_without_blocking() {
+        let (keys, typed) = mpsc::channel();
+        let mut input = NonBlockingInput::new(Typed(typed), b'-');
+        let mut byte = [0];
+        assert_eq!(input.read(&mut byte).unwrap(), 1);
+        assert_eq!(byte, *b"-");
+
+        keys.send(b'a').unwrap();
+        drop(keys);
+        let mut read = Vec::new();
+        while input.read(&mut byte).unwrap() == 1 {
+            if byte != *b"-" {
+                read.push(byte[0]);
+            }
+            thread::yield_now();
+        }
+        assert_eq!(read, b"a");
+    }
+}
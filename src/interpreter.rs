@@ -1,156 +1,1637 @@
-use std::io::{stdin, stdout, Read, StdinLock, StdoutLock, Write};
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    io::{self, stdin, stdout, Read, Write},
+    mem,
+    ops::Range,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 
-const TAPE_SIZE: usize = 30_000;
+#[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+use crate::jit;
+use crate::{
+    diagnostic::SourceError,
+    ir::{self, Op, Program, Span},
+    optimizer,
+};
 
-/// A Brainfuck interpreter.
+mod cell;
+mod counts;
+mod memo;
+mod pgo;
+mod snapshot;
+mod sparse;
+mod storage;
+mod tape;
+#[cfg(feature = "threaded")]
+mod threaded;
+mod trace;
+#[cfg(feature = "unsafe-fast")]
+mod unchecked;
+
+pub use cell::{BigCell, Cell};
+pub use counts::InstructionCounts;
+pub use pgo::Profile;
+pub use snapshot::TapeSnapshot;
+pub use sparse::SparseTape;
+pub use tape::Tape;
+pub use trace::Trace;
+
+/// The number of cells on the tape, unless another size is picked.
+pub const TAPE_SIZE: usize = 30_000;
+
+/// The fewest cells a tape may have.
+///
+/// The optimizer relies on this to tell which offsets from the tape pointer may point to the same
+/// cell.
+pub const MIN_TAPE_SIZE: usize = 64;
+
+/// The most cells a tape may have, which take up a gibibyte.
+pub const MAX_TAPE_SIZE: usize = 1 << 30;
+
+/// What happens when the tape pointer moves past an end of the tape.
+///
+/// Every model but [`TapeModel::Wrap`] runs the program on the
+/// [checked interpreter](Interpreter#the-checked-interpreter). The ends of the tape are checked for
+/// every cell the optimized program accesses, so a program that moves past an end and straight
+/// back without accessing a cell there may not be stopped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TapeModel {
+    /// The tape pointer wraps around to the other end of the tape.
+    #[default]
+    Wrap,
+
+    /// Moving past either end of the tape is an error, pointing at the op that did it.
+    Bounded,
+
+    /// Moving past the right end of the tape grows it, doubling its size as needed up to
+    /// [`MAX_TAPE_SIZE`] cells. Moving past the left end is an error.
+    Grow,
+
+    /// Moving past either end of the tape grows it like [`TapeModel::Grow`], so that the tape
+    /// extends as far left of the starting cell as it does right of it.
+    Infinite,
+
+    /// The tape extends endlessly both ways like [`TapeModel::Infinite`], but only stores the
+    /// cells that aren't zero, so that programs using cells far apart from each other only take
+    /// up memory for the cells they use. Cells are slower to access than on the other tapes, and
+    /// the size of the tape is ignored.
+    Sparse,
+}
+
+/// What happens when a cell overflows past its lowest or highest value.
+///
+/// Only [`Op::Add`]s are checked, which every `+` and `-` compiles to. The optimizer folds runs of
+/// adds together and replaces loops as if cells wrap around, so programs should be run unoptimized
+/// for every `+` and `-` to be checked on its own. Every policy but [`Overflow::Wrap`] runs the
+/// program on the [checked interpreter](Interpreter#the-checked-interpreter). Big cells never
+/// overflow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Cells wrap around to their other end.
+    #[default]
+    Wrap,
+
+    /// Cells stop at their lowest or highest value.
+    Saturate,
+
+    /// Overflowing a cell is an error, pointing at the op that did it.
+    Error,
+}
+
+/// What `,` does once the input has run out.
+///
+/// Programs expect one of these, depending on the implementation they were written for. Unlike
+/// the rest, this applies to native code too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Eof {
+    /// The cell is set to zero.
+    Zero,
+
+    /// The cell is set to -1, which is its highest value for unsigned cells.
+    MinusOne,
+
+    /// The cell keeps the value it had.
+    #[default]
+    Unchanged,
+
+    /// Reading past the end of the input is an error.
+    Error,
+}
+
+/// When the output of `.` is flushed to the writer it goes to.
+///
+/// Flushing stdout takes a system call, which is slow for every byte of programs that print a
+/// lot. Unlike the rest, this applies to native code too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Flush {
+    /// The output is flushed after every byte.
+    Every,
+
+    /// The output is flushed after every newline, and before `,` reads from the input, so that
+    /// prompts show up.
+    #[default]
+    Line,
+
+    /// The output is never flushed, which is left to whoever runs the program once it finishes.
+    End,
+}
+
+/// What `.` writes and `,` reads. Unlike the rest, this applies to native code too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IoFormat {
+    /// `.` writes the lowest byte of the cell, and `,` reads a byte into it, exactly as they are,
+    /// without translating newlines or decoding text, so that programs can work on binary data.
+    #[default]
+    Bytes,
+
+    /// `.` writes the value of the cell as a decimal number on a line of its own, and `,` reads
+    /// a decimal number, which may be negative and wraps around to fit the cell, skipping the
+    /// whitespace before it.
+    Numeric,
+}
+
+/// How `.` and `,` write the output and read the input of a program.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoMode {
+    pub format: IoFormat,
+    pub eof: Eof,
+    pub flush: Flush,
+}
+
+/// A Brainfuck interpreter, with a tape of `C` cells.
 ///
 /// To get started, instantiate an interpreter with [`Interpreter::new`] or
-/// [`Interpreter::from_program_str`], then run the program with [`Interpreter::run`].
+/// [`Interpreter::from_program_str`], then run the program with [`Interpreter::run`]. Interpreters
+/// start out with bytes for cells, which [`Interpreter::with_cells`] swaps for wider ones.
 ///
 /// # Example
 ///
 /// ```
-/// let mut interpreter = Interpreter::from_program_str(">><");
+/// use beef::interpreter::Interpreter;
+///
+/// let mut interpreter = Interpreter::from_program_str(">><").unwrap();
 /// interpreter.run().unwrap();
 /// ```
-pub struct Interpreter {
-    tape: [u8; TAPE_SIZE],
+///
+/// # The checked interpreter
+///
+/// Programs run on the fastest engine there is for them: the threaded dispatcher with the
+/// `threaded` feature, with loops memoized, or as native code. Those take the tape to wrap around
+/// and leave every cell to the program, so the options that need to look at every op as it runs
+/// make the program run on the checked interpreter instead, which executes one op at a time. These
+/// are the tape models and overflow policies other than wrapping, read-only cells, several tapes, a
+/// tape of its own, limits on steps and time, counting instructions, and traces.
+pub struct Interpreter<C: Cell = u8> {
+    tape: storage::Storage<C>,
     tape_pointer: usize,
+    tape_model: TapeModel,
+    overflow: Overflow,
+    io: IoMode,
+
+    /// The program, compiled to bytecode by [`ir::compile`] and then optimized.
+    program: Vec<Op>,
+    program_pointer: usize,
+
+    /// How many ops [`Interpreter::run_counted`] executed so far, if it ran the program.
+    steps: Option<u64>,
+
+    /// The number of ops the program may execute, from [`Interpreter::with_max_steps`].
+    max_steps: Option<u64>,
+
+    /// How long every run of the program may take, from [`Interpreter::with_timeout`].
+    timeout: Option<Duration>,
+
+    /// Where [`Interpreter::run_counted`] publishes how many ops it executed so far, since
+    /// [`Interpreter::with_progress`].
+    progress: Option<Arc<AtomicU64>>,
+
+    /// How many times [`Interpreter::run_counted`] executed each instruction, if they're counted
+    /// since [`Interpreter::with_instruction_counts`].
+    counts: Option<InstructionCounts>,
+
+    /// Where [`Interpreter::run_counted`] logs every instruction it executes, since
+    /// [`Interpreter::with_trace`].
+    trace: Option<Trace>,
+
+    /// The span of the source of every op of the program, to point errors at, if it is known.
+    spans: Vec<Span>,
+
+    /// The cached results of the loops marked with [`Op::Memoize`] that ran, by the index of their
+    /// [`Op::JumpIfZero`].
+    memo: HashMap<usize, memo::Memo<C>>,
+
+    /// The tape used instead of `tape` with [`TapeModel::Sparse`].
+    sparse: sparse::SparseTape<C>,
+
+    /// The tape given to [`Interpreter::with_tape`], used instead of every other tape.
+    custom_tape: Option<Box<dyn Tape<C>>>,
+
+    /// The ranges of cells the program may not write to, by their index on the tape.
+    read_only: Vec<Range<usize>>,
+
+    /// The pages of the tape in the last snapshot taken or restored, which the next snapshot
+    /// shares if they haven't changed.
+    pages: Vec<Arc<[C]>>,
+
+    /// Every tape of the interpreter, if it has more than one, where the tapes the program isn't
+    /// on wait for it to switch back with [`Op::SwitchTape`]. The current tape is in the fields
+    /// above, and its slot here holds an empty tape instead.
+    tapes: Vec<ParkedTape<C>>,
+    current_tape: usize,
+}
+
+/// A tape of an interpreter with several, with the state that belongs to each tape.
+struct ParkedTape<C: Cell> {
+    tape: storage::Storage<C>,
+    tape_pointer: usize,
+    sparse: sparse::SparseTape<C>,
+    read_only: Vec<Range<usize>>,
+}
+
+impl<C: Cell> ParkedTape<C> {
+    /// Return a tape of `len` zero cells.
+    fn new(len: usize) -> ParkedTape<C> {
+        ParkedTape {
+            tape: vec![C::default(); len].into_boxed_slice().into(),
+            tape_pointer: 0,
+            sparse: sparse::SparseTape::default(),
+            read_only: Vec::new(),
+        }
+    }
+}
+
+/// Return `count` tapes of `len` zero cells, for an interpreter on the first of them, or none if
+/// `count` is 1.
+fn parked_tapes<C: Cell>(count: usize, len: usize) -> Vec<ParkedTape<C>> {
+    if count == 1 {
+        return Vec::new();
+    }
+    (0..count)
+        .map(|index| ParkedTape::new(if index == 0 { 0 } else { len }))
+        .collect()
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    /// Return a new, empty interpreter.
+    pub fn new() -> Interpreter {
+        Interpreter {
+            tape: vec![0; TAPE_SIZE].into_boxed_slice().into(),
+            tape_pointer: 0,
+            tape_model: TapeModel::Wrap,
+            overflow: Overflow::Wrap,
+            io: IoMode::default(),
+
+            program: Vec::new(),
+            program_pointer: 0,
+            steps: None,
+            max_steps: None,
+            timeout: None,
+            progress: None,
+            counts: None,
+            trace: None,
+            spans: Vec::new(),
+
+            memo: HashMap::new(),
+            sparse: sparse::SparseTape::default(),
+            custom_tape: None,
+            read_only: Vec::new(),
+            pages: Vec::new(),
+
+            tapes: Vec::new(),
+            current_tape: 0,
+        }
+    }
+
+    /// Return an empty interpreter with a program preloaded.
+    ///
+    /// An error is returned if the program cannot be compiled, i.e. if there is an unmatched loop
+    /// bracket.
+    pub fn from_program_str(program: &str) -> Result<Interpreter> {
+        Ok(Interpreter::from_program(optimizer::optimize(ir::compile(
+            program,
+        )?)))
+    }
+
+    /// Return an empty interpreter with a compiled program preloaded, whose spans point errors at
+    /// the source of the op that caused them.
+    ///
+    /// The jumps of `program` must be linked, as they are after [`ir::compile`].
+    pub fn from_program(program: Program) -> Interpreter {
+        Interpreter {
+            spans: program.spans,
+            ..Interpreter::from_ops(program.ops)
+        }
+    }
+
+    /// Return an empty interpreter with a program preloaded, already compiled to bytecode.
+    ///
+    /// The jumps in `program` must be linked, as they are after [`ir::compile`].
+    pub fn from_ops(program: Vec<Op>) -> Interpreter {
+        Interpreter {
+            program,
+            ..Interpreter::new()
+        }
+    }
+
+    /// Replace the tape with an empty one of the same size, made of `D` cells instead of bytes, and
+    /// likewise for the other tapes.
+    pub fn with_cells<D: Cell>(self) -> Interpreter<D> {
+        Interpreter {
+            tape: vec![D::default(); self.tape.len()]
+                .into_boxed_slice()
+                .into(),
+            tape_pointer: 0,
+            tape_model: self.tape_model,
+            overflow: self.overflow,
+            io: self.io,
+
+            program: self.program,
+            program_pointer: self.program_pointer,
+            steps: self.steps,
+            max_steps: self.max_steps,
+            timeout: self.timeout,
+            progress: self.progress,
+            counts: self.counts,
+            trace: self.trace,
+            spans: self.spans,
+
+            memo: HashMap::new(),
+            sparse: sparse::SparseTape::default(),
+            custom_tape: None,
+            read_only: self.read_only,
+            pages: Vec::new(),
+
+            tapes: parked_tapes(self.tapes.len().max(1), self.tape.len()),
+            current_tape: 0,
+        }
+    }
+}
+
+impl<C: Cell> Interpreter<C> {
+    /// Replace the program with `program`, to run from its start on the tape the program before it
+    /// left behind, with the tape pointer where it left it.
+    ///
+    /// The jumps of `program` must be linked, as they are after [`ir::compile`].
+    pub fn load(&mut self, program: Program) {
+        self.program = program.ops;
+        self.spans = program.spans;
+        self.program_pointer = 0;
+        // The results of memoized loops are kept by where the loop is in the program.
+        self.memo.clear();
+    }
+
+    /// Replace the tape with an empty one of `tape_size` cells, instead of [`TAPE_SIZE`], and
+    /// likewise for the other tapes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tape_size` is not between [`MIN_TAPE_SIZE`] and [`MAX_TAPE_SIZE`].
+    #[must_use]
+    pub fn with_tape_size(self, tape_size: usize) -> Interpreter<C> {
+        assert!(
+            (MIN_TAPE_SIZE..=MAX_TAPE_SIZE).contains(&tape_size),
+            "the tape should have between {MIN_TAPE_SIZE} and {MAX_TAPE_SIZE} cells"
+        );
+        Interpreter {
+            tape: vec![C::default(); tape_size].into_boxed_slice().into(),
+            tape_pointer: 0,
+            tapes: parked_tapes(self.tapes.len().max(1), tape_size),
+            current_tape: 0,
+            ..self
+        }
+    }
+
+    /// Replace the tape with `count` empty tapes of the same size, which the program switches
+    /// between with [`Op::SwitchTape`], from [`ir::compile_with`].
+    ///
+    /// The program starts on the first tape, and every tape keeps its own tape pointer. The
+    /// other builders only change the tape the program is on, except for those that replace the
+    /// tape, which replace all of them. Several tapes run the program on the
+    /// [checked interpreter](Interpreter#the-checked-interpreter).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is 0.
+    #[must_use]
+    pub fn with_tapes(self, count: usize) -> Interpreter<C> {
+        assert!(count > 0, "there should be at least one tape");
+        Interpreter {
+            tape: vec![C::default(); self.tape.len()]
+                .into_boxed_slice()
+                .into(),
+            tape_pointer: 0,
+            sparse: sparse::SparseTape::default(),
+            tapes: parked_tapes(count, self.tape.len()),
+            current_tape: 0,
+            ..self
+        }
+    }
+
+    /// Back the tape with the file at `path`, mapped into memory, so that the program runs on its
+    /// contents and leaves its changes to the tape there.
+    ///
+    /// The bytes of the file are the cells of the tape, so a file that already exists replaces
+    /// the size of the tape with its own, while a new or empty one is extended to the size of the
+    /// tape first. The tape pointer starts on the first cell. Only bytes can be backed by a file,
+    /// and the tape stops being backed by it if it grows. The file must not be changed by anything
+    /// else while the interpreter uses it. Like with [`Interpreter::with_tape_contents`], the
+    /// program should be optimized for a [`StartingTape::Preloaded`](optimizer::StartingTape).
+    ///
+    /// An error is returned if the file can't be opened or mapped, if cells aren't bytes, or if
+    /// the file isn't between [`MIN_TAPE_SIZE`] and [`MAX_TAPE_SIZE`] bytes long.
+    pub fn with_tape_file(self, path: &Path) -> Result<Interpreter<C>> {
+        if C::from_bytes(&[]).is_none() {
+            bail!("Only tapes of 8-bit cells can be backed by a file");
+        }
+
+        let display = path.display();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .context(format!("Failed to open {display}"))?;
+
+        let len = file.metadata()?.len();
+        if len == 0 {
+            file.set_len(self.tape.len() as u64)
+                .context(format!("Failed to extend {display}"))?;
+        } else if !usize::try_from(len)
+            .is_ok_and(|len| (MIN_TAPE_SIZE..=MAX_TAPE_SIZE).contains(&len))
+        {
+            bail!(
+                "{display} holds {len} cells, but a tape has between {MIN_TAPE_SIZE} and \
+                 {MAX_TAPE_SIZE}"
+            );
+        }
+
+        let tape = storage::Storage::map(&file).context(format!("Failed to map {display}"))?;
+        Ok(Interpreter {
+            tape,
+            tape_pointer: 0,
+            ..self
+        })
+    }
+
+    /// Load `bytes` onto the tape, one per cell from the start of the tape, so that the program
+    /// starts out with them instead of zeroes.
+    ///
+    /// The bytes go on whichever tape the tape model uses, so the tape model has to be picked
+    /// first. The optimizer assumes the tape starts out zeroed by default, so the program should be
+    /// optimized for a [`StartingTape::Preloaded`](optimizer::StartingTape) for loops at its start
+    /// to run.
+    ///
+    /// An error is returned if the tape has fewer cells than there are `bytes`.
+    pub fn with_tape_contents(mut self, bytes: &[u8]) -> Result<Interpreter<C>> {
+        if self.tape_model == TapeModel::Sparse {
+            self.sparse.load(bytes);
+            return Ok(self);
+        }
+
+        if bytes.len() > self.tape.len() {
+            bail!(
+                "The tape only has {} cells, which can't hold {} bytes",
+                self.tape.len(),
+                bytes.len()
+            );
+        }
+        for (cell, &byte) in self.tape.iter_mut().zip(bytes) {
+            *cell = C::from_byte(byte);
+        }
+        Ok(self)
+    }
+
+    /// Run the program on `tape` instead of a tape of the interpreter, for tapes that are kept
+    /// somewhere else or do something on every access.
+    ///
+    /// The tape replaces all the tapes of the interpreter and the tape model, so the program stays
+    /// on it when it switches tapes, cells aren't checked for being read-only, and snapshots don't
+    /// include it. The program runs on the
+    /// [checked interpreter](Interpreter#the-checked-interpreter), which reaches the cells through
+    /// [`Tape`].
+    #[must_use]
+    pub fn with_tape(self, tape: impl Tape<C> + 'static) -> Interpreter<C> {
+        Interpreter {
+            custom_tape: Some(Box::new(tape)),
+            ..self
+        }
+    }
+
+    /// Pick what happens when the tape pointer moves past an end of the tape, instead of wrapping.
+    #[must_use]
+    pub fn with_tape_model(self, tape_model: TapeModel) -> Interpreter<C> {
+        Interpreter { tape_model, ..self }
+    }
+
+    /// Pick what happens when a cell overflows, instead of wrapping around.
+    #[must_use]
+    pub fn with_overflow(self, overflow: Overflow) -> Interpreter<C> {
+        Interpreter { overflow, ..self }
+    }
+
+    /// Pick what `,` does once the input has run out, instead of leaving the cell unchanged.
+    #[must_use]
+    pub fn with_eof(mut self, eof: Eof) -> Interpreter<C> {
+        self.io.eof = eof;
+        self
+    }
+
+    /// Pick what `.` writes and `,` reads, instead of bytes.
+    #[must_use]
+    pub fn with_io_format(mut self, format: IoFormat) -> Interpreter<C> {
+        self.io.format = format;
+        self
+    }
+
+    /// Pick when the output of `.` is flushed, instead of after every newline.
+    #[must_use]
+    pub fn with_flush(mut self, flush: Flush) -> Interpreter<C> {
+        self.io.flush = flush;
+        self
+    }
+
+    /// Make the cells from `cells.start` up to `cells.end` read-only, on top of the ones that
+    /// already are, so that writing to them stops the program with an error.
+    ///
+    /// Cells are numbered from the start of the tape, where the tape pointer starts, so with
+    /// [`TapeModel::Sparse`] the cells left of it can't be made read-only. Cells added before the
+    /// start of the tape as it grows shift the read-only cells along with the rest. Writes are
+    /// checked for every op of the optimized program, so a write the optimizer folds away, like
+    /// the add of `+-`, isn't caught. This runs the program on the
+    /// [checked interpreter](Interpreter#the-checked-interpreter).
+    #[must_use]
+    pub fn with_read_only(mut self, cells: Range<usize>) -> Interpreter<C> {
+        self.read_only.push(cells);
+        self
+    }
+
+    /// Stop the program with an error once it executed `limit` ops of the optimized program and
+    /// isn't done yet, so that programs that never finish don't run forever.
+    ///
+    /// The ops are counted like [`Interpreter::run_counted`] does, which every run does then,
+    /// except for [`Interpreter::run_pgo`] and [`Interpreter::run_budget`], which stay unlimited.
+    /// This runs the program on the [checked interpreter](Interpreter#the-checked-interpreter).
+    #[must_use]
+    pub fn with_max_steps(mut self, limit: u64) -> Interpreter<C> {
+        self.max_steps = Some(limit);
+        self
+    }
+
+    /// Stop the program with an error once a run of it took `timeout` and isn't done yet, leaving
+    /// the tape the way it was then.
+    ///
+    /// The time is checked every so many ops, counted like [`Interpreter::with_max_steps`] counts
+    /// them, so a program waiting for its input isn't stopped until the input comes in. This runs
+    /// the program on the [checked interpreter](Interpreter#the-checked-interpreter).
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Interpreter<C> {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Publish how many ops [`Interpreter::run_counted`] executed so far to `progress` every so
+    /// often, for another thread to show while the program runs.
+    ///
+    /// Unlike [`Interpreter::with_max_steps`], this doesn't make every run count its ops, so that
+    /// runs that don't are as fast as ever.
+    #[must_use]
+    pub fn with_progress(mut self, progress: Arc<AtomicU64>) -> Interpreter<C> {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Count how many times [`Interpreter::run_counted`] executes each instruction, and how many
+    /// times the loops iterate.
+    ///
+    /// The program shouldn't be optimized, as only ops made from a single instruction are counted
+    /// right. This runs the program on the
+    /// [checked interpreter](Interpreter#the-checked-interpreter).
+    #[must_use]
+    pub fn with_instruction_counts(mut self) -> Interpreter<C> {
+        self.counts = Some(InstructionCounts::default());
+        self
+    }
+
+    /// Log every op the program executes to `trace`, before executing it.
+    ///
+    /// Like with [`Interpreter::with_instruction_counts`], the program shouldn't be optimized for
+    /// every op to be a single instruction. This runs the program on the
+    /// [checked interpreter](Interpreter#the-checked-interpreter).
+    #[must_use]
+    pub fn with_trace(mut self, trace: Trace) -> Interpreter<C> {
+        self.trace = Some(trace);
+        self
+    }
+
+    /// Return the cells of the tape the program is on, which it leaves there once it finishes.
+    ///
+    /// With [`TapeModel::Sparse`], the program runs on a sparse tape instead, which isn't
+    /// returned.
+    pub fn tape(&self) -> &[C] {
+        &self.tape
+    }
+
+    /// Return the index of the cell the tape pointer is on, on the tape returned by
+    /// [`Interpreter::tape`].
+    pub fn tape_pointer(&self) -> usize {
+        self.tape_pointer
+    }
+
+    /// Set the cell at `index` on the tape returned by [`Interpreter::tape`] to `value`, even if
+    /// it's read-only, returning whether there is such a cell.
+    pub fn set_cell(&mut self, index: usize, value: C) -> bool {
+        let Some(cell) = self.tape.get_mut(index) else {
+            return false;
+        };
+        *cell = value;
+        true
+    }
+
+    /// Return the span of the source of the op the program runs next, unless it finished or the
+    /// spans of its ops aren't known.
+    pub fn span(&self) -> Option<Span> {
+        self.spans.get(self.program_pointer).copied()
+    }
+
+    /// Return every cell that isn't zero on the tape the program is on, in order, by its index,
+    /// along with the index of the cell the tape pointer is on, to show a person what the program
+    /// left behind.
+    ///
+    /// The indices are on the tape returned by [`Interpreter::tape`], or from the starting cell
+    /// with [`TapeModel::Sparse`], where the cells left of it have negative indices. Only the cell
+    /// under the tape pointer can be seen on a tape given to [`Interpreter::with_tape`], which is
+    /// at index 0 then.
+    pub fn nonzero_cells(&self) -> (Vec<(isize, C)>, isize) {
+        if let Some(tape) = &self.custom_tape {
+            let cell = tape.get(0);
+            return (
+                if cell.is_zero() {
+                    vec![]
+                } else {
+                    vec![(0, cell)]
+                },
+                0,
+            );
+        }
+        if self.tape_model == TapeModel::Sparse {
+            return (self.sparse.nonzero_cells(), self.sparse.index(0));
+        }
+
+        let cells = (0..)
+            .zip(self.tape.iter())
+            .filter(|(_, cell)| !cell.is_zero())
+            .map(|(index, cell)| (index, cell.clone()))
+            .collect();
+        // No tape holds more than `isize::MAX` cells.
+        #[allow(clippy::cast_possible_wrap)]
+        let pointer = self.tape_pointer as isize;
+        (cells, pointer)
+    }
+
+    /// Take a snapshot of the tape, the tape pointer and the program pointer, to go back to with
+    /// [`Interpreter::restore`].
+    ///
+    /// The interpreter holds on to the pages of the tape in the snapshot, so that the next
+    /// snapshot only copies the ones that changed since, and shares the rest. The other tapes of an
+    /// interpreter with several are copied whole.
+    pub fn snapshot(&mut self) -> TapeSnapshot<C> {
+        snapshot::take(self)
+    }
+
+    /// Put the tape, the tape pointer and the program pointer back the way they were when
+    /// `snapshot` was taken, so that the program picks up from there when it runs again.
+    ///
+    /// Only the pages of the tape that changed since are copied. A tape that grew since the
+    /// snapshot goes back to its old size, and stops being backed by a file if it had a different
+    /// size. The read-only cells go back to the ones of the snapshot too, as they shift along with
+    /// the tape when it grows. With several tapes, the program goes back to the tape it was on,
+    /// and the others go back to how they were too.
+    pub fn restore(&mut self, snapshot: &TapeSnapshot<C>) {
+        snapshot::restore(self, snapshot);
+    }
+
+    /// Run the program.
+    ///
+    /// This locks stdin and stdout until execution finishes.
+    ///
+    /// An error is returned if unable to read from stdin or write to stdout, if the program moves
+    /// somewhere the tape can't grow to, or if it overflows a cell with [`Overflow::Error`].
+    pub fn run(&mut self) -> Result<()> {
+        self.run_with(&mut stdin().lock(), &mut stdout().lock())
+    }
+
+    /// Run the program, reading its input from `input` and writing its output to `output`.
+    ///
+    /// An error is returned if unable to read from `input` or write to `output`, if the program
+    /// moves somewhere the tape can't grow to, or if it overflows a cell with [`Overflow::Error`].
+    pub fn run_with(&mut self, input: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+        if self.counted() {
+            return self.run_counted(input, output).map(drop);
+        }
+        if self.interpreted_only() {
+            while self.program_pointer < self.program.len() {
+                let op = self.program[self.program_pointer];
+                self.execute_op(op, input, output)?;
+                self.program_pointer += 1;
+            }
+            return Ok(());
+        }
+
+        #[cfg(feature = "threaded")]
+        return threaded::run(self, input, output);
+        #[cfg(not(feature = "threaded"))]
+        self.run_wrapping(input, output)
+    }
+
+    /// Run the program on a tape of its own cells that wraps around, without any of the options
+    /// of the [checked interpreter](Interpreter#the-checked-interpreter), so that no op needs to be
+    /// checked before it executes.
+    ///
+    /// The cells are borrowed once for every stretch of the program up to an [`Op::Memoize`],
+    /// rather than for every cell an op accesses.
+    #[cfg(not(feature = "threaded"))]
+    fn run_wrapping(&mut self, input: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+        loop {
+            let tape: &mut [C] = &mut self.tape;
+            while let Some(&op) = self.program.get(self.program_pointer) {
+                if op == Op::Memoize {
+                    break;
+                }
+                execute_wrapping(
+                    op,
+                    tape,
+                    &mut self.tape_pointer,
+                    &mut self.program_pointer,
+                    self.io,
+                    input,
+                    output,
+                )?;
+                self.program_pointer += 1;
+            }
+            if self.program_pointer >= self.program.len() {
+                return Ok(());
+            }
+            memo::run(self);
+            self.program_pointer += 1;
+        }
+    }
+
+    /// Run the program like [`Interpreter::run_with`], taking any reader and writer by value, like
+    /// a byte slice, a [`File`](std::fs::File), a [`TcpStream`](std::net::TcpStream), or a
+    /// `&mut Vec<u8>` to collect the output in.
+    pub fn run_with_io(&mut self, mut input: impl Read, mut output: impl Write) -> Result<()> {
+        self.run_with(&mut input, &mut output)
+    }
+
+    /// Run the program like [`Interpreter::run_with`] on `input`, and return its output.
+    pub fn run_collect(&mut self, input: &[u8]) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        self.run_with(&mut &input[..], &mut output)?;
+        Ok(output)
+    }
+
+    /// Run the program like [`Interpreter::run_collect`], and return its output as text.
+    ///
+    /// An error is also returned if the output isn't valid UTF-8.
+    pub fn run_collect_string(&mut self, input: &[u8]) -> Result<String> {
+        String::from_utf8(self.run_collect(input)?).context("The output isn't valid UTF-8")
+    }
+
+    /// Run at most `steps` ops of the program, continuing from where it left off, and return
+    /// whether it finished.
+    ///
+    /// This lets a host interleave running the program with its own work without threads, by
+    /// calling this repeatedly until it returns `true`. Steps are ops of the compiled program,
+    /// which may each stand for many instructions of the source code.
+    ///
+    /// This locks stdin and stdout until the steps are done. An error is returned if unable to
+    /// read from stdin or write to stdout.
+    pub fn run_budget(&mut self, steps: u64) -> Result<bool> {
+        self.run_budget_with(steps, &mut stdin().lock(), &mut stdout().lock())
+    }
+
+    /// Run at most `steps` ops of the program like [`Interpreter::run_budget`], reading its input
+    /// from `input` and writing its output to `output`.
+    pub fn run_budget_with(
+        &mut self,
+        steps: u64,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+    ) -> Result<bool> {
+        for _ in 0..steps {
+            let Some(&op) = self.program.get(self.program_pointer) else {
+                break;
+            };
+            self.execute_op(op, input, output)?;
+            self.program_pointer += 1;
+        }
+
+        Ok(self.program_pointer >= self.program.len())
+    }
+
+    /// Run the program like [`Interpreter::run_with`], returning how many ops it executed.
+    ///
+    /// Ops are always dispatched one by one, even with the `threaded` feature. The ops of loops
+    /// replayed by an [`Op::Memoize`] are counted as if they were executed.
+    pub fn run_counted(&mut self, input: &mut dyn Read, output: &mut dyn Write) -> Result<u64> {
+        let mut steps = 0;
+        let previous = self.steps.unwrap_or(0);
+        let mut result = self.count_steps(previous, &mut steps, input, output);
+        *self.steps.get_or_insert(0) += steps;
+        if let Some(progress) = &self.progress {
+            progress.store(previous + steps, Ordering::Relaxed);
+        }
+        if let Some(trace) = &mut self.trace {
+            let flushed = trace.flush().context("Failed to write the trace");
+            result = result.and(flushed);
+        }
+        result.map(|()| steps)
+    }
+
+    /// Run the program op by op like [`Interpreter::run_counted`], adding every op to `steps` as
+    /// it executes, so that the ops of a run that fails are counted too, with the one that failed.
+    ///
+    /// An error is returned before the op past the limit of [`Interpreter::with_max_steps`],
+    /// counting the `previous` ops of earlier runs, or once the run took longer than the timeout
+    /// of [`Interpreter::with_timeout`].
+    fn count_steps(
+        &mut self,
+        previous: u64,
+        steps: &mut u64,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+    ) -> Result<()> {
+        /// How many ops run between looking at the clock and publishing the progress.
+        const CLOCK_INTERVAL: u32 = 1 << 10;
+
+        let deadline = self
+            .timeout
+            .map(|timeout| (Instant::now() + timeout, timeout));
+        let mut until_clock = 0;
+        while let Some(&op) = self.program.get(self.program_pointer) {
+            if let Some(limit) = self.max_steps.filter(|&limit| previous + *steps >= limit) {
+                return Err(self.error(format!(
+                    "The program didn't finish within {limit} steps, and was stopped"
+                )));
+            }
+            if until_clock == 0 {
+                if let Some(progress) = &self.progress {
+                    progress.store(previous + *steps, Ordering::Relaxed);
+                }
+                if let Some((deadline, timeout)) = deadline {
+                    if Instant::now() >= deadline {
+                        return Err(self.error(format!(
+                            "The program didn't finish within {timeout:?}, and was stopped"
+                        )));
+                    }
+                }
+                until_clock = CLOCK_INTERVAL;
+            }
+            until_clock -= 1;
+            *steps += 1;
+            if self.trace.is_some() {
+                self.trace_op(op)?;
+            }
+            let program_pointer = self.program_pointer;
+            let result = match op {
+                Op::Memoize => {
+                    *steps += memo::run(self);
+                    Ok(())
+                }
+                op => self.execute_op(op, input, output),
+            };
+            if let Some(counts) = &mut self.counts {
+                counts.count(op, self.program_pointer != program_pointer);
+            }
+            result?;
+            self.program_pointer += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Log `op` to the trace, with the cell the tape pointer is on.
+    fn trace_op(&mut self, op: Op) -> Result<()> {
+        let (pointer, cell) = if let Some(tape) = &self.custom_tape {
+            // Tapes of their own don't say where their tape pointer is.
+            (0, tape.get(0))
+        } else if self.tape_model == TapeModel::Sparse {
+            (self.sparse.index(0), self.sparse.get(0))
+        } else {
+            // No tape holds more than `isize::MAX` cells.
+            #[allow(clippy::cast_possible_wrap)]
+            let pointer = self.tape_pointer as isize;
+            (pointer, self.tape[self.tape_pointer].clone())
+        };
+        let offset = self.spans.get(self.program_pointer).map(|span| span.start);
+        let location = self.location();
+        let trace = self.trace.as_mut().expect("the program is traced");
+        trace
+            .record(op, offset, &location, pointer, &cell)
+            .context("Failed to write the trace")
+    }
+
+    /// Return how many ops [`Interpreter::run_counted`] executed so far, counting those of runs
+    /// that failed, or `None` if it never ran the program.
+    pub fn steps(&self) -> Option<u64> {
+        self.steps
+    }
+
+    /// Return how many times [`Interpreter::run_counted`] executed each instruction so far, if
+    /// they're counted since [`Interpreter::with_instruction_counts`].
+    pub fn instruction_counts(&self) -> Option<&InstructionCounts> {
+        self.counts.as_ref()
+    }
+
+    /// Run the program without any bounds checks, for programs that are known to stay on the tape.
+    ///
+    /// This behaves just like [`Interpreter::run`], except that cells past the ends of the tape
+    /// don't wrap around when an op reaches them from the tape pointer, so programs that rely on
+    /// that may behave differently. Moving the tape pointer itself past an end still wraps. With
+    /// a tape model that grows the tape or overflow checks, the program runs with bounds checks
+    /// like [`Interpreter::run`].
+    ///
+    /// This locks stdin and stdout until execution finishes. An error is returned if unable to
+    /// read from stdin or write to stdout.
+    #[cfg(feature = "unsafe-fast")]
+    pub fn run_unchecked(&mut self) -> Result<()> {
+        self.run_unchecked_with(&mut stdin().lock(), &mut stdout().lock())
+    }
+
+    /// Run the program without any bounds checks like [`Interpreter::run_unchecked`], reading its
+    /// input from `input` and writing its output to `output`.
+    #[cfg(feature = "unsafe-fast")]
+    pub fn run_unchecked_with(
+        &mut self,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+    ) -> Result<()> {
+        if self.interpreted_only() {
+            return self.run_with(input, output);
+        }
+        unchecked::run(self, input, output)
+    }
+
+    /// Run the program, compiling it to native code with `backend` first.
+    ///
+    /// This behaves just like [`Interpreter::run`], but is much faster for programs that do a lot
+    /// of work. The program always runs from the start. Native code only works on a tape of bytes
+    /// that doesn't grow and wraps around on overflow, so otherwise the program is interpreted like
+    /// [`Interpreter::run`] instead.
+    ///
+    /// This locks stdin and stdout until execution finishes. An error is returned if the backend
+    /// does not support the host machine, or if unable to read from stdin or write to stdout.
+    #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+    pub fn run_jit(&mut self, backend: jit::Backend) -> Result<()> {
+        self.run_jit_with(backend, &mut stdin().lock(), &mut stdout().lock())
+    }
+
+    /// Run the program as native code like [`Interpreter::run_jit`], reading its input from
+    /// `input` and writing its output to `output`.
+    #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+    pub fn run_jit_with(
+        &mut self,
+        backend: jit::Backend,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+    ) -> Result<()> {
+        let interpreted_only = self.interpreted_only();
+        let tape = C::as_bytes_mut(&mut self.tape).filter(|_| !interpreted_only);
+        let Some(tape) = tape else {
+            return self.run_with(input, output);
+        };
+
+        jit::run::<C>(
+            backend,
+            &self.program,
+            tape,
+            &mut self.tape_pointer,
+            input,
+            output,
+            self.io,
+        )?;
+        self.program_pointer = self.program.len();
+
+        Ok(())
+    }
+
+    /// Run the program, counting how many times each of its loops iterates into `profile`.
+    ///
+    /// `spans` holds the span of every op of the program, which identify its loops in the
+    /// profile. With a JIT `backend`, loops that are hot in the profile are compiled to native
+    /// code, so long-running programs mostly run natively while the rest of their code stays
+    /// interpreted. With a tape model that grows the tape or overflow checks, every loop is
+    /// interpreted.
+    ///
+    /// This locks stdin and stdout until execution finishes. An error is returned if compiling a
+    /// loop fails, or if unable to read from stdin or write to stdout.
+    pub fn run_pgo(
+        &mut self,
+        spans: &[Span],
+        profile: &mut Profile,
+        #[cfg(any(feature = "jit", feature = "jit-x86-64"))] backend: Option<jit::Backend>,
+    ) -> Result<()> {
+        self.run_pgo_with(
+            spans,
+            profile,
+            #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+            backend,
+            &mut stdin().lock(),
+            &mut stdout().lock(),
+        )
+    }
+
+    /// Run the program while profiling its loops like [`Interpreter::run_pgo`], reading its input
+    /// from `input` and writing its output to `output`.
+    pub fn run_pgo_with(
+        &mut self,
+        spans: &[Span],
+        profile: &mut Profile,
+        #[cfg(any(feature = "jit", feature = "jit-x86-64"))] backend: Option<jit::Backend>,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+    ) -> Result<()> {
+        pgo::run(
+            self,
+            spans,
+            profile,
+            #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+            backend,
+            input,
+            output,
+        )
+    }
+
+    /// Execute a single op.
+    ///
+    /// An error is returned if:
+    ///
+    /// - The op is [`Op::Output`], and writing to `output` fails
+    /// - The op is [`Op::Input`], and reading from `input` fails
+    /// - The op reaches past an end of the tape with [`TapeModel::Bounded`], past the left end with
+    ///   [`TapeModel::Grow`], or grows the tape
+    ///   past [`MAX_TAPE_SIZE`] cells
+    /// - The op is an [`Op::Add`] that overflows a cell with [`Overflow::Error`]
+    /// - The op writes to a read-only cell
+    fn execute_op(&mut self, op: Op, input: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+        if self.custom_tape.is_some() {
+            return tape::execute_op(self, op, input, output);
+        }
+        if !matches!(self.tape_model, TapeModel::Wrap | TapeModel::Sparse) {
+            self.make_room(op)?;
+        }
+        if !self.read_only.is_empty() {
+            self.check_writes(op)?;
+        }
+        if self.tape_model == TapeModel::Sparse {
+            return tape::execute_op(self, op, input, output);
+        }
+
+        match op {
+            Op::Add { offset, amount } if self.overflow != Overflow::Wrap => {
+                if self.cell_mut(offset).saturating_add_amount(amount)
+                    && self.overflow == Overflow::Error
+                {
+                    return Err(self.error("Cell overflowed"));
+                }
+            }
+            Op::Scan(stride) if self.tape_model != TapeModel::Wrap => self.scan_growing(stride)?,
+            Op::SwitchTape(amount) => self.switch_tape(amount),
+            Op::Memoize => {
+                memo::run(self);
+            }
+            op => execute_wrapping(
+                op,
+                &mut self.tape,
+                &mut self.tape_pointer,
+                &mut self.program_pointer,
+                self.io,
+                input,
+                output,
+            )?,
+        }
+
+        Ok(())
+    }
+
+    /// Switch to the tape `amount` tapes after the current one, going around from the last tape
+    /// to the first, which does nothing with a single tape.
+    fn switch_tape(&mut self, amount: isize) {
+        if !self.tapes.is_empty() {
+            let count = self.tapes.len().cast_signed();
+            let index = (self.current_tape.cast_signed() + amount).rem_euclid(count);
+            self.select_tape(index.cast_unsigned());
+        }
+    }
+
+    /// Switch to the tape at `index`, parking the current one in its slot.
+    fn select_tape(&mut self, index: usize) {
+        for slot in [self.current_tape, index] {
+            let parked = &mut self.tapes[slot];
+            mem::swap(&mut self.tape, &mut parked.tape);
+            mem::swap(&mut self.tape_pointer, &mut parked.tape_pointer);
+            mem::swap(&mut self.sparse, &mut parked.sparse);
+            mem::swap(&mut self.read_only, &mut parked.read_only);
+        }
+        self.current_tape = index;
+    }
+
+    /// Return whether the program has to run on the checked interpreter, for one of the options
+    /// listed [on `Interpreter`](Interpreter#the-checked-interpreter).
+    fn interpreted_only(&self) -> bool {
+        self.tape_model != TapeModel::Wrap
+            || self.counted()
+            || self.overflow != Overflow::Wrap
+            || !self.read_only.is_empty()
+            || !self.tapes.is_empty()
+            || self.custom_tape.is_some()
+    }
+
+    /// Return an error if `op` writes to a read-only cell, which must be on the tape already.
+    ///
+    /// Clearing a cell that is already zero doesn't write to it, like a clear loop that doesn't
+    /// run.
+    fn check_writes(&self, op: Op) -> Result<()> {
+        let (offset, len, clear) = match op {
+            Op::Add { offset, .. }
+            | Op::Set { offset, .. }
+            | Op::MulAdd { offset, .. }
+            | Op::Input { offset } => (offset, 1, false),
+            Op::SetZero { offset } => (offset, 1, true),
+            Op::ClearRange { offset, len } => (offset, len, true),
+            Op::Move(_)
+            | Op::Scan(_)
+            | Op::JumpIfZero(_)
+            | Op::JumpIfNonZero(_)
+            | Op::Output { .. }
+            | Op::SwitchTape(_)
+            | Op::Memoize => return Ok(()),
+        };
+        // A multiply loop that wouldn't have run doesn't write anything either.
+        if matches!(op, Op::MulAdd { .. }) && self.current_is_zero() {
+            return Ok(());
+        }
+
+        for offset in (offset..).take(len) {
+            let index = match self.tape_model {
+                TapeModel::Sparse => usize::try_from(self.sparse.index(offset)).ok(),
+                _ => Some(self.offset_index(offset)),
+            };
+            let Some(index) =
+                index.filter(|index| self.read_only.iter().any(|cells| cells.contains(index)))
+            else {
+                continue;
+            };
+            if !(clear && self.cell_is_zero(offset)) {
+                return Err(self.error(format!("The program wrote to read-only cell {index}")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Return whether every op has to run through [`Interpreter::run_counted`], as it does when
+    /// the run is limited in steps or time, or instructions are counted or traced.
+    fn counted(&self) -> bool {
+        self.max_steps.is_some()
+            || self.timeout.is_some()
+            || self.counts.is_some()
+            || self.trace.is_some()
+    }
+
+    /// Return the error `message` at the op the program is at, which points at its instruction in
+    /// the source.
+    fn error(&self, message: impl Into<String>) -> anyhow::Error {
+        match self.spans.get(self.program_pointer) {
+            Some(span) => SourceError::new(message, span.start).into(),
+            None => anyhow!("{} at op {}", message.into(), self.program_pointer),
+        }
+    }
+
+    /// Describe where in the program the op at the program pointer is, for errors: the start of
+    /// its span of the source, or its index if its span isn't known.
+    fn location(&self) -> String {
+        match self.spans.get(self.program_pointer) {
+            Some(span) => span.start.to_string(),
+            None => format!("op {}", self.program_pointer),
+        }
+    }
+
+    /// Return whether the cell at the tape pointer is zero, on whichever tape the tape model uses.
+    fn current_is_zero(&self) -> bool {
+        self.cell_is_zero(0)
+    }
+
+    /// Return whether the cell `offset` cells away from the tape pointer is zero, on whichever
+    /// tape the tape model uses.
+    fn cell_is_zero(&self, offset: isize) -> bool {
+        match self.tape_model {
+            TapeModel::Sparse => self.sparse.is_zero(offset),
+            _ => self.tape[self.offset_index(offset)].is_zero(),
+        }
+    }
+
+    /// Return the cell `offset` cells away from the tape pointer.
+    fn cell_mut(&mut self, offset: isize) -> &mut C {
+        let index = self.offset_index(offset);
+        &mut self.tape[index]
+    }
+
+    /// Move the tape pointer by `amount` cells, wrapping around the ends of the tape.
+    fn move_tape_pointer(&mut self, amount: isize) {
+        self.tape_pointer = self.offset_index(amount);
+    }
+
+    /// Return the index of the cell `offset` cells away from the tape pointer, wrapping around the
+    /// ends of the tape.
+    fn offset_index(&self, offset: isize) -> usize {
+        wrapping_index(self.tape_pointer, offset, self.tape.len())
+    }
+
+    /// Grow the tape so that every cell `op` accesses is on it, for the tape models other than
+    /// [`TapeModel::Wrap`].
+    ///
+    /// An error is returned if `op` reaches past an end of the tape that doesn't grow, or if the
+    /// tape would grow past [`MAX_TAPE_SIZE`] cells.
+    fn make_room(&mut self, op: Op) -> Result<()> {
+        let (lowest, highest) = match op {
+            Op::Add { offset, .. }
+            | Op::SetZero { offset }
+            | Op::Set { offset, .. }
+            | Op::Output { offset }
+            | Op::Input { offset } => (offset, offset),
+            // Multiply loops are replaced without their jumps, so they also run when the current
+            // cell is zero. They don't change anything then, as their loop wouldn't have run.
+            Op::MulAdd { .. } if self.tape[self.tape_pointer].is_zero() => return Ok(()),
+            Op::MulAdd { offset, .. } => (offset.min(0), offset.max(0)),
+            Op::ClearRange { offset, len } => (offset, offset + len.cast_signed() - 1),
+            Op::Move(amount) => (amount, amount),
+            Op::Scan(_)
+            | Op::JumpIfZero(_)
+            | Op::JumpIfNonZero(_)
+            | Op::SwitchTape(_)
+            | Op::Memoize => return Ok(()),
+        };
+
+        self.grow_tape(lowest, highest)
+    }
+
+    /// Move the tape pointer to the first zero cell found by repeatedly moving `stride` cells
+    /// away from it, starting with the current cell, for the tape models other than
+    /// [`TapeModel::Wrap`].
+    ///
+    /// Scanning past an end of the tape grows it, and stops on the first new cell the scan visits,
+    /// since they are all zero. An error is returned if the scan finds no zero cell before an end
+    /// of the tape that doesn't grow.
+    fn scan_growing(&mut self, stride: isize) -> Result<()> {
+        let (tape, pointer) = (&self.tape, self.tape_pointer);
+        let step = stride.unsigned_abs();
+        let found = match stride {
+            1 => C::find_zero(&tape[pointer..]).map(|i| pointer + i),
+            -1 => C::rfind_zero(&tape[..=pointer]),
+            _ if stride > 0 => tape[pointer..]
+                .iter()
+                .step_by(step)
+                .position(Cell::is_zero)
+                .map(|i| pointer + i * step),
+            _ => tape[..=pointer]
+                .iter()
+                .rev()
+                .step_by(step)
+                .position(Cell::is_zero)
+                .map(|i| pointer - i * step),
+        };
+
+        if let Some(index) = found {
+            self.tape_pointer = index;
+            return Ok(());
+        }
+
+        let cells = if stride > 0 {
+            tape.len() - pointer
+        } else {
+            pointer + 1
+        };
+        let offset = (cells.div_ceil(step) * step).cast_signed() * stride.signum();
+        self.grow_tape(offset, offset)?;
+        self.move_tape_pointer(offset);
+        Ok(())
+    }
+
+    /// Grow the tape so that the cells from `lowest` to `highest` cells away from the tape pointer
+    /// are on it, doubling its size as many times as needed so that growing it one cell at a time
+    /// doesn't copy it every time.
+    ///
+    /// Cells added before the start of the tape shift the tape pointer along with the cells it
+    /// points to. An error is returned if cells would have to be added to an end of the tape that
+    /// doesn't grow, or if the tape would grow past [`MAX_TAPE_SIZE`] cells.
+    fn grow_tape(&mut self, lowest: isize, highest: isize) -> Result<()> {
+        let len = self.tape.len();
+        let before = if lowest < 0 {
+            lowest.unsigned_abs().saturating_sub(self.tape_pointer)
+        } else {
+            0
+        };
+        let after = self
+            .tape_pointer
+            .checked_add_signed(highest)
+            .map_or(0, |index| (index + 1).saturating_sub(len));
+        if before == 0 && after == 0 {
+            return Ok(());
+        }
+        if before > 0 && matches!(self.tape_model, TapeModel::Bounded | TapeModel::Grow) {
+            return Err(self.error("The program moved past the left end of the tape"));
+        }
+        if self.tape_model == TapeModel::Bounded {
+            return Err(self.error("The program moved past the right end of the tape"));
+        }
+
+        let needed = len + before + after;
+        if needed > MAX_TAPE_SIZE {
+            return Err(anyhow!("The tape grew past {MAX_TAPE_SIZE} cells"));
+        }
+        let mut new_len = len;
+        while new_len < needed {
+            new_len *= 2;
+        }
+        let new_len = new_len.min(MAX_TAPE_SIZE);
 
-    program: Vec<char>,
-    program_pointer: usize,
+        // The spare cells go on the side that grew, or before the start if both did.
+        let start = if before > 0 { new_len - len - after } else { 0 };
+        let mut tape = vec![C::default(); new_len].into_boxed_slice();
+        tape[start..start + len].clone_from_slice(&self.tape);
+        self.tape = tape.into();
+        self.tape_pointer += start;
+        for cells in &mut self.read_only {
+            *cells = cells.start + start..cells.end + start;
+        }
+        Ok(())
+    }
+}
 
-    /// Precomputed bracket map, calculated on every call to `run`.
-    ///
-    /// This maps starting brackets to their corresponding ending brackets, and vice versa. For
-    /// example: if a starting bracket at index 1 has its closing bracket at index 3, the index of
-    /// the closing bracket can be retrieved by `bracket_map[1]`, and the index of the starting
-    /// bracket at `bracket_map[3]`.
-    ///
-    /// This exists for both convenience and performance, as it allows for O(1) loop indexing during
-    /// runtime while only requiring one line.
-    bracket_map: Vec<usize>,
+/// Write a cell to `output`, as executed by `.`, flushing it when `io` says so.
+pub(crate) fn write_cell<C: Cell>(
+    output: &mut (impl Write + ?Sized),
+    cell: &C,
+    io: IoMode,
+) -> Result<()> {
+    let newline = match io.format {
+        IoFormat::Bytes => {
+            let byte = cell.low_byte();
+            output.write_all(&[byte])?;
+            byte == b'\n'
+        }
+        IoFormat::Numeric => {
+            writeln!(output, "{cell}")?;
+            true
+        }
+    };
+    if io.flush == Flush::Every || io.flush == Flush::Line && newline {
+        output.flush()?;
+    }
+    Ok(())
 }
 
-impl Interpreter {
-    /// Return a new, empty interpreter.
-    pub fn new() -> Interpreter {
-        Interpreter {
-            tape: [0; TAPE_SIZE],
-            tape_pointer: 0,
+/// Read a cell from `input`, as executed by `,`, or return `None` if the input has run out.
+pub(crate) fn read_cell(input: &mut (impl Read + ?Sized)) -> Result<Option<u8>> {
+    let mut value = [0];
+    loop {
+        match input.read(&mut value) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(value[0])),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error).context("Failed to read character from the input"),
+        }
+    }
+}
 
-            program: Vec::new(),
-            program_pointer: 0,
+/// Read a cell from `input` into `cell`, as executed by `,`, doing what `io` says once the input
+/// has run out. Unless the output is only flushed at the end, `output` is flushed first.
+pub(crate) fn read_into<C: Cell>(
+    cell: &mut C,
+    input: &mut (impl Read + ?Sized),
+    output: &mut (impl Write + ?Sized),
+    io: IoMode,
+) -> Result<()> {
+    if io.flush != Flush::End {
+        output.flush()?;
+    }
+    let value = match io.format {
+        IoFormat::Bytes => read_cell(input)?.map(C::from_byte),
+        IoFormat::Numeric => read_number(input)?,
+    };
+    match (value, io.eof) {
+        (Some(value), _) => *cell = value,
+        (None, Eof::Zero) => *cell = C::default(),
+        (None, Eof::MinusOne) => cell.set_value(-1),
+        (None, Eof::Unchanged) => {}
+        (None, Eof::Error) => bail!("The program read past the end of its input"),
+    }
+    Ok(())
+}
 
-            bracket_map: Vec::new(),
+/// Read a decimal number from `input` into a cell, wrapped around to fit, skipping the whitespace
+/// before it along with the byte after it. Returns `None` if the input runs out before the number.
+fn read_number<C: Cell>(input: &mut (impl Read + ?Sized)) -> Result<Option<C>> {
+    let mut byte = loop {
+        match read_cell(input)? {
+            None => return Ok(None),
+            Some(byte) if byte.is_ascii_whitespace() => {}
+            Some(byte) => break Some(byte),
         }
+    };
+    let negative = byte == Some(b'-');
+    if negative {
+        byte = read_cell(input)?;
     }
 
-    /// Return an empty interpreter with a program preloaded.
-    pub fn from_program_str(program: &str) -> Interpreter {
-        let mut interpreter = Interpreter::new();
-        interpreter.program = program.chars().collect();
-        interpreter
+    let mut cell = C::default();
+    let mut digits = 0;
+    loop {
+        match byte {
+            Some(digit @ b'0'..=b'9') => {
+                let mut shifted = C::default();
+                shifted.mul_add(&cell, 10);
+                shifted.add_amount(i32::from(digit - b'0'));
+                cell = shifted;
+                digits += 1;
+            }
+            Some(byte) if !byte.is_ascii_whitespace() => {
+                bail!("Expected a number in the input, found `{}`", byte as char);
+            }
+            _ if digits == 0 => bail!("Expected a number in the input after `-`"),
+            _ => break,
+        }
+        byte = read_cell(input)?;
     }
 
-    /// Run the program.
-    ///
-    /// This locks stdin and stdout until execution finishes.
-    ///
-    /// An error is returned:
-    ///
-    /// - Immediately, if there is an unmatched loop bracket
-    /// - At runtime, if unable to read from stdin or write to stdout
-    pub fn run(&mut self) -> Result<()> {
-        self.compute_bracket_map()?;
-
-        let mut stdin = stdin().lock();
-        let mut stdout = stdout().lock();
+    if negative {
+        let mut negated = C::default();
+        negated.mul_add(&cell, -1);
+        cell = negated;
+    }
+    Ok(Some(cell))
+}
 
-        while self.program_pointer < self.program.len() {
-            let instruction = self.program[self.program_pointer];
-            self.execute_instruction(instruction, &mut stdin, &mut stdout)?;
-            self.program_pointer += 1;
+/// Execute `op` on `tape` with the tape pointer at `pointer`, wrapping around the ends of the tape,
+/// and setting `program_pointer` to the target of a jump taken.
+///
+/// The cells are a plain slice here, so that a loop over the ops borrows them from the interpreter
+/// once instead of for every cell. [`Op::SwitchTape`] and [`Op::Memoize`] need the rest of the
+/// interpreter, and do nothing here.
+// Left out of line, the call and its result for every op more than double the run time.
+#[allow(clippy::inline_always)]
+#[inline(always)]
+fn execute_wrapping<C: Cell>(
+    op: Op,
+    tape: &mut [C],
+    pointer: &mut usize,
+    program_pointer: &mut usize,
+    io: IoMode,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<()> {
+    let index = |offset| wrapping_index(*pointer, offset, tape.len());
+    match op {
+        Op::Add { offset, amount } => tape[index(offset)].add_amount(amount),
+        Op::Move(amount) => *pointer = index(amount),
+        Op::SetZero { offset } => tape[index(offset)] = C::default(),
+        Op::ClearRange { offset, len } => clear_range(tape, index(offset), len),
+        Op::Set { offset, value } => tape[index(offset)].set_value(value),
+        Op::MulAdd { offset, factor } => {
+            let current = tape[*pointer].clone();
+            tape[index(offset)].mul_add(&current, factor);
         }
+        Op::Scan(stride) => *pointer = scan(tape, *pointer, stride),
 
-        Ok(())
+        Op::JumpIfZero(target) if tape[*pointer].is_zero() => *program_pointer = target,
+        Op::JumpIfNonZero(target) if !tape[*pointer].is_zero() => *program_pointer = target,
+        Op::JumpIfZero(_) | Op::JumpIfNonZero(_) | Op::SwitchTape(_) | Op::Memoize => {}
+
+        Op::Output { offset } => write_cell(output, &tape[index(offset)], io)?,
+        Op::Input { offset } => read_into(&mut tape[index(offset)], input, output, io)?,
     }
 
-    /// Execute a single instruction.
-    ///
-    /// An error is returned if:
-    ///
-    /// - The instruction is `.`, and writing to stdout fails
-    /// - The instruction is `,`, and reading from stdin fails
-    fn execute_instruction(
-        &mut self,
-        instruction: char,
-        stdin: &mut StdinLock,
-        stdout: &mut StdoutLock,
-    ) -> Result<()> {
-        let tape_val = &mut self.tape[self.tape_pointer];
+    Ok(())
+}
 
-        match instruction {
-            '>' => self.tape_pointer = (self.tape_pointer + 1) % TAPE_SIZE,
-            '<' => self.tape_pointer = (self.tape_pointer + TAPE_SIZE - 1) % TAPE_SIZE,
+/// Set `len` cells of `tape` to zero, starting at `start` and wrapping around the end of the tape.
+pub(crate) fn clear_range<C: Cell>(tape: &mut [C], start: usize, len: usize) {
+    let end = start + len.min(tape.len());
+    if let Some(wrapped) = end.checked_sub(tape.len()) {
+        tape[start..].fill(C::default());
+        tape[..wrapped].fill(C::default());
+    } else {
+        tape[start..end].fill(C::default());
+    }
+}
 
-            '+' => *tape_val = tape_val.wrapping_add(1),
-            '-' => *tape_val = tape_val.wrapping_sub(1),
+/// Return the index of the first zero cell found by repeatedly moving `stride` cells away from
+/// `pointer`, starting with the cell at `pointer` itself.
+///
+/// If every cell visited while going around the tape once is non-zero, `pointer` is returned
+/// unchanged.
+pub(crate) fn scan<C: Cell>(tape: &[C], pointer: usize, stride: isize) -> usize {
+    match stride {
+        // Single-cell strides are by far the most common, and can search the raw tape.
+        1 => C::find_zero(&tape[pointer..])
+            .map(|i| pointer + i)
+            .or_else(|| C::find_zero(&tape[..pointer])),
+        -1 => C::rfind_zero(&tape[..=pointer])
+            .or_else(|| C::rfind_zero(&tape[pointer..]).map(|i| pointer + i)),
 
-            '[' if *tape_val == 0 => self.program_pointer = self.bracket_map[self.program_pointer],
-            ']' if *tape_val != 0 => self.program_pointer = self.bracket_map[self.program_pointer],
+        _ => scan_strided(tape, pointer, stride),
+    }
+    .unwrap_or(pointer)
+}
 
-            '.' => {
-                write!(stdout, "{}", *tape_val as char)?;
-                stdout.flush()?;
-            }
-            ',' => {
-                *tape_val = stdin
-                    .bytes()
-                    .next()
-                    .context("Failed to read character from stdin")??;
-            }
+/// Scan for [`scan`] with a stride of more than one cell.
+///
+/// The tape is searched a lap at a time, up to where the stride wraps around the end of the tape,
+/// so each cell only takes a plain indexed load instead of wrapping arithmetic.
+fn scan_strided<C: Cell>(tape: &[C], pointer: usize, stride: isize) -> Option<usize> {
+    let len = tape.len();
+    let step = stride.unsigned_abs() % len;
+    if step == 0 {
+        return tape[pointer].is_zero().then_some(pointer);
+    }
 
-            _ => {}
+    let mut index = pointer;
+    let mut visited = 0;
+    // Every cell the scan can reach has been visited once `len` cells have, since the cells it
+    // visits repeat with a period of at most `len`.
+    while visited < len {
+        let (found, count) = if stride > 0 {
+            let cells = tape[index..].iter().step_by(step);
+            let found = cells.clone().position(Cell::is_zero);
+            (found.map(|i| index + i * step), cells.len())
+        } else {
+            let cells = tape[..=index].iter().rev().step_by(step);
+            let found = cells.clone().position(Cell::is_zero);
+            (found.map(|i| index - i * step), cells.len())
+        };
+        if found.is_some() {
+            return found;
         }
 
-        Ok(())
+        visited += count;
+        index = if stride > 0 {
+            index + count * step - len
+        } else {
+            index + len - count * step
+        };
     }
 
-    /// Compute the loop bracket map.
-    ///
-    /// An error is returned if there is an unmatched bracket.
-    fn compute_bracket_map(&mut self) -> Result<()> {
-        self.bracket_map = vec![0; self.program.len()];
-        let mut stack = Vec::new();
+    None
+}
 
-        for (i, &ch) in self.program.iter().enumerate() {
-            match ch {
-                '[' => stack.push(i),
-                ']' => {
-                    if let Some(open_index) = stack.pop() {
-                        self.bracket_map[open_index] = i;
-                        self.bracket_map[i] = open_index;
-                    } else {
-                        return Err(anyhow!("Unmatched ] at {i}"));
-                    }
-                }
-                _ => {}
-            }
-        }
+/// Return the index `offset` cells away from `index` on a tape of length `len`, wrapping around
+/// the ends of the tape.
+// Most ops go through this, so it has to be inlined into the engines monomorphized downstream.
+#[inline]
+pub(crate) fn wrapping_index(index: usize, offset: isize, len: usize) -> usize {
+    let distance = offset.unsigned_abs();
+    if distance >= len {
+        return wrapping_index(index, offset % len.cast_signed(), len);
+    }
 
-        Ok(())
+    if offset < 0 {
+        index
+            .checked_sub(distance)
+            .unwrap_or(index + len - distance)
+    } else if index + distance >= len {
+        index + distance - len
+    } else {
+        index + distance
     }
 }
 
+/// Reduce an amount to add modulo 256, so it can be applied to a cell with a wrapping add.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn wrap_to_cell(amount: i32) -> u8 {
+    amount as u8
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     fn run(program: &str) -> Interpreter {
-        let mut interpreter = Interpreter::from_program_str(program);
+        let mut interpreter = Interpreter::from_program_str(program).unwrap();
         interpreter.run().unwrap();
         interpreter
     }
@@ -193,10 +1674,710 @@ mod tests {
         assert_eq!(interpreter.tape[0], 0);
     }
 
+    #[test]
+    fn tape_size() {
+        let mut interpreter = Interpreter::from_program_str(&format!("<+>{}+", ">".repeat(65)))
+            .unwrap()
+            .with_tape_size(MIN_TAPE_SIZE);
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.tape.len(), MIN_TAPE_SIZE);
+        assert_eq!(interpreter.tape[MIN_TAPE_SIZE - 1], 1);
+        assert_eq!(interpreter.tape[1], 1);
+        assert_eq!(interpreter.tape_pointer, 1);
+    }
+
+    fn run_growing(tape_model: TapeModel, program: &str, tape: &[u8]) -> Result<Interpreter> {
+        let mut interpreter = Interpreter::from_program_str(program)
+            .unwrap()
+            .with_tape_size(MIN_TAPE_SIZE)
+            .with_tape_model(tape_model);
+        interpreter.tape[..tape.len()].copy_from_slice(tape);
+        interpreter.run_with(&mut &[1][..], &mut Vec::new())?;
+        Ok(interpreter)
+    }
+
+    #[test]
+    fn grow_tape() {
+        let interpreter =
+            run_growing(TapeModel::Grow, &format!("{}+<+", ">".repeat(70)), &[]).unwrap();
+        assert_eq!(interpreter.tape.len(), 2 * MIN_TAPE_SIZE);
+        assert_eq!(interpreter.tape[69..72], [1, 1, 0]);
+        assert_eq!(interpreter.tape[..2], [0, 0]);
+        assert_eq!(interpreter.tape_pointer, 69);
+
+        let interpreter =
+            run_growing(TapeModel::Grow, &format!("{}+", ">".repeat(300)), &[]).unwrap();
+        assert_eq!(interpreter.tape.len(), 512);
+        assert_eq!(interpreter.tape[300], 1);
+
+        assert!(run_growing(TapeModel::Grow, "<", &[]).is_err());
+        assert!(run_growing(TapeModel::Grow, ">+<<+", &[]).is_err());
+        // The multiply loop reaches past the left end, but never runs.
+        assert!(run_growing(TapeModel::Grow, ",-[-<+>]", &[]).is_ok());
+        assert!(run_growing(TapeModel::Grow, ",[-<+>]", &[]).is_err());
+    }
+
+    #[test]
+    fn bounded_tape() {
+        let interpreter = run_growing(TapeModel::Bounded, &format!("{}+", ">".repeat(63)), &[]);
+        assert_eq!(interpreter.unwrap().tape.len(), MIN_TAPE_SIZE);
+
+        // The move is folded into the offset of the add after it, which is what reaches past the
+        // end of the tape.
+        let error = run_growing(TapeModel::Bounded, "+<+", &[]).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "The program moved past the left end of the tape at 2"
+        );
+        let error = run_growing(TapeModel::Bounded, &format!("{}+", ">".repeat(64)), &[]);
+        assert_eq!(
+            error.err().unwrap().to_string(),
+            "The program moved past the right end of the tape at 64"
+        );
+
+        let ones = [1; MIN_TAPE_SIZE];
+        assert!(run_growing(TapeModel::Bounded, ",[>]", &ones[..10]).is_ok());
+        assert!(run_growing(TapeModel::Bounded, ",[>]", &ones).is_err());
+    }
+
+    #[test]
+    fn scan_loops_grow() {
+        // Each scan starts after reading a 1, so that the optimizer doesn't know which cells of the
+        // tape are zero.
+        let ones = [1; MIN_TAPE_SIZE];
+        let interpreter = run_growing(TapeModel::Grow, ",[>]", &ones[..MIN_TAPE_SIZE - 1]).unwrap();
+        assert_eq!(interpreter.tape_pointer, MIN_TAPE_SIZE - 1);
+        assert_eq!(interpreter.tape.len(), MIN_TAPE_SIZE);
+
+        let interpreter = run_growing(TapeModel::Grow, ",[>]", &ones).unwrap();
+        assert_eq!(interpreter.tape_pointer, MIN_TAPE_SIZE);
+        assert_eq!(interpreter.tape.len(), 2 * MIN_TAPE_SIZE);
+
+        // The scan lands on the first new cell it visits, past the end of the tape.
+        let interpreter = run_growing(TapeModel::Grow, ",[>>>]", &ones).unwrap();
+        assert_eq!(interpreter.tape_pointer, 66);
+
+        let interpreter = run_growing(TapeModel::Grow, ">>>>>,[<<]", &ones[..3]).unwrap();
+        assert_eq!(interpreter.tape_pointer, 3);
+
+        assert!(run_growing(TapeModel::Grow, ",[<]", &[]).is_err());
+        assert!(run_growing(TapeModel::Grow, ">,[<<]", &ones).is_err());
+    }
+
+    #[test]
+    fn infinite_tape() {
+        let interpreter = run_growing(TapeModel::Infinite, "<+<<+", &[]).unwrap();
+        assert_eq!(interpreter.tape.len(), 2 * MIN_TAPE_SIZE);
+        let pointer = interpreter.tape_pointer;
+        assert_eq!(interpreter.tape[pointer..pointer + 4], [1, 0, 1, 0]);
+
+        // The cells right of the start stay where they are relative to it.
+        let interpreter = run_growing(TapeModel::Infinite, ">+<<<<+", &[]).unwrap();
+        let pointer = interpreter.tape_pointer;
+        assert_eq!(interpreter.tape[pointer..pointer + 5], [1, 0, 0, 0, 1]);
+
+        let ones = [1; MIN_TAPE_SIZE];
+        let interpreter = run_growing(TapeModel::Infinite, ",[<]", &ones).unwrap();
+        assert_eq!(interpreter.tape.len(), 2 * MIN_TAPE_SIZE);
+        assert_eq!(interpreter.tape_pointer, MIN_TAPE_SIZE - 1);
+        assert_eq!(interpreter.tape[MIN_TAPE_SIZE..], ones);
+
+        let interpreter = run_growing(TapeModel::Infinite, &format!("{}+", "<".repeat(300)), &[]);
+        assert_eq!(interpreter.unwrap().tape.len(), 512);
+    }
+
+    fn run_overflowing(overflow: Overflow, program: &str) -> Result<Interpreter> {
+        let mut interpreter =
+            Interpreter::from_program(ir::compile(program).unwrap()).with_overflow(overflow);
+        interpreter.run_with(&mut &[][..], &mut Vec::new())?;
+        Ok(interpreter)
+    }
+
+    #[test]
+    fn saturate_cells() {
+        let program = format!("->{}[->++++++++++<]>-", "+".repeat(26));
+        let interpreter = run_overflowing(Overflow::Saturate, &program).unwrap();
+        assert_eq!(interpreter.tape[..3], [0, 0, 254]);
+
+        let interpreter = run_overflowing(Overflow::Saturate, &"+".repeat(300)).unwrap();
+        assert_eq!(interpreter.tape[0], 255);
+    }
+
+    #[test]
+    fn overflow_error() {
+        let error = run_overflowing(Overflow::Error, ">\n-").err().unwrap();
+        assert_eq!(error.to_string(), "Cell overflowed at 2");
+
+        let program = format!("[-]{}", "+".repeat(256));
+        let error = run_overflowing(Overflow::Error, &program).err().unwrap();
+        assert_eq!(error.to_string(), "Cell overflowed at 258");
+
+        // Ops without spans are pointed at by their index.
+        let mut interpreter =
+            Interpreter::from_ops(ir::compile("+--").unwrap().ops).with_overflow(Overflow::Error);
+        let error = interpreter
+            .run_with(&mut &[][..], &mut Vec::new())
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "Cell overflowed at op 2");
+        assert!(run_overflowing(Overflow::Error, &"+".repeat(255)).is_ok());
+    }
+
+    #[test]
+    fn tape_file() {
+        let path = std::env::temp_dir().join(format!("beef-tape-{}.bin", std::process::id()));
+        let run_on_file = |program: &str| -> Result<()> {
+            Interpreter::from_program_str(program)
+                .unwrap()
+                .with_tape_size(MIN_TAPE_SIZE)
+                .with_tape_file(&path)?
+                .run_with(&mut &[][..], &mut Vec::new())
+        };
+
+        // The tape persists from one run to the next.
+        run_on_file("+>++").unwrap();
+        run_on_file("+<+").unwrap();
+        let mut expected = vec![0; MIN_TAPE_SIZE];
+        expected[..2].copy_from_slice(&[2, 2]);
+        expected[MIN_TAPE_SIZE - 1] = 1;
+        assert_eq!(std::fs::read(&path).unwrap(), expected);
+
+        std::fs::write(&path, [1; 10]).unwrap();
+        assert!(run_on_file("+").is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        // Wider cells are refused before the file is created.
+        assert!(Interpreter::new()
+            .with_cells::<u16>()
+            .with_tape_file(&path)
+            .is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn nonzero_cells() {
+        let mut interpreter = Interpreter::from_program_str("+>>+++<").unwrap();
+        interpreter.run_collect(&[]).unwrap();
+        assert_eq!(interpreter.nonzero_cells(), (vec![(0, 1), (2, 3)], 1));
+
+        let mut sparse = Interpreter::from_program_str("+<<++>")
+            .unwrap()
+            .with_tape_model(TapeModel::Sparse);
+        sparse.run_collect(&[]).unwrap();
+        assert_eq!(sparse.nonzero_cells(), (vec![(-2, 2), (0, 1)], -1));
+    }
+
+    #[test]
+    fn load_programs() {
+        let mut interpreter = Interpreter::from_program_str("++>+").unwrap();
+        interpreter.run_collect(&[]).unwrap();
+        interpreter.load(ir::compile("[-<+>]<.").unwrap());
+        assert_eq!(interpreter.run_collect(&[]).unwrap(), [3]);
+        assert_eq!(interpreter.nonzero_cells(), (vec![(0, 3)], 0));
+    }
+
+    #[test]
+    fn tape_contents() {
+        let program = optimizer::optimize_on(
+            ir::compile("[.>]").unwrap(),
+            optimizer::MAX_LEVEL,
+            optimizer::StartingTape::Preloaded,
+        )
+        .0;
+        let mut interpreter = Interpreter::from_program(program)
+            .with_tape_contents(b"Hi")
+            .unwrap();
+        assert_eq!(interpreter.run_collect_string(&[]).unwrap(), "Hi");
+
+        let sparse = Interpreter::new()
+            .with_tape_model(TapeModel::Sparse)
+            .with_tape_contents(&[0, 5])
+            .unwrap();
+        assert!(sparse.sparse.is_zero(0) && !sparse.sparse.is_zero(1));
+
+        let error = Interpreter::new()
+            .with_tape_size(MIN_TAPE_SIZE)
+            .with_tape_contents(&[1; MIN_TAPE_SIZE + 1])
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "The tape only has 64 cells, which can't hold 65 bytes"
+        );
+    }
+
+    #[test]
+    fn read_only_cells() {
+        let run_read_only = |program: &str, tape_model: TapeModel| {
+            let mut interpreter = Interpreter::from_program_str(program)
+                .unwrap()
+                .with_tape_model(tape_model)
+                .with_read_only(2..4)
+                .with_read_only(TAPE_SIZE - 1..TAPE_SIZE);
+            interpreter.run_with(&mut &[1][..], &mut Vec::new())
+        };
+
+        assert!(run_read_only("+>+>>>>+<<.[-]>[-]", TapeModel::Wrap).is_ok());
+        let error = run_read_only(">>>,", TapeModel::Wrap).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "The program wrote to read-only cell 3 at 3"
+        );
+        let error = run_read_only("+[>+<-]\n<+", TapeModel::Wrap).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!("The program wrote to read-only cell {} at 9", TAPE_SIZE - 1)
+        );
+
+        // The read-only cells move along with the rest when the tape grows before its start.
+        assert!(run_read_only("<+", TapeModel::Infinite).is_ok());
+        let error = run_read_only("<<+>>>>-", TapeModel::Infinite)
+            .err()
+            .unwrap();
+        assert!(error.to_string().ends_with(" at 7"));
+        let error = run_read_only("<<+>>>>+", TapeModel::Sparse).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "The program wrote to read-only cell 2 at 7"
+        );
+
+        // Clearing the cells only writes to them if they aren't zero already.
+        let mut interpreter = Interpreter::from_program_str("[-]>[-]>[-]>[-]")
+            .unwrap()
+            .with_read_only(2..4);
+        interpreter.tape[3] = 1;
+        let error = interpreter.run().err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "The program wrote to read-only cell 3 at 0"
+        );
+    }
+
+    #[test]
+    fn several_tapes() {
+        let run_on_tapes = |program: &str, count: usize, tape_model: TapeModel| {
+            let extensions = ir::Extensions {
+                tape_switch: Some(['{', '}']),
+            };
+            let program = optimizer::optimize(ir::compile_with(program, extensions).unwrap());
+            let mut interpreter = Interpreter::from_program(program)
+                .with_tape_model(tape_model)
+                .with_tapes(count)
+                .with_read_only(0..1);
+            let output = interpreter.run_collect(&[]).unwrap();
+            (interpreter, output)
+        };
+
+        // Every tape keeps its own tape pointer, and switching goes around past either end.
+        let program = ">+}++>+++}{{.<.{{.<.";
+        let (interpreter, output) = run_on_tapes(program, 3, TapeModel::Wrap);
+        assert_eq!(output, [1, 0, 3, 2]);
+        assert_eq!(interpreter.current_tape, 1);
+        assert_eq!(interpreter.tape_pointer, 0);
+        assert_eq!(interpreter.tapes[0].tape[1], 1);
+        let (_, output) = run_on_tapes(program, 3, TapeModel::Sparse);
+        assert_eq!(output, [1, 0, 3, 2]);
+
+        // Read-only cells are only on the first tape, and a single tape doesn't switch.
+        let (_, output) = run_on_tapes("}+.", 2, TapeModel::Wrap);
+        assert_eq!(output, [1]);
+        let (interpreter, output) = run_on_tapes(">}+.{.", 1, TapeModel::Infinite);
+        assert_eq!(output, [1, 1]);
+        assert!(interpreter.tapes.is_empty());
+    }
+
+    #[test]
+    fn run_with_io() {
+        let mut output = Vec::new();
+        let mut interpreter = Interpreter::from_program_str(",[.-]").unwrap();
+        interpreter.run_with(&mut &[3][..], &mut output).unwrap();
+        assert_eq!(output, [3, 2, 1]);
+
+        // The input and the jump into the loop, plus an output, a decrement and a jump back out
+        // of each iteration.
+        let mut output = Vec::new();
+        let mut interpreter = Interpreter::from_program_str(",[.-]").unwrap();
+        let steps = interpreter.run_counted(&mut &[3][..], &mut output).unwrap();
+        assert_eq!(steps, 11);
+        assert_eq!(output, [3, 2, 1]);
+
+        assert!(Interpreter::from_program_str(",")
+            .unwrap()
+            .with_eof(Eof::Error)
+            .run_with(&mut &[][..], &mut output)
+            .is_err());
+    }
+
+    #[test]
+    fn max_steps() {
+        let program = || Interpreter::from_program_str(",[.-]").unwrap();
+        let mut output = Vec::new();
+        let mut interpreter = program().with_max_steps(11);
+        interpreter.run_with(&mut &[3][..], &mut output).unwrap();
+        assert_eq!(interpreter.steps(), Some(11));
+
+        // The run stops before the op past the limit, on a loop that never ends too.
+        let mut output = Vec::new();
+        let mut interpreter = program().with_max_steps(10);
+        let error = interpreter
+            .run_with(&mut &[3][..], &mut output)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The program didn't finish within 10 steps, and was stopped at 4"
+        );
+        assert_eq!(interpreter.steps(), Some(10));
+        assert_eq!(output, [3, 2, 1]);
+        let mut interpreter = Interpreter::from_program_str("+[]")
+            .unwrap()
+            .with_max_steps(1000);
+        assert!(interpreter.run_collect(&[]).is_err());
+    }
+
+    #[test]
+    fn timeout() {
+        let mut interpreter = Interpreter::from_program_str("+[>+<]")
+            .unwrap()
+            .with_timeout(Duration::from_millis(10));
+        let start = Instant::now();
+        let error = interpreter.run_collect(&[]).unwrap_err();
+        assert!(start.elapsed() >= Duration::from_millis(10));
+        assert_eq!(
+            error.to_string(),
+            "The program didn't finish within 10ms, and was stopped at 3"
+        );
+        // The state the program was stopped in stays behind.
+        assert_eq!(interpreter.tape[0], 1);
+        assert!(interpreter.steps().unwrap() > 0);
+
+        let mut interpreter = Interpreter::from_program_str("+.")
+            .unwrap()
+            .with_timeout(Duration::from_secs(10));
+        assert_eq!(interpreter.run_collect(&[]).unwrap(), [1]);
+    }
+
+    #[test]
+    fn publish_progress() {
+        let progress = Arc::new(AtomicU64::new(0));
+        let mut interpreter = Interpreter::from_program_str("++++[-]")
+            .unwrap()
+            .with_progress(Arc::clone(&progress));
+        interpreter.run_collect(&[]).unwrap();
+        // A run that isn't counted doesn't publish anything.
+        assert_eq!(progress.load(Ordering::Relaxed), 0);
+
+        interpreter.load(ir::compile("++++[-]").unwrap());
+        interpreter
+            .run_counted(&mut io::empty(), &mut io::sink())
+            .unwrap();
+        assert_eq!(progress.load(Ordering::Relaxed), 13);
+    }
+
+    #[test]
+    fn end_of_input() {
+        fn run_to_eof<C: Cell>(eof: Eof) -> Result<C> {
+            let mut interpreter = Interpreter::from_program_str("+,,")
+                .unwrap()
+                .with_cells::<C>()
+                .with_eof(eof);
+            interpreter.run_with(&mut &[5][..], &mut Vec::new())?;
+            Ok(interpreter.tape[0].clone())
+        }
+
+        assert_eq!(run_to_eof::<u8>(Eof::Zero).unwrap(), 0);
+        assert_eq!(run_to_eof::<u8>(Eof::MinusOne).unwrap(), 255);
+        assert_eq!(run_to_eof::<u16>(Eof::MinusOne).unwrap(), u16::MAX);
+        assert_eq!(run_to_eof::<u8>(Eof::Unchanged).unwrap(), 5);
+        assert_eq!(
+            run_to_eof::<u8>(Eof::Error).unwrap_err().to_string(),
+            "The program read past the end of its input"
+        );
+    }
+
+    #[test]
+    fn collect_output() {
+        let mut interpreter = Interpreter::from_program_str(",[.-]").unwrap();
+        assert_eq!(interpreter.run_collect(&[3]).unwrap(), [3, 2, 1]);
+
+        let mut interpreter = Interpreter::from_program_str("++++++++[>++++++++<-]>+.+.").unwrap();
+        assert_eq!(interpreter.run_collect_string(&[]).unwrap(), "AB");
+
+        // Bytes are written as they are, so UTF-8 comes out whole but a lone 255 isn't text.
+        let mut interpreter = Interpreter::from_program_str(",.,.").unwrap();
+        assert_eq!(interpreter.run_collect_string("é".as_bytes()).unwrap(), "é");
+        let mut interpreter = Interpreter::from_program_str("-.").unwrap();
+        assert_eq!(interpreter.run_collect(&[]).unwrap(), [255]);
+        let mut interpreter = Interpreter::from_program_str("-.").unwrap();
+        assert!(interpreter.run_collect_string(&[]).is_err());
+    }
+
+    #[test]
+    fn binary_io() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let mut interpreter = Interpreter::from_program_str(",[.,]")
+            .unwrap()
+            .with_eof(Eof::Zero);
+        assert_eq!(interpreter.run_collect(&bytes[1..]).unwrap(), bytes[1..]);
+
+        // Zero bytes and newlines go through too, which a program sees by counting them.
+        let mut interpreter = Interpreter::from_program_str(",.,.,.,.").unwrap();
+        assert_eq!(
+            interpreter.run_collect(b"\0\r\n\xff").unwrap(),
+            b"\0\r\n\xff"
+        );
+    }
+
+    #[test]
+    fn run_with_owned_io() {
+        let mut output = Vec::new();
+        let mut interpreter = Interpreter::from_program_str(",.,.,.").unwrap();
+        interpreter
+            .run_with_io((&[1][..]).chain(&[2, 3][..]), &mut output)
+            .unwrap();
+        assert_eq!(output, [1, 2, 3]);
+    }
+
+    #[test]
+    fn numeric_io() {
+        fn run_numeric<C: Cell>(program: &str, input: &str) -> Result<String> {
+            Interpreter::from_program_str(program)
+                .unwrap()
+                .with_cells::<C>()
+                .with_io_format(IoFormat::Numeric)
+                .run_collect_string(input.as_bytes())
+        }
+
+        assert_eq!(
+            run_numeric::<u8>(",>,[-<+>]<.", " 12\n30 ").unwrap(),
+            "42\n"
+        );
+        assert_eq!(run_numeric::<u8>(",.", "-1").unwrap(), "255\n");
+        assert_eq!(run_numeric::<u8>(",.", "300").unwrap(), "44\n");
+        assert_eq!(run_numeric::<i16>(",.", "-300").unwrap(), "-300\n");
+        assert_eq!(
+            run_numeric::<BigCell>(",+.", "99999999999999999999").unwrap(),
+            "100000000000000000000\n"
+        );
+
+        // The input runs out before a number like it does before a byte.
+        assert_eq!(run_numeric::<u8>("+,.", "  ").unwrap(), "1\n");
+        assert_eq!(
+            run_numeric::<u8>(",", "12a").unwrap_err().to_string(),
+            "Expected a number in the input, found `a`"
+        );
+        assert!(run_numeric::<u8>(",", "-").is_err());
+    }
+
+    #[test]
+    fn flush_output() {
+        /// A writer that counts how many times it is flushed.
+        #[derive(Default)]
+        struct Flushes(usize);
+
+        impl Write for Flushes {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                self.0 += 1;
+                Ok(())
+            }
+        }
+
+        let count_flushes = |flush| {
+            let mut output = Flushes::default();
+            Interpreter::from_program_str("+.>++++++++++.<.,")
+                .unwrap()
+                .with_flush(flush)
+                .run_with(&mut &[][..], &mut output)
+                .unwrap();
+            output.0
+        };
+        assert_eq!(count_flushes(Flush::Every), 4);
+        // The newline, and the input after the last byte.
+        assert_eq!(count_flushes(Flush::Line), 2);
+        assert_eq!(count_flushes(Flush::End), 0);
+    }
+
+    #[test]
+    fn run_in_steps() {
+        let program = "+++++[>+++.<-]>.";
+        let mut expected_output = Vec::new();
+        let mut expected = Interpreter::from_program_str(program).unwrap();
+        expected
+            .run_with(&mut &[][..], &mut expected_output)
+            .unwrap();
+
+        for steps in [1, 2, 7] {
+            let mut output = Vec::new();
+            let mut interpreter = Interpreter::from_program_str(program).unwrap();
+            let mut calls = 0;
+            while !interpreter
+                .run_budget_with(steps, &mut &[][..], &mut output)
+                .unwrap()
+            {
+                calls += 1;
+            }
+            assert!(calls > 1, "{steps}");
+            assert_eq!(interpreter.tape, expected.tape, "{steps}");
+            assert_eq!(output, expected_output, "{steps}");
+
+            // Finished programs stay finished.
+            assert!(interpreter
+                .run_budget_with(steps, &mut &[][..], &mut output)
+                .unwrap());
+        }
+
+        let mut interpreter = Interpreter::from_program_str("+").unwrap();
+        assert!(!interpreter.run_budget(0).unwrap());
+        assert!(interpreter.run_budget(1).unwrap());
+        assert!(Interpreter::from_program_str("")
+            .unwrap()
+            .run_budget(0)
+            .unwrap());
+    }
+
+    #[test]
+    fn step_through_source() {
+        let mut interpreter = Interpreter::from_program(ir::compile("+ >").unwrap());
+        assert_eq!(interpreter.span(), Some(Span { start: 0, end: 1 }));
+        interpreter
+            .run_budget_with(1, &mut &[][..], &mut Vec::new())
+            .unwrap();
+        assert_eq!(interpreter.span(), Some(Span { start: 2, end: 3 }));
+        interpreter
+            .run_budget_with(1, &mut &[][..], &mut Vec::new())
+            .unwrap();
+        assert_eq!(interpreter.span(), None);
+
+        assert!(interpreter.set_cell(1, 7));
+        assert!(!interpreter.set_cell(TAPE_SIZE, 7));
+        assert_eq!(interpreter.tape()[..2], [1, 7]);
+    }
+
     #[test]
     fn unmatched_loop_error() {
-        let mut interpreter = Interpreter::from_program_str("]");
-        assert!(interpreter.run().is_err());
+        assert!(Interpreter::from_program_str("]").is_err());
+    }
+
+    #[test]
+    fn folded_runs_wrap() {
+        let interpreter = run(&"+".repeat(300));
+        assert_eq!(interpreter.tape[0], 44);
+
+        let interpreter = run(&format!("{}>+", "<".repeat(TAPE_SIZE + 2)));
+        assert_eq!(interpreter.tape_pointer, TAPE_SIZE - 1);
+        assert_eq!(interpreter.tape[TAPE_SIZE - 1], 1);
+    }
+
+    #[test]
+    fn clear_loops() {
+        let interpreter = run("+++[-]>-[+]>++[---]");
+        assert_eq!(interpreter.tape[0], 0);
+        assert_eq!(interpreter.tape[1], 0);
+        assert_eq!(interpreter.tape[2], 0);
+    }
+
+    #[test]
+    fn multiply_loops() {
+        let interpreter = run("+++++[->++>---<<]");
+        assert_eq!(interpreter.tape[0], 0);
+        assert_eq!(interpreter.tape[1], 10);
+        assert_eq!(interpreter.tape[2], 241);
+    }
+
+    #[test]
+    fn multiply_loops_wrap() {
+        // 200 * 2 = 400, which wraps to 144.
+        let interpreter = run(&format!("{}[->++<]", "+".repeat(200)));
+        assert_eq!(interpreter.tape[1], 144);
+
+        // Counting up from 1 takes 255 iterations, and 255 * 2 wraps to 254.
+        let interpreter = run("+[>++<+]");
+        assert_eq!(interpreter.tape[0], 0);
+        assert_eq!(interpreter.tape[1], 254);
+
+        // The target may be on the other side of the tape.
+        let interpreter = run("++[-<+++>]");
+        assert_eq!(interpreter.tape[TAPE_SIZE - 1], 6);
+    }
+
+    #[test]
+    fn scan_loops() {
+        let interpreter = run("+>+>+>>+<<<<[>]");
+        assert_eq!(interpreter.tape_pointer, 3);
+
+        let interpreter = run(">>>+<+<+[<]");
+        assert_eq!(interpreter.tape_pointer, 0);
+
+        let interpreter = run("+>>+>>+>+<<<<<[>>]");
+        assert_eq!(interpreter.tape_pointer, 6);
+    }
+
+    #[test]
+    fn scan_strides() {
+        // Check against stepping through the tape one cell at a time.
+        let naive = |tape: &[u8], pointer, stride| {
+            let mut index = pointer;
+            (0..tape.len())
+                .find_map(|_| {
+                    let found = (tape[index] == 0).then_some(index);
+                    index = wrapping_index(index, stride, tape.len());
+                    found
+                })
+                .unwrap_or(pointer)
+        };
+
+        let mut tape = [1; 12];
+        for stride in [-13, -12, -5, -4, -3, -2, 2, 3, 4, 5, 12, 13] {
+            for zero in [None, Some(0), Some(5), Some(11)] {
+                tape.fill(1);
+                if let Some(zero) = zero {
+                    tape[zero] = 0;
+                }
+                for pointer in 0..tape.len() {
+                    assert_eq!(
+                        scan(&tape, pointer, stride),
+                        naive(&tape, pointer, stride),
+                        "stride {stride}, zero at {zero:?}, starting at {pointer}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scan_loops_wrap() {
+        let interpreter = run(">+<<+[<]");
+        assert_eq!(interpreter.tape_pointer, TAPE_SIZE - 2);
+
+        let interpreter = run("<+>+[>]");
+        assert_eq!(interpreter.tape_pointer, 1);
+    }
+
+    #[test]
+    fn fused_offsets_wrap() {
+        let interpreter = run("<+<<++>>>>+++<<");
+        assert_eq!(interpreter.tape[TAPE_SIZE - 1], 1);
+        assert_eq!(interpreter.tape[TAPE_SIZE - 3], 2);
+        assert_eq!(interpreter.tape[1], 3);
+        assert_eq!(interpreter.tape_pointer, TAPE_SIZE - 1);
+    }
+
+    #[test]
+    fn clear_ranges() {
+        let interpreter = run("+>+>+>+<<<<+<+[-]>[-]>[-]>[-]>[-]");
+        assert_eq!(interpreter.tape[TAPE_SIZE - 2..], [0, 0]);
+        assert_eq!(interpreter.tape[..4], [0, 0, 0, 1]);
+        assert_eq!(interpreter.tape_pointer, 2);
+    }
+
+    #[test]
+    fn set() {
+        let interpreter = run("+++[-]++>[-]-");
+        assert_eq!(interpreter.tape[0], 2);
+        assert_eq!(interpreter.tape[1], 255);
     }
 
     #[test]
@@ -0,0 +1,291 @@
+use std::{
+    fmt::{Debug, Display},
+    hash::Hash,
+};
+
+use memchr::{memchr, memrchr};
+
+mod big;
+
+pub use big::BigCell;
+
+/// The type of the cells on the tape.
+///
+/// Fixed-width cells, signed or not, wrap around on overflow, so every op behaves the same on any
+/// width of cells, modulo the width, while a [`BigCell`] never overflows. They are still read and
+/// written a byte at a time by `,` and `.`, unless they are read and written as decimal numbers,
+/// which [`Display`] writes.
+pub trait Cell: Clone + Default + Eq + Hash + Debug + Display + 'static {
+    /// Add `amount` to the cell, wrapping around on overflow if it has a fixed width.
+    fn add_amount(&mut self, amount: i32);
+
+    /// Add `amount` to the cell, stopping at its lowest or highest value instead of wrapping
+    /// around, and return whether it had to stop there.
+    fn saturating_add_amount(&mut self, amount: i32) -> bool;
+
+    /// Set the cell to `value`, wrapped around to fit.
+    fn set_value(&mut self, value: i32);
+
+    /// Add `current` times `factor` to the cell, wrapping around like [`Cell::add_amount`].
+    fn mul_add(&mut self, current: &Self, factor: i32);
+
+    /// Return whether the cell is zero.
+    fn is_zero(&self) -> bool;
+
+    /// Return the cell holding a byte read by `,`.
+    fn from_byte(byte: u8) -> Self;
+
+    /// Return the byte written by `.`, which is the lowest byte of the cell.
+    fn low_byte(&self) -> u8;
+
+    /// Return the index of the first zero cell in `cells`.
+    fn find_zero(cells: &[Self]) -> Option<usize> {
+        cells.iter().position(Cell::is_zero)
+    }
+
+    /// Return the index of the last zero cell in `cells`.
+    fn rfind_zero(cells: &[Self]) -> Option<usize> {
+        cells.iter().rposition(Cell::is_zero)
+    }
+
+    /// Return `bytes` as cells if cells are bytes, as they have to be for a tape backed by a file.
+    fn from_bytes(bytes: &[u8]) -> Option<&[Self]> {
+        let _ = bytes;
+        None
+    }
+
+    /// Return `bytes` as mutable cells if cells are bytes, like [`Cell::from_bytes`].
+    fn from_bytes_mut(bytes: &mut [u8]) -> Option<&mut [Self]> {
+        let _ = bytes;
+        None
+    }
+
+    /// Return the tape as bytes if its cells are bytes, as they have to be for native code.
+    #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+    fn as_bytes_mut(tape: &mut [Self]) -> Option<&mut [u8]> {
+        let _ = tape;
+        None
+    }
+}
+
+impl Cell for u8 {
+    fn add_amount(&mut self, amount: i32) {
+        *self = self.wrapping_add(super::wrap_to_cell(amount));
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn saturating_add_amount(&mut self, amount: i32) -> bool {
+        let sum = i32::from(*self).saturating_add(amount);
+        *self = sum.clamp(0, 255) as u8;
+        !(0..=255).contains(&sum)
+    }
+
+    fn set_value(&mut self, value: i32) {
+        *self = super::wrap_to_cell(value);
+    }
+
+    fn mul_add(&mut self, current: &u8, factor: i32) {
+        *self = self.wrapping_add(current.wrapping_mul(super::wrap_to_cell(factor)));
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+
+    fn from_byte(byte: u8) -> u8 {
+        byte
+    }
+
+    fn low_byte(&self) -> u8 {
+        *self
+    }
+
+    // Byte cells can search the raw tape.
+    fn find_zero(cells: &[u8]) -> Option<usize> {
+        memchr(0, cells)
+    }
+
+    fn rfind_zero(cells: &[u8]) -> Option<usize> {
+        memrchr(0, cells)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<&[u8]> {
+        Some(bytes)
+    }
+
+    fn from_bytes_mut(bytes: &mut [u8]) -> Option<&mut [u8]> {
+        Some(bytes)
+    }
+
+    #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+    fn as_bytes_mut(tape: &mut [u8]) -> Option<&mut [u8]> {
+        Some(tape)
+    }
+}
+
+/// Signed bytes hold the same bits as unsigned ones, since both wrap around the same way.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_possible_wrap,
+    clippy::cast_sign_loss
+)]
+impl Cell for i8 {
+    fn add_amount(&mut self, amount: i32) {
+        *self = self.wrapping_add(super::wrap_to_cell(amount) as i8);
+    }
+
+    fn saturating_add_amount(&mut self, amount: i32) -> bool {
+        let sum = i32::from(*self).saturating_add(amount);
+        *self = sum.clamp(-128, 127) as i8;
+        !(-128..=127).contains(&sum)
+    }
+
+    fn set_value(&mut self, value: i32) {
+        *self = super::wrap_to_cell(value) as i8;
+    }
+
+    fn mul_add(&mut self, current: &i8, factor: i32) {
+        *self = self.wrapping_add(current.wrapping_mul(super::wrap_to_cell(factor) as i8));
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+
+    fn from_byte(byte: u8) -> i8 {
+        byte as i8
+    }
+
+    fn low_byte(&self) -> u8 {
+        *self as u8
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<&[i8]> {
+        // SAFETY: `i8` has the same size and alignment as `u8`, and every bit pattern is valid for
+        // both.
+        Some(unsafe { std::slice::from_raw_parts(bytes.as_ptr().cast(), bytes.len()) })
+    }
+
+    fn from_bytes_mut(bytes: &mut [u8]) -> Option<&mut [i8]> {
+        // SAFETY: As above.
+        Some(unsafe { std::slice::from_raw_parts_mut(bytes.as_mut_ptr().cast(), bytes.len()) })
+    }
+
+    #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+    fn as_bytes_mut(tape: &mut [i8]) -> Option<&mut [u8]> {
+        // SAFETY: `i8` has the same size and alignment as `u8`, and every bit pattern is valid for
+        // both.
+        Some(unsafe { std::slice::from_raw_parts_mut(tape.as_mut_ptr().cast(), tape.len()) })
+    }
+}
+
+/// Implement [`Cell`] for wider integers, signed or not, which wrap amounts around the same way
+/// bytes do.
+macro_rules! impl_wide_cell {
+    ($($cell:ty),*) => {$(
+        #[allow(
+            clippy::cast_lossless,
+            clippy::cast_possible_truncation,
+            clippy::cast_possible_wrap,
+            clippy::cast_sign_loss
+        )]
+        impl Cell for $cell {
+            fn add_amount(&mut self, amount: i32) {
+                *self = self.wrapping_add(amount as $cell);
+            }
+
+            fn saturating_add_amount(&mut self, amount: i32) -> bool {
+                let (min, max) = (i128::from(<$cell>::MIN), i128::from(<$cell>::MAX));
+                let sum = i128::from(*self) + i128::from(amount);
+                *self = sum.clamp(min, max) as $cell;
+                !(min..=max).contains(&sum)
+            }
+
+            fn set_value(&mut self, value: i32) {
+                *self = value as $cell;
+            }
+
+            fn mul_add(&mut self, current: &$cell, factor: i32) {
+                *self = self.wrapping_add(current.wrapping_mul(factor as $cell));
+            }
+
+            fn is_zero(&self) -> bool {
+                *self == 0
+            }
+
+            fn from_byte(byte: u8) -> $cell {
+                byte as $cell
+            }
+
+            fn low_byte(&self) -> u8 {
+                *self as u8
+            }
+        }
+    )*};
+}
+
+impl_wide_cell!(u16, u32, u64, i16, i32, i64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_amounts() {
+        let mut cell = 0u16;
+        cell.add_amount(-1);
+        assert_eq!(cell, u16::MAX);
+        cell.add_amount(300);
+        assert_eq!(cell, 299);
+        assert_eq!(cell.low_byte(), 43);
+
+        let mut cell = 0u64;
+        cell.set_value(-2);
+        assert_eq!(cell, u64::MAX - 1);
+        cell.mul_add(&3, -1);
+        assert_eq!(cell, u64::MAX - 4);
+
+        let mut cell = 0u8;
+        cell.mul_add(&200, 2);
+        assert_eq!(cell, 144);
+        assert_eq!(u32::from_byte(255), 255);
+    }
+
+    #[test]
+    fn signed_cells() {
+        let mut cell = 0i8;
+        cell.add_amount(-1);
+        assert_eq!(cell, -1);
+        assert_eq!(cell.low_byte(), 255);
+        cell.add_amount(129);
+        assert_eq!(cell, -128);
+        assert_eq!(i8::from_byte(200), -56);
+
+        let mut cell = 0i16;
+        cell.set_value(-300);
+        cell.mul_add(&-2, 3);
+        assert_eq!(cell, -306);
+        assert_eq!(cell.low_byte(), 206);
+        assert_eq!(i16::from_byte(200), 200);
+    }
+
+    #[test]
+    fn saturate_amounts() {
+        let mut cell = 250u8;
+        assert!(cell.saturating_add_amount(10));
+        assert_eq!(cell, 255);
+        assert!(!cell.saturating_add_amount(-255));
+        assert_eq!(cell, 0);
+        assert!(cell.saturating_add_amount(i32::MIN));
+        assert_eq!(cell, 0);
+
+        let mut cell = 0i8;
+        assert!(cell.saturating_add_amount(-200));
+        assert_eq!(cell, -128);
+
+        let mut cell = u64::MAX - 1;
+        assert!(!cell.saturating_add_amount(1));
+        assert!(cell.saturating_add_amount(1));
+        assert_eq!(cell, u64::MAX);
+    }
+}
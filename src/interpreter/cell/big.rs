@@ -0,0 +1,252 @@
+use std::{cmp::Ordering, fmt};
+
+use super::Cell;
+
+/// A cell holding an integer of any size, which never overflows.
+///
+/// Unlike the fixed-width cells, big cells are signed, so decrementing a cell holding zero makes it
+/// -1. `.` writes the lowest byte of the cell in two's complement, so -1 is written as 255.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct BigCell {
+    negative: bool,
+
+    /// The magnitude of the cell, in 32-bit limbs from the least significant one up, without
+    /// leading zero limbs. Zero has no limbs and is never negative.
+    magnitude: Vec<u32>,
+}
+
+impl BigCell {
+    /// Add the number with `negative` and `magnitude` to the cell.
+    fn add_signed(&mut self, negative: bool, magnitude: &[u32]) {
+        if self.negative == negative {
+            add_magnitude(&mut self.magnitude, magnitude);
+        } else if compare_magnitudes(&self.magnitude, magnitude) == Ordering::Less {
+            let mut larger = magnitude.to_vec();
+            sub_magnitude(&mut larger, &self.magnitude);
+            self.magnitude = larger;
+            self.negative = negative;
+        } else {
+            sub_magnitude(&mut self.magnitude, magnitude);
+        }
+        self.normalize();
+    }
+
+    /// Drop the leading zero limbs of the cell, and make sure zero isn't negative.
+    fn normalize(&mut self) {
+        while self.magnitude.last() == Some(&0) {
+            self.magnitude.pop();
+        }
+        if self.magnitude.is_empty() {
+            self.negative = false;
+        }
+    }
+}
+
+impl Cell for BigCell {
+    fn add_amount(&mut self, amount: i32) {
+        self.add_signed(amount < 0, &[amount.unsigned_abs()]);
+    }
+
+    fn saturating_add_amount(&mut self, amount: i32) -> bool {
+        self.add_amount(amount);
+        false
+    }
+
+    fn set_value(&mut self, value: i32) {
+        self.negative = value < 0;
+        self.magnitude.clear();
+        self.magnitude.push(value.unsigned_abs());
+        self.normalize();
+    }
+
+    fn mul_add(&mut self, current: &BigCell, factor: i32) {
+        let product = scale_magnitude(&current.magnitude, factor.unsigned_abs());
+        self.add_signed(current.negative != (factor < 0), &product);
+    }
+
+    fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    fn from_byte(byte: u8) -> BigCell {
+        let mut cell = BigCell::default();
+        cell.set_value(i32::from(byte));
+        cell
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn low_byte(&self) -> u8 {
+        let low = self.magnitude.first().map_or(0, |&limb| limb as u8);
+        if self.negative {
+            low.wrapping_neg()
+        } else {
+            low
+        }
+    }
+}
+
+impl fmt::Display for BigCell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Split the magnitude into groups of nine decimal digits, from the least significant up.
+        let mut magnitude = self.magnitude.clone();
+        let mut groups = Vec::new();
+        while !magnitude.is_empty() {
+            groups.push(div_magnitude(&mut magnitude, 1_000_000_000));
+            while magnitude.last() == Some(&0) {
+                magnitude.pop();
+            }
+        }
+
+        let Some((highest, rest)) = groups.split_last() else {
+            return f.write_str("0");
+        };
+        if self.negative {
+            f.write_str("-")?;
+        }
+        write!(f, "{highest}")?;
+        for group in rest.iter().rev() {
+            write!(f, "{group:09}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare two magnitudes without leading zero limbs.
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+/// Add the magnitude `b` to `a`.
+#[allow(clippy::cast_possible_truncation)]
+fn add_magnitude(a: &mut Vec<u32>, b: &[u32]) {
+    if a.len() < b.len() {
+        a.resize(b.len(), 0);
+    }
+
+    let mut carry = 0;
+    for (index, limb) in a.iter_mut().enumerate() {
+        let sum = u64::from(*limb) + u64::from(b.get(index).copied().unwrap_or(0)) + carry;
+        *limb = sum as u32;
+        carry = sum >> 32;
+        if carry == 0 && index >= b.len() {
+            break;
+        }
+    }
+    if carry != 0 {
+        a.push(carry as u32);
+    }
+}
+
+/// Subtract the magnitude `b` from `a`, which must be at least as large.
+fn sub_magnitude(a: &mut [u32], b: &[u32]) {
+    let mut borrow = false;
+    for (index, limb) in a.iter_mut().enumerate() {
+        let (difference, borrowed) = limb.overflowing_sub(b.get(index).copied().unwrap_or(0));
+        let (difference, borrowed_again) = difference.overflowing_sub(u32::from(borrow));
+        *limb = difference;
+        borrow = borrowed || borrowed_again;
+        if !borrow && index >= b.len() {
+            break;
+        }
+    }
+    debug_assert!(!borrow, "subtracted a larger magnitude");
+}
+
+/// Divide the magnitude `a` by `divisor` in place, which may leave leading zero limbs, and return
+/// the remainder.
+#[allow(clippy::cast_possible_truncation)]
+fn div_magnitude(a: &mut [u32], divisor: u32) -> u32 {
+    let mut remainder = 0;
+    for limb in a.iter_mut().rev() {
+        let dividend = remainder << 32 | u64::from(*limb);
+        *limb = (dividend / u64::from(divisor)) as u32;
+        remainder = dividend % u64::from(divisor);
+    }
+    remainder as u32
+}
+
+/// Return the magnitude `a` times `factor`, which may have leading zero limbs.
+#[allow(clippy::cast_possible_truncation)]
+fn scale_magnitude(a: &[u32], factor: u32) -> Vec<u32> {
+    let mut product = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0;
+    for &limb in a {
+        let limb = u64::from(limb) * u64::from(factor) + carry;
+        product.push(limb as u32);
+        carry = limb >> 32;
+    }
+    product.push(carry as u32);
+    product
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(value: i32) -> BigCell {
+        let mut cell = BigCell::default();
+        cell.set_value(value);
+        cell
+    }
+
+    #[test]
+    fn never_overflow() {
+        // Doubling 1 seventy times carries across three limbs.
+        let mut cell = big(1);
+        for _ in 0..70 {
+            let current = cell.clone();
+            cell.mul_add(&current, 1);
+        }
+        assert_eq!(cell.magnitude, [0, 0, 64]);
+        assert_eq!(cell.low_byte(), 0);
+
+        // Subtracting borrows across all of them.
+        cell.add_amount(-1);
+        assert_eq!(cell.magnitude, [u32::MAX, u32::MAX, 63]);
+        assert_eq!(cell.low_byte(), 255);
+        let current = cell.clone();
+        cell.mul_add(&current, -1);
+        assert!(cell.is_zero());
+    }
+
+    #[test]
+    fn signed_values() {
+        let mut cell = BigCell::default();
+        cell.add_amount(-1);
+        assert!(cell.negative);
+        assert_eq!(cell.low_byte(), 255);
+
+        cell.add_amount(1);
+        assert_eq!(cell, BigCell::default());
+        assert!(cell.is_zero());
+
+        cell.mul_add(&big(-300), -3);
+        assert_eq!(cell, big(900));
+        cell.mul_add(&big(100), -10);
+        assert_eq!(cell, big(-100));
+        assert_eq!(cell.low_byte(), 156);
+        assert_eq!(BigCell::from_byte(200), big(200));
+    }
+
+    #[test]
+    fn write_decimal() {
+        assert_eq!(BigCell::default().to_string(), "0");
+        assert_eq!(big(-42).to_string(), "-42");
+
+        // 2^70 spans three limbs and three groups of digits.
+        let mut cell = big(1);
+        for _ in 0..70 {
+            let current = cell.clone();
+            cell.mul_add(&current, 1);
+        }
+        assert_eq!(cell.to_string(), "1180591620717411303424");
+
+        // The lower groups keep their leading zeros.
+        let mut cell = BigCell::default();
+        cell.mul_add(&big(-1_000_000_000), 1_000_000_000);
+        cell.add_amount(-5);
+        assert_eq!(cell.to_string(), "-1000000000000000005");
+    }
+}
@@ -0,0 +1,115 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::ir::Op;
+
+/// The eight instructions of Brainfuck, in the order they're counted and displayed in.
+const INSTRUCTIONS: [char; 8] = ['+', '-', '>', '<', '.', ',', '[', ']'];
+
+/// How many times a program executed each of the eight instructions, and how many times its loops
+/// iterated.
+///
+/// Only unoptimized programs are counted right, as every op of them is a single instruction.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InstructionCounts {
+    /// The counts of the instructions, at the index of the instruction in [`INSTRUCTIONS`].
+    counts: [u64; 8],
+
+    /// How many times the body of a loop started, after a `[` or jumping back from a `]`.
+    iterations: u64,
+}
+
+impl InstructionCounts {
+    /// Count `op` as executed, where `jumped` says whether it was a jump that was taken.
+    ///
+    /// Ops other than those [`crate::ir::compile`] lowers the instructions to aren't counted.
+    pub(super) fn count(&mut self, op: Op, jumped: bool) {
+        let (index, times) = match op {
+            Op::Add { amount, .. } if amount >= 0 => (0, u64::from(amount.unsigned_abs())),
+            Op::Add { amount, .. } => (1, u64::from(amount.unsigned_abs())),
+            Op::Move(amount) if amount >= 0 => (2, amount.unsigned_abs() as u64),
+            Op::Move(amount) => (3, amount.unsigned_abs() as u64),
+            Op::Output { .. } => (4, 1),
+            Op::Input { .. } => (5, 1),
+            Op::JumpIfZero(_) => (6, 1),
+            Op::JumpIfNonZero(_) => (7, 1),
+            _ => return,
+        };
+        self.counts[index] += times;
+        let iterated = match op {
+            Op::JumpIfZero(_) => !jumped,
+            Op::JumpIfNonZero(_) => jumped,
+            _ => false,
+        };
+        self.iterations += u64::from(iterated);
+    }
+
+    /// Return how many times `instruction` was executed, which is 0 for anything that isn't one
+    /// of the eight instructions.
+    pub fn get(&self, instruction: char) -> u64 {
+        INSTRUCTIONS
+            .iter()
+            .position(|&ch| ch == instruction)
+            .map_or(0, |index| self.counts[index])
+    }
+
+    /// Return how many times the loops of the program iterated, all together.
+    pub fn iterations(&self) -> u64 {
+        self.iterations
+    }
+
+    /// Return how many instructions were executed, all together.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+impl Display for InstructionCounts {
+    /// Display a line per instruction, with its count and the share of all instructions that it
+    /// makes up, followed by the number of loop iterations.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let total = self.total();
+        let width = total.to_string().len();
+        for (instruction, &count) in INSTRUCTIONS.iter().zip(&self.counts) {
+            // Counts past 2^52 lose some precision as floats, which a percentage doesn't show.
+            #[allow(clippy::cast_precision_loss)]
+            let share = count as f64 * 100.0 / total.max(1) as f64;
+            writeln!(f, "{instruction} {count:>width$} {share:>5.1}%")?;
+        }
+        writeln!(f, "Loops iterated {} times", self.iterations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interpreter::Interpreter, ir};
+
+    #[test]
+    fn count_instructions() {
+        let program = ir::compile("++[>+<-]>.").unwrap();
+        let mut interpreter = Interpreter::from_program(program).with_instruction_counts();
+        interpreter
+            .run_counted(&mut [].as_slice(), &mut Vec::new())
+            .unwrap();
+
+        let counts = interpreter.instruction_counts().unwrap();
+        assert_eq!(
+            INSTRUCTIONS.map(|instruction| counts.get(instruction)),
+            [4, 2, 3, 2, 1, 0, 1, 2]
+        );
+        assert_eq!(counts.iterations(), 2);
+        assert_eq!(counts.total(), 15);
+        assert_eq!(
+            counts.to_string(),
+            "+  4  26.7%\n\
+             -  2  13.3%\n\
+             >  3  20.0%\n\
+             <  2  13.3%\n\
+             .  1   6.7%\n\
+             ,  0   0.0%\n\
+             [  1   6.7%\n\
+             ]  2  13.3%\n\
+             Loops iterated 2 times\n"
+        );
+    }
+}
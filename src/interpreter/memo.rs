@@ -0,0 +1,144 @@
+use std::{collections::HashMap, io};
+
+use super::{Cell, Interpreter};
+use crate::{ir::Op, optimizer};
+
+/// The most results cached for each loop, so that loops starting from many different values don't
+/// use up memory without bound.
+const CAPACITY: usize = 4096;
+
+/// The cached results of a loop marked with [`Op::Memoize`].
+#[derive(Debug)]
+pub struct Memo<C> {
+    /// The offsets of the cells the loop accesses from the tape pointer, as found by
+    /// [`optimizer::memo_window`].
+    window: Vec<isize>,
+
+    /// The results of the loop, by the values of its window when it starts.
+    results: HashMap<Box<[C]>, Replay<C>>,
+}
+
+/// How a loop left its window, to replay it.
+#[derive(Debug)]
+struct Replay<C> {
+    values: Box<[C]>,
+    iterations: u64,
+}
+
+/// Run the loop marked by the [`Op::Memoize`] at the program pointer of `interpreter`, replaying
+/// its results if it already ran from the same values, and leave the program pointer on its end.
+///
+/// Returns how many ops the loop executed, including the ones it would have executed if it wasn't
+/// replayed. A loop that isn't entered is left to its [`Op::JumpIfZero`], and executes none. Loops
+/// aren't memoized when the tape grows, since their windows may reach past its ends, or when
+/// overflow is checked, so they are left to run as usual too.
+pub fn run<C: Cell>(interpreter: &mut Interpreter<C>) -> u64 {
+    if interpreter.tape[interpreter.tape_pointer].is_zero() || interpreter.interpreted_only() {
+        return 0;
+    }
+
+    let start = interpreter.program_pointer + 1;
+    let Op::JumpIfZero(end) = interpreter.program[start] else {
+        unreachable!("memoized loops start right after their mark");
+    };
+
+    let mut memo = interpreter.memo.remove(&start).unwrap_or_else(|| Memo {
+        window: optimizer::memo_window(&interpreter.program[start + 1..end])
+            .expect("only pure loops are marked"),
+        results: HashMap::new(),
+    });
+    let key = window_values(interpreter, &memo.window);
+
+    let iterations = if let Some(replay) = memo.results.get(&key) {
+        for (&offset, value) in memo.window.iter().zip(&replay.values) {
+            interpreter.cell_mut(offset).clone_from(value);
+        }
+        interpreter.program_pointer = end;
+        replay.iterations
+    } else {
+        let iterations = run_loop(interpreter, start, end);
+        if memo.results.len() < CAPACITY {
+            let values = window_values(interpreter, &memo.window);
+            memo.results.insert(key, Replay { values, iterations });
+        }
+        iterations
+    };
+
+    interpreter.memo.insert(start, memo);
+    1 + iterations * (end - start) as u64
+}
+
+/// Return the values of the cells at the offsets in `window`.
+fn window_values<C: Cell>(interpreter: &mut Interpreter<C>, window: &[isize]) -> Box<[C]> {
+    window
+        .iter()
+        .map(|&offset| interpreter.cell_mut(offset).clone())
+        .collect()
+}
+
+/// Run the loop from its [`Op::JumpIfZero`] at `start` to its [`Op::JumpIfNonZero`] at `end`,
+/// leaving the program pointer on its end, and return how many times it iterated.
+fn run_loop<C: Cell>(interpreter: &mut Interpreter<C>, start: usize, end: usize) -> u64 {
+    let mut iterations = 0;
+
+    interpreter.program_pointer = start;
+    while interpreter.program_pointer <= end {
+        let op = interpreter.program[interpreter.program_pointer];
+        if matches!(op, Op::JumpIfNonZero(_)) {
+            iterations += 1;
+        }
+        interpreter
+            .execute_op(op, &mut io::empty(), &mut io::sink())
+            .expect("pure loops don't do any I/O");
+        interpreter.program_pointer += 1;
+    }
+    interpreter.program_pointer = end;
+
+    iterations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir;
+
+    fn memoized(program: &str) -> Interpreter {
+        let program =
+            optimizer::optimize_with_stats(ir::compile(program).unwrap(), optimizer::MAX_LEVEL).0;
+        assert!(program.ops.contains(&Op::Memoize));
+        Interpreter::from_ops(program.ops)
+    }
+
+    #[test]
+    fn replay_loops() {
+        // Halve 4 into the next cell, then move the result out of the way, twice.
+        let program = "++[>++++[-->+<]>[->+<]<<-]";
+        let mut expected = Interpreter::from_program_str(program).unwrap();
+        expected.run().unwrap();
+        assert_eq!(expected.tape[..4], [0, 0, 0, 4]);
+
+        let mut interpreter = memoized(program);
+        let mut output = Vec::new();
+        let steps = interpreter
+            .run_counted(&mut io::empty(), &mut output)
+            .unwrap();
+        assert_eq!(interpreter.tape, expected.tape);
+        assert_eq!(interpreter.tape_pointer, expected.tape_pointer);
+
+        // The loop starts from the same values both times, so the second run is replayed.
+        let start = interpreter
+            .program
+            .iter()
+            .position(|&op| op == Op::Memoize)
+            .unwrap()
+            + 1;
+        assert_eq!(interpreter.memo[&start].results.len(), 1);
+
+        let mut unmemoized = Interpreter::from_program_str(program).unwrap();
+        let unmemoized_steps = unmemoized
+            .run_counted(&mut io::empty(), &mut output)
+            .unwrap();
+        // The mark is counted on top of its loop every time it runs.
+        assert_eq!(steps, unmemoized_steps + 2);
+    }
+}
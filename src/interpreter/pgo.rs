@@ -0,0 +1,245 @@
+#[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+use std::collections::{hash_map::Entry, HashMap};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    io::{Read, Write},
+};
+
+use anyhow::{Context, Result};
+
+use super::{Cell, Interpreter};
+use crate::ir::{Op, Span};
+#[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+use crate::{ir, jit};
+
+/// The number of iterations after which a loop is hot, and gets compiled to native code.
+#[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+pub const HOT_LOOP_ITERATIONS: u64 = 10_000;
+
+/// How many times each loop of a program iterated while it was interpreted, by the offset of its
+/// `[` in the source code.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    iterations: BTreeMap<usize, u64>,
+}
+
+impl Profile {
+    /// Parse a profile in the format it is displayed in: a line per loop, with the offset of its
+    /// `[` and its number of iterations separated by a space. Blank lines and lines starting with
+    /// `#` are ignored.
+    pub fn parse(text: &str) -> Result<Profile> {
+        let mut iterations = BTreeMap::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (offset, count) = line
+                .split_once(' ')
+                .and_then(|(offset, count)| Some((offset.parse().ok()?, count.parse().ok()?)))
+                .with_context(|| format!("Invalid profile entry on line {}", i + 1))?;
+            iterations.insert(offset, count);
+        }
+
+        Ok(Profile { iterations })
+    }
+
+    /// Return how many times the loop starting at `offset` in the source code iterated.
+    pub fn iterations(&self, offset: usize) -> u64 {
+        self.iterations.get(&offset).copied().unwrap_or(0)
+    }
+
+    /// Return whether the loop starting at `offset` in the source code is hot.
+    #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+    fn is_hot(&self, offset: usize) -> bool {
+        self.iterations(offset) >= HOT_LOOP_ITERATIONS
+    }
+
+    /// Count an iteration of the loop starting at `offset` in the source code.
+    fn record(&mut self, offset: usize) {
+        let count = self.iterations.entry(offset).or_insert(0);
+        *count = count.saturating_add(1);
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "# Loop iterations, by the offset of each `[` in the source code."
+        )?;
+        for (offset, count) in &self.iterations {
+            writeln!(f, "{offset} {count}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Run the program of `interpreter`, counting the iterations of its loops into `profile`.
+///
+/// `spans` holds the span of every op of the program. With a `backend`, loops that are hot in
+/// `profile` are compiled to native code when they next iterate, and run natively from then on.
+/// Their iterations aren't counted anymore once they run natively. Loops are only compiled on a
+/// tape of bytes that doesn't grow and wraps around on overflow.
+pub fn run<C: Cell>(
+    interpreter: &mut Interpreter<C>,
+    spans: &[Span],
+    profile: &mut Profile,
+    #[cfg(any(feature = "jit", feature = "jit-x86-64"))] backend: Option<jit::Backend>,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<()> {
+    // The compiled hot loops, by the index of their `JumpIfZero`.
+    #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+    let mut compiled = HashMap::new();
+    // Native code only works on a tape of bytes that doesn't grow and wraps around on overflow.
+    #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+    let backend = backend.filter(|_| {
+        !interpreter.interpreted_only() && C::as_bytes_mut(&mut interpreter.tape).is_some()
+    });
+
+    while let Some(&op) = interpreter.program.get(interpreter.program_pointer) {
+        let entered = !interpreter.current_is_zero();
+
+        // The index of the start of the loop whose body is about to run, if any.
+        let iteration = match op {
+            Op::JumpIfZero(_) if entered => Some(interpreter.program_pointer),
+            Op::JumpIfNonZero(start) if entered => Some(start),
+            _ => None,
+        };
+
+        if let Some(start) = iteration {
+            let offset = spans[start].start;
+            profile.record(offset);
+
+            #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+            if let Some(backend) = backend.filter(|_| profile.is_hot(offset)) {
+                run_compiled(interpreter, start, backend, &mut compiled, input, output)?;
+                continue;
+            }
+        }
+
+        interpreter.execute_op(op, input, output)?;
+        interpreter.program_pointer += 1;
+    }
+
+    Ok(())
+}
+
+/// Run the loop starting at `start` natively, compiling it first if it wasn't already, then move
+/// the program pointer past its end.
+#[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+fn run_compiled<C: Cell>(
+    interpreter: &mut Interpreter<C>,
+    start: usize,
+    backend: jit::Backend,
+    compiled: &mut HashMap<usize, jit::Compiled>,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<()> {
+    let Op::JumpIfZero(end) = interpreter.program[start] else {
+        unreachable!("loops start with a JumpIfZero");
+    };
+
+    let code = match compiled.entry(start) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            let mut ops = interpreter.program[start..=end].to_vec();
+            ir::link(&mut ops);
+            entry.insert(jit::compile(backend, &ops, interpreter.tape.len())?)
+        }
+    };
+    let tape = C::as_bytes_mut(&mut interpreter.tape).expect("only byte tapes run natively");
+    code.run::<C>(
+        tape,
+        &mut interpreter.tape_pointer,
+        input,
+        output,
+        interpreter.io,
+    )?;
+    interpreter.program_pointer = end + 1;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir;
+
+    #[test]
+    fn parse_profiles() {
+        let profile = Profile::parse("# comment\n\n3 10\n 0 20000 \n").unwrap();
+        assert_eq!(profile.iterations(0), 20_000);
+        assert_eq!(profile.iterations(3), 10);
+        assert_eq!(profile.iterations(1), 0);
+        assert_eq!(Profile::parse(&profile.to_string()).unwrap(), profile);
+
+        assert!(Profile::parse("1 2 3").is_err());
+        assert!(Profile::parse("1").is_err());
+        assert!(Profile::parse("-1 2").is_err());
+    }
+
+    /// Run a program without optimizing it, so that every loop is kept.
+    fn run_str(
+        program: &str,
+        profile: &mut Profile,
+        #[cfg(any(feature = "jit", feature = "jit-x86-64"))] backend: Option<jit::Backend>,
+    ) -> Interpreter {
+        let program = ir::compile(program).unwrap();
+        let mut interpreter = Interpreter::from_ops(program.ops);
+        interpreter
+            .run_pgo(
+                &program.spans,
+                profile,
+                #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+                backend,
+            )
+            .unwrap();
+        interpreter
+    }
+
+    #[test]
+    fn count_iterations() {
+        let program = "+++[>++[->+<]<-][+]";
+        let mut profile = Profile::default();
+        for runs in 1..=2 {
+            let interpreter = run_str(
+                program,
+                &mut profile,
+                #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+                None,
+            );
+            assert_eq!(interpreter.tape[..3], [0, 0, 6]);
+            assert_eq!(profile.iterations(3), 3 * runs);
+            assert_eq!(profile.iterations(7), 6 * runs);
+            assert_eq!(profile.iterations(16), 0);
+        }
+    }
+
+    #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+    #[test]
+    fn compile_hot_loops() {
+        let program = "++++++++[>++++++++<-]>[>+>++<<-]";
+        let expected = run_str(program, &mut Profile::default(), None);
+        assert_eq!(expected.tape[..4], [0, 0, 64, 128]);
+
+        for &backend in jit::Backend::ALL {
+            if !backend.is_supported() {
+                continue;
+            }
+
+            let mut profile = Profile::parse(&format!("22 {HOT_LOOP_ITERATIONS}")).unwrap();
+            let interpreter = run_str(program, &mut profile, Some(backend));
+            assert_eq!(interpreter.tape, expected.tape);
+            assert_eq!(interpreter.tape_pointer, expected.tape_pointer);
+
+            // The hot loop only counts the iteration that compiled it.
+            assert_eq!(profile.iterations(8), 8);
+            assert_eq!(profile.iterations(22), HOT_LOOP_ITERATIONS + 1);
+        }
+    }
+}
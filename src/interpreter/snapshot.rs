@@ -0,0 +1,208 @@
+use std::{ops::Range, sync::Arc};
+
+use super::{sparse::SparseTape, Cell, Interpreter, ParkedTape};
+
+/// The number of cells in each page of a snapshot, which is the unit snapshots share cells in.
+const PAGE_SIZE: usize = 4096;
+
+/// The state of an interpreter at some point of running its program, taken by
+/// [`Interpreter::snapshot`] to go back to with [`Interpreter::restore`].
+///
+/// The tape is kept in pages, which snapshots share with each other as long as the cells on them
+/// don't change, so taking many snapshots of a big tape only copies the parts the program changed
+/// in between. Cloning a snapshot only shares its pages. The tapes the program isn't on, if the
+/// interpreter has several, are copied whole.
+#[derive(Debug, Clone)]
+pub struct TapeSnapshot<C> {
+    pages: Vec<Arc<[C]>>,
+    sparse: SparseTape<C>,
+    read_only: Vec<Range<usize>>,
+    tape_pointer: usize,
+    program_pointer: usize,
+    tapes: Vec<TapeCopy<C>>,
+    current_tape: usize,
+}
+
+/// A copy of a tape the program isn't on, in a snapshot.
+#[derive(Debug, Clone)]
+struct TapeCopy<C> {
+    cells: Arc<[C]>,
+    sparse: SparseTape<C>,
+    read_only: Vec<Range<usize>>,
+    tape_pointer: usize,
+}
+
+impl<C> TapeSnapshot<C> {
+    /// Return the number of cells on the tape of the snapshot.
+    fn tape_len(&self) -> usize {
+        self.pages.iter().map(|page| page.len()).sum()
+    }
+}
+
+/// Take a snapshot of `interpreter`, sharing the pages of its last snapshot that haven't changed.
+pub fn take<C: Cell>(interpreter: &mut Interpreter<C>) -> TapeSnapshot<C> {
+    let pages: Vec<Arc<[C]>> = interpreter
+        .tape
+        .chunks(PAGE_SIZE)
+        .enumerate()
+        .map(|(index, cells)| match interpreter.pages.get(index) {
+            Some(page) if **page == *cells => Arc::clone(page),
+            _ => Arc::from(cells),
+        })
+        .collect();
+    interpreter.pages.clone_from(&pages);
+
+    TapeSnapshot {
+        pages,
+        sparse: interpreter.sparse.clone(),
+        read_only: interpreter.read_only.clone(),
+        tape_pointer: interpreter.tape_pointer,
+        program_pointer: interpreter.program_pointer,
+        tapes: interpreter
+            .tapes
+            .iter()
+            .map(|parked| TapeCopy {
+                cells: Arc::from(&*parked.tape),
+                sparse: parked.sparse.clone(),
+                read_only: parked.read_only.clone(),
+                tape_pointer: parked.tape_pointer,
+            })
+            .collect(),
+        current_tape: interpreter.current_tape,
+    }
+}
+
+/// Put `interpreter` back in the state of `snapshot`, only copying the pages that changed.
+pub fn restore<C: Cell>(interpreter: &mut Interpreter<C>, snapshot: &TapeSnapshot<C>) {
+    // Each tape goes back into its own cells, which may be a tape file.
+    if !interpreter.tapes.is_empty() {
+        interpreter.select_tape(snapshot.current_tape);
+    }
+    for (parked, copy) in interpreter.tapes.iter_mut().zip(&snapshot.tapes) {
+        restore_parked(parked, copy);
+    }
+
+    let len = snapshot.tape_len();
+    if interpreter.tape.len() != len {
+        interpreter.tape = vec![C::default(); len].into_boxed_slice().into();
+    }
+    for (cells, page) in interpreter.tape.chunks_mut(PAGE_SIZE).zip(&snapshot.pages) {
+        if *cells != **page {
+            cells.clone_from_slice(page);
+        }
+    }
+    interpreter.pages.clone_from(&snapshot.pages);
+
+    interpreter.sparse.clone_from(&snapshot.sparse);
+    interpreter.read_only.clone_from(&snapshot.read_only);
+    interpreter.tape_pointer = snapshot.tape_pointer;
+    interpreter.program_pointer = snapshot.program_pointer;
+}
+
+/// Put a tape the program isn't on back in the state of its copy.
+fn restore_parked<C: Cell>(parked: &mut ParkedTape<C>, copy: &TapeCopy<C>) {
+    if parked.tape.len() == copy.cells.len() {
+        parked.tape.clone_from_slice(&copy.cells);
+    } else {
+        parked.tape = Box::<[C]>::from(&*copy.cells).into();
+    }
+    parked.sparse.clone_from(&copy.sparse);
+    parked.read_only.clone_from(&copy.read_only);
+    parked.tape_pointer = copy.tape_pointer;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        interpreter::{TapeModel, TAPE_SIZE},
+        ir,
+    };
+
+    fn run_steps<C: Cell>(interpreter: &mut Interpreter<C>, steps: u64, input: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        interpreter
+            .run_budget_with(steps, &mut &input[..], &mut output)
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn roll_back() {
+        // The input and the jump into the loop, then two iterations of an output, a decrement and
+        // a jump back.
+        let mut interpreter = Interpreter::from_program_str(",[.-]").unwrap();
+        assert_eq!(run_steps(&mut interpreter, 8, &[5]), [5, 4]);
+        let snapshot = interpreter.snapshot();
+        assert_eq!(run_steps(&mut interpreter, u64::MAX, &[]), [3, 2, 1]);
+        assert!(interpreter.tape.iter().all(Cell::is_zero));
+
+        // The program picks up from the snapshot, and does the same thing again.
+        interpreter.restore(&snapshot);
+        assert_eq!(interpreter.tape[0], 3);
+        assert_eq!(run_steps(&mut interpreter, u64::MAX, &[]), [3, 2, 1]);
+        interpreter.restore(&snapshot);
+        assert_eq!(run_steps(&mut interpreter, u64::MAX, &[]), [3, 2, 1]);
+    }
+
+    #[test]
+    fn share_unchanged_pages() {
+        let mut interpreter = Interpreter::from_program_str(">+").unwrap();
+        let before = interpreter.snapshot();
+        run_steps(&mut interpreter, u64::MAX, &[]);
+        let after = interpreter.snapshot();
+
+        assert_eq!(after.tape_len(), TAPE_SIZE);
+        assert!(!Arc::ptr_eq(&before.pages[0], &after.pages[0]));
+        assert!(before.pages[1..]
+            .iter()
+            .zip(&after.pages[1..])
+            .all(|(before, after)| Arc::ptr_eq(before, after)));
+    }
+
+    #[test]
+    fn restore_a_grown_tape() {
+        let mut interpreter = Interpreter::from_program_str("+[>+]")
+            .unwrap()
+            .with_tape_size(64)
+            .with_tape_model(TapeModel::Grow);
+        let snapshot = interpreter.snapshot();
+        run_steps(&mut interpreter, 1000, &[]);
+        assert!(interpreter.tape.len() > 64);
+
+        interpreter.restore(&snapshot);
+        assert_eq!(interpreter.tape.len(), 64);
+        assert!(interpreter.tape.iter().all(Cell::is_zero));
+
+        let mut sparse = Interpreter::from_program_str("+<<+")
+            .unwrap()
+            .with_tape_model(TapeModel::Sparse);
+        let snapshot = sparse.snapshot();
+        run_steps(&mut sparse, u64::MAX, &[]);
+        assert!(!sparse.sparse.is_zero(0));
+        sparse.restore(&snapshot);
+        assert!(sparse.sparse.is_zero(0) && sparse.sparse.is_zero(-2));
+    }
+
+    #[test]
+    fn restore_other_tapes() {
+        let extensions = ir::Extensions {
+            tape_switch: Some(['{', '}']),
+        };
+        let program = ir::compile_with("+}++}+++", extensions).unwrap();
+        let mut interpreter = Interpreter::from_program(program).with_tapes(3);
+        run_steps(&mut interpreter, 3, &[]);
+        let snapshot = interpreter.snapshot();
+        run_steps(&mut interpreter, u64::MAX, &[]);
+        assert_eq!(interpreter.current_tape, 2);
+
+        // The program goes back to the second tape, halfway through adding to it.
+        interpreter.restore(&snapshot);
+        assert_eq!(interpreter.current_tape, 1);
+        assert_eq!(interpreter.tape[0], 1);
+        assert_eq!(interpreter.tapes[0].tape[0], 1);
+        assert!(interpreter.tapes[2].tape.iter().all(Cell::is_zero));
+        run_steps(&mut interpreter, u64::MAX, &[]);
+        assert_eq!(interpreter.tape[0], 3);
+    }
+}
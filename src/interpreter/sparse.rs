@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use super::{tape::Tape, Cell};
+
+/// The tape of [`TapeModel::Sparse`](super::TapeModel::Sparse), which only stores the cells that
+/// aren't zero, by their index from the starting cell.
+#[derive(Debug, Clone, Default)]
+pub struct SparseTape<C> {
+    cells: HashMap<isize, C>,
+    pointer: isize,
+}
+
+impl<C: Cell> SparseTape<C> {
+    /// Return whether the cell `offset` cells away from the tape pointer is zero.
+    pub fn is_zero(&self, offset: isize) -> bool {
+        self.cells
+            .get(&(self.pointer + offset))
+            .is_none_or(Cell::is_zero)
+    }
+
+    /// Set the cells from the starting cell onwards to `bytes`, replacing what they held.
+    pub fn load(&mut self, bytes: &[u8]) {
+        for (index, &byte) in (0..).zip(bytes) {
+            let cell = C::from_byte(byte);
+            if cell.is_zero() {
+                self.cells.remove(&index);
+            } else {
+                self.cells.insert(index, cell);
+            }
+        }
+    }
+
+    /// Return the index of the cell `offset` cells away from the tape pointer, from the starting
+    /// cell.
+    pub fn index(&self, offset: isize) -> isize {
+        self.pointer + offset
+    }
+
+    /// Return every cell that isn't zero, by its index from the starting cell, in order.
+    pub fn nonzero_cells(&self) -> Vec<(isize, C)> {
+        let mut cells: Vec<_> = self
+            .cells
+            .iter()
+            .filter(|(_, cell)| !cell.is_zero())
+            .map(|(&index, cell)| (index, cell.clone()))
+            .collect();
+        cells.sort_unstable_by_key(|&(index, _)| index);
+        cells
+    }
+}
+
+impl<C: Cell> Tape<C> for SparseTape<C> {
+    fn get(&self, offset: isize) -> C {
+        self.cells
+            .get(&(self.pointer + offset))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn set(&mut self, offset: isize, value: C) {
+        let index = self.pointer + offset;
+        if value.is_zero() {
+            self.cells.remove(&index);
+        } else {
+            self.cells.insert(index, value);
+        }
+    }
+
+    fn move_by(&mut self, amount: isize) {
+        self.pointer += amount;
+    }
+
+    fn len(&self) -> usize {
+        self.cells.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::{Interpreter, TapeModel};
+
+    fn run_sparse(program: &str, input: &[u8]) -> (Interpreter, Vec<u8>) {
+        let mut interpreter = Interpreter::from_program_str(program)
+            .unwrap()
+            .with_tape_model(TapeModel::Sparse);
+        let output = interpreter.run_collect(input).unwrap();
+        (interpreter, output)
+    }
+
+    #[test]
+    fn far_apart_cells() {
+        let far = ">".repeat(1 << 16);
+        let program = format!("+{far}{far}++{far}<<{far}{far}{far}+++[-<+>]<.");
+        let (interpreter, output) = run_sparse(&program, &[]);
+        assert_eq!(output, [3]);
+
+        // The cells the program zeroed aren't stored anymore.
+        let mut cells: Vec<_> = interpreter.sparse.cells.into_iter().collect();
+        cells.sort_unstable();
+        assert_eq!(cells, [(0, 1), (2 << 16, 2), ((6 << 16) - 3, 3)]);
+    }
+
+    #[test]
+    fn scan_and_clear() {
+        // Input keeps the optimizer from knowing where the scan ends up.
+        let (interpreter, output) = run_sparse(",[>,]<<[<]>.[-]>[-]>[-]", &[3, 2, 1, 0]);
+        assert_eq!(output, [3]);
+        assert_eq!(interpreter.sparse.pointer, 2);
+        assert!(interpreter.sparse.cells.is_empty());
+        assert!(interpreter.sparse.is_zero(-3));
+    }
+}
@@ -0,0 +1,71 @@
+use std::{
+    fmt::{self, Debug, Formatter},
+    fs::File,
+    io,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use memmap2::MmapMut;
+
+use super::Cell;
+
+/// The memory holding the cells of the tape, which is either owned by the interpreter or a file
+/// mapped into memory.
+pub enum Storage<C> {
+    Owned(Box<[C]>),
+
+    /// A file mapped into memory, whose bytes are the cells, so `C` must be a type of byte cells.
+    Mapped(MmapMut, PhantomData<C>),
+}
+
+impl<C: Cell> Storage<C> {
+    /// Map all of `file` into memory, as cells of type `C`, which must be bytes.
+    pub fn map(file: &File) -> io::Result<Storage<C>> {
+        debug_assert!(C::from_bytes(&[]).is_some(), "only byte cells are mapped");
+
+        // SAFETY: Changing the file from elsewhere while it's mapped would change the tape under
+        // the interpreter, which nothing can prevent. Tape files are documented to belong to the
+        // program running on them.
+        let map = unsafe { MmapMut::map_mut(file) }?;
+        Ok(Storage::Mapped(map, PhantomData))
+    }
+}
+
+impl<C: Cell> Deref for Storage<C> {
+    type Target = [C];
+
+    fn deref(&self) -> &[C] {
+        match self {
+            Storage::Owned(cells) => cells,
+            Storage::Mapped(map, _) => C::from_bytes(map).expect("only byte cells are mapped"),
+        }
+    }
+}
+
+impl<C: Cell> DerefMut for Storage<C> {
+    fn deref_mut(&mut self) -> &mut [C] {
+        match self {
+            Storage::Owned(cells) => cells,
+            Storage::Mapped(map, _) => C::from_bytes_mut(map).expect("only byte cells are mapped"),
+        }
+    }
+}
+
+impl<C: Cell> From<Box<[C]>> for Storage<C> {
+    fn from(cells: Box<[C]>) -> Storage<C> {
+        Storage::Owned(cells)
+    }
+}
+
+impl<C: Cell> PartialEq for Storage<C> {
+    fn eq(&self, other: &Storage<C>) -> bool {
+        **self == **other
+    }
+}
+
+impl<C: Cell> Debug for Storage<C> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
@@ -0,0 +1,172 @@
+use std::io::{Read, Write};
+
+use anyhow::Result;
+
+use super::{read_into, write_cell, Cell, Interpreter, Overflow};
+use crate::ir::Op;
+
+/// A tape the interpreter can run programs on, through [`Interpreter::with_tape`], for backends of
+/// its own like shared memory or tapes that record what the program does.
+///
+/// Cells are reached by their offset from the tape pointer, and every cell of the tape starts out
+/// zero. How the tape handles its ends is up to it.
+pub trait Tape<C> {
+    /// Return the value of the cell `offset` cells away from the tape pointer.
+    fn get(&self, offset: isize) -> C;
+
+    /// Set the cell `offset` cells away from the tape pointer to `value`.
+    fn set(&mut self, offset: isize, value: C);
+
+    /// Move the tape pointer by `amount` cells.
+    fn move_by(&mut self, amount: isize);
+
+    /// Return the number of cells the tape holds in memory.
+    fn len(&self) -> usize;
+
+    /// Return whether the tape holds no cells in memory, like one that only grows when written to.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Execute a single op of the program of `interpreter` on the tape it runs on instead of its own,
+/// which is either the one given to [`Interpreter::with_tape`] or the sparse tape of
+/// [`TapeModel::Sparse`](super::TapeModel::Sparse), like [`Interpreter::execute_op`] does on a
+/// tape of consecutive cells.
+pub fn execute_op<C: Cell>(
+    interpreter: &mut Interpreter<C>,
+    op: Op,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<()> {
+    let tape: &mut dyn Tape<C> = match &mut interpreter.custom_tape {
+        Some(tape) => tape.as_mut(),
+        None => &mut interpreter.sparse,
+    };
+    match op {
+        Op::Add { offset, amount } => {
+            let mut cell = tape.get(offset);
+            let overflowed = if interpreter.overflow == Overflow::Wrap {
+                cell.add_amount(amount);
+                false
+            } else {
+                cell.saturating_add_amount(amount)
+            };
+            tape.set(offset, cell);
+            if overflowed && interpreter.overflow == Overflow::Error {
+                return Err(interpreter.error("Cell overflowed"));
+            }
+        }
+        Op::Move(amount) => tape.move_by(amount),
+        Op::SetZero { offset } => tape.set(offset, C::default()),
+        Op::ClearRange { offset, len } => {
+            for offset in (offset..).take(len) {
+                tape.set(offset, C::default());
+            }
+        }
+        Op::Set { offset, value } => {
+            let mut cell = C::default();
+            cell.set_value(value);
+            tape.set(offset, cell);
+        }
+        Op::MulAdd { offset, factor } => {
+            // Multiply loops are replaced without their jumps, so they also run when the current
+            // cell is zero, which doesn't change anything.
+            let current = tape.get(0);
+            if !current.is_zero() {
+                let mut cell = tape.get(offset);
+                cell.mul_add(&current, factor);
+                tape.set(offset, cell);
+            }
+        }
+        Op::Scan(stride) => {
+            while !tape.get(0).is_zero() {
+                tape.move_by(stride);
+            }
+        }
+
+        Op::JumpIfZero(target) if tape.get(0).is_zero() => interpreter.program_pointer = target,
+        Op::JumpIfNonZero(target) if !tape.get(0).is_zero() => {
+            interpreter.program_pointer = target;
+        }
+        // Loops are only memoized on a tape that wraps around.
+        Op::JumpIfZero(_) | Op::JumpIfNonZero(_) | Op::Memoize => {}
+        Op::SwitchTape(amount) => interpreter.switch_tape(amount),
+
+        Op::Output { offset } => write_cell(output, &tape.get(offset), interpreter.io)?,
+        Op::Input { offset } => {
+            let mut cell = tape.get(offset);
+            read_into(&mut cell, input, output, interpreter.io)?;
+            tape.set(offset, cell);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::interpreter::wrapping_index;
+
+    /// A tape of a fixed number of cells that wraps around, and records the offsets the program
+    /// writes to.
+    struct RecordingTape {
+        cells: Vec<u8>,
+        pointer: usize,
+        writes: Rc<RefCell<Vec<isize>>>,
+    }
+
+    impl RecordingTape {
+        fn index(&self, offset: isize) -> usize {
+            wrapping_index(self.pointer, offset, self.cells.len())
+        }
+    }
+
+    impl Tape<u8> for RecordingTape {
+        fn get(&self, offset: isize) -> u8 {
+            self.cells[self.index(offset)]
+        }
+
+        fn set(&mut self, offset: isize, value: u8) {
+            self.writes.borrow_mut().push(offset);
+            let index = self.index(offset);
+            self.cells[index] = value;
+        }
+
+        fn move_by(&mut self, amount: isize) {
+            self.pointer = self.index(amount);
+        }
+
+        fn len(&self) -> usize {
+            self.cells.len()
+        }
+    }
+
+    fn run_on(program: &str) -> (Vec<u8>, Vec<isize>) {
+        let writes = Rc::default();
+        let tape = RecordingTape {
+            cells: vec![0; 64],
+            pointer: 0,
+            writes: Rc::clone(&writes),
+        };
+        let mut interpreter = Interpreter::from_program_str(program)
+            .unwrap()
+            .with_tape(tape);
+        let output = interpreter.run_collect(&[]).unwrap();
+        (output, writes.take())
+    }
+
+    #[test]
+    fn custom_tape() {
+        let (output, writes) = run_on("++>+[-<+>]<.");
+        assert_eq!(output, [3]);
+        // The optimized program adds to both cells, then the multiply loop adds to the cell left
+        // of it and clears its own.
+        assert_eq!(writes, [0, 1, -1, 0]);
+
+        assert_eq!(run_on("<+<++<[<]>.>.>.").0, [2, 1, 0]);
+    }
+}
@@ -0,0 +1,94 @@
+use std::io::{Read, Write};
+
+use anyhow::Result;
+
+use super::{clear_range, memo, read_into, scan, write_cell, Cell, Interpreter};
+use crate::ir::Op;
+
+/// A single op, compiled into a closure that executes it.
+type Handler<C> = Box<dyn Fn(&mut Interpreter<C>, &mut dyn Read, &mut dyn Write) -> Result<()>>;
+
+/// Run the program of `interpreter` with threaded code.
+///
+/// Every op is compiled into a [`Handler`] up front, with its operands already decoded, so the
+/// main loop makes a single indirect call per op instead of matching on it.
+pub fn run<C: Cell>(
+    interpreter: &mut Interpreter<C>,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<()> {
+    let handlers: Vec<Handler<C>> = interpreter.program.iter().copied().map(handler).collect();
+
+    while let Some(handler) = handlers.get(interpreter.program_pointer) {
+        handler(interpreter, input, output)?;
+        interpreter.program_pointer += 1;
+    }
+
+    Ok(())
+}
+
+/// Return the handler for an op.
+fn handler<C: Cell>(op: Op) -> Handler<C> {
+    match op {
+        Op::Add { offset, amount } => Box::new(move |interpreter, _, _| {
+            interpreter.cell_mut(offset).add_amount(amount);
+            Ok(())
+        }),
+        Op::Move(amount) => Box::new(move |interpreter, _, _| {
+            interpreter.move_tape_pointer(amount);
+            Ok(())
+        }),
+        Op::SetZero { offset } => Box::new(move |interpreter, _, _| {
+            *interpreter.cell_mut(offset) = C::default();
+            Ok(())
+        }),
+        Op::ClearRange { offset, len } => Box::new(move |interpreter, _, _| {
+            let start = interpreter.offset_index(offset);
+            clear_range(&mut interpreter.tape, start, len);
+            Ok(())
+        }),
+        Op::Set { offset, value } => Box::new(move |interpreter, _, _| {
+            interpreter.cell_mut(offset).set_value(value);
+            Ok(())
+        }),
+        Op::MulAdd { offset, factor } => Box::new(move |interpreter, _, _| {
+            let current = interpreter.tape[interpreter.tape_pointer].clone();
+            interpreter.cell_mut(offset).mul_add(&current, factor);
+            Ok(())
+        }),
+        Op::Scan(stride) => Box::new(move |interpreter, _, _| {
+            interpreter.tape_pointer = scan(&interpreter.tape, interpreter.tape_pointer, stride);
+            Ok(())
+        }),
+
+        Op::JumpIfZero(target) => Box::new(move |interpreter, _, _| {
+            if interpreter.tape[interpreter.tape_pointer].is_zero() {
+                interpreter.program_pointer = target;
+            }
+            Ok(())
+        }),
+        Op::JumpIfNonZero(target) => Box::new(move |interpreter, _, _| {
+            if !interpreter.tape[interpreter.tape_pointer].is_zero() {
+                interpreter.program_pointer = target;
+            }
+            Ok(())
+        }),
+
+        Op::Output { offset } => Box::new(move |interpreter, _, output| {
+            let io = interpreter.io;
+            write_cell(output, interpreter.cell_mut(offset), io)
+        }),
+        Op::Input { offset } => Box::new(move |interpreter, input, output| {
+            let io = interpreter.io;
+            read_into(interpreter.cell_mut(offset), input, output, io)
+        }),
+        Op::Memoize => Box::new(|interpreter, _, _| {
+            memo::run(interpreter);
+            Ok(())
+        }),
+        Op::SwitchTape(amount) => Box::new(move |interpreter, _, _| {
+            interpreter.switch_tape(amount);
+            Ok(())
+        }),
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    fmt::Display,
+    io::{self, Write},
+    ops::Range,
+};
+
+use crate::ir::Op;
+
+/// A log of the instructions a program executes, a line for each of them, with where the
+/// instruction is in the source, the index of the cell the tape pointer is on and the value of
+/// that cell before the instruction runs.
+pub struct Trace {
+    output: Box<dyn Write>,
+
+    /// The offsets in the source of the instructions to log, or `None` to log all of them.
+    range: Option<Range<usize>>,
+}
+
+impl Trace {
+    /// Return a trace writing every instruction to `output`.
+    pub fn new(output: Box<dyn Write>) -> Trace {
+        Trace {
+            output,
+            range: None,
+        }
+    }
+
+    /// Only log the instructions at the offsets of the source in `range`.
+    #[must_use]
+    pub fn with_range(mut self, range: Range<usize>) -> Trace {
+        self.range = Some(range);
+        self
+    }
+
+    /// Log `op` at `location`, about to run with the tape pointer on cell `pointer` holding `cell`,
+    /// unless its `offset` in the source is outside of the range. Ops without an offset are
+    /// only logged without a range.
+    pub(super) fn record(
+        &mut self,
+        op: Op,
+        offset: Option<usize>,
+        location: &str,
+        pointer: isize,
+        cell: &impl Display,
+    ) -> io::Result<()> {
+        if let Some(range) = &self.range {
+            if !offset.is_some_and(|offset| range.contains(&offset)) {
+                return Ok(());
+            }
+        }
+        // Unoptimized programs only have ops made from a single instruction, which are shown as it.
+        let instruction = match op {
+            Op::Add {
+                offset: 0,
+                amount: 1,
+            } => "+".to_owned(),
+            Op::Add {
+                offset: 0,
+                amount: -1,
+            } => "-".to_owned(),
+            Op::Move(1) => ">".to_owned(),
+            Op::Move(-1) => "<".to_owned(),
+            Op::Output { offset: 0 } => ".".to_owned(),
+            Op::Input { offset: 0 } => ",".to_owned(),
+            Op::JumpIfZero(_) => "[".to_owned(),
+            Op::JumpIfNonZero(_) => "]".to_owned(),
+            op => op.to_string(),
+        };
+        writeln!(
+            self.output,
+            "{location:>6} {instruction} pointer={pointer} cell={cell}"
+        )
+    }
+
+    /// Flush the lines logged so far.
+    pub(super) fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{interpreter::Interpreter, ir};
+
+    /// An output that can still be read once the trace that writes to it is gone.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn trace_instructions() {
+        let log = Shared::default();
+        let program = ir::compile("+[-]>.").unwrap();
+        let mut interpreter =
+            Interpreter::from_program(program).with_trace(Trace::new(Box::new(log.clone())));
+        interpreter
+            .run_counted(&mut [].as_slice(), &mut Vec::new())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(log.0.take()).unwrap(),
+            "     0 + pointer=0 cell=0\n\
+             \x20    1 [ pointer=0 cell=1\n\
+             \x20    2 - pointer=0 cell=1\n\
+             \x20    3 ] pointer=0 cell=0\n\
+             \x20    4 > pointer=0 cell=0\n\
+             \x20    5 . pointer=1 cell=0\n"
+        );
+
+        let trace = Trace::new(Box::new(log.clone())).with_range(2..4);
+        let program = ir::compile("++[-]").unwrap();
+        let mut interpreter = Interpreter::from_program(program).with_trace(trace);
+        interpreter
+            .run_counted(&mut [].as_slice(), &mut Vec::new())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(log.0.take()).unwrap(),
+            "     2 [ pointer=0 cell=2\n\
+             \x20    3 - pointer=0 cell=2\n\
+             \x20    3 - pointer=0 cell=1\n"
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use std::io::{Read, Write};
+
+use anyhow::Result;
+
+use super::{read_into, scan, wrapping_index, write_cell, Cell, Interpreter};
+use crate::ir::Op;
+
+/// Run the program of `interpreter` without checking the bounds of the tape or the program.
+///
+/// Instead of wrapping every cell access around the ends of the tape, the tape is padded on both
+/// sides by the furthest any op reaches from the tape pointer, so that every access is in bounds
+/// as long as the tape pointer is on the tape. The tape pointer itself still wraps around when it
+/// moves, but cells past the ends of the tape that are reached through the offset of an op are
+/// padding rather than the cells on the other end, which is the only behavior that differs from
+/// [`Interpreter::run`]. Padding is dropped once the program finishes.
+///
+/// The jumps of the program must be linked, as they are when it comes out of the optimizer.
+pub fn run<C: Cell>(
+    interpreter: &mut Interpreter<C>,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<()> {
+    let ops = &interpreter.program[..];
+    let len = interpreter.tape.len();
+    let margin = ops.iter().map(|&op| reach(op)).max().unwrap_or(0);
+
+    let mut tape = vec![C::default(); margin + len + margin];
+    tape[margin..margin + len].clone_from_slice(&interpreter.tape);
+    let mut pointer = margin + interpreter.tape_pointer;
+    let mut program_pointer = interpreter.program_pointer;
+    let io = interpreter.io;
+
+    let result = (|| {
+        while let Some(&op) = ops.get(program_pointer) {
+            let cell = move |offset: isize| pointer.wrapping_add_signed(offset);
+
+            // SAFETY: All of the unchecked accesses below are at most `reach(op)` cells away from
+            // the tape pointer. The tape pointer always stays within the unpadded part of the
+            // tape, and the padding on either side is at least that wide.
+            match op {
+                Op::Add { offset, amount } => {
+                    unsafe { tape.get_unchecked_mut(cell(offset)) }.add_amount(amount);
+                }
+                Op::Move(amount) => {
+                    pointer = margin + wrapping_index(pointer - margin, amount, len);
+                }
+                Op::SetZero { offset } => {
+                    *unsafe { tape.get_unchecked_mut(cell(offset)) } = C::default();
+                }
+                Op::ClearRange { offset, len } => {
+                    let start = cell(offset);
+                    unsafe { tape.get_unchecked_mut(start..start + len) }.fill(C::default());
+                }
+                Op::Set { offset, value } => {
+                    unsafe { tape.get_unchecked_mut(cell(offset)) }.set_value(value);
+                }
+                Op::MulAdd { offset, factor } => {
+                    let current = unsafe { tape.get_unchecked(pointer) }.clone();
+                    unsafe { tape.get_unchecked_mut(cell(offset)) }.mul_add(&current, factor);
+                }
+                Op::Scan(stride) => {
+                    pointer = margin + scan(&tape[margin..margin + len], pointer - margin, stride);
+                }
+
+                Op::JumpIfZero(target) if unsafe { tape.get_unchecked(pointer) }.is_zero() => {
+                    program_pointer = target;
+                }
+                Op::JumpIfNonZero(target) if !unsafe { tape.get_unchecked(pointer) }.is_zero() => {
+                    program_pointer = target;
+                }
+                // Loops are only memoized by the checked interpreter, and programs only run
+                // unchecked on a single tape, where switching does nothing.
+                Op::JumpIfZero(_) | Op::JumpIfNonZero(_) | Op::Memoize | Op::SwitchTape(_) => {}
+
+                Op::Output { offset } => {
+                    write_cell(output, unsafe { tape.get_unchecked(cell(offset)) }, io)?;
+                }
+                Op::Input { offset } => {
+                    let cell = unsafe { tape.get_unchecked_mut(cell(offset)) };
+                    read_into(cell, input, output, io)?;
+                }
+            }
+
+            program_pointer += 1;
+        }
+        Ok(())
+    })();
+
+    interpreter
+        .tape
+        .clone_from_slice(&tape[margin..margin + len]);
+    interpreter.tape_pointer = pointer - margin;
+    interpreter.program_pointer = program_pointer;
+    result
+}
+
+/// Return the furthest an op accesses a cell from the tape pointer, in either direction.
+fn reach(op: Op) -> usize {
+    match op {
+        Op::Add { offset, .. }
+        | Op::SetZero { offset }
+        | Op::Set { offset, .. }
+        | Op::MulAdd { offset, .. }
+        | Op::Output { offset }
+        | Op::Input { offset } => offset.unsigned_abs(),
+        Op::ClearRange { offset, len } => offset
+            .unsigned_abs()
+            .max((offset + len.cast_signed() - 1).unsigned_abs()),
+        Op::Move(_)
+        | Op::Scan(_)
+        | Op::JumpIfZero(_)
+        | Op::JumpIfNonZero(_)
+        | Op::Memoize
+        | Op::SwitchTape(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::TAPE_SIZE;
+
+    fn run_unchecked(program: &str) -> Interpreter {
+        let mut interpreter = Interpreter::from_program_str(program).unwrap();
+        interpreter.run_unchecked().unwrap();
+        interpreter
+    }
+
+    #[test]
+    fn same_as_checked() {
+        for program in [
+            "+++++[->++>---<<]>>[>]+<[-]->>[-]++",
+            ">>>+<+<+[<]<<[-]>[-]>[-]",
+            "++++++++[>++++++++<-]>[>+>++<<-]>>>>>>>+[<<]",
+            // Scans move the tape pointer, so they still wrap around.
+            "+[<]+",
+        ] {
+            let mut checked = Interpreter::from_program_str(program).unwrap();
+            checked.run().unwrap();
+            let unchecked = run_unchecked(program);
+            assert_eq!(unchecked.tape, checked.tape, "{program}");
+            assert_eq!(unchecked.tape_pointer, checked.tape_pointer, "{program}");
+        }
+    }
+
+    #[test]
+    fn offsets_past_the_ends_do_not_wrap() {
+        // The add is folded into an offset of -1, so it never moves onto the end of the tape.
+        let interpreter = run_unchecked("<+>");
+        assert_eq!(interpreter.tape[TAPE_SIZE - 1], 0);
+        assert_eq!(interpreter.tape_pointer, 0);
+
+        // The loop keeps the move from being folded into the offset of the add.
+        let interpreter = run_unchecked("<[.]+");
+        assert_eq!(interpreter.tape[TAPE_SIZE - 1], 1);
+        assert_eq!(interpreter.tape_pointer, TAPE_SIZE - 1);
+    }
+}
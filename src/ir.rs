@@ -0,0 +1,475 @@
+use std::fmt::{self, Display, Formatter};
+
+use anyhow::Result;
+
+use crate::diagnostic::SourceError;
+
+/// A single bytecode instruction.
+///
+/// Programs are lowered into a flat list of these by [`compile`] before they are executed, so the
+/// interpreter never has to look at the source text at runtime.
+///
+/// Ops that touch a single cell address it by its `offset` from the tape pointer. [`compile`]
+/// always uses an offset of zero, but the optimizer folds moves into the offsets of the ops that
+/// follow them, so straight-line code only has to move the tape pointer once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// Add `amount` to a cell, wrapping on overflow.
+    Add { offset: isize, amount: i32 },
+
+    /// Move the tape pointer, wrapping around the ends of the tape.
+    Move(isize),
+
+    /// Set a cell to zero.
+    ///
+    /// This is never emitted by [`compile`], only by the optimizer for clear loops like `[-]`.
+    SetZero { offset: isize },
+
+    /// Set `len` neighboring cells to zero, starting with the one `offset` cells away, and
+    /// wrapping around the ends of the tape.
+    ///
+    /// This is never emitted by [`compile`], only by the optimizer for runs of clears of
+    /// neighboring cells, like `[-]>[-]>[-]`, so that they can be cleared all at once.
+    ClearRange { offset: isize, len: usize },
+
+    /// Set a cell to `value`, wrapping it to fit.
+    ///
+    /// This is never emitted by [`compile`], only by the optimizer for a clear loop followed by
+    /// adds, like `[-]+++`.
+    Set { offset: isize, value: i32 },
+
+    /// Add the current cell multiplied by `factor` to the cell `offset` cells away, wrapping on
+    /// overflow.
+    ///
+    /// This is never emitted by [`compile`], only by the optimizer for multiply loops like
+    /// `[->++<]`. The current cell is left untouched; the loop is always followed by an
+    /// [`Op::SetZero`].
+    MulAdd { offset: isize, factor: i32 },
+
+    /// Move the tape pointer by `stride` until it lands on a zero cell, giving up after going
+    /// around the entire tape once.
+    ///
+    /// This is never emitted by [`compile`], only by the optimizer for the body of scan loops like
+    /// `[>]`. The surrounding loop is kept, so a tape without any zero cells still loops forever.
+    Scan(isize),
+
+    /// If the current cell is zero, jump to the [`Op::JumpIfNonZero`] at the given index.
+    JumpIfZero(usize),
+
+    /// If the current cell is non-zero, jump back to the [`Op::JumpIfZero`] at the given index.
+    JumpIfNonZero(usize),
+
+    /// Write a cell to the output.
+    Output { offset: isize },
+
+    /// Read a byte from the input into a cell.
+    Input { offset: isize },
+
+    /// Switch to the tape `amount` tapes after the current one, going around from the last tape to
+    /// the first, and the other way for negative amounts. Every tape keeps its own tape pointer.
+    ///
+    /// This is only emitted by [`compile_with`], for the instructions of
+    /// [`Extensions::tape_switch`]. Programs normally run on a single tape, where it does nothing.
+    SwitchTape(isize),
+
+    /// Mark the loop starting at the next op as pure: its body only changes a few cells around the
+    /// tape pointer, without moving it or doing any I/O, so how it leaves those cells only depends
+    /// on their values when it starts.
+    ///
+    /// This is never emitted by [`compile`], only by the optimizer at its highest level. The
+    /// interpreter caches the results of marked loops by the values they start with, and replays
+    /// them when the same values come up again; everything else treats this as a no-op.
+    Memoize,
+}
+
+impl Display for Op {
+    /// Format the op as a line of an IR listing, like `add 1 +3`.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Op::Add { offset, amount } => write!(f, "add {offset} {amount:+}"),
+            Op::Move(amount) => write!(f, "move {amount:+}"),
+            Op::SetZero { offset } => write!(f, "zero {offset}"),
+            Op::ClearRange { offset, len } => {
+                write!(f, "zero {offset}..{}", offset + len.cast_signed())
+            }
+            Op::Set { offset, value } => write!(f, "set {offset} {value}"),
+            Op::MulAdd { offset, factor } => write!(f, "muladd {offset} {factor:+}"),
+            Op::Scan(stride) => write!(f, "scan {stride:+}"),
+            Op::JumpIfZero(target) => write!(f, "jz {target}"),
+            Op::JumpIfNonZero(target) => write!(f, "jnz {target}"),
+            Op::Output { offset } => write!(f, "out {offset}"),
+            Op::Input { offset } => write!(f, "in {offset}"),
+            Op::SwitchTape(amount) => write!(f, "switch {amount:+}"),
+            Op::Memoize => write!(f, "memoize"),
+        }
+    }
+}
+
+/// The range of bytes of the source an op was compiled from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// Return the smallest span that covers both `self` and `other`.
+    #[must_use]
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// A compiled program: its ops, along with the span of the source each of them came from.
+///
+/// The optimizer keeps track of spans as it rewrites ops, so an op made from several others spans
+/// all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Program {
+    pub ops: Vec<Op>,
+
+    /// The span of every op in `ops`, at the same index.
+    pub spans: Vec<Span>,
+}
+
+impl Program {
+    pub fn with_capacity(capacity: usize) -> Program {
+        Program {
+            ops: Vec::with_capacity(capacity),
+            spans: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn push(&mut self, op: Op, span: Span) {
+        self.ops.push(op);
+        self.spans.push(span);
+    }
+
+    pub fn pop(&mut self) -> Option<(Op, Span)> {
+        Some((self.ops.pop()?, self.spans.pop()?))
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.ops.truncate(len);
+        self.spans.truncate(len);
+    }
+
+    /// Return the span covering every op from `start` on.
+    ///
+    /// Panics if there are no such ops.
+    pub fn span_from(&self, start: usize) -> Span {
+        let spans = &self.spans[start..];
+        spans[0].to(spans[spans.len() - 1])
+    }
+
+    /// Iterate over the ops along with their spans.
+    pub fn iter(&self) -> impl Iterator<Item = (Op, Span)> + '_ {
+        self.ops.iter().copied().zip(self.spans.iter().copied())
+    }
+}
+
+impl IntoIterator for Program {
+    type Item = (Op, Span);
+    type IntoIter = std::iter::Zip<std::vec::IntoIter<Op>, std::vec::IntoIter<Span>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ops.into_iter().zip(self.spans)
+    }
+}
+
+/// The instructions beyond those of Brainfuck that [`compile_with`] recognizes, which are
+/// otherwise comments.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Extensions {
+    /// The instructions that switch to the previous and to the next tape, like `{` and `}`.
+    pub tape_switch: Option<[char; 2]>,
+}
+
+/// Compile a program into bytecode.
+///
+/// Every instruction is lowered to exactly one [`Op`], spanning just that instruction, and every
+/// other character is discarded.
+///
+/// An error is returned if there is an unmatched loop bracket.
+pub fn compile(program: &str) -> Result<Program> {
+    compile_with(program, Extensions::default())
+}
+
+/// Compile a program into bytecode like [`compile`], also recognizing the instructions of
+/// `extensions`.
+pub fn compile_with(program: &str, extensions: Extensions) -> Result<Program> {
+    let mut compiled = Program::default();
+
+    // Holds the source index of every `[` that is still waiting for its `]`.
+    let mut stack = Vec::new();
+
+    for (i, ch) in program.char_indices() {
+        let op = match ch {
+            '+' => Op::Add {
+                offset: 0,
+                amount: 1,
+            },
+            '-' => Op::Add {
+                offset: 0,
+                amount: -1,
+            },
+            '>' => Op::Move(1),
+            '<' => Op::Move(-1),
+            '.' => Op::Output { offset: 0 },
+            ',' => Op::Input { offset: 0 },
+
+            '[' => {
+                stack.push(i);
+                Op::JumpIfZero(0)
+            }
+            ']' => {
+                stack
+                    .pop()
+                    .ok_or_else(|| SourceError::in_source("Unmatched ]", program, i))?;
+                Op::JumpIfNonZero(0)
+            }
+
+            _ => match extensions.tape_switch {
+                Some([previous, _]) if ch == previous => Op::SwitchTape(-1),
+                Some([_, next]) if ch == next => Op::SwitchTape(1),
+                _ => continue,
+            },
+        };
+
+        // Extensions may be any character, so instructions aren't always a single byte long.
+        compiled.push(
+            op,
+            Span {
+                start: i,
+                end: i + ch.len_utf8(),
+            },
+        );
+    }
+
+    if let Some(i) = stack.pop() {
+        return Err(SourceError::in_source("Unmatched [", program, i).into());
+    }
+
+    link(&mut compiled.ops);
+    Ok(compiled)
+}
+
+/// Return an error at the first character of `program` that is neither whitespace nor an
+/// instruction, counting those of `extensions`, if there is one.
+pub fn check_strict(program: &str, extensions: Extensions) -> Result<()> {
+    let is_instruction = |ch| {
+        "+-<>.,[]".contains(ch)
+            || extensions
+                .tape_switch
+                .is_some_and(|switches| switches.contains(&ch))
+    };
+    match program
+        .char_indices()
+        .find(|&(_, ch)| !ch.is_whitespace() && !is_instruction(ch))
+    {
+        // Look-alikes of instructions only tell themselves apart by their code point.
+        Some((i, ch)) if ch.is_ascii() => {
+            Err(SourceError::in_source(format!("Not an instruction: `{ch}`"), program, i).into())
+        }
+        Some((i, ch)) => Err(SourceError::in_source(
+            format!("Not an instruction: `{ch}` (U+{:04X})", u32::from(ch)),
+            program,
+            i,
+        )
+        .into()),
+        None => Ok(()),
+    }
+}
+
+/// Blank out the `#!` line at the start of `source`, if there is one, so that a program can be an
+/// executable script like `#!/usr/bin/env beef`.
+///
+/// The line is replaced with as many spaces as it has bytes, rather than removed, so that the
+/// offsets of the instructions after it stay the same. Otherwise, any `-` or `.` in it would be
+/// instructions, and its `!` would split off the input of `--bang-input`.
+pub fn blank_shebang(source: &mut String) {
+    if source.starts_with("#!") {
+        let end = source.find('\n').unwrap_or(source.len());
+        source.replace_range(..end, &" ".repeat(end));
+    }
+}
+
+/// Split the source of a program at the first `!` outside of its loops, into the program and the
+/// input that comes after the `!`, or `None` if there's no such `!`.
+///
+/// A `!` inside a loop, like in a comment loop at the start of the program, stays a comment.
+pub fn split_input(source: &str) -> (&str, Option<&str>) {
+    let mut depth = 0_usize;
+    for (i, byte) in source.bytes().enumerate() {
+        match byte {
+            b'[' => depth += 1,
+            b']' => depth = depth.saturating_sub(1),
+            b'!' if depth == 0 => return (&source[..i], Some(&source[i + 1..])),
+            _ => {}
+        }
+    }
+    (source, None)
+}
+
+/// Point every jump at its matching jump.
+///
+/// Passes that add or remove ops call this afterwards instead of patching the targets themselves.
+///
+/// # Panics
+///
+/// Panics if the jumps in `ops` aren't balanced.
+pub fn link(ops: &mut [Op]) {
+    let mut stack = Vec::new();
+
+    for i in 0..ops.len() {
+        match ops[i] {
+            Op::JumpIfZero(_) => stack.push(i),
+            Op::JumpIfNonZero(_) => {
+                let open_index = stack.pop().expect("jumps should be balanced");
+                ops[open_index] = Op::JumpIfZero(i);
+                ops[i] = Op::JumpIfNonZero(open_index);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INC: Op = Op::Add {
+        offset: 0,
+        amount: 1,
+    };
+    const DEC: Op = Op::Add {
+        offset: 0,
+        amount: -1,
+    };
+
+    #[test]
+    fn one_op_per_instruction() {
+        let ops = compile("+-><.,").unwrap().ops;
+        assert_eq!(
+            ops,
+            [
+                INC,
+                DEC,
+                Op::Move(1),
+                Op::Move(-1),
+                Op::Output { offset: 0 },
+                Op::Input { offset: 0 }
+            ]
+        );
+    }
+
+    #[test]
+    fn tape_switches() {
+        assert_eq!(compile("{}").unwrap().ops, []);
+
+        let extensions = Extensions {
+            tape_switch: Some(['{', '→']),
+        };
+        let program = compile_with("{→}+", extensions).unwrap();
+        assert_eq!(program.ops, [Op::SwitchTape(-1), Op::SwitchTape(1), INC]);
+        assert_eq!(program.spans[1], Span { start: 1, end: 4 });
+    }
+
+    #[test]
+    fn skip_comments() {
+        let program = compile("a+ é\n-").unwrap();
+        assert_eq!(program.ops, [INC, DEC]);
+        assert_eq!(
+            program.spans,
+            [Span { start: 1, end: 2 }, Span { start: 6, end: 7 }]
+        );
+    }
+
+    #[test]
+    fn link_loops() {
+        let ops = compile("[+[]]").unwrap().ops;
+        assert_eq!(
+            ops,
+            [
+                Op::JumpIfZero(4),
+                INC,
+                Op::JumpIfZero(3),
+                Op::JumpIfNonZero(2),
+                Op::JumpIfNonZero(0)
+            ]
+        );
+    }
+
+    #[test]
+    fn split_off_input() {
+        assert_eq!(split_input(",[.,]!hi!"), (",[.,]", Some("hi!")));
+        assert_eq!(split_input("[Say hi!]+!"), ("[Say hi!]+", Some("")));
+        assert_eq!(split_input("+[!]"), ("+[!]", None));
+    }
+
+    #[test]
+    fn blank_shebang_line() {
+        let mut source = "#!/usr/bin/env -S beef -O2\n+.".to_owned();
+        blank_shebang(&mut source);
+        assert_eq!(source, format!("{}\n+.", " ".repeat(26)));
+        assert_eq!(
+            compile(&source).unwrap().spans[0],
+            Span { start: 27, end: 28 }
+        );
+
+        let mut source = "+#!-".to_owned();
+        blank_shebang(&mut source);
+        assert_eq!(source, "+#!-");
+    }
+
+    #[test]
+    fn strict_characters() {
+        assert!(check_strict(" +[->+<]\n.", Extensions::default()).is_ok());
+        assert_eq!(
+            check_strict("+\n+\u{2039}", Extensions::default())
+                .unwrap_err()
+                .to_string(),
+            "Not an instruction: `\u{2039}` (U+2039) at 2:2"
+        );
+
+        let extensions = Extensions {
+            tape_switch: Some(['{', '}']),
+        };
+        assert!(check_strict("+}-", extensions).is_ok());
+        assert_eq!(
+            check_strict("+}-", Extensions::default())
+                .unwrap_err()
+                .to_string(),
+            "Not an instruction: `}` at 1:2"
+        );
+    }
+
+    #[test]
+    fn unmatched_brackets() {
+        assert_eq!(
+            compile("+\n+]").unwrap_err().to_string(),
+            "Unmatched ] at 2:2"
+        );
+        assert_eq!(
+            compile("[[]").unwrap_err().to_string(),
+            "Unmatched [ at 1:1"
+        );
+    }
+}
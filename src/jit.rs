@@ -0,0 +1,343 @@
+use std::{
+    any::Any,
+    io::{Read, Write},
+    slice,
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    interpreter::{self, Cell, IoMode},
+    ir::Op,
+};
+
+#[cfg(feature = "jit")]
+mod cranelift;
+#[cfg(feature = "jit-x86-64")]
+mod x86_64;
+
+/// A way of compiling programs to native code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Compile with Cranelift, which supports most common architectures.
+    #[cfg(feature = "jit")]
+    Cranelift,
+
+    /// Compile with a small hand-written x86-64 code generator.
+    #[cfg(feature = "jit-x86-64")]
+    X86_64,
+}
+
+impl Backend {
+    /// Every backend beef was built with, in order of preference.
+    pub const ALL: &[Backend] = &[
+        #[cfg(feature = "jit")]
+        Backend::Cranelift,
+        #[cfg(feature = "jit-x86-64")]
+        Backend::X86_64,
+    ];
+
+    /// Return whether this backend can generate code for the host machine.
+    pub fn is_supported(self) -> bool {
+        match self {
+            #[cfg(feature = "jit")]
+            Backend::Cranelift => cranelift_native::builder().is_ok(),
+            #[cfg(feature = "jit-x86-64")]
+            Backend::X86_64 => x86_64::IS_SUPPORTED,
+        }
+    }
+}
+
+/// The signature of a compiled program.
+///
+/// It takes the I/O state, the start of the tape, and the initial tape pointer, and returns the
+/// final tape pointer, or a negative number if an I/O error occured.
+type CompiledProgram = extern "C" fn(*mut Io, *mut u8, usize) -> isize;
+
+/// The I/O state of a running program, passed back to the helpers it calls for `.` and `,`.
+struct Io<'a> {
+    input: &'a mut dyn Read,
+    output: &'a mut dyn Write,
+    mode: IoMode,
+
+    /// Write and read a cell of the tape as the type of cells it holds, which are bytes, but
+    /// written and read as numbers differently if they are signed.
+    write_cell: fn(&mut dyn Write, u8, IoMode) -> Result<()>,
+    read_cell: fn(&mut dyn Read, &mut dyn Write, u8, IoMode) -> Result<u8>,
+
+    /// The error that stopped the program, if any.
+    error: Option<anyhow::Error>,
+}
+
+/// Compile a program to native code and run it on `tape` of `C` cells, which are bytes, starting at
+/// `tape_pointer`.
+///
+/// The tape pointer is updated once the program finishes, and `.` and `,` write and read the way
+/// `mode` says. An error is returned if the backend does not support the host machine, or
+/// if reading from `input` or writing to `output` fails.
+pub fn run<C: Cell>(
+    backend: Backend,
+    ops: &[Op],
+    tape: &mut [u8],
+    tape_pointer: &mut usize,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+    mode: IoMode,
+) -> Result<()> {
+    compile(backend, ops, tape.len())?.run::<C>(tape, tape_pointer, input, output, mode)
+}
+
+/// Compile a program to native code, for a tape of `tape_len` cells.
+///
+/// An error is returned if the backend does not support the host machine.
+pub fn compile(backend: Backend, ops: &[Op], tape_len: usize) -> Result<Compiled> {
+    match backend {
+        #[cfg(feature = "jit")]
+        Backend::Cranelift => cranelift::compile(ops, tape_len),
+        #[cfg(feature = "jit-x86-64")]
+        Backend::X86_64 => x86_64::compile(ops, tape_len),
+    }
+}
+
+/// A program compiled to native code, which can run any number of times.
+pub struct Compiled {
+    program: CompiledProgram,
+    tape_len: usize,
+
+    /// The executable memory `program` lives in, which is freed once it is dropped.
+    _memory: Box<dyn Any>,
+}
+
+impl Compiled {
+    /// Run the program on `tape` of `C` cells, which are bytes, starting at `tape_pointer`, and
+    /// update the tape pointer once it finishes. `.` and `,` write and read the way `mode` says.
+    ///
+    /// An error is returned if reading from `input` or writing to `output` fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tape isn't as long as the one the program was compiled for.
+    pub fn run<C: Cell>(
+        &self,
+        tape: &mut [u8],
+        tape_pointer: &mut usize,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+        mode: IoMode,
+    ) -> Result<()> {
+        assert_eq!(
+            tape.len(),
+            self.tape_len,
+            "the program was compiled for another tape"
+        );
+
+        let mut io = Io {
+            input,
+            output,
+            mode,
+            write_cell: write_cell::<C>,
+            read_cell: read_cell::<C>,
+            error: None,
+        };
+        let result = (self.program)(&raw mut io, tape.as_mut_ptr(), *tape_pointer);
+
+        match usize::try_from(result) {
+            Ok(pointer) => {
+                *tape_pointer = pointer;
+                Ok(())
+            }
+            Err(_) => Err(io
+                .error
+                .unwrap_or_else(|| anyhow!("Compiled program failed"))),
+        }
+    }
+}
+
+/// Write a cell to the output. Returns zero on success.
+extern "C" fn output(io: *mut Io, value: u8) -> u8 {
+    // SAFETY: Compiled programs only ever pass along the pointer given to them by `Compiled::run`.
+    let io = unsafe { &mut *io };
+
+    match (io.write_cell)(io.output, value, io.mode) {
+        Ok(()) => 0,
+        Err(error) => {
+            io.error = Some(error);
+            1
+        }
+    }
+}
+
+/// Read a cell from the input, which holds `current` until then. Returns a negative number on
+/// failure.
+extern "C" fn input(io: *mut Io, current: u8) -> i32 {
+    // SAFETY: Compiled programs only ever pass along the pointer given to them by `Compiled::run`.
+    let io = unsafe { &mut *io };
+
+    match (io.read_cell)(io.input, io.output, current, io.mode) {
+        Ok(cell) => i32::from(cell),
+        Err(error) => {
+            io.error = Some(error);
+            -1
+        }
+    }
+}
+
+/// Write a byte of the tape to `output` as a cell of type `C`.
+fn write_cell<C: Cell>(output: &mut dyn Write, value: u8, mode: IoMode) -> Result<()> {
+    interpreter::write_cell(output, &C::from_byte(value), mode)
+}
+
+/// Read a byte of the tape, which holds `current` until then, from `input` as a cell of type `C`.
+fn read_cell<C: Cell>(
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+    current: u8,
+    mode: IoMode,
+) -> Result<u8> {
+    let mut cell = C::from_byte(current);
+    interpreter::read_into(&mut cell, input, output, mode)?;
+    Ok(cell.low_byte())
+}
+
+/// Return the tape pointer after a scan. See [`Op::Scan`].
+extern "C" fn scan(tape: *const u8, len: usize, pointer: usize, stride: isize) -> usize {
+    // SAFETY: Compiled programs only ever pass along the tape given to them by `Compiled::run`.
+    let tape = unsafe { slice::from_raw_parts(tape, len) };
+    interpreter::scan(tape, pointer, stride)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        interpreter::{Eof, IoFormat},
+        ir, optimizer,
+    };
+
+    const TAPE_SIZE: usize = 64;
+
+    /// Run a program with every supported backend, and return the final tape, tape pointer, and
+    /// output of each.
+    fn run_with_input(program: &str, input: &[u8]) -> Vec<(Vec<u8>, usize, Vec<u8>)> {
+        let ops = optimizer::optimize(ir::compile(program).unwrap()).ops;
+
+        Backend::ALL
+            .iter()
+            .filter(|backend| backend.is_supported())
+            .map(|&backend| {
+                let mut tape = vec![0; TAPE_SIZE];
+                let mut tape_pointer = 0;
+                let mut output = Vec::new();
+                run::<u8>(
+                    backend,
+                    &ops,
+                    &mut tape,
+                    &mut tape_pointer,
+                    &mut &input[..],
+                    &mut output,
+                    IoMode::default(),
+                )
+                .unwrap();
+                (tape, tape_pointer, output)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn arithmetic_and_loops() {
+        for (tape, tape_pointer, _) in run_with_input("+++++[->++>---<<]>>[>]+<[-]->>[-]++", &[]) {
+            assert_eq!(tape[..5], [0, 10, 255, 1, 2]);
+            assert_eq!(tape_pointer, 4);
+        }
+    }
+
+    #[test]
+    fn wrapping() {
+        for (tape, tape_pointer, _) in run_with_input("<+<<-[>]", &[]) {
+            assert_eq!(tape[TAPE_SIZE - 1], 1);
+            assert_eq!(tape[TAPE_SIZE - 3], 255);
+            assert_eq!(tape_pointer, TAPE_SIZE - 2);
+        }
+    }
+
+    #[test]
+    fn input_and_output() {
+        for (_, _, output) in run_with_input(",+.>,.", b"ab") {
+            assert_eq!(output, b"bb");
+        }
+    }
+
+    #[test]
+    fn end_of_input() {
+        let ops = ir::compile("+,").unwrap().ops;
+
+        for &backend in Backend::ALL {
+            let run_to_eof = |eof| {
+                let mut tape = [0];
+                run::<u8>(
+                    backend,
+                    &ops,
+                    &mut tape,
+                    &mut 0,
+                    &mut &[][..],
+                    &mut Vec::new(),
+                    IoMode {
+                        eof,
+                        ..IoMode::default()
+                    },
+                )
+                .map(|()| tape[0])
+            };
+            assert_eq!(run_to_eof(Eof::Zero).unwrap(), 0);
+            assert_eq!(run_to_eof(Eof::MinusOne).unwrap(), 255);
+            assert_eq!(run_to_eof(Eof::Unchanged).unwrap(), 1);
+            assert!(run_to_eof(Eof::Error).is_err());
+        }
+    }
+
+    #[test]
+    fn binary_io() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        for (_, _, output) in run_with_input(&",.".repeat(256), &bytes) {
+            assert_eq!(output, bytes);
+        }
+    }
+
+    #[test]
+    fn numeric_io() {
+        let ops = ir::compile(",-.").unwrap().ops;
+        let mode = IoMode {
+            format: IoFormat::Numeric,
+            ..IoMode::default()
+        };
+
+        for &backend in Backend::ALL {
+            let mut output = Vec::new();
+            run::<u8>(
+                backend,
+                &ops,
+                &mut [0],
+                &mut 0,
+                &mut &b" 0"[..],
+                &mut output,
+                mode,
+            )
+            .unwrap();
+            assert_eq!(output, b"255\n");
+
+            // Signed bytes hold the same bits, but are written as negative numbers.
+            let mut output = Vec::new();
+            run::<i8>(
+                backend,
+                &ops,
+                &mut [0],
+                &mut 0,
+                &mut &b"-127"[..],
+                &mut output,
+                mode,
+            )
+            .unwrap();
+            assert_eq!(output, b"-128\n");
+        }
+    }
+}
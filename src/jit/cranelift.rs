@@ -0,0 +1,323 @@
+use std::mem::{self, ManuallyDrop};
+
+use anyhow::{anyhow, Result};
+use cranelift_codegen::{
+    ir::{
+        condcodes::IntCC, types, AbiParam, Block, FuncRef, InstBuilder, MemFlagsData, Type, Value,
+    },
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, FuncId, Linkage, Module};
+
+use super::{Compiled, CompiledProgram};
+use crate::{interpreter, ir::Op};
+
+/// Compile a program with Cranelift. See [`super::compile`].
+pub fn compile(ops: &[Op], tape_len: usize) -> Result<Compiled> {
+    let mut module = new_module()?;
+    let func = Codegen::compile(&mut module, ops, tape_len)?;
+    module.finalize_definitions()?;
+
+    // SAFETY: The function was declared with exactly this signature in `Codegen::compile`.
+    let program: CompiledProgram = unsafe { mem::transmute(module.get_finalized_function(func)) };
+    Ok(Compiled {
+        program,
+        tape_len,
+        _memory: Box::new(Memory(ManuallyDrop::new(module))),
+    })
+}
+
+/// A module whose memory is freed once it is dropped.
+struct Memory(ManuallyDrop<JITModule>);
+
+impl Drop for Memory {
+    fn drop(&mut self) {
+        // SAFETY: Memory is only dropped along with the `Compiled` holding it, which never hands out
+        // its program, so nothing can call into the module anymore.
+        unsafe { ManuallyDrop::take(&mut self.0).free_memory() };
+    }
+}
+
+/// Return a new module for the host machine, with the helpers compiled programs call linked in.
+fn new_module() -> Result<JITModule> {
+    let mut flags = settings::builder();
+    flags.set("opt_level", "speed")?;
+    flags.set("use_colocated_libcalls", "false")?;
+    flags.set("is_pic", "false")?;
+
+    let isa = cranelift_native::builder()
+        .map_err(|msg| anyhow!("Host machine is not supported by the JIT: {msg}"))?
+        .finish(settings::Flags::new(flags))?;
+
+    let mut builder = JITBuilder::with_isa(isa, default_libcall_names());
+    builder.symbol("beef_output", super::output as *const u8);
+    builder.symbol("beef_input", super::input as *const u8);
+    builder.symbol("beef_scan", super::scan as *const u8);
+
+    Ok(JITModule::new(builder))
+}
+
+/// The state needed while compiling a single program.
+struct Codegen<'a> {
+    builder: FunctionBuilder<'a>,
+    pointer_type: Type,
+
+    io: Value,
+    tape: Value,
+    tape_len: usize,
+
+    /// The tape pointer, as an index into the tape.
+    tape_pointer: Variable,
+
+    output: FuncRef,
+    input: FuncRef,
+    scan: FuncRef,
+
+    /// The block to jump to when an I/O helper fails.
+    error_block: Block,
+}
+
+impl Codegen<'_> {
+    /// Compile a program into a function in `module`, and return its ID.
+    fn compile(module: &mut JITModule, ops: &[Op], tape_len: usize) -> Result<FuncId> {
+        let frontend_config = module.target_config();
+        let pointer_type = frontend_config.pointer_type();
+
+        let mut signature = module.make_signature();
+        signature.params = vec![AbiParam::new(pointer_type); 3];
+        signature.returns = vec![AbiParam::new(pointer_type)];
+        let func = module.declare_function("program", Linkage::Local, &signature)?;
+
+        let mut output_signature = module.make_signature();
+        output_signature.params = vec![AbiParam::new(pointer_type), AbiParam::new(types::I8)];
+        output_signature.returns = vec![AbiParam::new(types::I8)];
+        let output = module.declare_function("beef_output", Linkage::Import, &output_signature)?;
+
+        let mut input_signature = module.make_signature();
+        input_signature.params = vec![AbiParam::new(pointer_type), AbiParam::new(types::I8)];
+        input_signature.returns = vec![AbiParam::new(types::I32)];
+        let input = module.declare_function("beef_input", Linkage::Import, &input_signature)?;
+
+        let mut scan_signature = module.make_signature();
+        scan_signature.params = vec![AbiParam::new(pointer_type); 4];
+        scan_signature.returns = vec![AbiParam::new(pointer_type)];
+        let scan = module.declare_function("beef_scan", Linkage::Import, &scan_signature)?;
+
+        let mut ctx = module.make_context();
+        ctx.func.signature = signature;
+        let mut func_ctx = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+
+        let output = module.declare_func_in_func(output, builder.func);
+        let input = module.declare_func_in_func(input, builder.func);
+        let scan = module.declare_func_in_func(scan, builder.func);
+
+        let entry_block = builder.create_block();
+        builder.append_block_params_for_function_params(entry_block);
+        builder.switch_to_block(entry_block);
+        let &[io, tape, initial_pointer] = builder.block_params(entry_block) else {
+            unreachable!("the signature has exactly three parameters");
+        };
+
+        let tape_pointer = builder.declare_var(pointer_type);
+        builder.def_var(tape_pointer, initial_pointer);
+        let error_block = builder.create_block();
+
+        let mut codegen = Codegen {
+            builder,
+            pointer_type,
+            io,
+            tape,
+            tape_len,
+            tape_pointer,
+            output,
+            input,
+            scan,
+            error_block,
+        };
+        codegen.compile_ops(ops);
+        codegen.builder.seal_all_blocks();
+        codegen.builder.finalize(frontend_config);
+
+        module.define_function(func, &mut ctx)?;
+        module.clear_context(&mut ctx);
+
+        Ok(func)
+    }
+
+    /// Compile the body of the program, followed by its return and error paths.
+    fn compile_ops(&mut self, ops: &[Op]) {
+        // Holds the body and exit blocks of every loop that is still open.
+        let mut loops = Vec::new();
+
+        for &op in ops {
+            match op {
+                Op::Add { offset, amount } => {
+                    let value = self.load(offset);
+                    let amount = self.cell_const(amount);
+                    let sum = self.builder.ins().iadd(value, amount);
+                    self.store(offset, sum);
+                }
+                Op::Move(amount) => {
+                    let pointer = self.builder.use_var(self.tape_pointer);
+                    let moved = self.wrapping_index(pointer, amount);
+                    self.builder.def_var(self.tape_pointer, moved);
+                }
+                Op::SetZero { offset } => {
+                    let zero = self.cell_const(0);
+                    self.store(offset, zero);
+                }
+                Op::ClearRange { offset, len } => {
+                    let zero = self.cell_const(0);
+                    for offset in (offset..).take(len) {
+                        self.store(offset, zero);
+                    }
+                }
+                Op::Set { offset, value } => {
+                    let value = self.cell_const(value);
+                    self.store(offset, value);
+                }
+                Op::MulAdd { offset, factor } => {
+                    let value = self.load(0);
+                    let factor = self.cell_const(factor);
+                    let product = self.builder.ins().imul(value, factor);
+                    let target = self.load(offset);
+                    let sum = self.builder.ins().iadd(target, product);
+                    self.store(offset, sum);
+                }
+                Op::Scan(stride) => {
+                    let len = self.pointer_const(self.tape_len.cast_signed());
+                    let stride = self.pointer_const(stride);
+                    let pointer = self.builder.use_var(self.tape_pointer);
+                    let call = self
+                        .builder
+                        .ins()
+                        .call(self.scan, &[self.tape, len, pointer, stride]);
+                    let found = self.builder.inst_results(call)[0];
+                    self.builder.def_var(self.tape_pointer, found);
+                }
+
+                Op::JumpIfZero(_) => {
+                    let body_block = self.builder.create_block();
+                    let exit_block = self.builder.create_block();
+                    let value = self.load(0);
+                    self.builder
+                        .ins()
+                        .brif(value, body_block, &[], exit_block, &[]);
+                    self.builder.switch_to_block(body_block);
+                    loops.push((body_block, exit_block));
+                }
+                Op::JumpIfNonZero(_) => {
+                    let (body_block, exit_block) = loops.pop().expect("jumps should be balanced");
+                    let value = self.load(0);
+                    self.builder
+                        .ins()
+                        .brif(value, body_block, &[], exit_block, &[]);
+                    self.builder.switch_to_block(exit_block);
+                }
+
+                Op::Output { offset } => {
+                    let value = self.load(offset);
+                    let call = self.builder.ins().call(self.output, &[self.io, value]);
+                    let failed = self.builder.inst_results(call)[0];
+                    self.continue_unless(failed);
+                }
+                Op::Input { offset } => {
+                    let current = self.load(offset);
+                    let call = self.builder.ins().call(self.input, &[self.io, current]);
+                    let result = self.builder.inst_results(call)[0];
+                    let failed = self
+                        .builder
+                        .ins()
+                        .icmp_imm_s(IntCC::SignedLessThan, result, 0);
+                    self.continue_unless(failed);
+                    let value = self.builder.ins().ireduce(types::I8, result);
+                    self.store(offset, value);
+                }
+                Op::Memoize | Op::SwitchTape(_) => {}
+            }
+        }
+
+        let pointer = self.builder.use_var(self.tape_pointer);
+        self.builder.ins().return_(&[pointer]);
+
+        self.builder.switch_to_block(self.error_block);
+        let failed = self.pointer_const(-1);
+        self.builder.ins().return_(&[failed]);
+    }
+
+    /// Jump to the error block if `failed` is non-zero, and continue in a new block otherwise.
+    fn continue_unless(&mut self, failed: Value) {
+        let next_block = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(failed, self.error_block, &[], next_block, &[]);
+        self.builder.switch_to_block(next_block);
+    }
+
+    /// Load the cell `offset` cells away from the tape pointer.
+    fn load(&mut self, offset: isize) -> Value {
+        let address = self.cell_address(offset);
+        self.builder
+            .ins()
+            .load(types::I8, MemFlagsData::trusted(), address, 0)
+    }
+
+    /// Store `value` in the cell `offset` cells away from the tape pointer.
+    fn store(&mut self, offset: isize, value: Value) {
+        let address = self.cell_address(offset);
+        self.builder
+            .ins()
+            .store(MemFlagsData::trusted(), value, address, 0);
+    }
+
+    /// Return the address of the cell `offset` cells away from the tape pointer.
+    fn cell_address(&mut self, offset: isize) -> Value {
+        let pointer = self.builder.use_var(self.tape_pointer);
+        let index = self.wrapping_index(pointer, offset);
+        self.builder.ins().iadd(self.tape, index)
+    }
+
+    /// Return the index `offset` cells away from `index`, wrapping around the ends of the tape.
+    ///
+    /// `index` must already be within the tape.
+    fn wrapping_index(&mut self, index: Value, offset: isize) -> Value {
+        let len = self.tape_len.cast_signed();
+        let offset = offset % len;
+        if offset == 0 {
+            return index;
+        }
+
+        let moved = self.builder.ins().iadd_imm_s(index, offset as i64);
+        let (wrapped, out_of_bounds) = if offset > 0 {
+            let wrapped = self.builder.ins().iadd_imm_s(moved, -(len as i64));
+            let out_of_bounds =
+                self.builder
+                    .ins()
+                    .icmp_imm_s(IntCC::SignedGreaterThanOrEqual, moved, len as i64);
+            (wrapped, out_of_bounds)
+        } else {
+            let wrapped = self.builder.ins().iadd_imm_s(moved, len as i64);
+            let out_of_bounds = self
+                .builder
+                .ins()
+                .icmp_imm_s(IntCC::SignedLessThan, moved, 0);
+            (wrapped, out_of_bounds)
+        };
+
+        self.builder.ins().select(out_of_bounds, wrapped, moved)
+    }
+
+    /// Return a constant that can be added to a cell, wrapping `value` modulo 256.
+    fn cell_const(&mut self, value: i32) -> Value {
+        let value = interpreter::wrap_to_cell(value);
+        self.builder.ins().iconst(types::I8, i64::from(value))
+    }
+
+    /// Return a pointer-sized constant.
+    fn pointer_const(&mut self, value: isize) -> Value {
+        self.builder.ins().iconst(self.pointer_type, value as i64)
+    }
+}
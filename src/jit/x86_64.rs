@@ -0,0 +1,251 @@
+use std::mem;
+
+use anyhow::{anyhow, Result};
+use dynasmrt::{
+    dynasm, x64::Assembler, AssemblyOffset, DynamicLabel, DynasmApi, DynasmLabelApi,
+    ExecutableBuffer,
+};
+
+use super::{Compiled, CompiledProgram};
+use crate::{interpreter, ir::Op};
+
+/// Whether the generated code can run on the host machine.
+///
+/// Besides requiring an x86-64 processor, the generated code follows the System V calling
+/// convention, which rules out Windows.
+pub const IS_SUPPORTED: bool = cfg!(all(target_arch = "x86_64", unix));
+
+/// Compile a program to x86-64 machine code. See [`super::compile`].
+pub fn compile(ops: &[Op], tape_len: usize) -> Result<Compiled> {
+    if !IS_SUPPORTED {
+        return Err(anyhow!(
+            "The x86-64 JIT backend does not support this machine"
+        ));
+    }
+
+    let (buffer, start) = assemble(ops, tape_len)?;
+
+    // SAFETY: `assemble` emits a function with exactly this signature, and we checked above that
+    // the host machine can run it.
+    let program: CompiledProgram = unsafe { mem::transmute(buffer.ptr(start)) };
+    Ok(Compiled {
+        program,
+        tape_len,
+        _memory: Box::new(buffer),
+    })
+}
+
+/// Assemble a program, and return the buffer holding it along with the offset of its entry point.
+///
+/// While the program runs, the registers are used as follows:
+///
+/// - `r12` holds the address of the start of the tape
+/// - `r13` holds the tape pointer, as an index into the tape
+/// - `r14` holds the I/O state passed to the helpers
+/// - `r15` holds the length of the tape
+/// - `rax`, `rcx`, and `rdx` are scratch registers
+fn assemble(ops: &[Op], tape_len: usize) -> Result<(ExecutableBuffer, AssemblyOffset)> {
+    let mut asm = Assembler::new()?;
+    let tape_len = i64::try_from(tape_len)?;
+    let start = asm.offset();
+
+    // Save all the callee-saved registers we use. Pushing an odd number of them also keeps the
+    // stack aligned to 16 bytes for the helper calls.
+    dynasm!(asm
+        ; push rbx
+        ; push r12
+        ; push r13
+        ; push r14
+        ; push r15
+        ; mov r14, rdi
+        ; mov r12, rsi
+        ; mov r13, rdx
+        ; mov r15, QWORD tape_len
+    );
+
+    // Holds the start and end labels of every loop that is still open.
+    let mut loops = Vec::new();
+
+    for &op in ops {
+        emit_op(&mut asm, op, tape_len, &mut loops);
+    }
+
+    dynasm!(asm
+        ; mov rax, r13
+        ; ->exit:
+        ; pop r15
+        ; pop r14
+        ; pop r13
+        ; pop r12
+        ; pop rbx
+        ; ret
+
+        ; ->error:
+        ; mov rax, -1
+        ; jmp ->exit
+    );
+
+    let buffer = asm
+        .finalize()
+        .map_err(|_| anyhow!("Failed to finalize compiled program"))?;
+    Ok((buffer, start))
+}
+
+/// Emit the code for a single op.
+///
+/// `loops` holds the start and end labels of every loop that is still open.
+fn emit_op(
+    asm: &mut Assembler,
+    op: Op,
+    tape_len: i64,
+    loops: &mut Vec<(DynamicLabel, DynamicLabel)>,
+) {
+    match op {
+        Op::Add { offset, amount } => {
+            emit_index(asm, offset, tape_len);
+            let amount = interpreter::wrap_to_cell(amount).cast_signed();
+            dynasm!(asm
+                ; add BYTE [r12 + rax], amount
+            );
+        }
+        Op::Move(amount) => {
+            emit_index(asm, amount, tape_len);
+            dynasm!(asm
+                ; mov r13, rax
+            );
+        }
+        Op::SetZero { offset } => {
+            emit_index(asm, offset, tape_len);
+            dynasm!(asm
+                ; mov BYTE [r12 + rax], 0
+            );
+        }
+        Op::ClearRange { offset, len } => {
+            for offset in (offset..).take(len) {
+                emit_op(asm, Op::SetZero { offset }, tape_len, loops);
+            }
+        }
+        Op::Set { offset, value } => {
+            emit_index(asm, offset, tape_len);
+            let value = interpreter::wrap_to_cell(value).cast_signed();
+            dynasm!(asm
+                ; mov BYTE [r12 + rax], value
+            );
+        }
+        Op::MulAdd { offset, factor } => {
+            let factor = i32::from(interpreter::wrap_to_cell(factor));
+            dynasm!(asm
+                ; movzx edx, BYTE [r12 + r13]
+                ; imul edx, edx, factor
+            );
+            emit_index(asm, offset, tape_len);
+            dynasm!(asm
+                ; add BYTE [r12 + rax], dl
+            );
+        }
+        Op::Scan(stride) => {
+            dynasm!(asm
+                ; mov rdi, r12
+                ; mov rsi, r15
+                ; mov rdx, r13
+                ; mov rcx, QWORD stride as i64
+                ; mov rax, QWORD address(super::scan as *const ())
+                ; call rax
+                ; mov r13, rax
+            );
+        }
+
+        Op::JumpIfZero(_) => {
+            let start_label = asm.new_dynamic_label();
+            let end_label = asm.new_dynamic_label();
+            dynasm!(asm
+                ; cmp BYTE [r12 + r13], 0
+                ; jz =>end_label
+                ; =>start_label
+            );
+            loops.push((start_label, end_label));
+        }
+        Op::JumpIfNonZero(_) => {
+            let (start_label, end_label) = loops.pop().expect("jumps should be balanced");
+            dynasm!(asm
+                ; cmp BYTE [r12 + r13], 0
+                ; jnz =>start_label
+                ; =>end_label
+            );
+        }
+
+        Op::Output { offset } => {
+            emit_index(asm, offset, tape_len);
+            dynasm!(asm
+                ; movzx esi, BYTE [r12 + rax]
+                ; mov rdi, r14
+                ; mov rax, QWORD address(super::output as *const ())
+                ; call rax
+                ; test al, al
+                ; jnz ->error
+            );
+        }
+        Op::Input { offset } => {
+            // The helper gets the cell, to leave it unchanged at the end of the input, and the
+            // index of the cell is kept across the call in `rbx`, which the helper preserves.
+            emit_index(asm, offset, tape_len);
+            dynasm!(asm
+                ; mov rbx, rax
+                ; movzx esi, BYTE [r12 + rbx]
+                ; mov rdi, r14
+                ; mov rax, QWORD address(super::input as *const ())
+                ; call rax
+                ; test eax, eax
+                ; js ->error
+                ; mov BYTE [r12 + rbx], al
+            );
+        }
+        Op::Memoize | Op::SwitchTape(_) => {}
+    }
+}
+
+/// Emit code that puts the index `offset` cells away from the tape pointer in `rax`, wrapping
+/// around the ends of the tape.
+fn emit_index(asm: &mut Assembler, offset: isize, tape_len: i64) {
+    let offset = offset as i64 % tape_len;
+
+    match i32::try_from(offset) {
+        Ok(0) => {
+            dynasm!(asm
+                ; mov rax, r13
+            );
+            return;
+        }
+        Ok(offset) => {
+            dynasm!(asm
+                ; lea rax, [r13 + offset]
+            );
+        }
+        Err(_) => {
+            dynasm!(asm
+                ; mov rax, QWORD offset
+                ; add rax, r13
+            );
+        }
+    }
+
+    if offset > 0 {
+        dynasm!(asm
+            ; mov rcx, rax
+            ; sub rcx, r15
+            ; cmp rax, r15
+            ; cmovae rax, rcx
+        );
+    } else {
+        dynasm!(asm
+            ; lea rcx, [rax + r15]
+            ; test rax, rax
+            ; cmovs rax, rcx
+        );
+    }
+}
+
+/// Return the address of a helper, as an immediate for `mov`.
+fn address(helper: *const ()) -> i64 {
+    helper.addr().cast_signed() as i64
+}
@@ -0,0 +1,17 @@
+//! The Brainfuck interpreter behind the `beef` command, for running programs from other Rust code.
+//!
+//! Programs are compiled to bytecode by [`ir::compile`], optimized by [`optimizer`], and run by an
+//! [`interpreter::Interpreter`], which can read its input from and write its output to anything.
+
+#![deny(clippy::all)]
+#![warn(clippy::pedantic)]
+// The docs say in their own words when functions return errors, and getters aren't worth marking.
+#![allow(clippy::missing_errors_doc, clippy::must_use_candidate)]
+
+pub mod check;
+pub mod diagnostic;
+pub mod interpreter;
+pub mod ir;
+#[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+pub mod jit;
+pub mod optimizer;
@@ -0,0 +1,170 @@
+use crate::check::{position, Problem};
+
+/// Look for instructions in `source` that are likely mistakes, returning every one found with an
+/// explanation, in the order they come in.
+///
+/// Unlike [`crate::check::check`], the program may still work as meant despite these, like a loop
+/// at the start of the program that is meant as a comment.
+pub fn lint(source: &str) -> Vec<Problem> {
+    let instructions: Vec<_> = source
+        .char_indices()
+        .filter(|&(_, ch)| "+-<>.,[]".contains(ch))
+        .collect();
+    let mut problems = Vec::new();
+    let mut warn = |offset, message: String| problems.push(Problem::new(source, offset, message));
+
+    // Holds the source index of every `[` that is still waiting for its `]`.
+    let mut stack = Vec::new();
+    // The source index of the last `]` that closed a loop.
+    let mut last_closed = None;
+    // The number of loops around a loop that never runs while inside of it, as what's in there
+    // doesn't matter, and is often a comment.
+    let mut dead = None;
+    // Where the tape pointer is from where it started, while that is known before the first loop.
+    let mut pointer = Some(0_isize);
+
+    for (n, &(i, ch)) in instructions.iter().enumerate() {
+        let previous = n.checked_sub(1).map(|n| instructions[n].1);
+        let next = instructions.get(n + 1).map(|&(_, ch)| ch);
+
+        match ch {
+            '[' => stack.push(i),
+            ']' if stack.pop().is_some() => last_closed = Some(i),
+            ']' => match last_closed {
+                Some(end) => {
+                    let (line, column) = position(source, end);
+                    warn(
+                        i,
+                        format!(
+                            "This ] has no [ to close, as every loop before it was closed by the \
+                             ] at {line}:{column}"
+                        ),
+                    );
+                }
+                None => warn(
+                    i,
+                    "This ] has no [ to close, as no loop starts before it".to_owned(),
+                ),
+            },
+            _ => {}
+        }
+        if let Some(depth) = dead {
+            if ch == ']' && stack.len() == depth {
+                dead = None;
+            }
+            continue;
+        }
+
+        match (previous, ch) {
+            (None, '[') => {
+                warn(
+                    i,
+                    "This loop never runs, as every cell is zero at the start of the program, \
+                     unless it's meant as a comment"
+                        .to_owned(),
+                );
+                dead = Some(stack.len() - 1);
+            }
+            (Some(']'), '[') => {
+                warn(
+                    i,
+                    "This loop never runs, as the loop right before it leaves the cell at zero"
+                        .to_owned(),
+                );
+                dead = Some(stack.len() - 1);
+            }
+            (_, '[') if next == Some(']') => warn(
+                i,
+                "This loop never ends once it runs, as it doesn't change anything".to_owned(),
+            ),
+            (Some(previous @ ('+' | '-' | '>' | '<')), _) if inverse(previous) == Some(ch) => {
+                warn(
+                    i,
+                    format!("This {ch} undoes the {previous} right before it"),
+                );
+            }
+            (_, ',') if next == Some(',') => warn(
+                i,
+                "The input this , reads is replaced by the next , before it's used".to_owned(),
+            ),
+            _ => {}
+        }
+
+        pointer = match (pointer, ch) {
+            // Loops that never run don't move the tape pointer.
+            _ if dead.is_some() => pointer,
+            (Some(0), '<') => {
+                warn(
+                    i,
+                    "This < moves the tape pointer left of where it starts, which is past the \
+                     end of the tape on most interpreters"
+                        .to_owned(),
+                );
+                None
+            }
+            (Some(pointer), '>') => Some(pointer + 1),
+            (Some(pointer), '<') => Some(pointer - 1),
+            (_, '[' | ']') | (None, _) => None,
+            (pointer, _) => pointer,
+        };
+    }
+
+    let (line, column) = position(source, source.len());
+    for start in stack {
+        warn(
+            start,
+            format!("This [ is never closed, as the program ends at {line}:{column} first"),
+        );
+    }
+    problems.sort_by_key(|problem| (problem.line, problem.column));
+    problems
+}
+
+/// Return the instruction that undoes `instruction`, if there is one.
+fn inverse(instruction: char) -> Option<char> {
+    match instruction {
+        '+' => Some('-'),
+        '-' => Some('+'),
+        '>' => Some('<'),
+        '<' => Some('>'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint_str(source: &str) -> Vec<String> {
+        lint(source).iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn find_mistakes() {
+        assert_eq!(lint_str("++[->+<]>."), Vec::<String>::new());
+        assert_eq!(
+            lint_str("[a comment, so ,, is fine] <+\n,,[-][.]+-"),
+            [
+                "1:1: This loop never runs, as every cell is zero at the start of the program, \
+                 unless it's meant as a comment",
+                "1:28: This < moves the tape pointer left of where it starts, which is past the \
+                 end of the tape on most interpreters",
+                "2:1: The input this , reads is replaced by the next , before it's used",
+                "2:6: This loop never runs, as the loop right before it leaves the cell at zero",
+                "2:10: This - undoes the + right before it",
+            ]
+        );
+        assert_eq!(
+            lint_str("+[[]\n]]"),
+            [
+                "1:3: This loop never ends once it runs, as it doesn't change anything",
+                "2:2: This ] has no [ to close, as every loop before it was closed by the ] at \
+                 2:1",
+            ]
+        );
+        assert_eq!(
+            lint_str("+[>\n"),
+            ["1:2: This [ is never closed, as the program ends at 2:1 first"]
+        );
+    }
+}
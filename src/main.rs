@@ -1,32 +1,3083 @@
 #![deny(clippy::all)]
 #![warn(clippy::pedantic)]
 
-use std::{fs, path::PathBuf};
+use std::{
+    env,
+    ffi::OsString,
+    fmt::{self, Write as _},
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, IsTerminal, Read, Write},
+    net::{TcpListener, TcpStream},
+    ops::Range,
+    path::{Path, PathBuf},
+    process::{self, Stdio},
+    time::{Duration, Instant},
+};
 
-use anyhow::{Context, Result};
-use clap::Parser;
-use interpreter::Interpreter;
+use anyhow::{anyhow, bail, Context, Result};
+#[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+use beef::jit;
+use beef::{check, diagnostic, interpreter, ir, optimizer};
+use clap::{
+    parser::ValueSource, ArgAction, ArgMatches, Args, CommandFactory, FromArgMatches, Parser,
+    Subcommand, ValueEnum,
+};
+use config::Config;
+use debugger::Debugger;
+use encoding::{Encoded, Encoding};
+use fifo::Fifo;
+use input::{LineInput, NonBlockingInput};
+use interpreter::{BigCell, Cell, Interpreter, Profile, Trace};
+use ir::{Extensions, Op, Program, Span};
+use metrics::Metrics;
+use optimizer::{DeadCode, StartingTape};
+use output::{Escaped, HexDump, Sanitized, Tee, Throttled, Timestamped};
+use progress::Progress;
+use report::RunReport;
+use session::{Recorder, Replay, Session};
+use terminal::RawMode;
+use watch::Watcher;
 
-mod interpreter;
+mod bench;
+mod cache;
+mod codegen;
+mod config;
+mod debugger;
+mod diff;
+mod dump;
+mod encoding;
+mod expect;
+mod fifo;
+mod format;
+mod input;
+mod lint;
+mod metrics;
+mod minify;
+mod output;
+mod progress;
+mod report;
+mod session;
+mod terminal;
+mod watch;
 
 /// A tiny Brainfuck interpreter.
 #[derive(Parser, Debug)]
-struct Args {
-    /// Path of the Brainfuck program to execute.
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Arguments for `run`, which is the default when no subcommand is given.
+    #[command(flatten)]
+    run: Option<RunArgs>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a Brainfuck program. This is the default when no subcommand is given.
+    Run(Box<RunArgs>),
+
+    /// Compile a Brainfuck program into a standalone executable.
+    ///
+    /// The program is translated to C, then compiled with the system C compiler.
+    Build(BuildArgs),
+
+    /// Translate a Brainfuck program into the source code of another language.
+    ///
+    /// The program is optimized first, so the generated code is both readable and fast.
+    Compile(CompileArgs),
+
+    /// Optimize a Brainfuck program into an equivalent, usually much shorter, Brainfuck program.
+    ///
+    /// As much of the program as possible is evaluated ahead of time, and comments are dropped.
+    /// The result relies on the semantics of beef: 8-bit wrapping cells, and a tape pointer that
+    /// wraps around the ends of the tape.
+    Optimize(OptimizeArgs),
+
+    /// Run a Brainfuck program several times, and report how long it takes.
+    ///
+    /// The program is compiled once, then timed over every run with its output discarded. Each
+    /// run gets the same input, and executes the same number of ops of the optimized program,
+    /// which is reported per second of the median time.
+    Bench(BenchArgs),
+
+    /// Run two Brainfuck programs, and report how the output and the tape they end with differ.
+    ///
+    /// Both programs get the same input, and run on 8-bit cells with a tape that wraps around.
+    /// This shows the first byte their outputs differ at and every cell their tapes differ at,
+    /// for checking that a changed or ported program still does the same thing. If the runs end
+    /// in different states, beef exits with an error once it reported them.
+    DiffState(DiffStateArgs),
+
+    /// Run two Brainfuck programs, and report whether they write the same output.
+    ///
+    /// Both programs get the same input, like for `diff-state`. The lines from where their outputs
+    /// start to differ are shown, and with `--tape`, so is every cell their tapes end up differing
+    /// at, for checking that a refactored or minified program still does the same thing. If the
+    /// runs differ, beef exits with an error once it reported them.
+    Diff(DiffArgs),
+
+    /// Run a Brainfuck program, then run it again whenever its file changes.
+    ///
+    /// The screen is cleared before every run, and a run that fails doesn't end the watch. The
+    /// file is looked at every 100 milliseconds, for a new modification time or length. Press
+    /// Ctrl+C to stop watching.
+    Watch(Box<RunArgs>),
+
+    /// Run a Brainfuck program one instruction at a time, to look at and change its tape.
+    ///
+    /// This shows the instruction the program is at, and waits for commands like `step`,
+    /// `continue`, `print 0..8` or `set 3 65` at the `(beef)` prompt. `help` lists every command.
+    /// `continue` stops at every instruction right after a `#`. The program reads its input from
+    /// the prompt too, unless it's given with `--input`.
+    Debug(DebugArgs),
+
+    /// Check Brainfuck programs for problems, without running them.
+    ///
+    /// Every problem is printed with the line and character it's at, like `hello.b:3:14: Unmatched
+    /// [`, and beef exits with an error if there are any, for pre-commit hooks and editors.
+    Check(CheckArgs),
+
+    /// Lay out a Brainfuck program to be easy to read, keeping its comments.
+    ///
+    /// Loops that don't fit on a line get their brackets on lines of their own, with their body
+    /// indented between them, and instructions are wrapped to the width of the line.
+    Fmt(FmtArgs),
+
+    /// Strip a Brainfuck program down to its instructions, for code golf and embedding.
+    ///
+    /// Unlike `optimize`, the result is the same program on every interpreter, rather than one
+    /// that relies on the semantics of beef.
+    Minify(MinifyArgs),
+
+    /// Remove everything from a Brainfuck program that isn't an instruction.
+    ///
+    /// Unlike `minify --shorten`, the instructions are kept as they are.
+    Strip(StripArgs),
+
+    /// Measure a Brainfuck program without running it.
+    ///
+    /// This reports how many times each instruction appears and its longest run, how many loops
+    /// there are and how deep they nest, how much of the source is comments, and which cells the
+    /// tape pointer can reach.
+    Stats(StatsArgs),
+
+    /// Look for likely mistakes in Brainfuck programs, without running them.
+    ///
+    /// This warns about loops that never run or never end, instructions that undo each other,
+    /// input that is read and then replaced before it's used, moving left of where the tape
+    /// pointer starts, and brackets that don't match, explaining each of them. beef exits with an
+    /// error if it finds any.
+    Lint(LintArgs),
+
+    /// Run Brainfuck programs on their input, and check that they write the output expected.
+    ///
+    /// A test is a program like `hello.b` or `hello.bf`, with its expected output in `hello.out`
+    /// next to it, and its input in `hello.in`, if it reads any. Every test in the directories
+    /// given is run, along with those in their subdirectories, and the lines of each output that
+    /// differ from the expected ones are shown. beef exits with an error if any tests fail, for
+    /// grading exercises and checking that programs still work.
+    Test(TestArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+// Every flag is an independent switch on the command line.
+#[allow(clippy::struct_excessive_bools)]
+struct RunArgs {
+    /// Path of the Brainfuck program to execute, or `-` to read it from stdin.
     ///
     /// The filename typically ends in `.b` or `.bf`, but `.b` is preferred as `.bf` often gets
-    /// confused with Befunge.
+    /// confused with Befunge. A program read from stdin reads its own input from the terminal,
+    /// if there is one, unless another input is given.
+    ///
+    /// Several programs run one after the other on the same tape, like `beef lib.b main.b`, each
+    /// starting where the tape pointer was left by the one before it.
+    #[arg(value_name = "PROGRAM_PATH", required_unless_present = "eval")]
+    program_paths: Vec<PathBuf>,
+
+    /// Run this text as the program, instead of a file, like `-e '++++++[>++++++++<-]>+++++.'`.
+    #[arg(short, long, value_name = "PROGRAM", allow_hyphen_values = true)]
+    #[arg(conflicts_with = "program_paths")]
+    eval: Option<String>,
+
+    /// Refuse to run a program with anything but instructions and whitespace in it, pointing at
+    /// the first other character.
+    ///
+    /// Without comments, none of them can run the `,` or `.` of a sentence by accident, and
+    /// look-alikes of instructions from other alphabets stand out. The instructions of
+    /// `--tape-switch` and the input after `--bang-input` are still allowed.
+    #[arg(long)]
+    strict: bool,
+
+    /// Stop at every `#` in the program, and drop into the debugger of `beef debug` there.
+    ///
+    /// Without this, `#` is a comment like any other. The program runs without being optimized,
+    /// on the default cells, and writes to `--output` or else the terminal along with the
+    /// debugger. Options for the tape and what `,` and `.` do still apply, while those about how
+    /// beef runs the program or where its input comes from can't be given along with this.
+    #[arg(long)]
+    #[arg(conflicts_with_all = [
+        "input_fifo",
+        "transcript",
+        "line_input",
+        "input_nonblocking",
+        "bang_input",
+        "raw_tty",
+        "listen",
+        "record",
+        "replay",
+        "jit",
+        "fusion_stats",
+        "emit_ir",
+        "dump_tape",
+        "json",
+        "time",
+        "stats",
+        "trace",
+        "opt_level",
+        "pgo",
+        "unsafe_fast",
+        "tape_file",
+        "tape_init",
+        "tape_init_hex",
+        "cell_size",
+        "signed",
+        "output_format",
+        "throttle",
+        "sanitize_output",
+        "output_encoding",
+        "max_steps",
+        "timeout",
+        "exit_cell",
+        "verbose",
+    ])]
+    break_on_hash: bool,
+
+    /// Path of a file to feed to the program as its input, instead of stdin.
+    #[arg(short, long, value_name = "PATH")]
+    input: Option<PathBuf>,
+
+    /// Path of a FIFO to feed to the program as its input, instead of stdin, which is made if
+    /// there's nothing there yet.
+    ///
+    /// The FIFO is only opened once `,` first reads it, so that two programs can talk to each
+    /// other through a FIFO each way without waiting for each other to open theirs.
+    #[arg(long, value_name = "PATH", conflicts_with = "input")]
+    input_fifo: Option<PathBuf>,
+
+    /// Feed this text to the program as its input, instead of stdin.
+    ///
+    /// Backslash escapes are replaced by the bytes they stand for: `\n`, `\r`, `\t`, `\0`, `\\`,
+    /// `\"`, `\'`, and `\xHH` for the byte with the hexadecimal value HH.
+    #[arg(long, value_name = "STRING", value_parser = parse_escapes)]
+    #[arg(conflicts_with_all = ["input", "input_fifo"])]
+    input_str: Option<Box<[u8]>>,
+
+    /// Don't read stdin at all, so that `,` always finds the input ended, like `--eof` says.
+    ///
+    /// This leaves stdin to whoever runs beef, in pipelines or servers. The text after `!` of
+    /// `--bang-input` is still fed to the program.
+    #[arg(long, conflicts_with_all = ["input", "input_fifo", "input_str"])]
+    no_input: bool,
+
+    /// Path of a file to write the output of the program to, instead of stdout.
+    ///
+    /// The file is overwritten, unless `--append` is given. Errors and warnings still go to
+    /// stderr.
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Path of a FIFO to write the output of the program to, instead of stdout, which is made if
+    /// there's nothing there yet.
+    ///
+    /// Like with `--input-fifo`, the FIFO is only opened once `.` first writes to it.
+    #[arg(long, value_name = "PATH", conflicts_with = "output")]
+    output_fifo: Option<PathBuf>,
+
+    /// Append the output of the program to the file given with `--output`, instead of
+    /// overwriting it.
+    #[arg(long, requires = "output")]
+    append: bool,
+
+    /// Path of a file to record everything the program writes to, while it still goes to stdout or
+    /// the file given with `--output`.
+    ///
+    /// The transcript is overwritten, even with `--append`.
+    #[arg(long, value_name = "PATH")]
+    transcript: Option<PathBuf>,
+
+    /// Start every line of the transcript with the number of seconds since the program started.
+    #[arg(long, requires = "transcript")]
+    timestamps: bool,
+
+    /// Read the input a whole line at a time, and only once `,` has used up the line before.
+    ///
+    /// Interactive programs then get their input the way a terminal sends it, a line at a time
+    /// after Enter, even when another program writes it to them in pieces.
+    #[arg(long)]
+    line_input: bool,
+
+    /// Never make `,` wait for stdin, and read BYTE instead while none of it has come in yet.
+    ///
+    /// This lets games poll the keyboard. BYTE is 0 unless given, and once stdin has ended, `,`
+    /// does what `--eof` says instead.
+    #[arg(long, value_name = "BYTE", num_args = 0..=1, require_equals = true)]
+    #[arg(default_missing_value = "0")]
+    #[arg(conflicts_with_all = ["input", "input_fifo", "input_str", "no_input", "line_input"])]
+    input_nonblocking: Option<u8>,
+
+    /// Feed the program the text after the first `!` outside of its loops as its input, instead
+    /// of stdin.
+    ///
+    /// Many classic programs, like Brainfuck interpreters written in Brainfuck, expect their input
+    /// to follow a `!` in the same file. Without one, the input comes from stdin as usual.
+    #[arg(long, conflicts_with_all = ["input", "input_fifo", "input_str", "input_nonblocking"])]
+    bang_input: bool,
+
+    /// Put the terminal into raw mode while the program runs, so that `,` gets every key as soon
+    /// as it's pressed, without Enter and without echoing it.
+    ///
+    /// Interactive games need this, usually along with `--input-nonblocking`. Ctrl-C still stops
+    /// the program, and nothing changes when stdin isn't a terminal.
+    #[arg(long)]
+    #[arg(conflicts_with_all = ["input", "input_fifo", "input_str", "no_input", "line_input"])]
+    raw_tty: bool,
+
+    /// Wait for a TCP connection on ADDRESS, like `127.0.0.1:4000`, then feed the program what
+    /// the other end sends as its input, and send its output back, instead of using stdin and
+    /// stdout.
+    ///
+    /// Only a single connection is accepted, and the program ends with an error if the other end
+    /// hangs up while it's still writing. Port 0 picks a free port, which is printed to stderr.
+    #[arg(long, value_name = "ADDRESS")]
+    #[arg(conflicts_with_all = [
+        "input",
+        "input_fifo",
+        "input_str",
+        "no_input",
+        "input_nonblocking",
+        "bang_input",
+        "raw_tty",
+        "replay",
+        "output",
+        "output_fifo",
+    ])]
+    listen: Option<String>,
+
+    /// Path of a JSON file to record the input of the program to, along with the time every part
+    /// of it came in at, so that `--replay` can feed it back later.
+    #[arg(long, value_name = "PATH", conflicts_with = "input_nonblocking")]
+    record: Option<PathBuf>,
+
+    /// Path of a session recorded by `--record` to feed to the program as its input, instead of
+    /// stdin, with the same timing as when it was recorded.
+    #[arg(long, value_name = "PATH")]
+    #[arg(conflicts_with_all = [
+        "input",
+        "input_fifo",
+        "input_str",
+        "no_input",
+        "input_nonblocking",
+        "bang_input",
+    ])]
+    replay: Option<PathBuf>,
+
+    /// Compile the program to native code before running it.
+    ///
+    /// This requires beef to be built with the `jit` feature for the Cranelift backend, or the
+    /// `jit-x86-64` feature for the x86-64 backend. If the backend is unavailable, or does not
+    /// support this machine, the program is interpreted as usual.
+    #[arg(long, value_name = "BACKEND", num_args = 0..=1, require_equals = true)]
+    #[arg(default_missing_value = "auto")]
+    jit: Option<JitBackend>,
+
+    /// Print how many superinstructions the optimizer fused to stderr before running.
+    #[arg(long)]
+    fusion_stats: bool,
+
+    /// Print the optimized IR instead of running the program.
+    ///
+    /// Each op is followed by the byte offsets of the source code it was compiled from. Code
+    /// removed by the optimizer because it can never run is listed as comments, where it used to
+    /// be.
+    #[arg(long)]
+    emit_ir: bool,
+
+    /// Print the cells the program left on the tape that aren't zero, and the cell the tape
+    /// pointer is on, to stderr once it finishes or fails.
+    ///
+    /// With several tapes, this is the tape the program is on.
+    #[arg(long)]
+    dump_tape: bool,
+
+    /// Print a report of the run to stderr as a single-line JSON object once it finishes or
+    /// fails, for scripts and graders to read.
+    ///
+    /// The report has the exit code, the error if there was one, the number of ops of the
+    /// optimized program that were executed, the cell the tape pointer ended on, the cells that
+    /// aren't zero as `[index, value]` pairs, and the time the program ran for in nanoseconds.
+    /// Ops are only counted when the program is interpreted, so they're `null` when it runs as
+    /// native code, without bounds checks, or with `--pgo`.
+    #[arg(long)]
+    json: bool,
+
+    /// Print how long the program ran for, how many ops of the optimized program it executed,
+    /// and how many it executed per second to stderr once it finishes or fails.
+    ///
+    /// Like with `--json`, ops are only counted when the program is interpreted.
+    #[arg(long)]
+    time: bool,
+
+    /// Print how many times each of the eight instructions was executed, and how many times the
+    /// loops iterated, to stderr once the program finishes or fails.
+    ///
+    /// Instructions are counted one by one, so the program isn't optimized, and is interpreted
+    /// with bounds checks even with `--jit` or `--unsafe-fast`.
+    #[arg(long, conflicts_with = "pgo")]
+    stats: bool,
+
+    /// Log every instruction the program executes to stderr, with its offset in the source, the
+    /// index of the cell the tape pointer is on and the value of that cell before the instruction
+    /// runs, to follow a small program step by step.
+    ///
+    /// Instructions are traced one by one, so the program isn't optimized, and is interpreted
+    /// with bounds checks even with `--jit` or `--unsafe-fast`.
+    #[arg(long, conflicts_with = "pgo")]
+    trace: bool,
+
+    /// Write the trace of `--trace` to PATH instead of stderr.
+    #[arg(long, value_name = "PATH", requires = "trace")]
+    trace_file: Option<PathBuf>,
+
+    /// Only trace the instructions at the offsets of the source from START up to END, or at a
+    /// single offset.
+    #[arg(
+        long,
+        value_name = "START..END",
+        value_parser = parse_offsets,
+        requires = "trace"
+    )]
+    trace_range: Option<Range<usize>>,
+
+    /// Compile and optimize the program even if it is cached, and don't cache it.
+    ///
+    /// Optimized programs are cached in `$XDG_CACHE_HOME/beef`, or `~/.cache/beef` if it is unset.
+    #[arg(long)]
+    no_cache: bool,
+
+    /// How much to optimize the program, from 0 to 4.
+    ///
+    /// Level 0 executes every instruction one by one, level 1 folds runs of instructions, level 2
+    /// also replaces common loops, and level 3 enables every optimization that doesn't cost memory
+    /// at runtime. Level 4 also caches the results of small loops while interpreting, which speeds
+    /// up loops that often run from the same values. Lower levels compile faster, and help narrow
+    /// down optimizer bugs.
+    #[arg(short = 'O', long, value_name = "LEVEL", default_value_t = optimizer::DEFAULT_LEVEL)]
+    #[arg(value_parser = opt_level_parser())]
+    opt_level: u8,
+
+    /// Interpret the program while profiling its loops, and compile the hot ones to native code.
+    ///
+    /// Loops that iterate often are compiled with the JIT backend picked by `--jit`, or the first
+    /// available one, and run natively from then on. This suits long-running programs that
+    /// spend most of their time in a few loops, like Brainfuck interpreters written in
+    /// Brainfuck. If no backend is available, the program is only profiled.
+    #[arg(long)]
+    pgo: bool,
+
+    /// Load the profile of earlier runs from PATH for `--pgo`, and save it there once the program
+    /// finishes.
+    ///
+    /// Loops that were hot in earlier runs are compiled as soon as they start.
+    #[arg(long, value_name = "PATH", requires = "pgo")]
+    profile: Option<PathBuf>,
+
+    /// Interpret the program without any bounds checks, for programs you trust.
+    ///
+    /// Cells past the ends of the tape don't wrap around when an instruction reaches them without
+    /// moving there, so programs that rely on that may behave differently. Moving past an end of
+    /// the tape still wraps. This requires beef to be built with the `unsafe-fast` feature, and
+    /// is ignored by `--jit`.
+    #[arg(long)]
+    unsafe_fast: bool,
+
+    /// The number of cells on the tape, from 64 to 2^30.
+    ///
+    /// With a `--tape-model` that grows the tape, this is only how many cells it starts with.
+    #[arg(long, value_name = "CELLS", default_value_t = interpreter::TAPE_SIZE)]
+    #[arg(value_parser = tape_size_parser())]
+    tape_size: usize,
+
+    /// Keep the tape in this file, mapped into memory, so that it persists across runs.
+    ///
+    /// Each byte of the file is a cell, so an existing file sets the number of cells instead of
+    /// `--tape-size`, while a new one starts out with `--tape-size` zero cells. Only 8-bit cells
+    /// fit in a tape file, and it can't grow, so it only works with `--tape-model wrap` or `error`.
+    #[arg(long, value_name = "FILE")]
+    tape_file: Option<PathBuf>,
+
+    /// Load the bytes of this file onto the tape before running the program, one per cell from
+    /// the cell the tape pointer starts on.
+    ///
+    /// This lets programs work on data without building it up with `+` first. The rest of the
+    /// cells start out zero, and the tape needs at least as many cells as the file has bytes.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["tape_init_hex", "tape_file"])]
+    tape_init: Option<PathBuf>,
+
+    /// Load these bytes onto the tape before running the program, like `--tape-init`, written as
+    /// pairs of hexadecimal digits that may be separated by whitespace.
+    #[arg(long, value_name = "HEX", conflicts_with = "tape_file", value_parser = parse_hex)]
+    tape_init_hex: Option<Box<[u8]>>,
+
+    /// The number of tapes, which the program switches between with the instructions of
+    /// `--tape-switch`.
+    ///
+    /// Every tape has `--tape-size` cells and a tape pointer of its own. The program starts on the
+    /// first tape, which is the only one `--tape-file`, `--tape-init` and `--read-only` apply to.
+    /// Tapes are only switched with bounds checks, so more than one tape is interpreted with them,
+    /// even with `--jit`, `--unsafe-fast` or `--pgo`.
+    #[arg(long, value_name = "COUNT", default_value_t = 1, value_parser = tapes_parser())]
+    tapes: usize,
+
+    /// The instructions that switch to the previous and to the next tape, when there is more than
+    /// one.
+    ///
+    /// These are two characters that aren't already instructions of Brainfuck. Switching past the
+    /// last tape goes around to the first one, and the other way around. With a single tape, they
+    /// are comments like any other character.
+    #[arg(long, value_name = "CHARS", default_value = "{}", value_parser = parse_tape_switch)]
+    tape_switch: [char; 2],
+
+    /// Pick the tape, the cells and what `,` does at the end of the input all at once, to match
+    /// the interpreters a program was written for.
+    ///
+    /// The cells of every preset are 8 bits wide, unsigned, and wrap around on overflow. Options
+    /// given along with the preset still take precedence over it, while it takes precedence over
+    /// the environment variables and the configuration files.
+    #[arg(long, value_name = "PRESET", value_enum)]
+    preset: Option<Preset>,
+
+    /// What happens when the tape pointer moves past an end of the tape.
+    ///
+    /// Programs that need more memory than they expect can silently corrupt their state by
+    /// wrapping around to the first cell, which stopping them or growing the tape avoids. Programs
+    /// that move left of the starting cell need the tape to grow both ways. The tape can't stop
+    /// the program or grow with `--jit`, `--unsafe-fast` or the native hot loops of `--pgo`, so
+    /// they are interpreted with bounds checks instead.
+    #[arg(long, visible_alias = "bounds", value_name = "MODEL", value_enum)]
+    #[arg(default_value_t = TapeModel::Wrap)]
+    tape_model: TapeModel,
+
+    /// The number of bits in each cell, which wrap around on overflow, or `big` for signed cells
+    /// that never overflow.
+    ///
+    /// `.` writes the lowest byte of a cell, in two's complement for negative big cells, and `,`
+    /// reads a byte into it. Native code only supports 8-bit cells, so `--jit` and the native hot
+    /// loops of `--pgo` interpret wider cells instead.
+    #[arg(long, value_name = "SIZE", value_enum, default_value_t = CellSize::Bits8)]
+    cell_size: CellSize,
+
+    /// Treat fixed-width cells as signed, so that 8-bit cells hold -128 to 127 rather than 0 to
+    /// 255.
+    ///
+    /// This is for dialects that expect signed cells. Their bits wrap around the same way either
+    /// way, loops still only stop on zero, and `.` writes the same byte. Big cells are always
+    /// signed.
+    #[arg(long)]
+    signed: bool,
+
+    /// What happens when `+` or `-` overflows a cell past its lowest or highest value.
+    ///
+    /// Checking for overflow needs every `+` and `-` to run on its own, so the program isn't
+    /// optimized and is interpreted with bounds checks, even with `--jit`, `--unsafe-fast` or
+    /// `--pgo`.
+    #[arg(long, value_name = "POLICY", value_enum, default_value_t = Overflow::Wrap)]
+    overflow: Overflow,
+
+    /// What `.` writes and `,` reads.
+    ///
+    /// Numbers suit math programs, whose results are hard to read as bytes. Native code writes and
+    /// reads numbers too, but the executables of `build` and the code of `compile` always write
+    /// and read bytes.
+    #[arg(long, value_name = "FORMAT", value_enum, default_value_t = IoFormat::Bytes)]
+    io: IoFormat,
+
+    /// What `,` does once the input has run out.
+    ///
+    /// Programs expect one of these, depending on the implementation they were written for, and
+    /// most of them work with the cell left unchanged. This applies to native code too, but not to
+    /// the executables of `build` and the code of `compile`, which stop with an error.
+    #[arg(long, value_name = "BEHAVIOR", value_enum, default_value_t = Eof::Unchanged)]
+    eof: Eof,
+
+    /// When the output of `.` is flushed, which is slow for programs that print a lot if it
+    /// happens for every byte.
+    #[arg(long, value_name = "WHEN", value_enum, default_value_t = Flush::Line)]
+    flush: Flush,
+
+    /// How the output is shown, which helps to inspect programs that write binary data or
+    /// control characters.
+    ///
+    /// This applies to stdout, `--output`, `--output-fifo` and `--listen` alike, but the
+    /// transcript still gets the bytes as they are.
+    #[arg(long, value_name = "FORMAT", value_enum, default_value_t = OutputFormat::Raw)]
+    output_format: OutputFormat,
+
+    /// Wait for DELAY, like `100ms` or `0.5s`, before writing every byte of the output, so that
+    /// animations play at a speed that can be watched.
+    ///
+    /// Every byte is flushed on its own then, whatever `--flush` says.
+    #[arg(long, value_name = "DELAY", value_parser = parse_duration)]
+    throttle: Option<Duration>,
+
+    /// Leave terminal escape sequences and control characters other than tabs and line breaks
+    /// out of the output, so that programs that can't be trusted can't mess with the terminal.
+    ///
+    /// The transcript still gets the bytes as they are.
+    #[arg(long, conflicts_with = "output_format")]
+    sanitize_output: bool,
+
+    /// The character set the bytes the program writes are in, which they're translated from into
+    /// UTF-8 for the terminal.
+    #[arg(long, value_name = "ENCODING", value_enum, default_value_t = OutputEncoding::Utf8)]
+    output_encoding: OutputEncoding,
+
+    /// Make the cells from START up to END read-only, so that the program stops with an error
+    /// pointing at the instruction that writes to one of them.
+    ///
+    /// Cells are numbered from 0, the cell the tape pointer starts on, and a single cell can be
+    /// given on its own. Repeat this for more ranges of cells. Writes are checked one by one, so
+    /// the program is interpreted with bounds checks, even with `--jit`, `--unsafe-fast` or
+    /// `--pgo`, and writes the optimizer folds away, like the `+` of `+-`, aren't caught.
+    #[arg(long, value_name = "START..END", value_parser = parse_cells)]
+    read_only: Vec<Range<usize>>,
+
+    /// Stop the program with an error once it executed STEPS ops of the optimized program and
+    /// isn't done yet, so that programs that never finish don't hang terminals, CI jobs or
+    /// servers.
+    ///
+    /// Steps are counted one by one, so the program is interpreted with bounds checks, even with
+    /// `--jit` or `--unsafe-fast`.
+    #[arg(long, value_name = "STEPS", conflicts_with = "pgo")]
+    max_steps: Option<u64>,
+
+    /// Stop the program with an error once it ran for DURATION, like `5s` or `500ms`, and isn't
+    /// done yet. `--dump-tape` and `--json` still show the state it was stopped in.
+    ///
+    /// The time is checked every so many steps, so the program is interpreted with bounds checks
+    /// like with `--max-steps`, and isn't stopped while it's waiting for its input.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    #[arg(conflicts_with = "pgo")]
+    timeout: Option<Duration>,
+
+    /// Exit with the value of the cell at INDEX once the program finishes, so that shell scripts
+    /// and tests can tell how it went. The index counts from the starting cell, on the tape the
+    /// program ends on.
+    ///
+    /// Exit codes are a byte, so only the lowest byte of the cell is kept, like `.` does. A
+    /// program that fails still exits with 1.
+    #[arg(long, value_name = "INDEX", allow_negative_numbers = true)]
+    exit_cell: Option<isize>,
+
+    /// Print what beef is doing to stderr: loading the program, compiling it or finding it in the
+    /// cache, and how it runs it. Twice, like `-vv`, also prints the cache file of the program
+    /// and the tape it runs on.
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Don't print warnings to stderr, like when beef falls back to the interpreter, nor the
+    /// progress of programs that run for a while. Errors are still printed.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+#[derive(Args, Debug)]
+struct BuildArgs {
+    /// Path of the Brainfuck program to compile.
+    program_path: PathBuf,
+
+    /// Path of the executable to write.
+    ///
+    /// Defaults to the path of the program without its extension.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// The C compiler to use.
+    ///
+    /// Defaults to the `CC` environment variable, or `cc` if it is unset.
+    #[arg(long, value_name = "COMPILER")]
+    cc: Option<OsString>,
+
+    /// How much to optimize the program, from 0 to 4, like for `run`.
+    ///
+    /// Level 4 only speeds up the interpreter, so the generated code is the same as for level 3.
+    #[arg(short = 'O', long, value_name = "LEVEL", default_value_t = optimizer::DEFAULT_LEVEL)]
+    #[arg(value_parser = opt_level_parser())]
+    opt_level: u8,
+
+    /// The number of cells on the tape of the generated program, from 64 to 2^30.
+    ///
+    /// The tape pointer wraps around the ends of the tape.
+    #[arg(long, value_name = "CELLS", default_value_t = interpreter::TAPE_SIZE)]
+    #[arg(value_parser = tape_size_parser())]
+    tape_size: usize,
+}
+
+#[derive(Args, Debug)]
+struct CompileArgs {
+    /// Path of the Brainfuck program to compile.
     program_path: PathBuf,
+
+    /// The language to translate the program into.
+    #[arg(short, long, value_enum, default_value_t = Target::C)]
+    target: Target,
+
+    /// Path of the file to write the generated code to.
+    ///
+    /// Defaults to stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// How much to optimize the program, from 0 to 4, like for `run`.
+    ///
+    /// Level 4 only speeds up the interpreter, so the generated code is the same as for level 3.
+    #[arg(short = 'O', long, value_name = "LEVEL", default_value_t = optimizer::DEFAULT_LEVEL)]
+    #[arg(value_parser = opt_level_parser())]
+    opt_level: u8,
+
+    /// The number of cells on the tape of the generated program, from 64 to 2^30.
+    ///
+    /// The tape pointer wraps around the ends of the tape.
+    #[arg(long, value_name = "CELLS", default_value_t = interpreter::TAPE_SIZE)]
+    #[arg(value_parser = tape_size_parser())]
+    tape_size: usize,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+/// Return the parser for `--opt-level`, which only accepts the levels the optimizer has.
+fn opt_level_parser() -> impl clap::builder::TypedValueParser<Value = u8> {
+    clap::value_parser!(u8).range(..=i64::from(optimizer::MAX_LEVEL))
+}
+
+/// Return the parser for `--tape-size`, which only accepts the sizes the interpreter supports.
+fn tape_size_parser() -> impl clap::builder::TypedValueParser<Value = usize> {
+    clap::builder::RangedU64ValueParser::<usize>::new()
+        .range(interpreter::MIN_TAPE_SIZE as u64..=interpreter::MAX_TAPE_SIZE as u64)
+}
+
+/// Return the parser for `--tapes`, which needs at least one tape.
+fn tapes_parser() -> impl clap::builder::TypedValueParser<Value = usize> {
+    clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+}
+
+/// Parse the bytes of `--tape-init-hex`, as pairs of hexadecimal digits that may be separated by
+/// whitespace.
+fn parse_hex(value: &str) -> Result<Box<[u8]>, String> {
+    let digits = value
+        .chars()
+        .filter(|digit| !digit.is_whitespace())
+        .map(|digit| {
+            digit
+                .to_digit(16)
+                .ok_or_else(|| format!("`{digit}` isn't a hexadecimal digit"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if digits.len() % 2 != 0 {
+        return Err("every byte takes two hexadecimal digits".to_owned());
+    }
+
+    Ok(digits
+        .chunks(2)
+        .map(|pair| {
+            u8::try_from(pair[0] << 4 | pair[1]).expect("two hexadecimal digits are a byte")
+        })
+        .collect())
+}
+
+/// Parse the text of `--input-str`, replacing its backslash escapes by the bytes they stand for.
+fn parse_escapes(value: &str) -> Result<Box<[u8]>, String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            bytes.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+        let byte = match chars.next() {
+            Some('n') => b'\n',
+            Some('r') => b'\r',
+            Some('t') => b'\t',
+            Some('0') => 0,
+            Some(ch @ ('\\' | '"' | '\'')) => ch as u8,
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                if digits.len() != 2 || !digits.chars().all(|digit| digit.is_ascii_hexdigit()) {
+                    return Err(format!("`\\x{digits}` doesn't have two hexadecimal digits"));
+                }
+                u8::from_str_radix(&digits, 16).expect("two hexadecimal digits are a byte")
+            }
+            Some(ch) => return Err(format!("`\\{ch}` isn't an escape")),
+            None => return Err("the text ends in the middle of an escape".to_owned()),
+        };
+        bytes.push(byte);
+    }
+    Ok(bytes.into_boxed_slice())
+}
+
+/// Parse a duration for `--throttle`, a number followed by `s`, `ms` or `us`.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let unit_start = value
+        .find(|ch: char| !ch.is_ascii_digit() && ch != '.')
+        .ok_or_else(|| format!("`{value}` doesn't have a unit like `ms` or `s`"))?;
+    let (number, unit) = value.split_at(unit_start);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("`{value}` doesn't start with a number"))?;
+    let seconds = match unit {
+        "s" => number,
+        "ms" => number / 1e3,
+        "us" | "µs" => number / 1e6,
+        _ => return Err(format!("`{unit}` isn't a unit like `ms` or `s`")),
+    };
+    // Only durations that are too long are left to fail here.
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("`{value}` is too long"))
+}
+
+/// Parse a range of cells for `--read-only`, either `START..END` or a single cell.
+fn parse_cells(value: &str) -> Result<Range<usize>, String> {
+    parse_range(value, "cell")
+}
+
+/// Parse a range of offsets in the source for `--trace-range`, either `START..END` or a single
+/// offset.
+fn parse_offsets(value: &str) -> Result<Range<usize>, String> {
+    parse_range(value, "offset")
+}
+
+/// Parse a range of `what`, either `START..END` or a single one of them.
+fn parse_range(value: &str, what: &str) -> Result<Range<usize>, String> {
+    let parse = |item: &str| {
+        item.parse::<usize>()
+            .map_err(|error| format!("invalid {what} `{item}`: {error}"))
+    };
+    let Some((start, end)) = value.split_once("..") else {
+        let item = parse(value)?;
+        return Ok(item..item + 1);
+    };
+
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start >= end {
+        return Err(format!("`{value}` doesn't hold any {what}s"));
+    }
+    Ok(start..end)
+}
+
+/// Parse the instructions of `--tape-switch`, two different characters that aren't already
+/// instructions of Brainfuck.
+fn parse_tape_switch(value: &str) -> Result<[char; 2], String> {
+    let chars: Vec<_> = value.chars().collect();
+    let [previous, next] = chars[..] else {
+        return Err(format!("`{value}` isn't two characters"));
+    };
+    if let Some(ch) = [previous, next]
+        .into_iter()
+        .find(|ch| "+-<>[].,".contains(*ch))
+    {
+        return Err(format!("`{ch}` is already an instruction of Brainfuck"));
+    }
+    if previous == next {
+        return Err(format!("`{previous}` can't switch both ways"));
+    }
+    Ok([previous, next])
+}
+
+#[derive(Args, Debug)]
+struct OptimizeArgs {
+    /// Path of the Brainfuck program to optimize.
+    program_path: PathBuf,
+
+    /// Path of the file to write the optimized program to.
+    ///
+    /// Defaults to stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// The number of cells on the tape of the optimized program, from 64 to 2^30.
+    ///
+    /// The tape pointer wraps around the ends of the tape.
+    #[arg(long, value_name = "CELLS", default_value_t = interpreter::TAPE_SIZE)]
+    #[arg(value_parser = tape_size_parser())]
+    tape_size: usize,
+}
+
+#[derive(Args, Debug)]
+struct BenchArgs {
+    /// Path of the Brainfuck program to benchmark.
+    program_path: PathBuf,
+
+    /// How many times to run the program while timing it.
+    #[arg(short = 'n', long, default_value_t = 10)]
+    #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+    runs: u32,
 
-    let program = fs::read_to_string(&args.program_path)
-        .context(format!("Failed to read {}", &args.program_path.display()))?;
+    /// How many times to run the program before timing it, to warm up the caches.
+    #[arg(long, value_name = "RUNS", default_value_t = 1)]
+    warmup: u32,
 
-    let mut interpreter = Interpreter::from_program_str(&program);
-    interpreter.run()?;
+    /// Path of a file to feed to the program as its input on every run.
+    ///
+    /// Defaults to no input, so `,` leaves cells unchanged.
+    #[arg(short, long, value_name = "PATH")]
+    input: Option<PathBuf>,
+
+    /// Print the results as a single-line JSON object with times in nanoseconds, for tracking
+    /// them over time.
+    #[arg(long)]
+    json: bool,
+
+    /// Compile the program to native code once, and time running the compiled code.
+    ///
+    /// Like for `run`, the program is interpreted if the backend is unavailable.
+    #[arg(long, value_name = "BACKEND", num_args = 0..=1, require_equals = true)]
+    #[arg(default_missing_value = "auto")]
+    jit: Option<JitBackend>,
+
+    /// Interpret the program without any bounds checks, like `run --unsafe-fast`.
+    #[arg(long)]
+    unsafe_fast: bool,
+
+    /// How much to optimize the program, from 0 to 4, like for `run`.
+    ///
+    /// Level 4 only speeds up the interpreter, so with `--jit` it times the same code as level 3.
+    #[arg(short = 'O', long, value_name = "LEVEL", default_value_t = optimizer::DEFAULT_LEVEL)]
+    #[arg(value_parser = opt_level_parser())]
+    opt_level: u8,
+
+    /// The number of cells on the tape, from 64 to 2^30.
+    ///
+    /// With a `--tape-model` that grows the tape, this is only how many cells it starts with.
+    #[arg(long, value_name = "CELLS", default_value_t = interpreter::TAPE_SIZE)]
+    #[arg(value_parser = tape_size_parser())]
+    tape_size: usize,
+
+    /// What happens when the tape pointer moves past an end of the tape, like for `run`.
+    #[arg(long, visible_alias = "bounds", value_name = "MODEL", value_enum)]
+    #[arg(default_value_t = TapeModel::Wrap)]
+    tape_model: TapeModel,
+
+    /// The number of bits in each cell, or `big` for cells that never overflow, like for `run`.
+    #[arg(long, value_name = "SIZE", value_enum, default_value_t = CellSize::Bits8)]
+    cell_size: CellSize,
+
+    /// Treat fixed-width cells as signed, like for `run`.
+    #[arg(long)]
+    signed: bool,
+}
+
+#[derive(Args, Debug)]
+struct DiffStateArgs {
+    /// Path of the first Brainfuck program to run.
+    first_path: PathBuf,
+
+    /// Path of the second Brainfuck program to run.
+    second_path: PathBuf,
+
+    /// Path of a file to feed to both programs as their input.
+    ///
+    /// Defaults to no input, so `,` does what `--eof` says right away.
+    #[arg(short, long, value_name = "PATH")]
+    input: Option<PathBuf>,
+
+    /// What `,` does once the input has run out, like for `run`.
+    ///
+    /// Unlike for `run`, this defaults to `zero`, so that programs that read until the end of
+    /// their input stop there rather than time out.
+    #[arg(long, value_name = "BEHAVIOR", value_enum, default_value_t = Eof::Zero)]
+    eof: Eof,
+
+    /// Stop with an error once a program ran for DURATION, like `5s` or `500ms`, and isn't done
+    /// yet.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "10s")]
+    timeout: Duration,
+
+    /// How much to optimize the programs, from 0 to 4, like for `run`.
+    #[arg(short = 'O', long, value_name = "LEVEL", default_value_t = optimizer::DEFAULT_LEVEL)]
+    #[arg(value_parser = opt_level_parser())]
+    opt_level: u8,
+
+    /// How much to optimize the second program instead, from 0 to 4.
+    ///
+    /// Diffing a program against itself at another level checks that the optimizer doesn't
+    /// change what it does.
+    #[arg(long, value_name = "LEVEL", value_parser = opt_level_parser())]
+    second_opt_level: Option<u8>,
+
+    /// The number of cells on the tapes, from 64 to 2^30.
+    #[arg(long, value_name = "CELLS", default_value_t = interpreter::TAPE_SIZE)]
+    #[arg(value_parser = tape_size_parser())]
+    tape_size: usize,
+}
+
+#[derive(Args, Debug)]
+struct DiffArgs {
+    #[command(flatten)]
+    runs: DiffStateArgs,
+
+    /// Compare the tapes the programs end with too, and their tape pointers.
+    #[arg(long)]
+    tape: bool,
+}
+
+#[derive(Args, Debug)]
+struct DebugArgs {
+    /// Path of the Brainfuck program to debug.
+    program_path: PathBuf,
+
+    /// Path of a file to feed to the program as its input, instead of the lines typed at the
+    /// prompt.
+    #[arg(short, long, value_name = "PATH")]
+    input: Option<PathBuf>,
+
+    /// What `,` does once the input has run out, like for `run`.
+    #[arg(long, value_name = "BEHAVIOR", value_enum, default_value_t = Eof::Unchanged)]
+    eof: Eof,
+
+    /// The number of cells on the tape, from 64 to 2^30.
+    #[arg(long, value_name = "CELLS", default_value_t = interpreter::TAPE_SIZE)]
+    #[arg(value_parser = tape_size_parser())]
+    tape_size: usize,
+}
+
+#[derive(Args, Debug)]
+struct CheckArgs {
+    /// Paths of the Brainfuck programs to check, or `-` to read one from stdin.
+    #[arg(value_name = "PROGRAM_PATH", required = true)]
+    program_paths: Vec<PathBuf>,
+
+    /// The most instructions a program may have.
+    #[arg(long, value_name = "COUNT")]
+    max_instructions: Option<usize>,
+
+    /// The deepest the loops of a program may nest.
+    #[arg(long, value_name = "DEPTH")]
+    max_depth: Option<usize>,
+
+    /// Only allow instructions and whitespace, so that any comment is a problem.
+    #[arg(long)]
+    strict: bool,
+}
+
+#[derive(Args, Debug)]
+struct FmtArgs {
+    /// Path of the Brainfuck program to format, or `-` to read it from stdin.
+    program_path: PathBuf,
+
+    /// The most characters on a line. Comments longer than that are kept on a line of their own.
+    #[arg(long, value_name = "COLUMNS", default_value_t = 80)]
+    #[arg(value_parser = clap::value_parser!(u16).range(1..))]
+    width: u16,
+
+    /// The number of spaces to indent the body of a loop by.
+    #[arg(long, value_name = "SPACES", default_value_t = 2)]
+    indent: u8,
+
+    /// Write the formatted program back to its file, instead of to stdout.
+    #[arg(short, long, conflicts_with = "check")]
+    write: bool,
+
+    /// Don't write anything, but exit with an error if the program isn't formatted.
+    #[arg(long)]
+    check: bool,
+}
+
+#[derive(Args, Debug)]
+struct MinifyArgs {
+    /// Path of the Brainfuck program to minify, or `-` to read it from stdin.
+    program_path: PathBuf,
+
+    /// Path of the file to write the minified program to.
+    ///
+    /// Defaults to stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Also leave out instructions that undo each other, like `+-` and `<>`, and loops that can
+    /// never run, like a comment loop at the start of the program or a loop right after another.
+    #[arg(short, long)]
+    shorten: bool,
+}
+
+#[derive(Args, Debug)]
+struct LintArgs {
+    /// Paths of the Brainfuck programs to lint, or `-` to read one from stdin.
+    #[arg(value_name = "PROGRAM_PATH", required = true)]
+    program_paths: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct StripArgs {
+    /// Path of the Brainfuck program to strip, or `-` to read it from stdin.
+    program_path: PathBuf,
+
+    /// Path of the file to write the stripped program to.
+    ///
+    /// Defaults to stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Other characters to keep, like `--keep '{}'` for the instructions of `--tape-switch`.
+    #[arg(short, long, value_name = "CHARS", default_value = "")]
+    keep: String,
+}
+
+#[derive(Args, Debug)]
+struct StatsArgs {
+    /// Path of the Brainfuck program to measure, or `-` to read it from stdin.
+    program_path: PathBuf,
+
+    /// Print the metrics as a JSON object instead.
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct TestArgs {
+    /// Paths of the tests to run, or of directories to run every test in.
+    #[arg(value_name = "PATH", default_value = ".")]
+    paths: Vec<PathBuf>,
+
+    /// What `,` does once the input has run out, like for `run`.
+    ///
+    /// Unlike for `run`, this defaults to `zero`, so that programs that read until the end of
+    /// their input stop there rather than time out.
+    #[arg(long, value_name = "BEHAVIOR", value_enum, default_value_t = Eof::Zero)]
+    eof: Eof,
+
+    /// Fail a test once its program ran for DURATION, like `5s` or `500ms`, and isn't done yet.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "10s")]
+    timeout: Duration,
+
+    /// How much to optimize the programs, from 0 to 4, like for `run`.
+    #[arg(short = 'O', long, value_name = "LEVEL", default_value_t = optimizer::DEFAULT_LEVEL)]
+    #[arg(value_parser = opt_level_parser())]
+    opt_level: u8,
+
+    /// The number of cells on the tape, from 64 to 2^30.
+    #[arg(long, value_name = "CELLS", default_value_t = interpreter::TAPE_SIZE)]
+    #[arg(value_parser = tape_size_parser())]
+    tape_size: usize,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Target {
+    /// C99.
+    C,
+
+    /// Rust, as the `main.rs` of a binary crate without dependencies.
+    Rust,
+
+    /// A WebAssembly module, importing the functions to read and write bytes from the host.
+    Wasm,
+
+    /// Textual LLVM IR, which can be compiled with `clang` or optimized further with `opt`.
+    #[value(name = "llvm-ir")]
+    LlvmIr,
+
+    /// x86-64 assembly for the GNU assembler, using Intel syntax.
+    Asm,
+
+    /// JavaScript for Node.js.
+    Js,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum TapeModel {
+    /// Wrap the tape pointer around to the other end of the tape.
+    Wrap,
+
+    /// Stop the program with an error pointing at the instruction that moved past an end of the
+    /// tape.
+    Error,
+
+    /// Grow the tape when moving past its right end, up to 2^30 cells. Moving past its left end is
+    /// an error.
+    Grow,
+
+    /// Grow the tape when moving past either of its ends, up to 2^30 cells in all, so that it
+    /// extends left of the starting cell too.
+    Infinite,
+
+    /// Extend the tape endlessly both ways, only storing the cells that aren't zero, for programs
+    /// that use cells far apart from each other. Cells are slower to access, and `--tape-size` is
+    /// ignored.
+    Sparse,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Preset {
+    /// Like the original interpreter: 30000 cells, where moving past an end of the tape is an
+    /// error, and `,` leaves the cell unchanged.
+    Classic,
+
+    /// Like most interpreters today: a tape that grows to the right, and `,` sets the cell to
+    /// zero.
+    Modern,
+
+    /// Like code golf sites: a tape that grows both ways, for programs that start by moving left,
+    /// and `,` sets the cell to zero.
+    Golf,
+}
+
+impl Preset {
+    /// Set the options of `args` that the preset picks to those of the preset, unless they're
+    /// `given` on the command line, by their ID.
+    fn apply(self, args: &mut RunArgs, given: impl Fn(&str) -> bool) {
+        let (tape_model, eof) = match self {
+            Preset::Classic => (TapeModel::Error, Eof::Unchanged),
+            Preset::Modern => (TapeModel::Grow, Eof::Zero),
+            Preset::Golf => (TapeModel::Infinite, Eof::Zero),
+        };
+        if self == Preset::Classic && !given("tape_size") {
+            args.tape_size = interpreter::TAPE_SIZE;
+        }
+        if !given("tape_model") {
+            args.tape_model = tape_model;
+        }
+        if !given("cell_size") {
+            args.cell_size = CellSize::Bits8;
+        }
+        if !given("signed") {
+            args.signed = false;
+        }
+        if !given("overflow") {
+            args.overflow = Overflow::Wrap;
+        }
+        if !given("eof") {
+            args.eof = eof;
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum CellSize {
+    #[value(name = "8")]
+    Bits8,
+    #[value(name = "16")]
+    Bits16,
+    #[value(name = "32")]
+    Bits32,
+    #[value(name = "64")]
+    Bits64,
+    Big,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Overflow {
+    /// Wrap the cell around to its other end.
+    Wrap,
+
+    /// Stop the cell at its lowest or highest value.
+    Saturate,
+
+    /// Stop the program with an error pointing at the instruction that overflowed.
+    Error,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum IoFormat {
+    /// Write the lowest byte of the cell, and read a byte into it, exactly as they are.
+    Bytes,
+
+    /// Write the value of the cell as a decimal number on a line of its own, and read a decimal
+    /// number after any whitespace into it.
+    Numeric,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Eof {
+    /// Set the cell to zero.
+    Zero,
+
+    /// Set the cell to -1, which is 255 for 8-bit cells.
+    MinusOne,
+
+    /// Leave the cell as it was.
+    Unchanged,
+
+    /// Stop the program with an error.
+    Error,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Flush {
+    /// Flush after every byte.
+    Every,
+
+    /// Flush after every newline, and before `,` reads the input, so that prompts show up.
+    Line,
+
+    /// Flush once the program finishes.
+    End,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum OutputFormat {
+    /// Write the bytes as they are.
+    Raw,
+
+    /// Write a hex dump of the bytes, 16 to a line.
+    Hex,
+
+    /// Write the bytes that aren't printable ASCII as escapes like `\x0A`.
+    Escaped,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum OutputEncoding {
+    /// Write the bytes as they are, as UTF-8 or ASCII.
+    Utf8,
+
+    /// Translate the bytes from ISO 8859-1.
+    Latin1,
+
+    /// Translate the bytes from 128 up from code page 437, drawing boxes and shades like the IBM
+    /// PC.
+    Cp437,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum JitBackend {
+    /// Use the first available backend that supports this machine.
+    Auto,
+
+    /// Compile with Cranelift.
+    Cranelift,
+
+    /// Compile with the hand-written x86-64 backend.
+    #[value(name = "x86-64")]
+    X86_64,
+}
+
+fn main() {
+    let result = parse().and_then(|cli| match (cli.command, cli.run) {
+        (Some(Command::Run(args)), _) => run(&args),
+        (None, Some(args)) => run(&args),
+        (Some(Command::Build(args)), _) => build(&args),
+        (Some(Command::Compile(args)), _) => compile(&args),
+        (Some(Command::Optimize(args)), _) => optimize(&args),
+        (Some(Command::Bench(args)), _) => bench(&args),
+        (Some(Command::DiffState(args)), _) => diff_state(&args),
+        (Some(Command::Diff(args)), _) => diff(&args),
+        (Some(Command::Watch(args)), _) => watch(&args),
+        (Some(Command::Debug(args)), _) => debug(&args),
+        (Some(Command::Check(args)), _) => check(&args),
+        (Some(Command::Fmt(args)), _) => fmt(&args),
+        (Some(Command::Minify(args)), _) => minify(&args),
+        (Some(Command::Strip(args)), _) => strip(&args),
+        (Some(Command::Stats(args)), _) => stats(&args),
+        (Some(Command::Lint(args)), _) => lint(&args),
+        (Some(Command::Test(args)), _) => test(&args),
+        (None, None) => {
+            unreachable!("clap requires either a subcommand or the arguments for `run`")
+        }
+    });
+    if let Err(error) = result {
+        // This is what returning the error from `main` prints, followed by the line of the program
+        // the error is at.
+        eprintln!("Error: {error:?}");
+        if let Some(snippet) = diagnostic::snippet(&error) {
+            eprint!("\n{snippet}");
+        }
+        process::exit(1);
+    }
+}
+
+/// Parse the command line, with the options it leaves out set by `--preset`, or else by the
+/// environment variables, or else by the configuration files.
+fn parse() -> Result<Cli> {
+    let command = Config::load()?.apply(config::with_env(Cli::command()))?;
+    let matches = command.get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+    apply_preset(&mut cli, &matches);
+    Ok(cli)
+}
+
+/// Set the options of the command that runs a program to those of its `--preset`, if it has one,
+/// except for those given on the command line that `matches` came from.
+fn apply_preset(cli: &mut Cli, matches: &ArgMatches) {
+    let run = match (&mut cli.command, &mut cli.run) {
+        (Some(Command::Run(args)), _) => Some((&mut **args, matches.subcommand_matches("run"))),
+        (Some(Command::Watch(args)), _) => Some((&mut **args, matches.subcommand_matches("watch"))),
+        (None, Some(args)) => Some((args, Some(matches))),
+        _ => None,
+    };
+    if let Some((args, Some(matches))) = run {
+        if let Some(preset) = args.preset {
+            preset.apply(args, |id| {
+                matches.value_source(id) == Some(ValueSource::CommandLine)
+            });
+        }
+    }
+}
+
+/// Read the program at `path`, or from stdin if `path` is `-`, leaving out its `#!` line.
+fn read_program(path: &Path) -> Result<String> {
+    let mut source = if path == Path::new("-") {
+        io::read_to_string(io::stdin()).context("Failed to read the program from stdin")?
+    } else {
+        fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?
+    };
+    ir::blank_shebang(&mut source);
+    Ok(source)
+}
+
+/// Compile `source`, the program at `path`, showing the line of an unmatched bracket if there is
+/// one.
+fn compile_source(path: &Path, source: &str) -> Result<Program> {
+    ir::compile(source)
+        .map_err(|error| diagnostic::with_source(error, source, &path.display().to_string()))
+}
+
+/// Read the program at `path`, then compile and optimize it at `level`, like
+/// [`optimize_program`] does.
+fn compile_program(path: &Path, level: u8, tape_size: usize) -> Result<Program> {
+    let program = compile_source(path, &read_program(path)?)?;
+    Ok(optimize_program(program, level, tape_size))
+}
+
+/// Optimize `program` at `level`. From the default level up, as much of it as possible is also
+/// evaluated ahead of time, on a tape of `tape_size` cells.
+fn optimize_program(program: Program, level: u8, tape_size: usize) -> Program {
+    let program = optimizer::optimize_with_stats(program, level).0;
+    if level < optimizer::DEFAULT_LEVEL {
+        return program;
+    }
+    optimizer::fold_prefix(&program, tape_size)
+}
+
+/// Set up `interpreter` to run with the tape, I/O, limits and trace picked by `args`.
+fn configure(args: &RunArgs, interpreter: Interpreter) -> Result<Interpreter> {
+    let interpreter = interpreter
+        .with_tape_size(args.tape_size)
+        .with_tapes(args.tapes)
+        .with_tape_model(tape_model(args.tape_model))
+        .with_overflow(overflow(args.overflow))
+        .with_io_format(io_format(args.io))
+        .with_eof(eof(args.eof))
+        .with_flush(flush(args.flush));
+    let interpreter = args
+        .read_only
+        .iter()
+        .fold(interpreter, |interpreter, cells| {
+            interpreter.with_read_only(cells.clone())
+        });
+    let interpreter = match args.max_steps {
+        Some(limit) => interpreter.with_max_steps(limit),
+        None => interpreter,
+    };
+    let interpreter = match args.timeout {
+        Some(timeout) => interpreter.with_timeout(timeout),
+        None => interpreter,
+    };
+    let interpreter = if args.stats {
+        interpreter.with_instruction_counts()
+    } else {
+        interpreter
+    };
+    if !args.trace {
+        return Ok(interpreter);
+    }
+
+    let output: Box<dyn Write> = match &args.trace_file {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).context(format!("Failed to create {}", path.display()))?,
+        )),
+        None => Box::new(io::stderr()),
+    };
+    let trace = Trace::new(output);
+    let trace = match args.trace_range.clone() {
+        Some(range) => trace.with_range(range),
+        None => trace,
+    };
+    Ok(interpreter.with_trace(trace))
+}
+
+/// Print `message` to stderr if `-v` was given at least `level` times.
+fn log(args: &RunArgs, level: u8, message: fmt::Arguments) {
+    if args.verbose >= level {
+        eprintln!("{message}");
+    }
+}
+
+/// Compile `program`, the source of the program called `name`, with `extensions`, and optimize it
+/// at `level` for a program starting on `tape`, or load it from the cache unless `--no-cache` says
+/// not to.
+fn load(
+    args: &RunArgs,
+    program: &str,
+    name: &str,
+    extensions: Extensions,
+    level: u8,
+    tape: StartingTape,
+) -> Result<Program> {
+    if args.verbose > 0 {
+        let instructions = ir::compile_with(program, extensions)?.len();
+        log(
+            args,
+            1,
+            format_args!("Loaded {name}, with {instructions} instructions"),
+        );
+    }
+    let start = Instant::now();
+    let (compiled, cached) = if args.no_cache {
+        let compiled =
+            optimizer::optimize_on(ir::compile_with(program, extensions)?, level, tape).0;
+        (compiled, false)
+    } else {
+        if let Some(path) = cache::path(program, extensions, level, tape) {
+            log(
+                args,
+                2,
+                format_args!("Its cache file is {}", path.display()),
+            );
+        }
+        cache::load_or_compile(program, extensions, level, tape)?
+    };
+    let ops = compiled.len();
+    let time = start.elapsed();
+    if cached {
+        log(
+            args,
+            1,
+            format_args!("Found it in the cache optimized to {ops} ops, in {time:.3?}"),
+        );
+    } else {
+        log(
+            args,
+            1,
+            format_args!("Optimized it to {ops} ops at level {level}, in {time:.3?}"),
+        );
+    }
+    Ok(compiled)
+}
+
+/// Read the source of every program `args` picks, along with its name for messages.
+fn read_sources(args: &RunArgs) -> Result<Vec<(String, String)>> {
+    if let Some(program) = &args.eval {
+        return Ok(vec![(
+            program.clone(),
+            "the program of `--eval`".to_owned(),
+        )]);
+    }
+    args.program_paths
+        .iter()
+        .map(|path| {
+            let name = if path == Path::new("-") {
+                "the program from stdin".to_owned()
+            } else {
+                path.display().to_string()
+            };
+            Ok((read_program(path)?, name))
+        })
+        .collect()
+}
+
+/// A program to run, with its name and its source for messages.
+struct NamedProgram<'a> {
+    name: &'a str,
+    source: &'a str,
+    program: Program,
+}
+
+/// Compile and optimize the programs of `sources`, or load them from the cache, and return them by
+/// their names, along with the input that comes after their `!` with `--bang-input`, in order.
+///
+/// `--fusion-stats` and `--emit-ir` are printed for every program here.
+fn compile_all<'a>(
+    args: &RunArgs,
+    sources: &'a [(String, String)],
+) -> Result<(Vec<NamedProgram<'a>>, Option<String>)> {
+    // Overflow is only checked for every `+` and `-` when each of them is an op of its own, and
+    // instructions are only counted and traced when every one of them is.
+    let level = if args.overflow == Overflow::Wrap && !args.stats && !args.trace {
+        args.opt_level
+    } else {
+        0
+    };
+    // Loops at the start of the program may run on data that is already on the tape.
+    let tape =
+        if args.tape_file.is_some() || args.tape_init.is_some() || args.tape_init_hex.is_some() {
+            StartingTape::Preloaded
+        } else {
+            StartingTape::Zeroed
+        };
+    // The instructions that switch tapes are comments unless there is another tape to switch to.
+    let extensions = Extensions {
+        tape_switch: (args.tapes > 1).then_some(args.tape_switch),
+    };
+
+    let mut programs = Vec::with_capacity(sources.len());
+    let mut bang_input = None::<String>;
+    for (i, (source, name)) in sources.iter().enumerate() {
+        let (program, input) = if args.bang_input {
+            ir::split_input(source)
+        } else {
+            (&source[..], None)
+        };
+        if let Some(input) = input {
+            bang_input.get_or_insert_default().push_str(input);
+        }
+        // Every program after the first starts on the tape the one before it left behind.
+        let tape = if i == 0 {
+            tape
+        } else {
+            StartingTape::Preloaded
+        };
+        let compiled = if args.strict {
+            ir::check_strict(program, extensions)
+        } else {
+            Ok(())
+        };
+        let compiled = compiled
+            .and_then(|()| load(args, program, name, extensions, level, tape))
+            .map_err(|error| diagnostic::with_source(error, program, name));
+        let compiled = if sources.len() > 1 {
+            compiled.context(format!("Failed to compile {name}"))?
+        } else {
+            compiled?
+        };
+
+        if args.fusion_stats || args.emit_ir {
+            let (compiled, stats) =
+                optimizer::optimize_on(ir::compile_with(program, extensions)?, level, tape);
+            if args.fusion_stats {
+                eprintln!("{}", stats.fusion);
+            }
+            if args.emit_ir {
+                if sources.len() > 1 {
+                    println!("; {name}");
+                }
+                print!("{}", listing(&compiled, &stats.dead_code));
+            }
+        }
+        programs.push(NamedProgram {
+            name,
+            source: program,
+            program: compiled,
+        });
+    }
+    Ok((programs, bang_input))
+}
+
+fn run(args: &RunArgs) -> Result<()> {
+    if args.break_on_hash {
+        return run_to_break(args);
+    }
+    let sources = read_sources(args)?;
+    if args.pgo && sources.len() > 1 {
+        bail!("`--pgo` only works with a single program");
+    }
+    let (programs, bang_input) = compile_all(args, &sources)?;
+    if args.emit_ir {
+        return Ok(());
+    }
+    let interpreter = configure(args, Interpreter::new())?;
+    let bang_input = bang_input.as_deref();
+
+    if args.tape_file.is_some() && !matches!(args.tape_model, TapeModel::Wrap | TapeModel::Error) {
+        bail!("A tape file can't grow, so it only works with `--tape-model wrap` or `error`");
+    }
+
+    match (args.cell_size, args.signed) {
+        (CellSize::Bits8, false) => run_interpreter(args, interpreter, programs, bang_input),
+        (CellSize::Bits16, false) => {
+            run_interpreter(args, interpreter.with_cells::<u16>(), programs, bang_input)
+        }
+        (CellSize::Bits32, false) => {
+            run_interpreter(args, interpreter.with_cells::<u32>(), programs, bang_input)
+        }
+        (CellSize::Bits64, false) => {
+            run_interpreter(args, interpreter.with_cells::<u64>(), programs, bang_input)
+        }
+        (CellSize::Bits8, true) => {
+            run_interpreter(args, interpreter.with_cells::<i8>(), programs, bang_input)
+        }
+        (CellSize::Bits16, true) => {
+            run_interpreter(args, interpreter.with_cells::<i16>(), programs, bang_input)
+        }
+        (CellSize::Bits32, true) => {
+            run_interpreter(args, interpreter.with_cells::<i32>(), programs, bang_input)
+        }
+        (CellSize::Bits64, true) => {
+            run_interpreter(args, interpreter.with_cells::<i64>(), programs, bang_input)
+        }
+        (CellSize::Big, _) => run_interpreter(
+            args,
+            interpreter.with_cells::<BigCell>(),
+            programs,
+            bang_input,
+        ),
+    }
+}
+
+/// Run `programs` one after the other on a tape of `C` cells, with the interpreter or native code
+/// picked by `args`, feeding them `bang_input` unless `args` picks another input.
+fn run_interpreter<C: Cell>(
+    args: &RunArgs,
+    mut interpreter: Interpreter<C>,
+    programs: Vec<NamedProgram>,
+    bang_input: Option<&str>,
+) -> Result<()> {
+    if let Some(path) = &args.tape_file {
+        interpreter = interpreter.with_tape_file(path)?;
+    }
+    if let Some(path) = &args.tape_init {
+        let bytes = fs::read(path).context(format!("Failed to read {}", path.display()))?;
+        interpreter = interpreter.with_tape_contents(&bytes)?;
+    } else if let Some(bytes) = &args.tape_init_hex {
+        interpreter = interpreter.with_tape_contents(bytes)?;
+    }
+
+    // A program that came from stdin leaves the terminal to type its input into. Without one,
+    // stdin has already ended by now, and so has the input.
+    let reads_stdin = args.listen.is_none()
+        && args.replay.is_none()
+        && args.input.is_none()
+        && args.input_fifo.is_none()
+        && args.input_str.is_none()
+        && !args.no_input
+        && bang_input.is_none();
+    let from_stdin = args.program_paths.iter().any(|path| path == Path::new("-"));
+    let terminal = if reads_stdin && from_stdin {
+        File::open("/dev/tty").ok()
+    } else {
+        None
+    };
+    let raw_mode = if args.raw_tty {
+        RawMode::enable(terminal.as_ref())?
+    } else {
+        None
+    };
+
+    let connection = args.listen.as_deref().map(accept).transpose()?;
+
+    let mut input = open_input(args, bang_input, terminal, connection.as_ref())?;
+    let output = open_output(args, connection)?;
+    // A trace on stderr would get mixed up with the progress.
+    let progress =
+        (io::stderr().is_terminal() && !args.quiet && !(args.trace && args.trace_file.is_none()))
+            .then(Progress::start);
+    let output: Box<dyn Write> = match &progress {
+        Some(progress) => {
+            interpreter = interpreter.with_progress(progress.steps());
+            Box::new(progress.output(output))
+        }
+        None => output,
+    };
+    // The interpreter flushes the output whenever `--flush` says so.
+    let mut output = BufWriter::new(output);
+
+    let mut recorder = None;
+    let input: &mut dyn Read = if args.record.is_some() {
+        recorder.insert(Recorder::new(&mut *input))
+    } else {
+        &mut *input
+    };
+    let mut waiting = progress
+        .as_ref()
+        .map(|progress| progress.input(&mut *input));
+    let input: &mut dyn Read = match &mut waiting {
+        Some(waiting) => waiting,
+        None => input,
+    };
+    let start = Instant::now();
+    let result = run_all(args, &mut interpreter, programs, input, &mut output);
+    let time = start.elapsed();
+    drop(progress);
+    drop(raw_mode);
+    // The output the program wrote and the input it read before failing still go out.
+    let flushed = output.flush();
+    if args.dump_tape {
+        let (cells, pointer) = interpreter.nonzero_cells();
+        eprint!("{}", dump::format_tape(&cells, pointer));
+    }
+    let failed = result.is_err() || flushed.is_err();
+    let exit_code = match args.exit_cell {
+        Some(index) if !failed => cell_exit_code(&interpreter, index),
+        _ => u8::from(failed),
+    };
+    if args.json || args.time {
+        let report = report(&interpreter, &result, &flushed, exit_code, time);
+        if args.time {
+            eprintln!("{}", report.timing());
+        }
+        if args.json {
+            eprintln!("{}", report.to_json());
+        }
+    }
+    if let Some(counts) = interpreter.instruction_counts() {
+        eprint!("{counts}");
+    }
+    if let (Some(path), Some(recorder)) = (&args.record, &recorder) {
+        fs::write(path, recorder.session().to_json())
+            .context(format!("Failed to write {}", path.display()))?;
+    }
+    result?;
+    flushed.context("Failed to write the output")?;
+    if exit_code != 0 {
+        // Nothing gets dropped once beef exits, so the output and the tape are done with first.
+        drop(output);
+        drop(interpreter);
+        process::exit(exit_code.into());
+    }
+    Ok(())
+}
+
+/// Return the lowest byte of the cell at `index` from the starting cell, for `--exit-cell`.
+fn cell_exit_code<C: Cell>(interpreter: &Interpreter<C>, index: isize) -> u8 {
+    let (cells, _) = interpreter.nonzero_cells();
+    cells
+        .iter()
+        .find(|(cell_index, _)| *cell_index == index)
+        .map_or(0, |(_, cell)| cell.low_byte())
+}
+
+/// Open the input `args` picks for `,`, which is `bang_input` if `args` doesn't pick another one,
+/// or else the terminal the program came from if there is one, or else stdin.
+fn open_input<'a>(
+    args: &'a RunArgs,
+    bang_input: Option<&'a str>,
+    terminal: Option<File>,
+    connection: Option<&TcpStream>,
+) -> Result<Box<dyn Read + 'a>> {
+    // Nothing else may lock stdin while the input is read from it in the background.
+    let input: Box<dyn Read> = if let Some(connection) = connection {
+        Box::new(BufReader::new(
+            connection
+                .try_clone()
+                .context("Failed to read from the connection")?,
+        ))
+    } else if let Some(path) = &args.replay {
+        let json =
+            fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+        let session =
+            Session::from_json(&json).context(format!("Failed to read {}", path.display()))?;
+        Box::new(Replay::new(session))
+    } else if let Some(idle) = args.input_nonblocking {
+        Box::new(match terminal {
+            Some(terminal) => NonBlockingInput::new(terminal, idle),
+            None => NonBlockingInput::new(io::stdin(), idle),
+        })
+    } else {
+        let sources = (&args.input, &args.input_fifo, &args.input_str, bang_input);
+        let input: Box<dyn BufRead> = match sources {
+            (Some(path), _, _, _) => Box::new(BufReader::new(
+                File::open(path).context(format!("Failed to open {}", path.display()))?,
+            )),
+            (None, Some(path), _, _) => Box::new(BufReader::new(Fifo::new(path)?)),
+            (None, None, Some(bytes), _) => Box::new(&bytes[..]),
+            (None, None, None, Some(text)) => Box::new(text.as_bytes()),
+            (None, None, None, None) if args.no_input => Box::new(io::empty()),
+            (None, None, None, None) => match terminal {
+                Some(terminal) => Box::new(BufReader::new(terminal)),
+                None => Box::new(io::stdin().lock()),
+            },
+        };
+        if args.line_input {
+            Box::new(LineInput::new(input))
+        } else {
+            Box::new(input)
+        }
+    };
+    Ok(input)
+}
+
+/// Open the output `args` picks for `.`, which is `connection` if there is one.
+fn open_output(args: &RunArgs, connection: Option<TcpStream>) -> Result<Box<dyn Write>> {
+    let output: Box<dyn Write> = match (&args.output, &args.output_fifo, connection) {
+        (_, _, Some(connection)) => Box::new(connection),
+        (Some(path), _, None) => Box::new(
+            OpenOptions::new()
+                .create(true)
+                .append(args.append)
+                .truncate(!args.append)
+                .write(true)
+                .open(path)
+                .context(format!("Failed to open {}", path.display()))?,
+        ),
+        (None, Some(path), None) => Box::new(Fifo::new(path)?),
+        (None, None, None) => Box::new(io::stdout().lock()),
+    };
+    let output: Box<dyn Write> = match args.output_format {
+        OutputFormat::Raw if args.sanitize_output => Box::new(Sanitized::new(output)),
+        OutputFormat::Raw => output,
+        OutputFormat::Hex => Box::new(HexDump::new(output)),
+        OutputFormat::Escaped => Box::new(Escaped::new(output)),
+    };
+    let output: Box<dyn Write> = match &args.transcript {
+        Some(path) => {
+            let transcript = BufWriter::new(
+                File::create(path).context(format!("Failed to open {}", path.display()))?,
+            );
+            if args.timestamps {
+                Box::new(Tee::new(output, Timestamped::new(transcript)))
+            } else {
+                Box::new(Tee::new(output, transcript))
+            }
+        }
+        None => output,
+    };
+    let output: Box<dyn Write> = match args.output_encoding {
+        OutputEncoding::Utf8 => output,
+        OutputEncoding::Latin1 => Box::new(Encoded::new(output, Encoding::Latin1)),
+        OutputEncoding::Cp437 => Box::new(Encoded::new(output, Encoding::Cp437)),
+    };
+    Ok(match args.throttle {
+        Some(delay) => Box::new(Throttled::new(output, delay)),
+        None => output,
+    })
+}
+
+/// Listen on `address`, and return the first connection to it.
+fn accept(address: &str) -> Result<TcpStream> {
+    let listener = TcpListener::bind(address).context(format!("Failed to listen on {address}"))?;
+    eprintln!("Listening on {}", listener.local_addr()?);
+    let (connection, _) = listener
+        .accept()
+        .context(format!("Failed to accept a connection on {address}"))?;
+    Ok(connection)
+}
+
+/// Return the report of a run on `interpreter` that took `time` and ended with `result`, after
+/// which its output was `flushed`.
+fn report<C: Cell>(
+    interpreter: &Interpreter<C>,
+    result: &Result<()>,
+    flushed: &io::Result<()>,
+    exit_code: u8,
+    time: Duration,
+) -> RunReport<C> {
+    let (cells, tape_pointer) = interpreter.nonzero_cells();
+    let error = match (result, flushed) {
+        (Err(error), _) => Some(format!("{error:#}")),
+        (Ok(()), Err(error)) => Some(format!("Failed to write the output: {error}")),
+        (Ok(()), Ok(())) => None,
+    };
+    RunReport {
+        exit_code,
+        error,
+        error_position: result.as_ref().err().and_then(diagnostic::position),
+        ops: interpreter.steps(),
+        tape_pointer,
+        cells,
+        time,
+    }
+}
+
+/// Run `programs` one after the other on `interpreter`, until one of them fails, reading their
+/// input from `input` and writing their output to `output`.
+fn run_all<C: Cell>(
+    args: &RunArgs,
+    interpreter: &mut Interpreter<C>,
+    programs: Vec<NamedProgram>,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<()> {
+    let several = programs.len() > 1;
+    // How the programs run is the same for every one of them, so it's only told about once.
+    let quieted = several.then(|| RunArgs {
+        verbose: 0,
+        quiet: true,
+        ..args.clone()
+    });
+    for (i, program) in programs.into_iter().enumerate() {
+        let args = quieted.as_ref().filter(|_| i > 0).unwrap_or(args);
+        let NamedProgram {
+            name,
+            source,
+            program,
+        } = program;
+        let spans = program.spans.clone();
+        interpreter.load(program);
+        let result = dispatch(args, interpreter, &spans, input, output)
+            .map_err(|error| diagnostic::with_source(error, source, name));
+        if several {
+            result.context(format!("Failed to run {name}"))?;
+        } else {
+            result?;
+        }
+    }
+    Ok(())
+}
+
+/// Run a program with the interpreter or native code picked by `args`, reading its input from
+/// `input` and writing its output to `output`.
+fn dispatch<C: Cell>(
+    args: &RunArgs,
+    interpreter: &mut Interpreter<C>,
+    spans: &[Span],
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<()> {
+    log(
+        args,
+        2,
+        format_args!(
+            "The tape starts with {} cells of `--cell-size {}`{}, with `--tape-model {}`",
+            args.tape_size,
+            value_name(&args.cell_size),
+            if args.signed { " --signed" } else { "" },
+            value_name(&args.tape_model)
+        ),
+    );
+    if args.pgo {
+        log(
+            args,
+            1,
+            format_args!("Running it with profile-guided JIT compilation"),
+        );
+        return run_pgo(args, interpreter, spans, input, output);
+    }
+
+    let bounded = args.tape_model != TapeModel::Wrap;
+    let overflow_checked = args.overflow != Overflow::Wrap;
+    let read_only = !args.read_only.is_empty();
+    let multiple_tapes = args.tapes > 1;
+    let limited = args.max_steps.is_some() || args.timeout.is_some();
+    let counted = args.stats || args.trace;
+    let checked = bounded || overflow_checked || read_only || multiple_tapes || limited || counted;
+    let fast = args.jit.is_some() || args.unsafe_fast;
+    if args.quiet {
+        // `--quiet` leaves the warnings out.
+    } else if bounded && fast {
+        warn_bounded();
+    } else if overflow_checked && fast {
+        warn_overflow();
+    } else if read_only && fast {
+        warn_read_only();
+    } else if multiple_tapes && fast {
+        warn_tapes();
+    } else if limited && fast {
+        warn_limits();
+    } else if counted && fast {
+        warn_counted();
+    } else if args.jit.is_some() && args.cell_size != CellSize::Bits8 {
+        warn_wide_cells();
+    }
+
+    let native = !checked && args.cell_size == CellSize::Bits8;
+    if let Some(backend) = args.jit.filter(|_| native) {
+        #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+        if let Some(native) = jit_backend(backend) {
+            let name = value_name(&backend);
+            log(
+                args,
+                1,
+                format_args!("Running it as native code, with `--jit={name}`"),
+            );
+            return interpreter.run_jit_with(native, input, output);
+        }
+
+        if !args.quiet {
+            warn_no_jit(backend);
+        }
+    }
+
+    if args.unsafe_fast && !checked {
+        #[cfg(feature = "unsafe-fast")]
+        {
+            log(
+                args,
+                1,
+                format_args!("Interpreting it without bounds checks"),
+            );
+            return interpreter.run_unchecked_with(input, output);
+        }
+
+        #[cfg(not(feature = "unsafe-fast"))]
+        if !args.quiet {
+            warn_no_unsafe_fast();
+        }
+    }
+
+    if checked {
+        log(args, 1, format_args!("Interpreting it with bounds checks"));
+    } else {
+        log(args, 1, format_args!("Interpreting it"));
+    }
+    if args.json || args.time || args.stats {
+        return interpreter.run_counted(input, output).map(drop);
+    }
+    interpreter.run_with(input, output)
+}
+
+/// Return the name of `value` on the command line.
+fn value_name(value: &impl ValueEnum) -> String {
+    let value = value.to_possible_value().expect("no value is skipped");
+    value.get_name().to_owned()
+}
+
+/// Warn that the program is interpreted, as the JIT backend picked by `--jit` is unavailable.
+fn warn_no_jit(backend: JitBackend) {
+    eprintln!(
+        "No JIT backend matching `--jit={}` is available, falling back to the interpreter",
+        value_name(&backend)
+    );
+}
+
+/// Warn that the program is interpreted with bounds checks, as the tape can't stop the program or
+/// grow otherwise.
+fn warn_bounded() {
+    eprintln!("The tape can only stop or grow with bounds checks, falling back to the interpreter");
+}
+
+/// Warn that the program is interpreted with bounds checks, as overflow is only checked there.
+fn warn_overflow() {
+    eprintln!("Overflow can only be checked with bounds checks, falling back to the interpreter");
+}
+
+/// Warn that the program is interpreted with bounds checks, as writes to read-only cells are only
+/// checked there.
+fn warn_read_only() {
+    eprintln!(
+        "Read-only cells can only be checked with bounds checks, falling back to the interpreter"
+    );
+}
+
+/// Warn that the program is interpreted with bounds checks, as tapes are only switched there.
+fn warn_tapes() {
+    eprintln!("Tapes can only be switched with bounds checks, falling back to the interpreter");
+}
+
+/// Warn that the program is interpreted with bounds checks, as steps and time are only limited
+/// there.
+fn warn_limits() {
+    eprintln!("Runs can only be limited with bounds checks, falling back to the interpreter");
+}
+
+/// Warn that the program is interpreted with bounds checks, as instructions are only counted and
+/// traced there.
+fn warn_counted() {
+    eprintln!(
+        "Instructions can only be counted or traced with bounds checks, falling back to the \
+         interpreter"
+    );
+}
+
+/// Warn that the program is interpreted, as native code only supports 8-bit cells.
+fn warn_wide_cells() {
+    eprintln!("Native code only supports 8-bit cells, falling back to the interpreter");
+}
+
+/// Warn that the program is interpreted with bounds checks, as beef was built without
+/// `unsafe-fast`.
+#[cfg(not(feature = "unsafe-fast"))]
+fn warn_no_unsafe_fast() {
+    eprintln!("beef was built without the `unsafe-fast` feature, falling back to the interpreter");
+}
+
+/// Run a program like [`run`] every time its file changes, until beef is stopped.
+fn watch(args: &RunArgs) -> Result<()> {
+    let paths = &args.program_paths;
+    if args.eval.is_some() || paths.iter().any(|path| path == Path::new("-")) {
+        bail!("Only programs in files can be watched");
+    }
+    if args.exit_cell.is_some() {
+        bail!("`--exit-cell` would end the watch after the first run");
+    }
+
+    let mut watchers: Vec<_> = paths.iter().map(|path| Watcher::new(path)).collect();
+    let names: Vec<_> = paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    loop {
+        if io::stdout().is_terminal() {
+            // Clear the screen, and move the cursor back to its top left corner.
+            print!("\x1b[2J\x1b[H");
+            io::stdout().flush().context("Failed to clear the screen")?;
+        }
+        if let Err(error) = run(args) {
+            eprintln!("Error: {error:#}");
+            if let Some(snippet) = diagnostic::snippet(&error) {
+                eprint!("{snippet}");
+            }
+        }
+        eprintln!("Watching {} for changes", names.join(", "));
+        watch::wait(&mut watchers);
+    }
+}
+
+fn debug(args: &DebugArgs) -> Result<()> {
+    let source = read_program(&args.program_path)?;
+    let input = match &args.input {
+        Some(path) => Some(fs::read(path).context(format!("Failed to read {}", path.display()))?),
+        None => None,
+    };
+    let name = args.program_path.display().to_string();
+    // Every instruction is an op of its own, without optimizing the program.
+    let program =
+        ir::compile(&source).map_err(|error| diagnostic::with_source(error, &source, &name))?;
+    let interpreter = Interpreter::from_program(program)
+        .with_tape_size(args.tape_size)
+        .with_eof(eof(args.eof));
+    debug_program(interpreter, &source, &name, input.as_deref(), None, false)
+}
+
+/// Run the program of `args` with `--break-on-hash`, in the debugger once it reaches a `#`.
+fn run_to_break(args: &RunArgs) -> Result<()> {
+    let [(source, name)] = &read_sources(args)?[..] else {
+        bail!("`--break-on-hash` only works with a single program");
+    };
+    // These can come from the configuration files too, which clap doesn't check for conflicts.
+    if args.cell_size != CellSize::Bits8 || args.signed {
+        bail!("`--break-on-hash` only works with the default cells, which are 8 bits and unsigned");
+    }
+    if args.tape_model == TapeModel::Sparse {
+        bail!("`--break-on-hash` doesn't work with `--tape-model sparse`");
+    }
+    let input = match (&args.input, &args.input_str) {
+        (Some(path), _) => {
+            Some(fs::read(path).context(format!("Failed to read {}", path.display()))?)
+        }
+        (None, Some(text)) => Some(text.to_vec()),
+        (None, None) if args.no_input => Some(Vec::new()),
+        (None, None) => None,
+    };
+
+    let extensions = Extensions {
+        tape_switch: (args.tapes > 1).then_some(args.tape_switch),
+    };
+    let program = if args.strict {
+        ir::check_strict(source, extensions)
+    } else {
+        Ok(())
+    };
+    let program = program
+        .and_then(|()| ir::compile_with(source, extensions))
+        .map_err(|error| diagnostic::with_source(error, source, name))?;
+    let mut interpreter = configure(args, Interpreter::new())?;
+    interpreter.load(program);
+
+    let mut output = if args.output.is_some() || args.output_fifo.is_some() {
+        Some(open_output(args, None)?)
+    } else {
+        None
+    };
+    debug_program(
+        interpreter,
+        source,
+        name,
+        input.as_deref(),
+        output.as_deref_mut(),
+        true,
+    )
+}
+
+/// Debug `interpreter`, whose program is `source` called `name`, like [`Debugger::run`] does
+/// at the terminal, with what the program reads in `input`, or else typed at the prompt, and
+/// what it writes in `output`, or else shown along with the prompt.
+fn debug_program(
+    interpreter: Interpreter,
+    source: &str,
+    name: &str,
+    input: Option<&[u8]>,
+    output: Option<&mut (dyn Write + '_)>,
+    run_first: bool,
+) -> Result<()> {
+    Debugger::new(
+        interpreter,
+        source,
+        name,
+        diagnostic::colored(&io::stdout()),
+    )
+    .run(
+        &mut io::stdin().lock(),
+        input,
+        &mut io::stdout().lock(),
+        output,
+        run_first,
+    )
+}
+
+/// Run a program with `--pgo`, loading its profile from `--profile` first and saving it there
+/// afterwards, even if the program fails.
+fn run_pgo<C: Cell>(
+    args: &RunArgs,
+    interpreter: &mut Interpreter<C>,
+    spans: &[Span],
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<()> {
+    let mut profile = match &args.profile {
+        Some(path) if path.exists() => {
+            let text =
+                fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+            Profile::parse(&text).context(format!("Invalid profile {}", path.display()))?
+        }
+        _ => Profile::default(),
+    };
+
+    #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+    let backend = jit_backend(args.jit.unwrap_or(JitBackend::Auto));
+    #[cfg(not(any(feature = "jit", feature = "jit-x86-64")))]
+    let backend = None::<()>;
+    if args.quiet {
+        // `--quiet` leaves the warnings out.
+    } else if args.tape_model != TapeModel::Wrap {
+        eprintln!(
+            "The tape can only stop or grow with bounds checks, so hot loops are interpreted"
+        );
+    } else if args.overflow != Overflow::Wrap {
+        eprintln!("Overflow can only be checked with bounds checks, so hot loops are interpreted");
+    } else if !args.read_only.is_empty() {
+        eprintln!(
+            "Read-only cells can only be checked with bounds checks, so hot loops are interpreted"
+        );
+    } else if args.tapes > 1 {
+        eprintln!("Tapes can only be switched with bounds checks, so hot loops are interpreted");
+    } else if args.cell_size != CellSize::Bits8 {
+        eprintln!("Native code only supports 8-bit cells, so hot loops are interpreted");
+    } else if backend.is_none() {
+        eprintln!("No JIT backend is available, so hot loops are interpreted");
+    }
+
+    let result = interpreter.run_pgo_with(
+        spans,
+        &mut profile,
+        #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+        backend,
+        input,
+        output,
+    );
+
+    if let Some(path) = &args.profile {
+        fs::write(path, profile.to_string())
+            .context(format!("Failed to write {}", path.display()))?;
+    }
+    result
+}
+
+/// Return a listing of an optimized program, one op per line followed by the span of the source it
+/// came from, with the dead code removed from it commented out where it used to be.
+fn listing(program: &Program, dead_code: &[DeadCode]) -> String {
+    let mut listing = String::new();
+    let mut dead_code = dead_code.iter().peekable();
+    let mut depth = 0usize;
+
+    for i in 0..=program.len() {
+        while let Some(dead) = dead_code.next_if(|dead| dead.index == i) {
+            let (span, reason) = (dead.span, dead.reason);
+            writeln!(listing, "      ; dropped {span}, as {reason}:").unwrap();
+            let mut dead_depth = depth;
+            for op in &dead.ops {
+                // The targets of dead jumps are meaningless, so leave them out.
+                let line = match op {
+                    Op::JumpIfZero(_) => "jz".to_string(),
+                    Op::JumpIfNonZero(_) => "jnz".to_string(),
+                    op => op.to_string(),
+                };
+                // The jumps of a loop that runs once are dropped without the rest of the loop,
+                // so they may not be balanced.
+                if matches!(op, Op::JumpIfNonZero(_)) {
+                    dead_depth = dead_depth.saturating_sub(1);
+                }
+                writeln!(listing, "      ;   {}{line}", "  ".repeat(dead_depth)).unwrap();
+                if matches!(op, Op::JumpIfZero(_)) {
+                    dead_depth += 1;
+                }
+            }
+        }
+
+        let Some(&op) = program.ops.get(i) else {
+            break;
+        };
+        if matches!(op, Op::JumpIfNonZero(_)) {
+            depth -= 1;
+        }
+        let line = format!("{}{op}", "  ".repeat(depth));
+        writeln!(listing, "{i:>5} {line:<32} ; {}", program.spans[i]).unwrap();
+        if matches!(op, Op::JumpIfZero(_)) {
+            depth += 1;
+        }
+    }
+
+    listing
+}
+
+fn build(args: &BuildArgs) -> Result<()> {
+    let program = compile_program(&args.program_path, args.opt_level, args.tape_size)?;
+    let code = codegen::c::emit(&program.ops, args.tape_size);
+
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| args.program_path.with_extension(""));
+    if output == args.program_path {
+        return Err(anyhow!(
+            "Refusing to overwrite {}, pass an output path with --output",
+            output.display()
+        ));
+    }
+
+    let cc = args
+        .cc
+        .clone()
+        .or_else(|| env::var_os("CC"))
+        .unwrap_or_else(|| "cc".into());
+    // The source is piped to the compiler rather than written to a file, which another user could
+    // have created in its place in the shared temporary directory.
+    let mut compiler = process::Command::new(&cc)
+        .args(["-O2", "-x", "c", "-o"])
+        .arg(&output)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .spawn()
+        .context(format!("Failed to run {}", cc.to_string_lossy()))?;
+    let written = compiler
+        .stdin
+        .take()
+        .map(|mut stdin| stdin.write_all(code.as_bytes()));
+    let status = compiler
+        .wait()
+        .context(format!("Failed to run {}", cc.to_string_lossy()))?;
+    if !status.success() {
+        return Err(anyhow!("{} exited with {status}", cc.to_string_lossy()));
+    }
+    if let Some(written) = written {
+        written.context(format!("Failed to write to {}", cc.to_string_lossy()))?;
+    }
 
     Ok(())
 }
+
+fn compile(args: &CompileArgs) -> Result<()> {
+    let ops = compile_program(&args.program_path, args.opt_level, args.tape_size)?.ops;
+    let tape_size = args.tape_size;
+    let code = match args.target {
+        Target::C => codegen::c::emit(&ops, tape_size).into_bytes(),
+        Target::Rust => codegen::rust::emit(&ops, tape_size).into_bytes(),
+        Target::Wasm => codegen::wasm::emit(&ops, tape_size)?,
+        Target::LlvmIr => codegen::llvm::emit(&ops, tape_size).into_bytes(),
+        Target::Asm => codegen::asm::emit(&ops, tape_size).into_bytes(),
+        Target::Js => codegen::js::emit(&ops, tape_size).into_bytes(),
+    };
+
+    write_output(args.output.as_deref(), &code)
+}
+
+fn optimize(args: &OptimizeArgs) -> Result<()> {
+    let path = &args.program_path;
+    let program = optimizer::optimize(compile_source(path, &read_program(path)?)?);
+
+    // Evaluating ahead of time can make the program longer, e.g. when it prints a long string
+    // generated by a short loop, so keep whichever version is shorter.
+    let folded = optimizer::fold_prefix(&program, args.tape_size);
+    let folded = codegen::brainfuck::emit(&folded.ops);
+    let unfolded = codegen::brainfuck::emit(&program.ops);
+    let code = if folded.len() < unfolded.len() {
+        folded
+    } else {
+        unfolded
+    };
+
+    write_output(args.output.as_deref(), code.as_bytes())
+}
+
+fn bench(args: &BenchArgs) -> Result<()> {
+    let program = read_program(&args.program_path)?;
+    let ops = optimizer::optimize_with_stats(
+        compile_source(&args.program_path, &program)?,
+        args.opt_level,
+    )
+    .0
+    .ops;
+    let input = match &args.input {
+        Some(path) => fs::read(path).context(format!("Failed to read {}", path.display()))?,
+        None => Vec::new(),
+    };
+
+    let report = match (args.cell_size, args.signed) {
+        (CellSize::Bits8, false) => bench_cells::<u8>(args, &ops, &input)?,
+        (CellSize::Bits16, false) => bench_cells::<u16>(args, &ops, &input)?,
+        (CellSize::Bits32, false) => bench_cells::<u32>(args, &ops, &input)?,
+        (CellSize::Bits64, false) => bench_cells::<u64>(args, &ops, &input)?,
+        (CellSize::Bits8, true) => bench_cells::<i8>(args, &ops, &input)?,
+        (CellSize::Bits16, true) => bench_cells::<i16>(args, &ops, &input)?,
+        (CellSize::Bits32, true) => bench_cells::<i32>(args, &ops, &input)?,
+        (CellSize::Bits64, true) => bench_cells::<i64>(args, &ops, &input)?,
+        (CellSize::Big, _) => bench_cells::<BigCell>(args, &ops, &input)?,
+    };
+
+    if args.json {
+        println!(
+            "{}",
+            report.to_json(&args.program_path.display().to_string())
+        );
+    } else {
+        println!("{report}");
+    }
+
+    Ok(())
+}
+
+/// Benchmark `ops` on a tape of `C` cells, each run reading `input`.
+fn bench_cells<C: Cell>(args: &BenchArgs, ops: &[Op], input: &[u8]) -> Result<bench::Report> {
+    // Counting ops slows the interpreter down, so they are counted in a run of their own.
+    let ops_per_run =
+        bench_interpreter::<C>(args, ops).run_counted(&mut &input[..], &mut io::sink())?;
+    Ok(bench::Report::new(
+        bench_times::<C>(args, ops, input)?,
+        ops_per_run,
+    ))
+}
+
+/// Return an interpreter for a run of `ops` picked by `args`, on a tape of `C` cells.
+fn bench_interpreter<C: Cell>(args: &BenchArgs, ops: &[Op]) -> Interpreter<C> {
+    Interpreter::from_ops(ops.to_vec())
+        .with_tape_size(args.tape_size)
+        .with_tape_model(tape_model(args.tape_model))
+        .with_cells()
+}
+
+/// Time the runs of `ops` picked by `args` on a tape of `C` cells, each reading `input`.
+fn bench_times<C: Cell>(args: &BenchArgs, ops: &[Op], input: &[u8]) -> Result<Vec<Duration>> {
+    let bounded = args.tape_model != TapeModel::Wrap;
+    if bounded && (args.jit.is_some() || args.unsafe_fast) {
+        warn_bounded();
+    } else if args.jit.is_some() && args.cell_size != CellSize::Bits8 {
+        warn_wide_cells();
+    }
+
+    let native = !bounded && args.cell_size == CellSize::Bits8;
+    if let Some(backend) = args.jit.filter(|_| native) {
+        #[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+        if let Some(backend) = jit_backend(backend) {
+            let compiled = jit::compile(backend, ops, args.tape_size)?;
+            return bench::measure(
+                args.warmup,
+                args.runs,
+                || (vec![0; args.tape_size], 0),
+                |(mut tape, mut tape_pointer)| {
+                    compiled.run::<C>(
+                        &mut tape,
+                        &mut tape_pointer,
+                        &mut &input[..],
+                        &mut io::sink(),
+                        interpreter::IoMode::default(),
+                    )
+                },
+            );
+        }
+
+        warn_no_jit(backend);
+    }
+
+    let interpreter = || bench_interpreter::<C>(args, ops);
+
+    if args.unsafe_fast && !bounded {
+        #[cfg(feature = "unsafe-fast")]
+        return bench::measure(args.warmup, args.runs, interpreter, |mut interpreter| {
+            interpreter.run_unchecked_with(&mut &input[..], &mut io::sink())
+        });
+
+        #[cfg(not(feature = "unsafe-fast"))]
+        warn_no_unsafe_fast();
+    }
+
+    bench::measure(args.warmup, args.runs, interpreter, |mut interpreter| {
+        interpreter.run_with(&mut &input[..], &mut io::sink())
+    })
+}
+
+fn diff_state(args: &DiffStateArgs) -> Result<()> {
+    let runs = run_both(args)?;
+    let diff = diff::StateDiff::new([&runs[0], &runs[1]]);
+    let names = [&args.first_path, &args.second_path].map(|path| path.display().to_string());
+    println!("{}", diff.report([&names[0], &names[1]]));
+    if !diff.is_empty() {
+        bail!("The runs ended in different states");
+    }
+    Ok(())
+}
+
+fn diff(args: &DiffArgs) -> Result<()> {
+    let runs = run_both(&args.runs)?;
+    let names =
+        [&args.runs.first_path, &args.runs.second_path].map(|path| path.display().to_string());
+    let names = [names[0].as_str(), names[1].as_str()];
+    let output = diff::lines(
+        [&runs[0].output, &runs[1].output],
+        names,
+        diagnostic::colored(&io::stdout()),
+    );
+    let mut state = diff::StateDiff::new([&runs[0], &runs[1]]);
+    // The outputs are shown line by line instead.
+    state.output = None;
+    let tape = Some(state).filter(|state| args.tape && !state.is_empty());
+
+    match (output, tape) {
+        (None, None) if args.tape => println!("The runs ended in the same state"),
+        (None, None) => println!("The outputs are the same"),
+        (output, tape) => {
+            if let Some(output) = output {
+                println!("{output}");
+            }
+            if let Some(tape) = tape {
+                println!("{}", tape.report(names));
+            }
+            bail!("The runs differ");
+        }
+    }
+    Ok(())
+}
+
+/// Run the programs of `args` on their input, and return the states they end in.
+fn run_both(args: &DiffStateArgs) -> Result<[diff::RunState; 2]> {
+    let input = match &args.input {
+        Some(path) => fs::read(path).context(format!("Failed to read {}", path.display()))?,
+        None => Vec::new(),
+    };
+    let run = |path: &Path, level| -> Result<diff::RunState> {
+        let source = read_program(path)?;
+        let program = compile_source(path, &source)?;
+        let program = optimize_program(program, level, args.tape_size);
+        let mut interpreter = Interpreter::from_program(program)
+            .with_tape_size(args.tape_size)
+            .with_eof(eof(args.eof))
+            .with_timeout(args.timeout);
+        let mut output = Vec::new();
+        let name = path.display().to_string();
+        interpreter
+            .run_with(&mut &input[..], &mut output)
+            .map_err(|error| diagnostic::with_source(error, &source, &name))
+            .context(format!("Failed to run {name}"))?;
+        Ok(diff::RunState {
+            output,
+            tape: interpreter.tape().to_vec(),
+            tape_pointer: interpreter.tape_pointer(),
+        })
+    };
+    let second_level = args.second_opt_level.unwrap_or(args.opt_level);
+    Ok([
+        run(&args.first_path, args.opt_level)?,
+        run(&args.second_path, second_level)?,
+    ])
+}
+
+fn check(args: &CheckArgs) -> Result<()> {
+    let rules = check::Rules {
+        max_instructions: args.max_instructions,
+        max_depth: args.max_depth,
+        strict: args.strict,
+    };
+
+    let mut count = 0;
+    for path in &args.program_paths {
+        let problems = check::check(&read_program(path)?, &rules);
+        for problem in &problems {
+            println!("{}:{problem}", path.display());
+        }
+        count += problems.len();
+    }
+    match count {
+        0 => Ok(()),
+        1 => bail!("Found a problem"),
+        count => bail!("Found {count} problems"),
+    }
+}
+
+fn fmt(args: &FmtArgs) -> Result<()> {
+    let path = &args.program_path;
+    let source = if path == Path::new("-") {
+        io::read_to_string(io::stdin()).context("Failed to read the program from stdin")?
+    } else {
+        fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?
+    };
+    // The `#!` line is kept, so it's only left out to check the brackets.
+    let mut program = source.clone();
+    ir::blank_shebang(&mut program);
+    compile_source(path, &program)?;
+
+    let style = format::Style {
+        width: args.width.into(),
+        indent: args.indent.into(),
+    };
+    let formatted = format::format(&source, style);
+    if args.check {
+        if formatted != source {
+            bail!("{} isn't formatted", path.display());
+        }
+        Ok(())
+    } else if args.write && path != Path::new("-") {
+        fs::write(path, formatted).context(format!("Failed to write {}", path.display()))
+    } else {
+        write_output(None, formatted.as_bytes())
+    }
+}
+
+fn minify(args: &MinifyArgs) -> Result<()> {
+    let program = read_program(&args.program_path)?;
+    compile_source(&args.program_path, &program)?;
+    let mut code = minify::minify(&program, args.shorten);
+    code.push('\n');
+    write_output(args.output.as_deref(), code.as_bytes())
+}
+
+fn strip(args: &StripArgs) -> Result<()> {
+    let program = read_program(&args.program_path)?;
+    let mut code = minify::strip(&program, &args.keep);
+    code.push('\n');
+    write_output(args.output.as_deref(), code.as_bytes())
+}
+
+fn lint(args: &LintArgs) -> Result<()> {
+    let mut count = 0;
+    for path in &args.program_paths {
+        let warnings = lint::lint(&read_program(path)?);
+        for warning in &warnings {
+            println!("{}:{warning}", path.display());
+        }
+        count += warnings.len();
+    }
+    match count {
+        0 => Ok(()),
+        1 => bail!("Found a likely mistake"),
+        count => bail!("Found {count} likely mistakes"),
+    }
+}
+
+fn stats(args: &StatsArgs) -> Result<()> {
+    let program = read_program(&args.program_path)?;
+    compile_source(&args.program_path, &program)?;
+    let metrics = Metrics::new(&program);
+    if args.json {
+        println!("{}", metrics.to_json());
+    } else {
+        println!("{metrics}");
+    }
+    Ok(())
+}
+
+fn test(args: &TestArgs) -> Result<()> {
+    let cases = expect::discover(&args.paths)?;
+    if cases.is_empty() {
+        bail!("Found no tests, which are programs like `hello.b` with their output in `hello.out`");
+    }
+
+    let color = diagnostic::colored(&io::stdout());
+    let (green, red, reset) = if color {
+        ("\x1b[1;32m", "\x1b[1;31m", "\x1b[0m")
+    } else {
+        ("", "", "")
+    };
+    let mut failed = 0;
+    for case in &cases {
+        let failure = run_test(args, case)
+            .and_then(|actual| {
+                let expected = fs::read(&case.output)
+                    .context(format!("Failed to read {}", case.output.display()))?;
+                Ok(diff::lines(
+                    [&expected, &actual],
+                    ["expected", "actual"],
+                    color,
+                ))
+            })
+            .unwrap_or_else(|error| Some(format!("{error:#}")));
+        match failure {
+            None => println!("{green}PASS{reset} {}", case.program.display()),
+            Some(failure) => {
+                failed += 1;
+                println!("{red}FAIL{reset} {}", case.program.display());
+                for line in failure.lines() {
+                    println!("    {line}");
+                }
+            }
+        }
+    }
+
+    let passed = cases.len() - failed;
+    println!("\n{passed} passed, {failed} failed");
+    match failed {
+        0 => Ok(()),
+        1 => bail!("A test failed"),
+        failed => bail!("{failed} tests failed"),
+    }
+}
+
+/// Run the program of `case` on its input, with the semantics picked by `args`, and return its
+/// output.
+fn run_test(args: &TestArgs, case: &expect::Case) -> Result<Vec<u8>> {
+    let name = case.program.display().to_string();
+    let source = read_program(&case.program)?;
+    let program = compile_source(&case.program, &source)?;
+    let input = match &case.input {
+        Some(path) => fs::read(path).context(format!("Failed to read {}", path.display()))?,
+        None => Vec::new(),
+    };
+    Interpreter::from_program(optimize_program(program, args.opt_level, args.tape_size))
+        .with_tape_size(args.tape_size)
+        .with_eof(eof(args.eof))
+        .with_timeout(args.timeout)
+        .run_collect(&input)
+        .map_err(|error| diagnostic::with_source(error, &source, &name))
+}
+
+/// Write generated code to the file at `path`, or to stdout if there is none.
+fn write_output(path: Option<&Path>, code: &[u8]) -> Result<()> {
+    match path {
+        Some(path) => fs::write(path, code).context(format!("Failed to write {}", path.display())),
+        None => io::stdout()
+            .write_all(code)
+            .context("Failed to write to stdout"),
+    }
+}
+
+/// Return the overflow policy of the interpreter for `policy`.
+fn overflow(policy: Overflow) -> interpreter::Overflow {
+    match policy {
+        Overflow::Wrap => interpreter::Overflow::Wrap,
+        Overflow::Saturate => interpreter::Overflow::Saturate,
+        Overflow::Error => interpreter::Overflow::Error,
+    }
+}
+
+/// Return what `.` writes and `,` reads in the interpreter for `format`.
+fn io_format(format: IoFormat) -> interpreter::IoFormat {
+    match format {
+        IoFormat::Bytes => interpreter::IoFormat::Bytes,
+        IoFormat::Numeric => interpreter::IoFormat::Numeric,
+    }
+}
+
+/// Return what `,` does at the end of the input in the interpreter for `behavior`.
+fn eof(behavior: Eof) -> interpreter::Eof {
+    match behavior {
+        Eof::Zero => interpreter::Eof::Zero,
+        Eof::MinusOne => interpreter::Eof::MinusOne,
+        Eof::Unchanged => interpreter::Eof::Unchanged,
+        Eof::Error => interpreter::Eof::Error,
+    }
+}
+
+/// Return when the interpreter flushes the output for `when`.
+fn flush(when: Flush) -> interpreter::Flush {
+    match when {
+        Flush::Every => interpreter::Flush::Every,
+        Flush::Line => interpreter::Flush::Line,
+        Flush::End => interpreter::Flush::End,
+    }
+}
+
+/// Return the tape model of the interpreter for `model`.
+fn tape_model(model: TapeModel) -> interpreter::TapeModel {
+    match model {
+        TapeModel::Wrap => interpreter::TapeModel::Wrap,
+        TapeModel::Error => interpreter::TapeModel::Bounded,
+        TapeModel::Grow => interpreter::TapeModel::Grow,
+        TapeModel::Infinite => interpreter::TapeModel::Infinite,
+        TapeModel::Sparse => interpreter::TapeModel::Sparse,
+    }
+}
+
+/// Return the JIT backend to use for `backend`, or [`None`] if beef was built without it or it
+/// does not support this machine.
+#[cfg(any(feature = "jit", feature = "jit-x86-64"))]
+fn jit_backend(backend: JitBackend) -> Option<jit::Backend> {
+    let backend = match backend {
+        JitBackend::Auto => {
+            return jit::Backend::ALL
+                .iter()
+                .copied()
+                .find(|backend| backend.is_supported())
+        }
+        #[cfg(feature = "jit")]
+        JitBackend::Cranelift => jit::Backend::Cranelift,
+        #[cfg(feature = "jit-x86-64")]
+        JitBackend::X86_64 => jit::Backend::X86_64,
+        #[allow(unreachable_patterns)]
+        _ => return None,
+    };
+
+    backend.is_supported().then_some(backend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes() {
+        assert_eq!(*parse_escapes(r"a\nb\r\t\0").unwrap(), *b"a\nb\r\t\0");
+        assert_eq!(*parse_escapes(r#"\\\"\'"#).unwrap(), *b"\\\"'");
+        assert_eq!(*parse_escapes(r"\x41\xfF").unwrap(), [b'A', 255]);
+        assert_eq!(*parse_escapes("é").unwrap(), *"é".as_bytes());
+        assert_eq!(*parse_escapes("").unwrap(), []);
+
+        assert_eq!(parse_escapes(r"\q").unwrap_err(), r"`\q` isn't an escape");
+        assert_eq!(
+            parse_escapes(r"ab\").unwrap_err(),
+            "the text ends in the middle of an escape"
+        );
+        assert_eq!(
+            parse_escapes(r"\x4").unwrap_err(),
+            r"`\x4` doesn't have two hexadecimal digits"
+        );
+        assert_eq!(
+            parse_escapes(r"\xg1").unwrap_err(),
+            r"`\xg1` doesn't have two hexadecimal digits"
+        );
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(*parse_hex("00ff7F").unwrap(), [0, 255, 127]);
+        assert_eq!(
+            *parse_hex(" de ad\nbe\tef ").unwrap(),
+            [0xde, 0xad, 0xbe, 0xef]
+        );
+        assert_eq!(*parse_hex("").unwrap(), []);
+
+        assert_eq!(
+            parse_hex("abc").unwrap_err(),
+            "every byte takes two hexadecimal digits"
+        );
+        assert_eq!(
+            parse_hex("0g").unwrap_err(),
+            "`g` isn't a hexadecimal digit"
+        );
+        assert_eq!(
+            parse_hex("0x41").unwrap_err(),
+            "`x` isn't a hexadecimal digit"
+        );
+    }
+
+    #[test]
+    fn duration() {
+        assert_eq!(parse_duration("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("10ms"), Ok(Duration::from_millis(10)));
+        assert_eq!(parse_duration("250us"), Ok(Duration::from_micros(250)));
+        assert_eq!(parse_duration("3µs"), Ok(Duration::from_micros(3)));
+        assert_eq!(parse_duration("0ms"), Ok(Duration::ZERO));
+
+        assert_eq!(
+            parse_duration("").unwrap_err(),
+            "`` doesn't have a unit like `ms` or `s`"
+        );
+        assert_eq!(
+            parse_duration("10").unwrap_err(),
+            "`10` doesn't have a unit like `ms` or `s`"
+        );
+        assert_eq!(
+            parse_duration("ms").unwrap_err(),
+            "`ms` doesn't start with a number"
+        );
+        assert_eq!(
+            parse_duration("1.2.3s").unwrap_err(),
+            "`1.2.3s` doesn't start with a number"
+        );
+        assert_eq!(
+            parse_duration("5m").unwrap_err(),
+            "`m` isn't a unit like `ms` or `s`"
+        );
+        assert_eq!(
+            parse_duration("-1s").unwrap_err(),
+            "`-1s` doesn't start with a number"
+        );
+        let long = format!("{}s", "9".repeat(30));
+        assert_eq!(
+            parse_duration(&long).unwrap_err(),
+            format!("`{long}` is too long")
+        );
+    }
+
+    #[test]
+    fn tape_switch() {
+        assert_eq!(parse_tape_switch("^v"), Ok(['^', 'v']));
+        assert_eq!(parse_tape_switch("éü"), Ok(['é', 'ü']));
+
+        assert_eq!(
+            parse_tape_switch("").unwrap_err(),
+            "`` isn't two characters"
+        );
+        assert_eq!(
+            parse_tape_switch("^").unwrap_err(),
+            "`^` isn't two characters"
+        );
+        assert_eq!(
+            parse_tape_switch("^v!").unwrap_err(),
+            "`^v!` isn't two characters"
+        );
+        assert_eq!(
+            parse_tape_switch("^.").unwrap_err(),
+            "`.` is already an instruction of Brainfuck"
+        );
+        assert_eq!(
+            parse_tape_switch("^^").unwrap_err(),
+            "`^` can't switch both ways"
+        );
+    }
+
+    /// Parse the arguments of `beef run` like [`parse`] does, without reading a config file.
+    fn run_args(args: &[&str]) -> RunArgs {
+        let command = Cli::command();
+        let matches = command
+            .try_get_matches_from(["beef", "run"].iter().chain(args))
+            .unwrap();
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        apply_preset(&mut cli, &matches);
+        let Some(Command::Run(args)) = cli.command else {
+            unreachable!("the command is `run`");
+        };
+        *args
+    }
+
+    #[test]
+    fn presets() {
+        let args = run_args(&["--preset", "classic", "a.b"]);
+        assert_eq!(args.tape_model, TapeModel::Error);
+        assert!(matches!(args.eof, Eof::Unchanged));
+        assert_eq!(args.tape_size, interpreter::TAPE_SIZE);
+
+        let args = run_args(&["--preset", "golf", "a.b"]);
+        assert_eq!(args.tape_model, TapeModel::Infinite);
+        assert!(matches!(args.eof, Eof::Zero));
+
+        // Options given along with the preset win over it.
+        let args = run_args(&[
+            "--preset",
+            "modern",
+            "--tape-model",
+            "wrap",
+            "--cell-size",
+            "16",
+            "--signed",
+            "--overflow",
+            "error",
+            "--eof",
+            "unchanged",
+            "a.b",
+        ]);
+        assert_eq!(args.tape_model, TapeModel::Wrap);
+        assert_eq!(args.cell_size, CellSize::Bits16);
+        assert!(args.signed);
+        assert_eq!(args.overflow, Overflow::Error);
+        assert!(matches!(args.eof, Eof::Unchanged));
+
+        let args = run_args(&["--preset", "classic", "--tape-size", "100", "a.b"]);
+        assert_eq!(args.tape_size, 100);
+        assert_eq!(args.tape_model, TapeModel::Error);
+
+        let args = run_args(&["a.b"]);
+        assert_eq!(args.tape_model, TapeModel::Wrap);
+        assert!(matches!(args.eof, Eof::Unchanged));
+
+        // The preset applies when `run` is left out too.
+        let matches = Cli::command()
+            .try_get_matches_from(["beef", "--preset", "golf", "a.b"])
+            .unwrap();
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        apply_preset(&mut cli, &matches);
+        assert_eq!(cli.run.unwrap().tape_model, TapeModel::Infinite);
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt::{self, Display, Formatter, Write as _};
+
+/// The eight instructions of Brainfuck, in the order they're reported in.
+const INSTRUCTIONS: [char; 8] = ['+', '-', '>', '<', '.', ',', '[', ']'];
+
+/// What can be told about the source of a program without running it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    /// How many times each instruction appears, at its index in [`INSTRUCTIONS`].
+    pub counts: [usize; 8],
+
+    /// The longest run of each instruction repeated back to back, at its index in
+    /// [`INSTRUCTIONS`].
+    pub longest_runs: [usize; 8],
+
+    /// The number of loops.
+    pub loops: usize,
+
+    /// How deep the loops nest at most, which is 0 without loops.
+    pub max_depth: usize,
+
+    /// The share of the characters of the source, other than whitespace, that aren't instructions.
+    pub comment_ratio: f64,
+
+    /// The lowest and highest cells the tape pointer can reach, from where it starts, or `None` if
+    /// that depends on how many times a loop that moves the tape pointer runs.
+    pub tape_span: Option<(isize, isize)>,
+}
+
+impl Metrics {
+    /// Measure the source of a program, whose loop brackets must match, as checked by
+    /// [`crate::ir::compile`].
+    pub fn new(source: &str) -> Metrics {
+        let mut counts = [0; 8];
+        let mut longest_runs = [0; 8];
+        let mut run = (' ', 0);
+        let mut comments = 0_usize;
+        let mut max_depth = 0;
+
+        // Holds the tape pointer at the `[` of every loop that is still open.
+        let mut stack = Vec::new();
+        let mut pointer = 0_isize;
+        let mut tape_span = Some((0, 0));
+
+        for ch in source.chars() {
+            let Some(index) = INSTRUCTIONS.iter().position(|&other| other == ch) else {
+                comments += usize::from(!ch.is_whitespace());
+                continue;
+            };
+            counts[index] += 1;
+            run = if run.0 == ch {
+                (ch, run.1 + 1)
+            } else {
+                (ch, 1)
+            };
+            longest_runs[index] = longest_runs[index].max(run.1);
+
+            match ch {
+                '>' => pointer += 1,
+                '<' => pointer -= 1,
+                '[' => {
+                    stack.push(pointer);
+                    max_depth = max_depth.max(stack.len());
+                }
+                // A loop that ends elsewhere than it started moves the tape pointer further with
+                // every iteration.
+                ']' if stack.pop() != Some(pointer) => tape_span = None,
+                _ => {}
+            }
+            if let Some((lowest, highest)) = &mut tape_span {
+                *lowest = pointer.min(*lowest);
+                *highest = pointer.max(*highest);
+            }
+        }
+
+        let instructions: usize = counts.iter().sum();
+        // Sources past 2^52 characters lose some precision as floats, which a ratio doesn't show.
+        #[allow(clippy::cast_precision_loss)]
+        let comment_ratio = comments as f64 / (instructions + comments).max(1) as f64;
+        Metrics {
+            counts,
+            longest_runs,
+            loops: counts[6],
+            max_depth,
+            comment_ratio,
+            tape_span,
+        }
+    }
+
+    /// Return the metrics as a single-line JSON object, with the counts and runs by instruction.
+    pub fn to_json(&self) -> String {
+        let by_instruction = |values: &[usize; 8]| {
+            let fields: Vec<_> = INSTRUCTIONS
+                .iter()
+                .zip(values)
+                .map(|(instruction, value)| format!(r#""{instruction}":{value}"#))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        };
+        let mut json = format!(
+            r#"{{"instructions":{},"longest_runs":{},"loops":{},"max_depth":{},"comment_ratio":{},"tape_span":"#,
+            by_instruction(&self.counts),
+            by_instruction(&self.longest_runs),
+            self.loops,
+            self.max_depth,
+            self.comment_ratio,
+        );
+        match self.tape_span {
+            Some((lowest, highest)) => write!(json, "[{lowest},{highest}]}}").unwrap(),
+            None => json.push_str("null}"),
+        }
+        json
+    }
+}
+
+impl Display for Metrics {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let width = self.counts.iter().max().unwrap_or(&0).to_string().len();
+        writeln!(f, "Instructions, with their longest run:")?;
+        for (i, instruction) in INSTRUCTIONS.iter().enumerate() {
+            writeln!(
+                f,
+                "  {instruction} {:>width$} {:>width$}",
+                self.counts[i], self.longest_runs[i]
+            )?;
+        }
+        writeln!(
+            f,
+            "Loops: {}, nested at most {} deep",
+            self.loops, self.max_depth
+        )?;
+        writeln!(f, "Comments: {:.1}%", self.comment_ratio * 100.0)?;
+        match self.tape_span {
+            Some((lowest, highest)) => write!(f, "Tape: cells {lowest} to {highest}"),
+            None => write!(f, "Tape: depends on how often its loops run"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measure_programs() {
+        let metrics = Metrics::new("Add: +++[->>+<<]\n<< [[-]>]");
+        assert_eq!(metrics.counts, [4, 2, 3, 4, 0, 0, 3, 3]);
+        assert_eq!(metrics.longest_runs, [3, 1, 2, 2, 0, 0, 2, 1]);
+        assert_eq!(metrics.loops, 3);
+        assert_eq!(metrics.max_depth, 2);
+        assert!((metrics.comment_ratio - 4.0 / 23.0).abs() < 1e-9);
+        assert_eq!(metrics.tape_span, None);
+        assert_eq!(
+            metrics.to_json(),
+            r#"{"instructions":{"+":4,"-":2,">":3,"<":4,".":0,",":0,"[":3,"]":3},"longest_runs":{"+":3,"-":1,">":2,"<":2,".":0,",":0,"[":2,"]":1},"loops":3,"max_depth":2,"comment_ratio":0.17391304347826086,"tape_span":null}"#
+        );
+
+        let metrics = Metrics::new("+[->>+<<]<.");
+        assert_eq!(metrics.tape_span, Some((-1, 2)));
+        assert_eq!(
+            metrics.to_string(),
+            "Instructions, with their longest run:\n  \
+               + 2 1\n  \
+               - 1 1\n  \
+               > 2 2\n  \
+               < 3 2\n  \
+               . 1 1\n  \
+               , 0 0\n  \
+               [ 1 1\n  \
+               ] 1 1\n\
+             Loops: 1, nested at most 1 deep\n\
+             Comments: 0.0%\n\
+             Tape: cells -1 to 2"
+        );
+    }
+}
@@ -0,0 +1,80 @@
+/// Return the instructions of `source`, leaving out everything else.
+///
+/// With `shorten`, instructions that undo each other, like `+-` and `<>`, are left out too, and so
+/// are loops that can never run: those right after another loop, and those at the start of the
+/// program, where every cell is zero. Unlike [`crate::optimizer`], this doesn't rely on how wide
+/// cells are or what happens at the ends of the tape, so the program does the same on any
+/// interpreter where `+-` and `<>` do nothing.
+///
+/// The loop brackets of `source` must match, as checked by [`crate::ir::compile`].
+pub fn minify(source: &str, shorten: bool) -> String {
+    let instructions: Vec<char> = strip(source, "").chars().collect();
+    if !shorten {
+        return instructions.into_iter().collect();
+    }
+
+    let mut minified = Vec::with_capacity(instructions.len());
+    let mut i = 0;
+    while let Some(&ch) = instructions.get(i) {
+        i += 1;
+        match (minified.last(), ch) {
+            // Nothing that happened so far left a trace, so every cell is still zero. After a loop,
+            // the cell at the tape pointer is always zero.
+            (None | Some(']'), '[') => i = skip_loop(&instructions, i),
+            (Some('+'), '-') | (Some('-'), '+') | (Some('>'), '<') | (Some('<'), '>') => {
+                minified.pop();
+            }
+            _ => minified.push(ch),
+        }
+    }
+    minified.into_iter().collect()
+}
+
+/// Return the instructions of `source` and the characters of `keep`, in the order they come in,
+/// leaving out everything else.
+///
+/// `keep` holds the instructions of an extension of Brainfuck, like those of `--tape-switch`.
+pub fn strip(source: &str, keep: &str) -> String {
+    source
+        .chars()
+        .filter(|&ch| "+-<>.,[]".contains(ch) || keep.contains(ch))
+        .collect()
+}
+
+/// Return the index after the `]` of the loop whose body starts at `start` in `instructions`.
+fn skip_loop(instructions: &[char], start: usize) -> usize {
+    let mut depth = 1_usize;
+    for (i, &ch) in instructions.iter().enumerate().skip(start) {
+        match ch {
+            '[' => depth += 1,
+            ']' if depth == 1 => return i + 1,
+            ']' => depth -= 1,
+            _ => {}
+        }
+    }
+    instructions.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_comments() {
+        assert_eq!(minify("Add 2: ++\n[-] clear", false), "++[-]");
+        assert_eq!(minify("+-[comment]", false), "+-[]");
+    }
+
+    #[test]
+    fn keep_extensions() {
+        assert_eq!(strip("Next tape: } +", "{}"), "}+");
+        assert_eq!(strip("+{-}", ""), "+-");
+    }
+
+    #[test]
+    fn shorten_programs() {
+        assert_eq!(minify("[comment, with a -.]++>+-<<>.", true), "++.");
+        assert_eq!(minify(",[->+<][dead [loop]]>+-<[.]", true), ",[->+<]");
+        assert_eq!(minify("+><-[dead].-+[<]", true), ".[<]");
+    }
+}
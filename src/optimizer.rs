@@ -0,0 +1,1458 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+};
+
+use crate::{
+    interpreter,
+    ir::{self, Op, Program, Span},
+};
+
+/// What [`optimize_with_stats`] did to a program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// How many superinstructions were fused.
+    pub fusion: FusionStats,
+
+    /// The code that was removed because it can never run, in program order.
+    pub dead_code: Vec<DeadCode>,
+}
+
+/// How many superinstructions were fused by [`optimize_with_stats`], to measure what they save.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FusionStats {
+    /// The number of ops before fusing superinstructions.
+    pub ops_before: usize,
+    /// The number of ops after fusing superinstructions.
+    pub ops_after: usize,
+
+    /// The number of [`Op::Set`]s fused from a clear followed by adds.
+    pub sets: usize,
+
+    /// The number of [`Op::ClearRange`]s fused from clears of neighboring cells.
+    pub clear_ranges: usize,
+}
+
+impl Display for FusionStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Fused superinstructions, going from {} to {} ops:",
+            self.ops_before, self.ops_after
+        )?;
+        writeln!(f, "  Set: {}", self.sets)?;
+        write!(f, "  ClearRange: {}", self.clear_ranges)
+    }
+}
+
+/// A run of ops that was removed because it can never run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadCode {
+    /// The index in the optimized program the ops used to start at.
+    pub index: usize,
+
+    /// The removed ops. The targets of their jumps are meaningless.
+    pub ops: Vec<Op>,
+
+    /// The span of the source the removed ops came from.
+    pub span: Span,
+
+    /// Why the ops can never run.
+    pub reason: DeadCodeReason,
+}
+
+/// Why a run of ops can never run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeadCodeReason {
+    /// The ops form a loop that starts when the current cell is known to be zero.
+    NeverEntered,
+
+    /// The ops come after a loop that never exits.
+    AfterInfiniteLoop,
+
+    /// The ops are the jumps of a loop that is known to run exactly once.
+    RunsOnce,
+}
+
+impl Display for DeadCodeReason {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            DeadCodeReason::NeverEntered => write!(f, "the loop is never entered"),
+            DeadCodeReason::AfterInfiniteLoop => write!(f, "it follows a loop that never exits"),
+            DeadCodeReason::RunsOnce => write!(f, "the loop always runs exactly once"),
+        }
+    }
+}
+
+/// What the tape holds when a program starts, which the optimizer relies on to tell which loops
+/// are never entered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StartingTape {
+    /// Every cell starts out zero.
+    #[default]
+    Zeroed,
+
+    /// The tape may already hold data when the program starts, like one loaded from a file.
+    Preloaded,
+}
+
+/// The most ops [`fold_prefix`] executes before leaving the rest of the program for runtime.
+const FOLD_BUDGET: usize = 1_000_000;
+
+/// The highest optimization level, which runs every pass.
+pub const MAX_LEVEL: u8 = 4;
+
+/// The optimization level used unless another one is picked, which runs every pass that doesn't
+/// trade memory for speed.
+pub const DEFAULT_LEVEL: u8 = 3;
+
+/// The most cells the body of a loop may access for the loop to be memoized.
+const MAX_MEMO_WINDOW: usize = 8;
+
+/// An optimization pass.
+struct Pass {
+    /// The lowest optimization level the pass runs at.
+    level: u8,
+
+    run: fn(Program, &mut Stats, StartingTape) -> Program,
+}
+
+/// Every optimization pass, in the order they run.
+///
+/// Each level runs the passes of the levels below it, and some more:
+///
+/// - Level 0 runs no passes at all, so every instruction is executed one by one.
+/// - Level 1 folds runs of adds and moves.
+/// - Level 2 replaces clear, multiply, and scan loops, and folds moves into offsets.
+/// - Level 3 fuses superinstructions and removes dead code.
+/// - Level 4 marks pure loops for the interpreter to memoize.
+const PASSES: &[Pass] = &[
+    Pass {
+        level: 1,
+        run: |program, _, _| fold_runs(program),
+    },
+    Pass {
+        level: 2,
+        run: |program, _, _| clear_loops(program),
+    },
+    Pass {
+        level: 2,
+        run: |program, _, _| multiply_loops(program),
+    },
+    Pass {
+        level: 2,
+        run: |program, _, _| scan_loops(program),
+    },
+    Pass {
+        level: 2,
+        run: |program, _, _| fuse_offsets(program),
+    },
+    Pass {
+        level: 3,
+        run: |program, stats, _| {
+            stats.fusion.ops_before = program.len();
+            let program = fuse_superinstructions(program, &mut stats.fusion);
+            stats.fusion.ops_after = program.len();
+            program
+        },
+    },
+    Pass {
+        level: 3,
+        run: |program, stats, tape| eliminate_dead_code(&program, tape, &mut stats.dead_code),
+    },
+    Pass {
+        level: 4,
+        run: |program, _, _| memoize_loops(&program),
+    },
+];
+
+/// Optimize a compiled program at [`DEFAULT_LEVEL`].
+///
+/// The returned program has the same observable behavior as the original, but usually executes
+/// far fewer ops.
+pub fn optimize(program: Program) -> Program {
+    optimize_with_stats(program, DEFAULT_LEVEL).0
+}
+
+/// Optimize a compiled program by running the passes up to `level`, and also return what was
+/// done to it.
+///
+/// Level 0 returns the program untouched, and [`DEFAULT_LEVEL`] is the same as [`optimize`].
+/// Levels above [`MAX_LEVEL`] are treated like it.
+pub fn optimize_with_stats(program: Program, level: u8) -> (Program, Stats) {
+    optimize_on(program, level, StartingTape::Zeroed)
+}
+
+/// Optimize a compiled program like [`optimize_with_stats`], for a program that starts on `tape`.
+pub fn optimize_on(mut program: Program, level: u8, tape: StartingTape) -> (Program, Stats) {
+    let mut stats = Stats::default();
+    // Without fusing superinstructions, none are fused.
+    stats.fusion.ops_before = program.len();
+    stats.fusion.ops_after = program.len();
+
+    for pass in PASSES.iter().filter(|pass| pass.level <= level) {
+        program = (pass.run)(program, &mut stats, tape);
+    }
+
+    ir::link(&mut program.ops);
+    (program, stats)
+}
+
+/// Fold runs of adds, moves and tape switches into a single op each.
+///
+/// For example, `+++--` becomes a single add of 1 and `>><` becomes `Move(1)`. Runs that cancel
+/// out are dropped entirely.
+fn fold_runs(program: Program) -> Program {
+    let mut folded = Program::with_capacity(program.len());
+
+    for (op, span) in program {
+        match (folded.ops.last_mut(), op) {
+            (
+                Some(Op::Add {
+                    offset: 0,
+                    amount: total,
+                }),
+                Op::Add { offset: 0, amount },
+            ) => *total += amount,
+            (Some(Op::Move(total)), Op::Move(amount))
+            | (Some(Op::SwitchTape(total)), Op::SwitchTape(amount)) => *total += amount,
+            _ => {
+                folded.push(op, span);
+                continue;
+            }
+        }
+
+        let last_span = folded.spans.last_mut().expect("an op was just folded");
+        *last_span = last_span.to(span);
+
+        if matches!(
+            folded.ops.last(),
+            Some(Op::Add { amount: 0, .. } | Op::Move(0) | Op::SwitchTape(0))
+        ) {
+            folded.pop();
+        }
+    }
+
+    folded
+}
+
+/// Replace clear loops like `[-]` and `[+]` with [`Op::SetZero`].
+///
+/// Any loop that only adds an odd amount is a clear loop: counting by an odd step eventually hits
+/// every value of a cell, so it always ends on zero.
+fn clear_loops(program: Program) -> Program {
+    let mut cleared = Program::with_capacity(program.len());
+
+    for (op, span) in program {
+        cleared.push(op, span);
+
+        if let [.., Op::JumpIfZero(_), Op::Add { offset: 0, amount }, Op::JumpIfNonZero(_)] =
+            cleared.ops[..]
+        {
+            if amount % 2 != 0 {
+                let start = cleared.len() - 3;
+                let span = cleared.span_from(start);
+                cleared.truncate(start);
+                cleared.push(Op::SetZero { offset: 0 }, span);
+            }
+        }
+    }
+
+    cleared
+}
+
+/// Replace multiply loops with [`Op::MulAdd`]s followed by an [`Op::SetZero`].
+///
+/// A multiply loop only adds and moves, ends up back where it started, and changes its starting
+/// cell by exactly one on every iteration. For example, `[->++>+++<<]` adds two times the current
+/// cell to the next cell and three times the current cell to the one after that, then clears the
+/// current cell.
+///
+/// The ops replacing a loop all span the whole loop.
+fn multiply_loops(program: Program) -> Program {
+    let mut linearized = Program::with_capacity(program.len());
+
+    for (op, span) in program {
+        linearized.push(op, span);
+
+        if !matches!(op, Op::JumpIfNonZero(_)) {
+            continue;
+        }
+
+        // If this isn't the matching start of the loop, the body contains the end of an inner loop
+        // and is rejected by `linearize_loop` anyway.
+        let Some(start) = linearized
+            .ops
+            .iter()
+            .rposition(|op| matches!(op, Op::JumpIfZero(_)))
+        else {
+            continue;
+        };
+
+        let body = &linearized.ops[start + 1..linearized.len() - 1];
+        if let Some(mul_adds) = linearize_loop(body) {
+            let span = linearized.span_from(start);
+            linearized.truncate(start);
+            for mul_add in mul_adds {
+                linearized.push(mul_add, span);
+            }
+            linearized.push(Op::SetZero { offset: 0 }, span);
+        }
+    }
+
+    linearized
+}
+
+/// Return the [`Op::MulAdd`]s equivalent to a loop body, or [`None`] if it isn't the body of a
+/// multiply loop.
+fn linearize_loop(body: &[Op]) -> Option<Vec<Op>> {
+    // The total amount added to each cell in a single iteration, indexed by offset.
+    let mut deltas: Vec<(isize, i32)> = Vec::new();
+    let mut offset = 0;
+
+    for &op in body {
+        match op {
+            Op::Add {
+                offset: add_offset,
+                amount,
+            } => match deltas.iter_mut().find(|(o, _)| *o == offset + add_offset) {
+                Some((_, delta)) => *delta += amount,
+                None => deltas.push((offset + add_offset, amount)),
+            },
+            Op::Move(amount) => offset += amount,
+            _ => return None,
+        }
+    }
+
+    if offset != 0 {
+        return None;
+    }
+
+    // A loop counting down runs once per unit in the current cell, but one counting up runs once
+    // per unit it takes to wrap around to zero, so its factors have to be negated.
+    let direction = match deltas.iter().find(|(o, _)| *o == 0) {
+        Some((_, -1)) => 1,
+        Some((_, 1)) => -1,
+        _ => return None,
+    };
+
+    Some(
+        deltas
+            .into_iter()
+            .filter(|&(offset, factor)| offset != 0 && factor != 0)
+            .map(|(offset, factor)| Op::MulAdd {
+                offset,
+                factor: factor * direction,
+            })
+            .collect(),
+    )
+}
+
+/// Replace the body of scan loops like `[>]` and `[<<]` with an [`Op::Scan`].
+fn scan_loops(mut program: Program) -> Program {
+    let ops = &mut program.ops;
+    for i in 2..ops.len() {
+        if let [Op::JumpIfZero(_), Op::Move(stride), Op::JumpIfNonZero(_)] = ops[i - 2..=i] {
+            ops[i - 1] = Op::Scan(stride);
+        }
+    }
+
+    program
+}
+
+/// Fold moves into the offsets of the ops that follow them.
+///
+/// Within a run of ops that only touch single cells, the moves are accumulated and only applied
+/// once, right before the next op that depends on the position of the tape pointer. For example,
+/// `>+>-<.` becomes adds at offsets 1 and 2, an output at offset 1, and a single `Move(1)`.
+///
+/// The ops keep their spans, and the accumulated move spans all of the moves it replaces.
+fn fuse_offsets(program: Program) -> Program {
+    let mut fused = Program::with_capacity(program.len());
+    let mut pending = 0;
+    let mut pending_span: Option<Span> = None;
+
+    for (op, span) in program {
+        let op = match op {
+            Op::Move(amount) => {
+                pending += amount;
+                pending_span = Some(pending_span.map_or(span, |pending| pending.to(span)));
+                continue;
+            }
+
+            Op::Add { offset, amount } => Op::Add {
+                offset: offset + pending,
+                amount,
+            },
+            Op::SetZero { offset } => Op::SetZero {
+                offset: offset + pending,
+            },
+            Op::ClearRange { offset, len } => Op::ClearRange {
+                offset: offset + pending,
+                len,
+            },
+            Op::Set { offset, value } => Op::Set {
+                offset: offset + pending,
+                value,
+            },
+            Op::Output { offset } => Op::Output {
+                offset: offset + pending,
+            },
+            Op::Input { offset } => Op::Input {
+                offset: offset + pending,
+            },
+
+            Op::MulAdd { .. }
+            | Op::Scan(_)
+            | Op::JumpIfZero(_)
+            | Op::JumpIfNonZero(_)
+            | Op::Memoize
+            | Op::SwitchTape(_) => {
+                if let Some(pending_span) = pending_span.take().filter(|_| pending != 0) {
+                    fused.push(Op::Move(pending), pending_span);
+                }
+                pending = 0;
+                op
+            }
+        };
+        fused.push(op, span);
+    }
+
+    if let Some(pending_span) = pending_span.filter(|_| pending != 0) {
+        fused.push(Op::Move(pending), pending_span);
+    }
+
+    fused
+}
+
+/// Fuse common sequences of ops into superinstructions, so they take a single dispatch.
+///
+/// Adds right after a clear of the same cell, like `[-]+++`, become a single [`Op::Set`]. The
+/// clears that are left then merge with clears of neighboring cells right before or after them,
+/// like `[-]>[-]>[-]`, into a single [`Op::ClearRange`].
+fn fuse_superinstructions(program: Program, stats: &mut FusionStats) -> Program {
+    let mut fused = Program::with_capacity(program.len());
+
+    for (op, span) in program {
+        match (fused.ops.last_mut(), op) {
+            (Some(last @ Op::SetZero { .. }), Op::Add { offset, amount })
+                if *last == (Op::SetZero { offset }) =>
+            {
+                *last = Op::Set {
+                    offset,
+                    value: amount,
+                };
+                stats.sets += 1;
+            }
+            (
+                Some(Op::Set {
+                    offset: set_offset,
+                    value,
+                }),
+                Op::Add { offset, amount },
+            ) if *set_offset == offset => *value += amount,
+            _ => {
+                fused.push(op, span);
+                continue;
+            }
+        }
+
+        let last_span = fused.spans.last_mut().expect("an op was just fused");
+        *last_span = last_span.to(span);
+    }
+
+    fuse_clear_ranges(fused, stats)
+}
+
+/// Merge runs of clears of neighboring cells into [`Op::ClearRange`]s, for
+/// [`fuse_superinstructions`].
+fn fuse_clear_ranges(program: Program, stats: &mut FusionStats) -> Program {
+    let mut fused = Program::with_capacity(program.len());
+
+    for (op, span) in program {
+        let merged = match (fused.ops.last(), op) {
+            (Some(&Op::SetZero { offset: last }), Op::SetZero { offset })
+                if last.abs_diff(offset) == 1 =>
+            {
+                stats.clear_ranges += 1;
+                Op::ClearRange {
+                    offset: last.min(offset),
+                    len: 2,
+                }
+            }
+            (Some(&Op::ClearRange { offset: start, len }), Op::SetZero { offset })
+                if offset == start + len.cast_signed() || offset == start - 1 =>
+            {
+                Op::ClearRange {
+                    offset: start.min(offset),
+                    len: len + 1,
+                }
+            }
+            _ => {
+                fused.push(op, span);
+                continue;
+            }
+        };
+
+        *fused.ops.last_mut().expect("an op was just merged") = merged;
+        let last_span = fused.spans.last_mut().expect("an op was just merged");
+        *last_span = last_span.to(span);
+    }
+
+    fused
+}
+
+/// Evaluate the longest prefix of an optimized program that doesn't read any input or switch tapes,
+/// and replace it with ops that recreate its output and the state of the tape it leaves behind.
+///
+/// The prefix always ends between two top-level ops or loops, and stops short of any loop that
+/// would take the whole prefix past [`FOLD_BUDGET`] executed ops, so programs that take a long
+/// time to run still fold quickly. Programs that never read input, like most "Hello World!"
+/// programs, often fold down to nothing but their output.
+///
+/// The tape is assumed to have `tape_size` cells, and the returned program is linked. The ops
+/// replacing the prefix all span the whole prefix.
+pub fn fold_prefix(program: &Program, tape_size: usize) -> Program {
+    let ops = &program.ops;
+    let mut state = Evaluator {
+        tape: vec![0; tape_size],
+        tape_pointer: 0,
+        output: Vec::new(),
+        budget: FOLD_BUDGET,
+    };
+    let mut start = 0;
+
+    while start < ops.len() {
+        let end = match ops[start..] {
+            [Op::JumpIfZero(end), ..] | [Op::Memoize, Op::JumpIfZero(end), ..] => end + 1,
+            _ => start + 1,
+        };
+        if ops[start..end]
+            .iter()
+            .any(|op| matches!(op, Op::Input { .. } | Op::SwitchTape(_)))
+        {
+            break;
+        }
+
+        // A loop may run out of budget halfway through, so run it on a copy of the state.
+        if end - start == 1 {
+            state.execute(ops, start, end);
+        } else {
+            let mut next = state.clone();
+            if !next.execute(ops, start, end) {
+                break;
+            }
+            state = next;
+        }
+
+        start = end;
+    }
+
+    if start == 0 {
+        return program.clone();
+    }
+    let span = program.spans[0].to(program.spans[start - 1]);
+
+    let len = tape_size.cast_signed();
+    let shortest_offset = |index: usize| {
+        let offset = index.cast_signed();
+        if offset > len / 2 {
+            offset - len
+        } else {
+            offset
+        }
+    };
+
+    let mut folded = Program::default();
+    for &value in &state.output {
+        let value = i32::from(value);
+        folded.push(Op::Set { offset: 0, value }, span);
+        folded.push(Op::Output { offset: 0 }, span);
+    }
+    for (index, &value) in state.tape.iter().enumerate() {
+        let offset = shortest_offset(index);
+        match value {
+            0 if index == 0 && !state.output.is_empty() => {
+                folded.push(Op::SetZero { offset }, span);
+            }
+            0 => {}
+            value => {
+                let value = i32::from(value);
+                folded.push(Op::Set { offset, value }, span);
+            }
+        }
+    }
+    if state.tape_pointer != 0 {
+        folded.push(Op::Move(shortest_offset(state.tape_pointer)), span);
+    }
+
+    folded.ops.extend_from_slice(&ops[start..]);
+    folded.spans.extend_from_slice(&program.spans[start..]);
+    ir::link(&mut folded.ops);
+    folded
+}
+
+/// Executes the prefix of a program for [`fold_prefix`].
+#[derive(Clone)]
+struct Evaluator {
+    tape: Vec<u8>,
+    tape_pointer: usize,
+    output: Vec<u8>,
+
+    /// How many more ops may be executed.
+    budget: usize,
+}
+
+impl Evaluator {
+    /// Execute `ops[start..end]`, which must not contain any [`Op::Input`] or [`Op::SwitchTape`].
+    ///
+    /// Returns `false` if the budget ran out before reaching `end`.
+    fn execute(&mut self, ops: &[Op], start: usize, end: usize) -> bool {
+        let mut program_pointer = start;
+
+        while program_pointer < end {
+            let Some(budget) = self.budget.checked_sub(1) else {
+                return false;
+            };
+            self.budget = budget;
+
+            match ops[program_pointer] {
+                Op::Add { offset, amount } => {
+                    let cell = self.cell_mut(offset);
+                    *cell = cell.wrapping_add(interpreter::wrap_to_cell(amount));
+                }
+                Op::Move(amount) => self.tape_pointer = self.index(amount),
+                Op::SetZero { offset } => *self.cell_mut(offset) = 0,
+                Op::ClearRange { offset, len } => {
+                    let start = self.index(offset);
+                    interpreter::clear_range(&mut self.tape, start, len);
+                }
+                Op::Set { offset, value } => {
+                    *self.cell_mut(offset) = interpreter::wrap_to_cell(value);
+                }
+                Op::MulAdd { offset, factor } => {
+                    let product = self.tape[self.tape_pointer]
+                        .wrapping_mul(interpreter::wrap_to_cell(factor));
+                    let cell = self.cell_mut(offset);
+                    *cell = cell.wrapping_add(product);
+                }
+                Op::Scan(stride) => {
+                    self.tape_pointer = interpreter::scan(&self.tape, self.tape_pointer, stride);
+                }
+
+                Op::JumpIfZero(target) if self.tape[self.tape_pointer] == 0 => {
+                    program_pointer = target;
+                }
+                Op::JumpIfNonZero(target) if self.tape[self.tape_pointer] != 0 => {
+                    program_pointer = target;
+                }
+                Op::JumpIfZero(_) | Op::JumpIfNonZero(_) | Op::Memoize => {}
+
+                Op::Output { offset } => {
+                    let value = *self.cell_mut(offset);
+                    self.output.push(value);
+                }
+                Op::Input { .. } => unreachable!("the prefix should not read any input"),
+                Op::SwitchTape(_) => unreachable!("the prefix should not switch tapes"),
+            }
+
+            program_pointer += 1;
+        }
+
+        true
+    }
+
+    fn cell_mut(&mut self, offset: isize) -> &mut u8 {
+        let index = self.index(offset);
+        &mut self.tape[index]
+    }
+
+    fn index(&self, offset: isize) -> usize {
+        interpreter::wrapping_index(self.tape_pointer, offset, self.tape.len())
+    }
+}
+
+/// Remove code that can never run, recording what was removed in `dead_code`.
+///
+/// The values of cells are tracked through the program while they are known, which resolves some
+/// loops statically:
+///
+/// - A loop that starts on a zero cell is never entered. The tape starts out zeroed unless it is
+///   [`StartingTape::Preloaded`], and every loop ends on a zero cell, so this catches loops at the
+///   start of the program and loops right after another loop, like the second loop in `[-][>]`.
+/// - A loop that starts on a non-zero cell and whose body is known to zero it always runs exactly
+///   once, like the loop in `+[-.]`, so both of its jumps are removed.
+/// - An empty loop that starts on a non-zero cell never exits, like the loop in `+[]`, so nothing
+///   after it is ever reached.
+fn eliminate_dead_code(
+    program: &Program,
+    tape: StartingTape,
+    dead_code: &mut Vec<DeadCode>,
+) -> Program {
+    let ops = &program.ops;
+    let mut live = Program::with_capacity(ops.len());
+    let dead = |live: &Program, range: std::ops::RangeInclusive<usize>, reason| DeadCode {
+        index: live.len(),
+        ops: ops[range.clone()].to_vec(),
+        span: program.spans[*range.start()].to(program.spans[*range.end()]),
+        reason,
+    };
+
+    let mut facts = match tape {
+        StartingTape::Zeroed => Facts::zeroed(),
+        StartingTape::Preloaded => Facts::unknown(),
+    };
+    let mut depth = 0;
+    // The ends of the loops that were found to run exactly once.
+    let mut inlined_ends = Vec::new();
+    let mut i = 0;
+
+    while i < ops.len() {
+        let op = ops[i];
+
+        match op {
+            Op::JumpIfZero(_) => {
+                let end = loop_end(ops, i);
+                let entered = facts.get(0);
+
+                if entered == Fact::Value(0) {
+                    dead_code.push(dead(&live, i..=end, DeadCodeReason::NeverEntered));
+                    i = end + 1;
+                    continue;
+                }
+
+                // Inside another loop, the rest of the program still has to stay balanced.
+                if entered.is_non_zero() && end == i + 1 && depth == 0 {
+                    live.push(ops[i], program.spans[i]);
+                    live.push(ops[end], program.spans[end]);
+                    if end + 1 < ops.len() {
+                        let rest = end + 1..=ops.len() - 1;
+                        dead_code.push(dead(&live, rest, DeadCodeReason::AfterInfiniteLoop));
+                    }
+                    return live;
+                }
+
+                if entered.is_non_zero() && runs_once(&ops[i + 1..end], facts.clone()) {
+                    dead_code.push(dead(&live, i..=i, DeadCodeReason::RunsOnce));
+                    inlined_ends.push(end);
+                    i += 1;
+                    continue;
+                }
+
+                depth += 1;
+                facts = Facts::unknown();
+                facts.set(0, Fact::NonZero);
+            }
+            Op::JumpIfNonZero(_) if inlined_ends.last() == Some(&i) => {
+                inlined_ends.pop();
+                dead_code.push(dead(&live, i..=i, DeadCodeReason::RunsOnce));
+                i += 1;
+                continue;
+            }
+            Op::JumpIfNonZero(_) => {
+                depth -= 1;
+                facts = Facts::after_loop();
+            }
+
+            op => facts.apply(op),
+        }
+
+        live.push(op, program.spans[i]);
+        i += 1;
+    }
+
+    live
+}
+
+/// Return whether a loop body zeroes the current cell when it runs with the given `facts`, so
+/// that the loop around it exits after a single iteration.
+///
+/// Scans are never inlined, since they only work inside a loop.
+fn runs_once(body: &[Op], mut facts: Facts) -> bool {
+    let mut i = 0;
+
+    while i < body.len() {
+        match body[i] {
+            Op::JumpIfZero(_) => {
+                i = loop_end(body, i);
+                facts = Facts::after_loop();
+            }
+            Op::Scan(_) => return false,
+            op => facts.apply(op),
+        }
+        i += 1;
+    }
+
+    facts.get(0) == Fact::Value(0)
+}
+
+/// Mark pure loops with an [`Op::Memoize`], so that the interpreter caches their results.
+///
+/// A loop is pure if [`memo_window`] finds the cells it accesses. Clear and multiply loops are
+/// already gone by now, so this mostly catches loops that don't step their current cell by one,
+/// like `[-->+<]`. The marks span their loop.
+fn memoize_loops(program: &Program) -> Program {
+    let mut memoized = Program::with_capacity(program.len());
+
+    for (i, (op, span)) in program.iter().enumerate() {
+        if matches!(op, Op::JumpIfZero(_)) {
+            let end = loop_end(&program.ops, i);
+            if memo_window(&program.ops[i + 1..end]).is_some() {
+                memoized.push(Op::Memoize, program.spans[i].to(program.spans[end]));
+            }
+        }
+        memoized.push(op, span);
+    }
+
+    memoized
+}
+
+/// Return the offsets of the cells a loop body accesses from the tape pointer, sorted and
+/// including the current cell, or [`None`] if the loop can't be memoized.
+///
+/// That is the case unless the body is a non-empty run of ops that only change cells, without
+/// moving the tape pointer, doing any I/O, or containing other loops, and it accesses at most
+/// [`MAX_MEMO_WINDOW`] cells.
+pub fn memo_window(body: &[Op]) -> Option<Vec<isize>> {
+    if body.is_empty() {
+        return None;
+    }
+
+    let mut window = vec![0];
+    for &op in body {
+        match op {
+            Op::Add { offset, .. }
+            | Op::SetZero { offset }
+            | Op::Set { offset, .. }
+            | Op::MulAdd { offset, .. } => window.push(offset),
+            Op::ClearRange { offset, len } if len <= MAX_MEMO_WINDOW => {
+                window.extend((offset..).take(len));
+            }
+            _ => return None,
+        }
+    }
+
+    window.sort_unstable();
+    window.dedup();
+    (window.len() <= MAX_MEMO_WINDOW).then_some(window)
+}
+
+/// What is known about the value of a cell.
+///
+/// Known values are exact, rather than wrapped around to the width of a cell, so that they hold on
+/// cells of any width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fact {
+    Unknown,
+    NonZero,
+    Value(i32),
+}
+
+/// The furthest from zero a [`Fact::Value`] may be, so that it never wraps around on cells of 8
+/// bits or more.
+const MAX_FACT_VALUE: i32 = 255;
+
+impl Fact {
+    /// Return the fact that a cell holds `value`, or that nothing is known about it if the value
+    /// may have wrapped around, or is unknown.
+    fn value(value: Option<i32>) -> Fact {
+        match value {
+            Some(value) if value.abs() <= MAX_FACT_VALUE => Fact::Value(value),
+            _ => Fact::Unknown,
+        }
+    }
+
+    fn is_non_zero(self) -> bool {
+        matches!(self, Fact::NonZero) || matches!(self, Fact::Value(value) if value != 0)
+    }
+}
+
+/// How close to the tape pointer a cell has to be for [`Facts`] to keep track of it.
+///
+/// Offsets this close only point to the same cell if they are equal, on any tape of at least
+/// [`interpreter::MIN_TAPE_SIZE`] cells. Cells further away may be any other cell depending on the
+/// size of the tape, so the optimizer doesn't assume anything about them.
+const FACT_RANGE: usize = interpreter::MIN_TAPE_SIZE / 2;
+
+/// Return whether [`Facts`] keep track of the cell at `offset`.
+fn in_fact_range(offset: isize) -> bool {
+    offset.unsigned_abs() < FACT_RANGE
+}
+
+/// What is known about the cells within [`FACT_RANGE`] of the tape pointer at some point in a
+/// program, by their offset from it.
+#[derive(Debug, Clone)]
+struct Facts {
+    cells: HashMap<isize, Fact>,
+
+    /// What is known about the cells missing from `cells`: either they are all zero, like at the
+    /// start of the program, or nothing is known about them.
+    rest: Fact,
+}
+
+impl Facts {
+    /// Return the facts at the start of the program, where every cell is zero.
+    fn zeroed() -> Facts {
+        Facts {
+            cells: HashMap::new(),
+            rest: Fact::Value(0),
+        }
+    }
+
+    /// Return facts that don't know anything.
+    fn unknown() -> Facts {
+        Facts {
+            cells: HashMap::new(),
+            rest: Fact::Unknown,
+        }
+    }
+
+    /// Return the facts right after a loop, which always ends on a zero cell.
+    fn after_loop() -> Facts {
+        let mut facts = Facts::unknown();
+        facts.set(0, Fact::Value(0));
+        facts
+    }
+
+    fn get(&self, offset: isize) -> Fact {
+        if !in_fact_range(offset) {
+            return Fact::Unknown;
+        }
+        self.cells.get(&offset).copied().unwrap_or(self.rest)
+    }
+
+    /// Set the fact about the cell at `offset`. Out of [`FACT_RANGE`], the cell may be any of the
+    /// others, so everything is forgotten instead.
+    fn set(&mut self, offset: isize, fact: Fact) {
+        if !in_fact_range(offset) {
+            *self = Facts::unknown();
+        } else if fact == self.rest {
+            self.cells.remove(&offset);
+        } else {
+            self.cells.insert(offset, fact);
+        }
+    }
+
+    /// Update the facts to after `op`, which must not be a jump.
+    fn apply(&mut self, op: Op) {
+        match op {
+            Op::Add { offset, amount } => {
+                let fact = match self.get(offset) {
+                    Fact::Value(value) => Fact::value(value.checked_add(amount)),
+                    fact => fact,
+                };
+                // Adding anything to a non-zero cell may zero it.
+                let fact = if fact == Fact::NonZero && amount != 0 {
+                    Fact::Unknown
+                } else {
+                    fact
+                };
+                self.set(offset, fact);
+            }
+            Op::Move(amount) => {
+                let cells = self
+                    .cells
+                    .drain()
+                    .map(|(offset, fact)| (offset - amount, fact))
+                    .collect::<HashMap<_, _>>();
+                if cells.keys().all(|&offset| in_fact_range(offset)) {
+                    self.cells = cells;
+                } else {
+                    *self = Facts::unknown();
+                }
+            }
+            Op::SetZero { offset } => self.set(offset, Fact::Value(0)),
+            Op::ClearRange { offset, len } => {
+                // A longer range always reaches out of range, which forgets everything anyway.
+                for offset in (offset..).take(len.min(2 * FACT_RANGE)) {
+                    self.set(offset, Fact::Value(0));
+                }
+            }
+            Op::Set { offset, value } => self.set(offset, Fact::value(Some(value))),
+            Op::MulAdd { offset, factor } => {
+                let fact = match (self.get(0), self.get(offset)) {
+                    (Fact::Value(current), Fact::Value(value)) => Fact::value(
+                        current
+                            .checked_mul(factor)
+                            .and_then(|product| value.checked_add(product)),
+                    ),
+                    (Fact::Value(0), fact) => fact,
+                    _ => Fact::Unknown,
+                };
+                self.set(offset, fact);
+            }
+            Op::Scan(_) => *self = Facts::after_loop(),
+            Op::Input { offset } => self.set(offset, Fact::Unknown),
+            Op::Output { .. } | Op::Memoize => {}
+            Op::SwitchTape(_) => *self = Facts::unknown(),
+            Op::JumpIfZero(_) | Op::JumpIfNonZero(_) => {
+                unreachable!("jumps are handled by callers")
+            }
+        }
+    }
+}
+
+/// Return the index of the end of the loop starting at `start`.
+///
+/// Unlike the targets of the jumps, this is still accurate in the middle of the optimizer.
+fn loop_end(ops: &[Op], start: usize) -> usize {
+    let mut depth = 0;
+
+    for (i, op) in ops.iter().enumerate().skip(start) {
+        match op {
+            Op::JumpIfZero(_) => depth += 1,
+            Op::JumpIfNonZero(_) => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    unreachable!("jumps should be balanced")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn optimize_str(program: &str) -> Vec<Op> {
+        optimize(ir::compile(program).unwrap()).ops
+    }
+
+    fn add(offset: isize, amount: i32) -> Op {
+        Op::Add { offset, amount }
+    }
+
+    fn mul_add(offset: isize, factor: i32) -> Op {
+        Op::MulAdd { offset, factor }
+    }
+
+    const SET_ZERO: Op = Op::SetZero { offset: 0 };
+
+    #[test]
+    fn fold_adds_and_moves() {
+        assert_eq!(optimize_str("+++-->>><"), [add(0, 1), Op::Move(2)]);
+    }
+
+    #[test]
+    fn drop_cancelled_runs() {
+        assert_eq!(optimize_str("+-<>."), [Op::Output { offset: 0 }]);
+    }
+
+    #[test]
+    fn fold_across_dropped_runs() {
+        assert_eq!(optimize_str("+><+"), [add(0, 2)]);
+    }
+
+    #[test]
+    fn clear_loops() {
+        assert_eq!(
+            optimize_str("+[-]>>[+]"),
+            [add(0, 1), SET_ZERO, Op::SetZero { offset: 2 }, Op::Move(2)]
+        );
+        assert_eq!(optimize_str("[---]"), [SET_ZERO]);
+    }
+
+    #[test]
+    fn keep_loops_that_may_not_clear() {
+        assert_eq!(
+            optimize_str(",[--]"),
+            [
+                Op::Input { offset: 0 },
+                Op::JumpIfZero(3),
+                add(0, -2),
+                Op::JumpIfNonZero(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn multiply_loops() {
+        assert_eq!(
+            optimize_str("[->++>---<<]"),
+            [mul_add(1, 2), mul_add(2, -3), SET_ZERO]
+        );
+    }
+
+    #[test]
+    fn multiply_loops_counting_up() {
+        assert_eq!(optimize_str("[<+>+]"), [mul_add(-1, -1), SET_ZERO]);
+    }
+
+    #[test]
+    fn keep_loops_that_do_not_multiply() {
+        for program in ["[->+]", "[-->+<]", "[->.<]", "[->[-]<]"] {
+            let ops = optimize_str(&format!(",{program}"));
+            assert!(
+                ops.iter().any(|op| matches!(op, Op::JumpIfZero(_))),
+                "{program}"
+            );
+        }
+    }
+
+    #[test]
+    fn scan_loops() {
+        assert_eq!(
+            optimize_str(",[>],[<<]"),
+            [
+                Op::Input { offset: 0 },
+                Op::JumpIfZero(3),
+                Op::Scan(1),
+                Op::JumpIfNonZero(1),
+                Op::Input { offset: 0 },
+                Op::JumpIfZero(7),
+                Op::Scan(-2),
+                Op::JumpIfNonZero(5)
+            ]
+        );
+    }
+
+    #[test]
+    fn fuse_offsets() {
+        assert_eq!(
+            optimize_str(">+>-<.,<<"),
+            [
+                add(1, 1),
+                add(2, -1),
+                Op::Output { offset: 1 },
+                Op::Input { offset: 1 },
+                Op::Move(-1)
+            ]
+        );
+    }
+
+    #[test]
+    fn move_before_ops_that_need_the_pointer() {
+        assert_eq!(
+            optimize_str(">+[>+<-]>[>]"),
+            [
+                add(1, 1),
+                Op::Move(1),
+                mul_add(1, 1),
+                SET_ZERO,
+                Op::Move(1),
+                Op::JumpIfZero(7),
+                Op::Scan(1),
+                Op::JumpIfNonZero(5)
+            ]
+        );
+    }
+
+    #[test]
+    fn fuse_sets() {
+        assert_eq!(
+            optimize_str("[-]+++>[-]<+>-"),
+            [
+                Op::Set {
+                    offset: 0,
+                    value: 3
+                },
+                Op::SetZero { offset: 1 },
+                add(0, 1),
+                add(1, -1),
+                Op::Move(1)
+            ]
+        );
+        assert_eq!(
+            optimize_str("[-]++.+"),
+            [
+                Op::Set {
+                    offset: 0,
+                    value: 2
+                },
+                Op::Output { offset: 0 },
+                add(0, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn fusion_stats() {
+        let (_, stats) = optimize_with_stats(ir::compile("[-]+>[-]->[-].").unwrap(), MAX_LEVEL);
+        assert_eq!(
+            stats.fusion,
+            FusionStats {
+                ops_before: 7,
+                ops_after: 5,
+                sets: 2,
+                clear_ranges: 0,
+            }
+        );
+
+        let (_, stats) = optimize_with_stats(ir::compile("[-]>[-]>[-]>>[-]").unwrap(), MAX_LEVEL);
+        assert_eq!(stats.fusion.clear_ranges, 1);
+        assert_eq!(stats.fusion.ops_after, 3);
+    }
+
+    #[test]
+    fn fuse_clear_ranges() {
+        let range = |offset, len| Op::ClearRange { offset, len };
+        assert_eq!(optimize_str(">[-]>[-]>[-]"), [range(1, 3), Op::Move(3)]);
+        assert_eq!(optimize_str("<[-]<[-]"), [range(-2, 2), Op::Move(-2)]);
+        assert_eq!(
+            optimize_str("[-]>[-]+>[-]"),
+            [SET_ZERO, set(1, 1), Op::SetZero { offset: 2 }, Op::Move(2)]
+        );
+        assert_eq!(
+            optimize_str("[-]>>[-]<<[-]"),
+            [SET_ZERO, Op::SetZero { offset: 2 }, SET_ZERO]
+        );
+    }
+
+    #[test]
+    fn drop_loops_that_are_never_entered() {
+        let (program, stats) =
+            optimize_with_stats(ir::compile("[.]+[,.][>]>[<]").unwrap(), MAX_LEVEL);
+        assert_eq!(
+            program.ops,
+            [
+                add(0, 1),
+                Op::JumpIfZero(4),
+                Op::Input { offset: 0 },
+                Op::Output { offset: 0 },
+                Op::JumpIfNonZero(1),
+                Op::Move(1),
+                Op::JumpIfZero(8),
+                Op::Scan(-1),
+                Op::JumpIfNonZero(6)
+            ]
+        );
+        assert_eq!(stats.dead_code.len(), 2);
+        assert_eq!(stats.dead_code[0].index, 0);
+        assert_eq!(stats.dead_code[1].index, 5);
+        assert_eq!(stats.dead_code[1].ops.len(), 3);
+        assert!(stats
+            .dead_code
+            .iter()
+            .all(|dead| dead.reason == DeadCodeReason::NeverEntered));
+
+        // On a preloaded tape, only loops right after another loop are known to start on zero.
+        let (program, stats) = optimize_on(
+            ir::compile("[.]+[,.][>]").unwrap(),
+            MAX_LEVEL,
+            StartingTape::Preloaded,
+        );
+        assert_eq!(
+            program.ops[..3],
+            [
+                Op::JumpIfZero(2),
+                Op::Output { offset: 0 },
+                Op::JumpIfNonZero(0)
+            ]
+        );
+        assert_eq!(stats.dead_code.len(), 1);
+        assert_eq!(stats.dead_code[0].index, 8);
+    }
+
+    #[test]
+    fn drop_code_after_infinite_loops() {
+        let (program, stats) = optimize_with_stats(ir::compile("++[]+.").unwrap(), MAX_LEVEL);
+        assert_eq!(
+            program.ops,
+            [add(0, 2), Op::JumpIfZero(2), Op::JumpIfNonZero(1)]
+        );
+        assert_eq!(
+            stats.dead_code,
+            [DeadCode {
+                index: 3,
+                ops: vec![add(0, 1), Op::Output { offset: 0 }],
+                span: Span { start: 4, end: 6 },
+                reason: DeadCodeReason::AfterInfiniteLoop,
+            }]
+        );
+
+        // Inside another loop, the infinite loop may never be reached.
+        assert_eq!(optimize_str(",[+[]]+").len(), 7);
+    }
+
+    #[test]
+    fn drop_loops_on_cells_known_to_be_zero() {
+        assert_eq!(
+            optimize_str(",>[.]<[-]>>[.]"),
+            [
+                Op::Input { offset: 0 },
+                Op::Move(1),
+                Op::SetZero { offset: -1 },
+                Op::Move(1)
+            ]
+        );
+
+        // Offsets a whole tape apart point to the same cell.
+        let (program, stats) = optimize_with_stats(
+            ir::compile(&format!("+{}[-]", ">".repeat(interpreter::TAPE_SIZE))).unwrap(),
+            MAX_LEVEL,
+        );
+        assert_eq!(
+            program.ops,
+            [add(0, 1), Op::SetZero { offset: 30_000 }, Op::Move(30_000)]
+        );
+        assert!(stats.dead_code.is_empty());
+
+        // So do offsets a smaller tape apart, as long as it's big enough for the interpreter.
+        let program = format!("+{}[.]", ">".repeat(interpreter::MIN_TAPE_SIZE));
+        let (_, stats) = optimize_with_stats(ir::compile(&program).unwrap(), MAX_LEVEL);
+        assert!(stats.dead_code.is_empty());
+
+        // A cell may be wider than a byte, so adding 256 doesn't zero it.
+        let program = format!("{}[-.]>-[.]", "+".repeat(256));
+        let (_, stats) = optimize_with_stats(ir::compile(&program).unwrap(), MAX_LEVEL);
+        assert!(stats.dead_code.is_empty());
+    }
+
+    #[test]
+    fn inline_loops_that_run_once() {
+        let (program, stats) = optimize_with_stats(ir::compile("++[>+<[-]].").unwrap(), MAX_LEVEL);
+        assert_eq!(
+            program.ops,
+            [add(0, 2), add(1, 1), SET_ZERO, Op::Output { offset: 0 }]
+        );
+        // The targets of the dropped jumps are meaningless, so only check where they were.
+        let dropped = stats.dead_code.iter().map(|dead| (dead.index, dead.reason));
+        assert_eq!(
+            dropped.collect::<Vec<_>>(),
+            [(1, DeadCodeReason::RunsOnce), (3, DeadCodeReason::RunsOnce)]
+        );
+
+        // The loop has to start on a non-zero cell, and scans can't leave their loop.
+        assert_eq!(optimize_str(",[>+<[-]]").len(), 5);
+        assert_eq!(optimize_str("+[>]").len(), 4);
+    }
+
+    fn fold_prefix_str(program: &str) -> Vec<Op> {
+        fold_prefix(&optimize(ir::compile(program).unwrap()), 8).ops
+    }
+
+    fn set(offset: isize, value: i32) -> Op {
+        Op::Set { offset, value }
+    }
+
+    #[test]
+    fn fold_prefixes() {
+        assert_eq!(
+            fold_prefix_str("++[->+++<]>.<<-"),
+            [
+                set(0, 6),
+                Op::Output { offset: 0 },
+                Op::SetZero { offset: 0 },
+                set(1, 6),
+                set(-1, 255),
+                Op::Move(-1)
+            ]
+        );
+    }
+
+    #[test]
+    fn fold_prefixes_up_to_input() {
+        assert_eq!(
+            fold_prefix_str("+>++,[.,]"),
+            [
+                set(0, 1),
+                set(1, 2),
+                Op::Input { offset: 1 },
+                Op::Move(1),
+                Op::JumpIfZero(7),
+                Op::Output { offset: 0 },
+                Op::Input { offset: 0 },
+                Op::JumpIfNonZero(4)
+            ]
+        );
+        assert_eq!(fold_prefix_str(",+."), optimize_str(",+."));
+    }
+
+    #[test]
+    fn fold_prefixes_within_budget() {
+        assert_eq!(
+            fold_prefix_str("+++[]+"),
+            [set(0, 3), Op::JumpIfZero(2), Op::JumpIfNonZero(1)]
+        );
+    }
+
+    #[test]
+    fn optimization_levels() {
+        let program = "+++[-]>[->+<]";
+        let at_level = |level| {
+            optimize_with_stats(ir::compile(program).unwrap(), level)
+                .0
+                .ops
+        };
+
+        assert_eq!(at_level(0), ir::compile(program).unwrap().ops);
+        assert_eq!(at_level(1).len(), 11);
+        assert_eq!(
+            at_level(2),
+            [add(0, 3), SET_ZERO, Op::Move(1), mul_add(1, 1), SET_ZERO]
+        );
+        assert_eq!(at_level(3), optimize_str(program));
+        assert_eq!(at_level(MAX_LEVEL + 1), optimize_str(program));
+    }
+
+    #[test]
+    fn memoize_pure_loops() {
+        let at_max_level = |program| {
+            optimize_with_stats(ir::compile(program).unwrap(), MAX_LEVEL)
+                .0
+                .ops
+        };
+
+        assert_eq!(
+            at_max_level(",[-->+<]"),
+            [
+                Op::Input { offset: 0 },
+                Op::Memoize,
+                Op::JumpIfZero(5),
+                add(0, -2),
+                add(1, 1),
+                Op::JumpIfNonZero(2),
+            ]
+        );
+        assert_eq!(
+            memo_window(&[add(2, 1), add(-1, 1), add(2, 1)]),
+            Some(vec![-1, 0, 2])
+        );
+
+        for program in [
+            ",[-->+<.]",
+            ",[-->+]",
+            ",[>]",
+            ",[--[>]]",
+            ",[]",
+            ",[-->+>+>+>+>+>+>+>+<<<<<<<<]",
+        ] {
+            assert!(!at_max_level(program).contains(&Op::Memoize), "{program}");
+        }
+    }
+
+    #[test]
+    fn track_spans() {
+        let program = optimize(ir::compile(",++ [-]+ >>.\n[->+<]").unwrap());
+        let span = |start, end| Span { start, end };
+        assert_eq!(
+            program.iter().collect::<Vec<_>>(),
+            [
+                (Op::Input { offset: 0 }, span(0, 1)),
+                (add(0, 2), span(1, 3)),
+                (set(0, 1), span(4, 8)),
+                (Op::Output { offset: 2 }, span(11, 12)),
+                (Op::Move(2), span(9, 11)),
+                (mul_add(1, 1), span(13, 19)),
+                (SET_ZERO, span(13, 19))
+            ]
+        );
+    }
+
+    #[test]
+    fn relink_loops() {
+        assert_eq!(
+            optimize_str("++[>>-<<]"),
+            [
+                add(0, 2),
+                Op::JumpIfZero(3),
+                add(2, -1),
+                Op::JumpIfNonZero(1)
+            ]
+        );
+    }
+}
@@ -0,0 +1,395 @@
+use std::{
+    io::{self, Write},
+    slice, thread,
+    time::{Duration, Instant},
+};
+
+/// An output that writes everything to `output` and to `copy` alike, like `tee`.
+#[derive(Debug)]
+pub struct Tee<W, C> {
+    output: W,
+    copy: C,
+}
+
+impl<W: Write, C: Write> Tee<W, C> {
+    /// Return an output writing to both `output` and `copy`.
+    pub fn new(output: W, copy: C) -> Tee<W, C> {
+        Tee { output, copy }
+    }
+}
+
+impl<W: Write, C: Write> Write for Tee<W, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // The copy gets exactly the bytes that made it to the output.
+        let len = self.output.write(buf)?;
+        self.copy.write_all(&buf[..len])?;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()?;
+        self.copy.flush()
+    }
+}
+
+/// An output that starts every line with the time since it was created, in seconds.
+#[derive(Debug)]
+pub struct Timestamped<W> {
+    inner: W,
+    start: Instant,
+
+    /// Whether the next byte starts a line.
+    line_start: bool,
+}
+
+impl<W: Write> Timestamped<W> {
+    /// Return an output writing timestamped lines to `inner`, timed from now.
+    pub fn new(inner: W) -> Timestamped<W> {
+        Timestamped {
+            inner,
+            start: Instant::now(),
+            line_start: true,
+        }
+    }
+}
+
+impl<W: Write> Write for Timestamped<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for line in buf.split_inclusive(|&byte| byte == b'\n') {
+            if self.line_start {
+                write!(
+                    self.inner,
+                    "[{:>10.3}s] ",
+                    self.start.elapsed().as_secs_f64()
+                )?;
+            }
+            self.inner.write_all(line)?;
+            self.line_start = line.ends_with(b"\n");
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// An output that waits before writing every byte, then flushes it on its own, so that the output
+/// shows up one byte at a time.
+#[derive(Debug)]
+pub struct Throttled<W> {
+    inner: W,
+    delay: Duration,
+}
+
+impl<W: Write> Throttled<W> {
+    /// Return an output writing to `inner`, waiting for `delay` before every byte.
+    pub fn new(inner: W, delay: Duration) -> Throttled<W> {
+        Throttled { inner, delay }
+    }
+}
+
+impl<W: Write> Write for Throttled<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for byte in buf {
+            thread::sleep(self.delay);
+            self.inner.write_all(slice::from_ref(byte))?;
+            self.inner.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// An output that writes the bytes that aren't printable ASCII as escapes like `\x0A`, and `\` as
+/// `\\`, so that control characters and binary data can be seen for what they are.
+///
+/// Newlines are followed by a line break as well, to keep lines apart.
+#[derive(Debug)]
+pub struct Escaped<W> {
+    inner: W,
+}
+
+impl<W: Write> Escaped<W> {
+    /// Return an output writing the escaped bytes to `inner`.
+    pub fn new(inner: W) -> Escaped<W> {
+        Escaped { inner }
+    }
+}
+
+impl<W: Write> Write for Escaped<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            match byte {
+                b'\\' => self.inner.write_all(b"\\\\")?,
+                b' '..=b'~' => self.inner.write_all(&[byte])?,
+                b'\n' => self.inner.write_all(b"\\x0A\n")?,
+                _ => write!(self.inner, "\\x{byte:02X}")?,
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// An output that leaves out terminal escape sequences and control characters other than tabs and
+/// line breaks, so that a program can't move the cursor, change colours or the window title, or
+/// send anything back to the terminal.
+#[derive(Debug)]
+pub struct Sanitized<W> {
+    inner: W,
+    state: Sequence,
+}
+
+/// Where a [`Sanitized`] output is in the sequence it's leaving out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sequence {
+    /// Not in a sequence.
+    None,
+
+    /// After an `ESC`.
+    Escape,
+
+    /// In a control sequence like `ESC [ 2 J`, which ends at a byte from `@` to `~`.
+    Control,
+
+    /// In a string like the window title, which ends at a `BEL` or `ESC \`.
+    String,
+
+    /// After an `ESC` in a string.
+    StringEscape,
+
+    /// After the first byte of a UTF-8 character, which may turn out to be a C1 control character.
+    Utf8Lead,
+}
+
+impl<W: Write> Sanitized<W> {
+    /// Return an output writing the sanitized bytes to `inner`.
+    pub fn new(inner: W) -> Sanitized<W> {
+        Sanitized {
+            inner,
+            state: Sequence::None,
+        }
+    }
+}
+
+impl<W: Write> Write for Sanitized<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        const ESC: u8 = 0x1b;
+        const BEL: u8 = 0x07;
+
+        let mut start = 0;
+        for (i, &byte) in buf.iter().enumerate() {
+            if self.state == Sequence::Utf8Lead && !(0x80..=0x9f).contains(&byte) {
+                // It's a character like `é` after all, which is written as it came.
+                self.inner.write_all(b"\xc2")?;
+                self.state = Sequence::None;
+            }
+            // The bytes are copied over a run at a time, up to the first one that's left out.
+            let keep = match (self.state, byte) {
+                (Sequence::None, b'\t' | b'\n' | b'\r' | b' '..=b'~' | 0x80..=0xc1 | 0xc3..) => {
+                    true
+                }
+                (Sequence::None | Sequence::String | Sequence::StringEscape, ESC) => {
+                    self.state = if self.state == Sequence::None {
+                        Sequence::Escape
+                    } else {
+                        Sequence::StringEscape
+                    };
+                    false
+                }
+                (Sequence::None, 0xc2) => {
+                    self.state = Sequence::Utf8Lead;
+                    false
+                }
+                (Sequence::Control, b'@'..=b'~') | (Sequence::String, BEL) => {
+                    self.state = Sequence::None;
+                    false
+                }
+                (Sequence::None | Sequence::Control | Sequence::String, _) => false,
+                (Sequence::Escape, b'[') => {
+                    self.state = Sequence::Control;
+                    false
+                }
+                (Sequence::Escape, b']' | b'P' | b'X' | b'^' | b'_') => {
+                    self.state = Sequence::String;
+                    false
+                }
+                // Other sequences, like `ESC c`, are only two bytes long.
+                (Sequence::Escape, _) => {
+                    self.state = Sequence::None;
+                    false
+                }
+                (Sequence::StringEscape, _) => {
+                    self.state = if byte == b'\\' {
+                        Sequence::None
+                    } else {
+                        Sequence::String
+                    };
+                    false
+                }
+                (Sequence::Utf8Lead, _) => {
+                    self.state = match byte {
+                        0x9b => Sequence::Control,
+                        0x90 | 0x98 | 0x9d..=0x9f => Sequence::String,
+                        _ => Sequence::None,
+                    };
+                    false
+                }
+            };
+            if !keep {
+                self.inner.write_all(&buf[start..i])?;
+                start = i + 1;
+            }
+        }
+        self.inner.write_all(&buf[start..])?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// An output that writes a hex dump of the bytes, 16 to a line after the offset of the first
+/// one.
+#[derive(Debug)]
+pub struct HexDump<W: Write> {
+    inner: W,
+
+    /// How many bytes were written so far.
+    offset: usize,
+}
+
+impl<W: Write> HexDump<W> {
+    /// Return an output writing a hex dump to `inner`.
+    pub fn new(inner: W) -> HexDump<W> {
+        HexDump { inner, offset: 0 }
+    }
+}
+
+impl<W: Write> Write for HexDump<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            // A line is only ended once there's another one to start, as the output may end here.
+            if self.offset.is_multiple_of(16) {
+                let separator = if self.offset == 0 { "" } else { "\n" };
+                write!(self.inner, "{separator}{:08X}:", self.offset)?;
+            }
+            write!(self.inner, " {byte:02X}")?;
+            self.offset += 1;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for HexDump<W> {
+    fn drop(&mut self) {
+        if self.offset > 0 {
+            // The output was flushed and checked before, so this only ends the last line.
+            let _ = self
+                .inner
+                .write_all(b"\n")
+                .and_then(|()| self.inner.flush());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tee_with_timestamps() {
+        let mut output = Tee::new(Vec::new(), Timestamped::new(Vec::new()));
+        output.write_all(b"Hi\nthe").unwrap();
+        output.write_all(b"re\n\n").unwrap();
+        assert_eq!(output.output, b"Hi\nthere\n\n");
+
+        let transcript = String::from_utf8(output.copy.inner).unwrap();
+        let lines: Vec<_> = transcript
+            .lines()
+            .map(|line| line.split_once("s] ").unwrap().1)
+            .collect();
+        assert_eq!(lines, ["Hi", "there", ""]);
+        assert!(transcript.starts_with("[     0.000s] Hi\n"));
+    }
+
+    #[test]
+    fn escape_bytes() {
+        let mut output = Escaped::new(Vec::new());
+        output.write_all(b"a\\b\n\x00\xff~").unwrap();
+        assert_eq!(output.inner, b"a\\\\b\\x0A\n\\x00\\xFF~");
+    }
+
+    #[test]
+    fn sanitize_escape_sequences() {
+        let mut output = Sanitized::new(Vec::new());
+        output
+            .write_all(b"\x1b[2J\x1b[1;31mred\x1b[0m\r\n")
+            .unwrap();
+        output.write_all(b"\x1b]0;title\x07\x1b]0;ti").unwrap();
+        output
+            .write_all(b"tle\x1b\\\x1bcbell\x07\x7f\ttab\n")
+            .unwrap();
+        // `é` is kept, but the CSI encoded as UTF-8 and the sequence after it aren't.
+        output.write_all(b"caf\xc3\xa9 \xc2").unwrap();
+        output.write_all(b"\xa0\xc2\x9b31m\xc2\x1b[Aok").unwrap();
+        assert_eq!(
+            output.inner,
+            b"red\r\nbell\ttab\ncaf\xc3\xa9 \xc2\xa0\xc2ok"
+        );
+    }
+
+    #[test]
+    fn hex_dump() {
+        let mut dump = Vec::new();
+        let mut output = HexDump::new(&mut dump);
+        output.write_all(b"Hello, World! Hi").unwrap();
+        output.write_all(b"\n").unwrap();
+        drop(output);
+        assert_eq!(
+            String::from_utf8(dump).unwrap(),
+            "00000000: 48 65 6C 6C 6F 2C 20 57 6F 72 6C 64 21 20 48 69\n\
+             00000010: 0A\n"
+        );
+    }
+
+    /// An output that counts how many times it was flushed.
+    #[derive(Default)]
+    struct Flushes {
+        bytes: Vec<u8>,
+        flushes: usize,
+    }
+
+    impl Write for Flushes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.bytes.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn throttle_bytes() {
+        let mut output = Throttled::new(Flushes::default(), Duration::from_millis(5));
+        let start = Instant::now();
+        output.write_all(b"abc").unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(15));
+        assert_eq!(output.inner.bytes, b"abc");
+        assert_eq!(output.inner.flushes, 3);
+    }
+}
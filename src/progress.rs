@@ -0,0 +1,226 @@
+use std::{
+    io::{self, Read, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// How long a program runs before its progress is shown, so that quick runs don't flicker.
+const DELAY: Duration = Duration::from_secs(1);
+
+/// How often the progress is shown again.
+const INTERVAL: Duration = Duration::from_millis(100);
+
+/// The frames of the spinner, one for every time the progress is shown.
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Shows on stderr how long a program has been running, and how many ops it executed if they're
+/// counted, from another thread, until it's dropped.
+///
+/// The progress is drawn after the cursor, which stays where it is, so that it's erased by
+/// clearing the rest of the line. The output and input returned by [`Progress::output`] and
+/// [`Progress::input`] do that whenever the program writes its output or waits for its input.
+#[derive(Debug)]
+pub struct Progress {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<State>,
+
+    /// Wakes the thread up once the program is done.
+    done: Condvar,
+
+    /// How many ops the program executed so far, as published by
+    /// [`crate::interpreter::Interpreter::with_progress`].
+    steps: Arc<AtomicU64>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Whether the progress is shown after the cursor.
+    shown: bool,
+
+    /// Whether the program is waiting for its input, when the progress stays hidden, so that it
+    /// doesn't get in the way of typing it.
+    reading: bool,
+
+    done: bool,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl State {
+    /// Erase the progress, if it's shown.
+    fn hide(&mut self) {
+        if self.shown {
+            eprint!("\x1b[K");
+            self.shown = false;
+        }
+    }
+}
+
+impl Progress {
+    /// Start showing the progress of a program that starts running now.
+    pub fn start() -> Progress {
+        let shared = Arc::new(Shared::default());
+        let thread = thread::spawn({
+            let shared = Arc::clone(&shared);
+            move || show(&shared, Instant::now())
+        });
+        Progress {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Return where the interpreter publishes how many ops the program executed so far.
+    pub fn steps(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.shared.steps)
+    }
+
+    /// Return an output writing to `inner`, which erases the progress first.
+    pub fn output<W: Write>(&self, inner: W) -> ProgressOutput<W> {
+        ProgressOutput {
+            inner,
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    /// Return an input reading from `inner`, which erases the progress and keeps it hidden while
+    /// waiting.
+    pub fn input<R: Read>(&self, inner: R) -> ProgressInput<R> {
+        ProgressInput {
+            inner,
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.shared.lock().done = true;
+        self.shared.done.notify_one();
+        if let Some(thread) = self.thread.take() {
+            // The thread doesn't panic, and if it did, there's nothing left to stop.
+            let _ = thread.join();
+        }
+        self.shared.lock().hide();
+    }
+}
+
+/// Show the progress of a program that started running at `start` every so often, until it's
+/// done.
+fn show(shared: &Shared, start: Instant) {
+    let mut state = shared.lock();
+    let mut next = start + DELAY;
+    for frame in SPINNER.iter().cycle() {
+        loop {
+            if state.done {
+                return;
+            }
+            let now = Instant::now();
+            if now >= next {
+                break;
+            }
+            state = shared
+                .done
+                .wait_timeout(state, next - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        if !state.reading {
+            let steps = shared.steps.load(Ordering::Relaxed);
+            // The cursor is saved before and restored after, so that it stays where the output of
+            // the program goes on.
+            eprint!("\x1b[K\x1b7{}\x1b8", status(*frame, start.elapsed(), steps));
+            state.shown = true;
+        }
+        next += INTERVAL;
+    }
+}
+
+/// Describe a program that has been running for `elapsed` and executed `steps` ops, or an unknown
+/// number of them if it's 0, behind `frame` of the spinner.
+fn status(frame: char, elapsed: Duration, steps: u64) -> String {
+    if steps == 0 {
+        format!("{frame} Running for {:.1}s", elapsed.as_secs_f64())
+    } else {
+        format!(
+            "{frame} Running for {:.1}s, executed {steps} ops",
+            elapsed.as_secs_f64()
+        )
+    }
+}
+
+/// An output that erases the progress before writing to `inner`, so that the output of the
+/// program takes its place.
+#[derive(Debug)]
+pub struct ProgressOutput<W> {
+    inner: W,
+    shared: Arc<Shared>,
+}
+
+impl<W: Write> Write for ProgressOutput<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // The progress isn't shown again until the output is written.
+        let mut state = self.shared.lock();
+        state.hide();
+        let written = self.inner.write(buf);
+        drop(state);
+        written
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut state = self.shared.lock();
+        state.hide();
+        let flushed = self.inner.flush();
+        drop(state);
+        flushed
+    }
+}
+
+/// An input that erases the progress, and keeps it hidden while waiting for `inner`.
+#[derive(Debug)]
+pub struct ProgressInput<R> {
+    inner: R,
+    shared: Arc<Shared>,
+}
+
+impl<R: Read> Read for ProgressInput<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.shared.lock();
+        state.hide();
+        state.reading = true;
+        drop(state);
+        let read = self.inner.read(buf);
+        self.shared.lock().reading = false;
+        read
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_progress() {
+        assert_eq!(
+            status('⠋', Duration::from_millis(1300), 0),
+            "⠋ Running for 1.3s"
+        );
+        assert_eq!(
+            status('⠙', Duration::from_secs(75), 123_456),
+            "⠙ Running for 75.0s, executed 123456 ops"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    fmt::{Display, Write as _},
+    time::Duration,
+};
+
+use crate::bench::json_string;
+
+/// What a run of a program did, for scripts and graders to read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunReport<C> {
+    /// The code beef exits with, which is 1 if the run failed.
+    pub exit_code: u8,
+
+    /// The error the run failed with, if it did.
+    pub error: Option<String>,
+
+    /// The line and column of the program the run failed at, both from 1, if it failed at an
+    /// instruction.
+    pub error_position: Option<(usize, usize)>,
+
+    /// The number of ops of the optimized program the run executed, if they were counted.
+    pub ops: Option<u64>,
+
+    /// The index of the cell the tape pointer ended on.
+    pub tape_pointer: isize,
+
+    /// Every cell the run left on the tape that isn't zero, by its index.
+    pub cells: Vec<(isize, C)>,
+
+    /// How long the program ran for.
+    pub time: Duration,
+}
+
+impl<C: Display> RunReport<C> {
+    /// Return the report as a single-line JSON object, with the cells as `[index, value]` pairs
+    /// and the time in nanoseconds.
+    pub fn to_json(&self) -> String {
+        let cells: Vec<_> = self
+            .cells
+            .iter()
+            .map(|(index, cell)| format!("[{index},{cell}]"))
+            .collect();
+        let mut json = format!(r#"{{"exit_code":{},"error":"#, self.exit_code);
+        match &self.error {
+            Some(error) => json.push_str(&json_string(error)),
+            None => json.push_str("null"),
+        }
+        json.push_str(r#","error_position":"#);
+        match self.error_position {
+            Some((line, column)) => write!(json, r#"{{"line":{line},"column":{column}}}"#).unwrap(),
+            None => json.push_str("null"),
+        }
+        json.push_str(r#","ops":"#);
+        match self.ops {
+            Some(ops) => write!(json, "{ops}").unwrap(),
+            None => json.push_str("null"),
+        }
+        write!(
+            json,
+            r#","tape_pointer":{},"cells":[{}],"time_ns":{}}}"#,
+            self.tape_pointer,
+            cells.join(","),
+            self.time.as_nanos()
+        )
+        .unwrap();
+        json
+    }
+
+    /// Describe how long the program ran for, and how many ops it executed in that time if they
+    /// were counted.
+    pub fn timing(&self) -> String {
+        match self.ops {
+            Some(ops) => {
+                let per_second = u128::from(ops) * 1_000_000_000 / self.time.as_nanos().max(1);
+                format!(
+                    "Ran for {:.3?}, executing {ops} ops, {per_second} per second",
+                    self.time
+                )
+            }
+            None => format!("Ran for {:.3?}", self.time),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emit_json() {
+        let report = RunReport {
+            exit_code: 0,
+            error: None,
+            error_position: None,
+            ops: Some(12),
+            tape_pointer: 1,
+            cells: vec![(-1, 3), (2, 255)],
+            time: Duration::from_micros(5),
+        };
+        assert_eq!(
+            report.to_json(),
+            r#"{"exit_code":0,"error":null,"error_position":null,"ops":12,"tape_pointer":1,"cells":[[-1,3],[2,255]],"time_ns":5000}"#
+        );
+
+        let report = RunReport::<u8> {
+            exit_code: 1,
+            error: Some("The program moved past the left end of the tape at 2:1".to_owned()),
+            error_position: Some((2, 1)),
+            ops: None,
+            tape_pointer: 0,
+            cells: vec![],
+            time: Duration::ZERO,
+        };
+        assert_eq!(
+            report.to_json(),
+            r#"{"exit_code":1,"error":"The program moved past the left end of the tape at 2:1","error_position":{"line":2,"column":1},"ops":null,"tape_pointer":0,"cells":[],"time_ns":0}"#
+        );
+    }
+
+    #[test]
+    fn describe_timing() {
+        let mut report = RunReport::<u8> {
+            exit_code: 0,
+            error: None,
+            error_position: None,
+            ops: Some(3000),
+            tape_pointer: 0,
+            cells: vec![],
+            time: Duration::from_millis(2),
+        };
+        assert_eq!(
+            report.timing(),
+            "Ran for 2.000ms, executing 3000 ops, 1500000 per second"
+        );
+        report.ops = None;
+        assert_eq!(report.timing(), "Ran for 2.000ms");
+    }
+}
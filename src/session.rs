@@ -0,0 +1,342 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Read},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+
+/// The input of a run of a program, with the time each part of it came in at, as recorded by
+/// [`Recorder`] and fed back by [`Replay`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Session {
+    pub chunks: Vec<Chunk>,
+}
+
+/// Bytes of input that came in together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// The time since the start of the run, in milliseconds.
+    pub at_ms: u64,
+    pub bytes: Vec<u8>,
+}
+
+impl Session {
+    /// Return the session as a JSON object, like
+    /// `{"chunks":[{"at_ms":0,"bytes":[104,105,10]}]}`, with a chunk on each line.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"chunks\":[");
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            let bytes: Vec<_> = chunk.bytes.iter().map(u8::to_string).collect();
+            write!(
+                json,
+                "{separator}\n{{\"at_ms\":{},\"bytes\":[{}]}}",
+                chunk.at_ms,
+                bytes.join(",")
+            )
+            .unwrap();
+        }
+        json.push_str("\n]}\n");
+        json
+    }
+
+    /// Read a session from the JSON written by [`Session::to_json`].
+    pub fn from_json(json: &str) -> Result<Session> {
+        let mut parser = Parser { json, position: 0 };
+        let mut chunks = Vec::new();
+        parser.object(|parser, key| {
+            if key != "chunks" {
+                bail!("Unknown field `{key}` in the session");
+            }
+            parser.array(|parser| {
+                let (mut at_ms, mut bytes) = (None, None);
+                parser.object(|parser, key| {
+                    match key {
+                        "at_ms" => at_ms = Some(parser.number()?),
+                        "bytes" => {
+                            let mut chunk = Vec::new();
+                            parser.array(|parser| {
+                                chunk.push(parser.byte()?);
+                                Ok(())
+                            })?;
+                            bytes = Some(chunk);
+                        }
+                        _ => bail!("Unknown field `{key}` in a chunk of the session"),
+                    }
+                    Ok(())
+                })?;
+                chunks.push(Chunk {
+                    at_ms: at_ms.ok_or_else(|| anyhow!("A chunk of the session has no `at_ms`"))?,
+                    bytes: bytes.ok_or_else(|| anyhow!("A chunk of the session has no `bytes`"))?,
+                });
+                Ok(())
+            })
+        })?;
+        parser.skip_whitespace();
+        if parser.position < json.len() {
+            bail!("Unexpected text after the session, at {}", parser.position);
+        }
+        Ok(Session { chunks })
+    }
+}
+
+/// Reads the little JSON a session is made of: objects, arrays, strings without escapes and
+/// whole numbers.
+struct Parser<'a> {
+    json: &'a str,
+
+    /// The byte index in `json` of the next character to read.
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.json[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skip past `token`, after any whitespace, if it comes next.
+    fn eat(&mut self, token: char) -> bool {
+        self.skip_whitespace();
+        let found = self.json[self.position..].starts_with(token);
+        if found {
+            self.position += token.len_utf8();
+        }
+        found
+    }
+
+    fn expect(&mut self, token: char) -> Result<()> {
+        if !self.eat(token) {
+            bail!("Expected `{token}` in the session, at {}", self.position);
+        }
+        Ok(())
+    }
+
+    /// Read a list of `open`, then items read by `item` and separated by commas, then `close`.
+    fn list(
+        &mut self,
+        [open, close]: [char; 2],
+        mut item: impl FnMut(&mut Self) -> Result<()>,
+    ) -> Result<()> {
+        self.expect(open)?;
+        if self.eat(close) {
+            return Ok(());
+        }
+        loop {
+            item(self)?;
+            if self.eat(close) {
+                return Ok(());
+            }
+            if !self.eat(',') {
+                bail!(
+                    "Expected `,` or `{close}` in the session, at {}",
+                    self.position
+                );
+            }
+        }
+    }
+
+    /// Read an object, passing the key of every field to `field` to read its value.
+    fn object(&mut self, mut field: impl FnMut(&mut Self, &str) -> Result<()>) -> Result<()> {
+        self.list(['{', '}'], |parser| {
+            let key = parser.string()?;
+            parser.expect(':')?;
+            field(parser, key)
+        })
+    }
+
+    /// Read an array, calling `item` to read every item of it.
+    fn array(&mut self, item: impl FnMut(&mut Self) -> Result<()>) -> Result<()> {
+        self.list(['[', ']'], item)
+    }
+
+    fn string(&mut self) -> Result<&'a str> {
+        self.expect('"')?;
+        let start = self.position;
+        let len = self.json[start..]
+            .find(['"', '\\'])
+            .filter(|&len| self.json[start + len..].starts_with('"'))
+            .ok_or_else(|| anyhow!("Expected a field name in the session, at {start}"))?;
+        self.position += len + 1;
+        Ok(&self.json[start..start + len])
+    }
+
+    fn number(&mut self) -> Result<u64> {
+        self.skip_whitespace();
+        let rest = &self.json[self.position..];
+        let len = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let number = rest[..len].parse().map_err(|_| {
+            anyhow!(
+                "Expected a whole number in the session, at {}",
+                self.position
+            )
+        })?;
+        self.position += len;
+        Ok(number)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        self.skip_whitespace();
+        let start = self.position;
+        let number = self.number()?;
+        u8::try_from(number).map_err(|_| anyhow!("{number} isn't a byte, at {start}"))
+    }
+}
+
+/// An input that records everything read from `inner` into a [`Session`], with the time it came
+/// in at.
+#[derive(Debug)]
+pub struct Recorder<R> {
+    inner: R,
+    start: Instant,
+    session: Session,
+}
+
+impl<R: Read> Recorder<R> {
+    /// Return an input recording what's read from `inner`, timed from now.
+    pub fn new(inner: R) -> Recorder<R> {
+        Recorder {
+            inner,
+            start: Instant::now(),
+            session: Session::default(),
+        }
+    }
+
+    /// Return the session recorded so far.
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+}
+
+impl<R: Read> Read for Recorder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        if len > 0 {
+            let at_ms = u64::try_from(self.start.elapsed().as_millis()).unwrap_or(u64::MAX);
+            // Bytes that are read one by one but came in together make up a single chunk.
+            match self.session.chunks.last_mut() {
+                Some(chunk) if chunk.at_ms == at_ms => chunk.bytes.extend(&buf[..len]),
+                _ => self.session.chunks.push(Chunk {
+                    at_ms,
+                    bytes: buf[..len].to_vec(),
+                }),
+            }
+        }
+        Ok(len)
+    }
+}
+
+/// An input that feeds a recorded [`Session`] back, holding every chunk back until the time it
+/// came in at when it was recorded.
+#[derive(Debug)]
+pub struct Replay {
+    chunks: std::vec::IntoIter<Chunk>,
+    start: Instant,
+    chunk: Vec<u8>,
+
+    /// The index in `chunk` of the next byte `,` reads.
+    position: usize,
+}
+
+impl Replay {
+    /// Return an input replaying `session`, timed from now.
+    pub fn new(session: Session) -> Replay {
+        Replay {
+            chunks: session.chunks.into_iter(),
+            start: Instant::now(),
+            chunk: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.chunk.len() {
+            let Some(chunk) = self.chunks.next() else {
+                return Ok(0);
+            };
+            let at = Duration::from_millis(chunk.at_ms);
+            thread::sleep(at.saturating_sub(self.start.elapsed()));
+            self.chunk = chunk.bytes;
+            self.position = 0;
+        }
+
+        let rest = &self.chunk[self.position..];
+        let len = rest.len().min(buf.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_and_replay() {
+        let mut recorder = Recorder::new(&b"hi\n"[..]);
+        let mut byte = [0];
+        while recorder.read(&mut byte).unwrap() > 0 {}
+        let session = recorder.session().clone();
+        let bytes: Vec<_> = session
+            .chunks
+            .iter()
+            .flat_map(|chunk| chunk.bytes.clone())
+            .collect();
+        assert_eq!(bytes, b"hi\n");
+
+        let session = Session::from_json(&session.to_json()).unwrap();
+        let mut replayed = Vec::new();
+        Replay::new(session).read_to_end(&mut replayed).unwrap();
+        assert_eq!(replayed, b"hi\n");
+    }
+
+    #[test]
+    fn session_json() {
+        let session = Session {
+            chunks: vec![
+                Chunk {
+                    at_ms: 0,
+                    bytes: vec![104, 105],
+                },
+                Chunk {
+                    at_ms: 1500,
+                    bytes: vec![],
+                },
+            ],
+        };
+        let json = session.to_json();
+        assert_eq!(
+            json,
+            "{\"chunks\":[\n{\"at_ms\":0,\"bytes\":[104,105]},\n{\"at_ms\":1500,\"bytes\":[]}\n]}\n"
+        );
+        assert_eq!(Session::from_json(&json).unwrap(), session);
+
+        let spaced = r#" { "chunks" : [ { "bytes" : [ 1 , 2 ] , "at_ms" : 3 } ] } "#;
+        assert_eq!(
+            Session::from_json(spaced).unwrap().chunks,
+            [Chunk {
+                at_ms: 3,
+                bytes: vec![1, 2],
+            }]
+        );
+
+        let error = |json| Session::from_json(json).unwrap_err().to_string();
+        assert_eq!(
+            error(r#"{"chunks":[{"at_ms":0}]}"#),
+            "A chunk of the session has no `bytes`"
+        );
+        assert_eq!(
+            error(r#"{"chunks":[{"at_ms":0,"bytes":[256]}]}"#),
+            "256 isn't a byte, at 31"
+        );
+        assert_eq!(
+            error(r#"{"chunks":[]"#),
+            "Expected `,` or `}` in the session, at 12"
+        );
+    }
+}
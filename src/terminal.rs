@@ -0,0 +1,102 @@
+use std::fs::File;
+#[cfg(unix)]
+use std::{io, mem::MaybeUninit, os::fd::AsRawFd, sync::OnceLock};
+
+#[cfg(unix)]
+use anyhow::Context;
+use anyhow::Result;
+
+/// The file descriptor of the terminal [`RawMode::enable`] changed, with the settings it had
+/// before, which the signal handler restores too.
+#[cfg(unix)]
+static ORIGINAL: OnceLock<(libc::c_int, libc::termios)> = OnceLock::new();
+
+/// Keeps a terminal in cbreak mode until it's dropped, so that `,` gets every key as soon as it's
+/// pressed, without Enter and without the terminal echoing it.
+///
+/// Ctrl-C still stops the program, and restores the terminal on the way out.
+#[derive(Debug)]
+pub struct RawMode {
+    /// The terminal, unless it's the one on stdin, which stays open until the settings are back.
+    _terminal: Option<File>,
+}
+
+impl RawMode {
+    /// Put the terminal `terminal` is open on into cbreak mode, or the one on stdin if it's
+    /// `None`. If the file isn't a terminal, nothing changes and `None` is returned.
+    #[cfg(unix)]
+    pub fn enable(terminal: Option<&File>) -> Result<Option<RawMode>> {
+        let terminal = terminal
+            .map(File::try_clone)
+            .transpose()
+            .context("Failed to open the terminal")?;
+        let fd = terminal
+            .as_ref()
+            .map_or(libc::STDIN_FILENO, AsRawFd::as_raw_fd);
+        // SAFETY: These only read and write the settings of the terminal, which stays open, through
+        // pointers to settings that live on the stack or forever.
+        unsafe {
+            if libc::isatty(fd) == 0 {
+                return Ok(None);
+            }
+
+            let mut settings = MaybeUninit::uninit();
+            if libc::tcgetattr(fd, settings.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error())
+                    .context("Failed to read the settings of the terminal");
+            }
+            let (_, original) = *ORIGINAL.get_or_init(|| (fd, settings.assume_init()));
+
+            let mut cbreak = original;
+            cbreak.c_lflag &= !(libc::ICANON | libc::ECHO);
+            cbreak.c_cc[libc::VMIN] = 1;
+            cbreak.c_cc[libc::VTIME] = 0;
+            let handler: extern "C" fn(libc::c_int) = restore_and_raise;
+            let handler = handler as *const () as libc::sighandler_t;
+            for signal in [libc::SIGINT, libc::SIGQUIT, libc::SIGTERM, libc::SIGHUP] {
+                libc::signal(signal, handler);
+            }
+            if libc::tcsetattr(fd, libc::TCSANOW, &raw const cbreak) != 0 {
+                return Err(io::Error::last_os_error())
+                    .context("Failed to put the terminal into raw mode");
+            }
+        }
+
+        Ok(Some(RawMode {
+            _terminal: terminal,
+        }))
+    }
+
+    /// Fail, as only Unix terminals can be put into raw mode.
+    #[cfg(not(unix))]
+    pub fn enable(_terminal: Option<&File>) -> Result<Option<RawMode>> {
+        anyhow::bail!("`--raw-tty` only works on Unix")
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        restore();
+    }
+}
+
+/// Put the settings of the terminal back the way they were before [`RawMode::enable`].
+#[cfg(unix)]
+fn restore() {
+    if let Some((fd, original)) = ORIGINAL.get() {
+        // SAFETY: `original` was read from the terminal by `tcgetattr`.
+        unsafe { libc::tcsetattr(*fd, libc::TCSANOW, original) };
+    }
+}
+
+/// Restore the terminal, then die of `signal` like the program would have without the handler.
+#[cfg(unix)]
+extern "C" fn restore_and_raise(signal: libc::c_int) {
+    restore();
+    // SAFETY: Both are async-signal-safe, and the default handler ends the process.
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
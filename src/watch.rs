@@ -0,0 +1,83 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// How long to wait between looking at the file again.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A file that is looked at every so often, to tell when it changes.
+///
+/// A change is a new modification time or length. Files that are missing count as a change too
+/// once they're back, so editors that save by replacing the file are caught as well.
+#[derive(Debug)]
+pub struct Watcher {
+    path: PathBuf,
+
+    /// The modification time and length of the file when it was last looked at, or `None` if it
+    /// couldn't be read.
+    last: Option<(SystemTime, u64)>,
+}
+
+impl Watcher {
+    /// Return a watcher for the file at `path`, which only sees changes made from now on.
+    pub fn new(path: &Path) -> Watcher {
+        Watcher {
+            path: path.to_owned(),
+            last: stamp(path),
+        }
+    }
+
+    /// Return whether the file changed since it was last looked at.
+    pub fn changed(&mut self) -> bool {
+        let stamp = stamp(&self.path);
+        let changed = stamp.is_some() && stamp != self.last;
+        self.last = stamp;
+        changed
+    }
+}
+
+/// Wait until one of the files of `watchers` changes.
+pub fn wait(watchers: &mut [Watcher]) {
+    // Every file is looked at, so that changes to several of them at once are only seen once.
+    while !watchers
+        .iter_mut()
+        .fold(false, |changed, watcher| watcher.changed() | changed)
+    {
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Return the modification time and length of the file at `path`, if it can be read.
+fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    #[test]
+    fn see_changes() {
+        let path = env::temp_dir().join(format!("beef-watch-{}.b", process::id()));
+        fs::write(&path, "+").unwrap();
+        let mut watcher = Watcher::new(&path);
+        assert!(!watcher.changed());
+
+        // The length changes too, in case the modification time doesn't before the next tick.
+        fs::write(&path, "++").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        fs::remove_file(&path).unwrap();
+        assert!(!watcher.changed());
+        fs::write(&path, "++").unwrap();
+        assert!(watcher.changed());
+        fs::remove_file(path).unwrap();
+    }
+}